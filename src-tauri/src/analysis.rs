@@ -1,28 +1,33 @@
 use crate::models::*;
 
 /// Parse LLM JSON response into a ChapterAnalysis struct.
-/// Includes tolerance for common LLM output issues (markdown fences, trailing commas).
+/// Includes tolerance for common LLM output issues (markdown fences, trailing commas),
+/// falling back to `repair_json` for truncated or loosely formatted output.
 pub fn parse_analysis_json(json_str: &str) -> Result<ChapterAnalysis, String> {
     let cleaned = clean_json_response(json_str);
-    serde_json::from_str::<ChapterAnalysis>(&cleaned).map_err(|e| {
-        format!(
-            "JSON 解析失败: {}。原始文本前200字: {}",
-            e,
-            &cleaned[..cleaned.len().min(200)]
-        )
-    })
+    serde_json::from_str::<ChapterAnalysis>(&cleaned)
+        .or_else(|e| serde_json::from_str::<ChapterAnalysis>(&repair_json(&cleaned)).map_err(|_| e))
+        .map_err(|e| {
+            format!(
+                "JSON 解析失败: {}。原始文本前200字: {}",
+                e,
+                cleaned.chars().take(200).collect::<String>()
+            )
+        })
 }
 
 /// Parse LLM JSON response into a NovelSummary struct.
 pub fn parse_summary_json(json_str: &str) -> Result<NovelSummary, String> {
     let cleaned = clean_json_response(json_str);
-    serde_json::from_str::<NovelSummary>(&cleaned).map_err(|e| {
-        format!(
-            "汇总 JSON 解析失败: {}。原始文本前200字: {}",
-            e,
-            &cleaned[..cleaned.len().min(200)]
-        )
-    })
+    serde_json::from_str::<NovelSummary>(&cleaned)
+        .or_else(|e| serde_json::from_str::<NovelSummary>(&repair_json(&cleaned)).map_err(|_| e))
+        .map_err(|e| {
+            format!(
+                "汇总 JSON 解析失败: {}。原始文本前200字: {}",
+                e,
+                cleaned.chars().take(200).collect::<String>()
+            )
+        })
 }
 
 /// Merge multiple segment analyses into one combined analysis.
@@ -167,6 +172,85 @@ pub fn merge_segment_analyses(segments: Vec<ChapterAnalysis>) -> ChapterAnalysis
     merged
 }
 
+/// Add the dimensions `base` lacks from `new`; dimensions `base` already has are kept as is.
+pub fn merge_new_dimensions(base: ChapterAnalysis, new: ChapterAnalysis) -> ChapterAnalysis {
+    ChapterAnalysis {
+        characters: base.characters.or(new.characters),
+        plot: base.plot.or(new.plot),
+        foreshadowing: base.foreshadowing.or(new.foreshadowing),
+        writing_technique: base.writing_technique.or(new.writing_technique),
+        rhetoric: base.rhetoric.or(new.rhetoric),
+        emotion: base.emotion.or(new.emotion),
+        themes: base.themes.or(new.themes),
+        worldbuilding: base.worldbuilding.or(new.worldbuilding),
+    }
+}
+
+/// Compare two analyses field by field. List entries with a `name` (characters,
+/// locations, devices...) are matched by name so a reordered list is not a change.
+pub fn diff_analyses(before: &ChapterAnalysis, after: &ChapterAnalysis) -> Vec<FieldDiff> {
+    let before = serde_json::to_value(before).unwrap_or_default();
+    let after = serde_json::to_value(after).unwrap_or_default();
+    let mut diffs = Vec::new();
+    diff_values("", Some(&before), Some(&after), &mut diffs);
+    diffs
+}
+
+fn diff_values(
+    path: &str,
+    before: Option<&serde_json::Value>,
+    after: Option<&serde_json::Value>,
+    diffs: &mut Vec<FieldDiff>,
+) {
+    use serde_json::Value;
+
+    if before == after {
+        return;
+    }
+    let join = |key: &str| {
+        if path.is_empty() {
+            key.to_string()
+        } else {
+            format!("{}.{}", path, key)
+        }
+    };
+
+    match (before, after) {
+        (Some(Value::Object(b)), Some(Value::Object(a))) => {
+            let mut keys: Vec<&String> = b.keys().collect();
+            keys.extend(a.keys().filter(|k| !b.contains_key(*k)));
+            for key in keys {
+                diff_values(&join(key), b.get(key), a.get(key), diffs);
+            }
+        }
+        (Some(Value::Array(b)), Some(Value::Array(a)))
+            if b.iter().chain(a.iter()).all(|v| v.get("name").is_some()) =>
+        {
+            let name_of = |v: &Value| v["name"].as_str().unwrap_or_default().to_string();
+            let mut names: Vec<String> = b.iter().map(name_of).collect();
+            for v in a {
+                let name = name_of(v);
+                if !names.contains(&name) {
+                    names.push(name);
+                }
+            }
+            for name in names {
+                diff_values(
+                    &format!("{}[{}]", path, name),
+                    b.iter().find(|v| name_of(v) == name),
+                    a.iter().find(|v| name_of(v) == name),
+                    diffs,
+                );
+            }
+        }
+        _ => diffs.push(FieldDiff {
+            path: path.to_string(),
+            before: before.cloned(),
+            after: after.cloned(),
+        }),
+    }
+}
+
 /// Clean common LLM output issues from JSON response.
 pub fn clean_json_response(raw: &str) -> String {
     let mut s = raw.trim().to_string();
@@ -190,10 +274,177 @@ pub fn clean_json_response(raw: &str) -> String {
     s
 }
 
+/// Best-effort rewrite of almost-JSON into valid JSON. Handles prose around the object,
+/// Chinese quotes and punctuation used as delimiters, unquoted keys, raw newlines inside
+/// strings, trailing commas and output truncated mid-way (dropping the incomplete tail and
+/// closing what is still open).
+pub fn repair_json(raw: &str) -> String {
+    struct Open {
+        closer: char,
+        after_colon: bool,
+    }
+
+    let cleaned = clean_json_response(raw);
+    let start = match cleaned.find(['{', '[']) {
+        Some(i) => i,
+        None => return cleaned,
+    };
+    let chars: Vec<char> = cleaned[start..].chars().collect();
+
+    let mut out = String::new();
+    let mut stack: Vec<Open> = Vec::new();
+    // Output length and open closers at the last point where the JSON was complete
+    // apart from its closing brackets.
+    let mut safe: (usize, Vec<char>) = (0, Vec::new());
+    let mut in_string: Option<char> = None;
+    let mut string_is_value = false;
+    let mut escaped = false;
+
+    let is_value_pos = |stack: &[Open]| match stack.last() {
+        Some(o) => o.closer == ']' || o.after_colon,
+        None => false,
+    };
+    let snapshot = |out: &str, stack: &[Open]| {
+        (
+            out.len(),
+            stack.iter().map(|o| o.closer).collect::<Vec<_>>(),
+        )
+    };
+
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        if let Some(terminator) = in_string {
+            if escaped {
+                out.push(c);
+                escaped = false;
+            } else if c == '\\' {
+                out.push(c);
+                escaped = true;
+            } else if c == terminator {
+                out.push('"');
+                in_string = None;
+                if string_is_value {
+                    safe = snapshot(&out, &stack);
+                }
+            } else {
+                match c {
+                    '"' => out.push_str("\\\""),
+                    '\n' => out.push_str("\\n"),
+                    '\r' => {}
+                    '\t' => out.push_str("\\t"),
+                    _ => out.push(c),
+                }
+            }
+            i += 1;
+            continue;
+        }
+
+        match c {
+            '"' | '“' | '”' => {
+                in_string = Some(if c == '"' { '"' } else { '”' });
+                string_is_value = is_value_pos(&stack);
+                out.push('"');
+            }
+            '{' | '[' => {
+                stack.push(Open {
+                    closer: if c == '{' { '}' } else { ']' },
+                    after_colon: false,
+                });
+                out.push(c);
+                safe = snapshot(&out, &stack);
+            }
+            '}' | ']' => {
+                let open = match stack.pop() {
+                    Some(o) => o,
+                    None => break,
+                };
+                out.push(open.closer);
+                safe = snapshot(&out, &stack);
+                if stack.is_empty() {
+                    break;
+                }
+            }
+            ',' | '，' => {
+                let next = chars[i + 1..].iter().find(|c| !c.is_whitespace());
+                if !matches!(next, Some('}') | Some(']')) {
+                    safe = snapshot(&out, &stack);
+                    out.push(',');
+                    if let Some(o) = stack.last_mut() {
+                        o.after_colon = false;
+                    }
+                }
+            }
+            ':' | '：' => {
+                out.push(':');
+                if let Some(o) = stack.last_mut() {
+                    o.after_colon = true;
+                }
+            }
+            c if c.is_alphanumeric() || c == '_' || c == '-' => {
+                let mut end = i;
+                while end < chars.len()
+                    && (chars[end].is_alphanumeric() || matches!(chars[end], '_' | '-' | '.' | '+'))
+                {
+                    end += 1;
+                }
+                let word: String = chars[i..end].iter().collect();
+                let is_value = is_value_pos(&stack);
+                if matches!(word.as_str(), "true" | "false" | "null") || word.parse::<f64>().is_ok()
+                {
+                    out.push_str(&word);
+                } else {
+                    out.push_str(&serde_json::to_string(&word).unwrap_or_default());
+                }
+                if is_value && end < chars.len() {
+                    safe = snapshot(&out, &stack);
+                }
+                i = end;
+                continue;
+            }
+            _ => out.push(c),
+        }
+        i += 1;
+    }
+
+    if stack.is_empty() {
+        return out;
+    }
+
+    // Truncated output: keep a value string that was cut off, drop any other partial tail.
+    if in_string.is_some() && string_is_value {
+        if escaped {
+            out.pop();
+        }
+        out.push('"');
+        safe = snapshot(&out, &stack);
+    }
+    let (len, closers) = safe;
+    out.truncate(len);
+    let trimmed = out.trim_end().len();
+    out.truncate(trimmed);
+    if out.ends_with(',') {
+        out.pop();
+    }
+    for closer in closers.iter().rev() {
+        out.push(*closer);
+    }
+    out
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_parse_error_excerpt_on_cjk() {
+        // Byte 200 falls inside a character here; the error must not panic.
+        let broken = format!("分析如下：{}", "主角离开了村庄，".repeat(40));
+        assert!(broken.len() > 200 && !broken.is_char_boundary(200));
+        assert!(parse_analysis_json(&broken).is_err());
+        assert!(parse_summary_json(&broken).is_err());
+    }
+
     #[test]
     fn test_clean_json_response_with_fences() {
         let raw = "```json\n{\"plot\": {\"summary\": \"test\"}}\n```";
@@ -224,4 +475,69 @@ mod tests {
         assert!(analysis.plot.is_some());
         assert_eq!(analysis.plot.unwrap().summary, "测试摘要");
     }
+
+    #[test]
+    fn test_merge_new_dimensions() {
+        let base = parse_analysis_json(
+            r#"{"plot": {"summary": "旧摘要", "key_events": [], "conflicts": [], "suspense": []}}"#,
+        )
+        .unwrap();
+        let new = parse_analysis_json(
+            r#"{"plot": {"summary": "新摘要", "key_events": [], "conflicts": [], "suspense": []},
+                "themes": {"motifs": ["成长"], "values": []}}"#,
+        )
+        .unwrap();
+
+        let merged = merge_new_dimensions(base, new);
+        assert_eq!(merged.plot.unwrap().summary, "旧摘要");
+        assert!(merged.themes.is_some());
+        assert!(merged.characters.is_none());
+    }
+
+    #[test]
+    fn test_diff_analyses() {
+        let before = parse_analysis_json(
+            r#"{"plot": {"summary": "旧摘要", "key_events": [], "conflicts": [], "suspense": []},
+                "characters": {"characters": [
+                    {"name": "甲", "role": "主角", "traits": [], "actions": "出场"},
+                    {"name": "乙", "role": "配角", "traits": [], "actions": "旁观"}
+                ], "relationships": []}}"#,
+        )
+        .unwrap();
+        let after = parse_analysis_json(
+            r#"{"plot": {"summary": "新摘要", "key_events": [], "conflicts": [], "suspense": []},
+                "characters": {"characters": [
+                    {"name": "乙", "role": "配角", "traits": [], "actions": "旁观"},
+                    {"name": "甲", "role": "主角", "traits": ["果断"], "actions": "出场"}
+                ], "relationships": []}}"#,
+        )
+        .unwrap();
+
+        let diffs = diff_analyses(&before, &after);
+        let paths: Vec<&str> = diffs.iter().map(|d| d.path.as_str()).collect();
+        assert_eq!(
+            paths,
+            vec!["characters.characters[甲].traits", "plot.summary"]
+        );
+        assert_eq!(diffs[1].before, Some(serde_json::json!("旧摘要")));
+    }
+
+    #[test]
+    fn test_repair_json_truncated() {
+        let raw = r#"{"plot": {"summary": "测试摘要", "key_events": [{"event": "事件1"}, {"event": "事件"#;
+        let analysis = parse_analysis_json(raw).unwrap();
+        let plot = analysis.plot.unwrap();
+        assert_eq!(plot.summary, "测试摘要");
+        assert_eq!(plot.key_events.len(), 2);
+    }
+
+    #[test]
+    fn test_repair_json_loose_syntax() {
+        let raw =
+            "分析结果如下：\n{plot: {summary: “他说\"走吧\"”，conflicts: [“冲突一”,],}}\n以上。";
+        let repaired = repair_json(raw);
+        let value: serde_json::Value = serde_json::from_str(&repaired).unwrap();
+        assert_eq!(value["plot"]["summary"], "他说\"走吧\"");
+        assert_eq!(value["plot"]["conflicts"][0], "冲突一");
+    }
 }
@@ -0,0 +1,785 @@
+//! Headless front end for NovelParser: import, analyze, summarize and export
+//! without starting the webview. Shares the GUI's database by default.
+
+use clap::{Args, Parser, Subcommand};
+use novelparser_lib::html_text::ExtractOptions;
+use novelparser_lib::import::{self, ImportControl};
+use novelparser_lib::models::*;
+use novelparser_lib::progress::ProgressSink;
+use novelparser_lib::storage::Database;
+use novelparser_lib::{chapter_edit, export, fingerprint, importer, pipeline, resync, txt_parser};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+#[derive(Parser)]
+#[command(name = "novelparser-cli", version, about = "NovelParser 命令行版")]
+struct Cli {
+    /// Data directory holding novelparser.db (defaults to the GUI's app data dir)
+    #[arg(long, global = true)]
+    data_dir: Option<PathBuf>,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Import an EPUB, MOBI/AZW3, FB2, DOCX, HTML or Markdown file (or a folder of HTML or
+    /// Markdown chapters), a single TXT, or several TXT files as one novel
+    Import(ImportArgs),
+    /// Re-read a novel's source file: keep analyses of unchanged chapters, flag changed ones
+    /// as stale and append new ones
+    Resync { novel_id: String },
+    /// List the chapter-heading rule sets used to split TXT files
+    Headings,
+    /// List imported novels
+    List,
+    /// List the chapters of a novel
+    Chapters { novel_id: String },
+    /// List chapters that repeat an earlier chapter of the novel, exactly or nearly
+    Duplicates { novel_id: String },
+    /// Rename a chapter
+    Rename { chapter_id: i64, title: String },
+    /// Merge adjacent chapters into the first of them
+    Merge {
+        #[arg(required = true, num_args = 2..)]
+        chapter_ids: Vec<i64>,
+    },
+    /// Split a chapter in two; the second half becomes a new chapter after it
+    Split {
+        chapter_id: i64,
+        /// Start the new chapter at this paragraph (1 = first non-empty line)
+        #[arg(long, conflicts_with = "byte", required_unless_present = "byte")]
+        paragraph: Option<usize>,
+        /// Start the new chapter at this byte offset
+        #[arg(long)]
+        byte: Option<usize>,
+        /// Title of the new chapter (default: "<title>（续）")
+        #[arg(long)]
+        title: Option<String>,
+    },
+    /// Move a chapter to another position (1 = first)
+    Move { chapter_id: i64, position: usize },
+    /// Analyze unanalyzed chapters (or the given chapter ids) through the LLM API
+    Analyze {
+        novel_id: String,
+        /// Only analyze these chapter ids
+        #[arg(long, value_delimiter = ',')]
+        chapters: Vec<i64>,
+        /// Override the novel's enabled dimensions, e.g. plot,characters
+        #[arg(long, value_delimiter = ',', value_parser = parse_dimension)]
+        dimensions: Vec<AnalysisDimension>,
+        /// Only request dimensions missing from existing analyses and merge them in
+        #[arg(long)]
+        incremental: bool,
+        /// Stop dispatching chapters after this many failures (default: configured policy)
+        #[arg(long)]
+        stop_after: Option<u32>,
+    },
+    /// Show the analysis job queue of a novel
+    Jobs { novel_id: String },
+    /// Continue an interrupted batch from the persisted job queue
+    Resume {
+        novel_id: String,
+        /// Re-queue failed jobs before resuming
+        #[arg(long)]
+        retry_failed: bool,
+        /// Stop dispatching chapters after this many failures (default: configured policy)
+        #[arg(long)]
+        stop_after: Option<u32>,
+    },
+    /// List recorded LLM calls of a novel, or print one call in full
+    Calls {
+        novel_id: String,
+        /// Only calls for this chapter id
+        #[arg(long)]
+        chapter: Option<i64>,
+        /// Print prompt and response of this call id
+        #[arg(long)]
+        show: Option<i64>,
+    },
+    /// List the analysis versions of a chapter, or switch the active one
+    Versions {
+        chapter_id: i64,
+        /// Make this version id the chapter's current analysis
+        #[arg(long)]
+        activate: Option<i64>,
+    },
+    /// Generate the whole-book summary from analyzed chapters
+    Summarize { novel_id: String },
+    /// Export the Markdown report into a directory
+    Export {
+        novel_id: String,
+        dir: String,
+        /// Convert the report: s2t, t2s, s2tw or s2hk
+        #[arg(long, value_parser = parse_conversion)]
+        convert: Option<ChineseConversion>,
+    },
+    /// Show or update the LLM configuration
+    Config {
+        #[arg(long)]
+        base_url: Option<String>,
+        #[arg(long)]
+        api_key: Option<String>,
+        #[arg(long)]
+        model: Option<String>,
+        #[arg(long)]
+        max_context_tokens: Option<u32>,
+        #[arg(long)]
+        temperature: Option<f32>,
+        #[arg(long)]
+        max_concurrent_tasks: Option<u32>,
+        /// auto, heuristic, cl100k, o200k, or the path of a HuggingFace tokenizer.json
+        #[arg(long, value_parser = parse_tokenizer)]
+        tokenizer: Option<TokenizerChoice>,
+    },
+}
+
+#[derive(Args)]
+struct ImportArgs {
+    #[arg(required = true)]
+    paths: Vec<String>,
+    /// Treat each TXT file as one chapter instead of splitting by headings
+    #[arg(long)]
+    files: bool,
+    /// For books: import every chapter, not only the suggested ones
+    #[arg(long)]
+    all: bool,
+    /// For EPUB and HTML: keep ruby readings in parentheses instead of dropping them
+    #[arg(long)]
+    keep_ruby: bool,
+    /// For a single TXT: heading rule set to split by (default: auto-detect)
+    #[arg(long)]
+    headings: Option<String>,
+    /// For TXT: encoding of the files, e.g. gbk, big5, shift_jis, utf-16le (default: detect)
+    #[arg(long)]
+    encoding: Option<String>,
+    /// Convert the text: s2t, t2s, s2tw or s2hk
+    #[arg(long, value_parser = parse_conversion, conflicts_with = "append")]
+    convert: Option<ChineseConversion>,
+    /// Append the chapters to this novel instead of creating a new one, skipping those it
+    /// already has
+    #[arg(long, value_name = "NOVEL_ID")]
+    append: Option<String>,
+}
+
+fn parse_dimension(s: &str) -> Result<AnalysisDimension, String> {
+    serde_json::from_value(serde_json::Value::String(s.to_string())).map_err(|_| {
+        let names: Vec<String> = AnalysisDimension::all()
+            .iter()
+            .map(|d| serde_json::to_string(d).unwrap_or_default())
+            .collect();
+        format!("未知维度 {}，可选: {}", s, names.join(", "))
+    })
+}
+
+fn parse_tokenizer(s: &str) -> Result<TokenizerChoice, String> {
+    Ok(match s {
+        "auto" => TokenizerChoice::Auto,
+        "heuristic" => TokenizerChoice::Heuristic,
+        "cl100k" => TokenizerChoice::Cl100k,
+        "o200k" => TokenizerChoice::O200k,
+        path if path.ends_with(".json") => TokenizerChoice::HuggingFace(path.to_string()),
+        _ => {
+            return Err(format!(
+                "未知 tokenizer {}，可选: auto, heuristic, cl100k, o200k 或 tokenizer.json 路径",
+                s
+            ))
+        }
+    })
+}
+
+fn parse_conversion(s: &str) -> Result<ChineseConversion, String> {
+    serde_json::from_value(serde_json::Value::String(s.to_lowercase()))
+        .map_err(|_| format!("未知转换 {}，可选: s2t, t2s, s2tw, s2hk", s))
+}
+
+/// Prints pipeline progress as plain lines; streaming chunks are skipped.
+struct StdoutSink;
+
+impl ProgressSink for StdoutSink {
+    fn analysis_progress(&self, p: ProgressEvent) {
+        println!("[{}] ({}/{}) {}", p.status, p.current, p.total, p.message);
+    }
+
+    fn batch_progress(&self, p: ProgressEvent) {
+        println!("[{}] ({}/{}) {}", p.status, p.current, p.total, p.message);
+    }
+
+    fn streaming(&self, _chunk: StreamingChunk) {}
+
+    fn import_progress(&self, p: ProgressEvent) {
+        println!("[{}] ({}/{}) {}", p.status, p.current, p.total, p.message);
+    }
+}
+
+fn default_data_dir() -> PathBuf {
+    // Same location as tauri's app_data_dir() for the bundle identifier.
+    dirs::data_dir()
+        .unwrap_or_else(|| PathBuf::from("."))
+        .join("top.initsnow.novelparser")
+}
+
+fn import(db: &Database, args: ImportArgs) -> Result<String, String> {
+    let ImportArgs {
+        paths,
+        files,
+        all,
+        keep_ruby,
+        headings,
+        encoding,
+        convert,
+        append,
+    } = args;
+    let is_book = |p: &String| importer::is_supported(p);
+
+    let parse = || -> Result<(BookInfo, SourceType, ImportOptions, Vec<ParsedChapter>), String> {
+        Ok(if paths.len() == 1 && is_book(&paths[0]) {
+            let path = &paths[0];
+            let options = ExtractOptions { keep_ruby };
+            let (_, previews) = importer::preview(path, options)?;
+            let selected: Vec<usize> = previews
+                .iter()
+                .filter(|c| all || c.suggested)
+                .map(|c| c.index)
+                .collect();
+            let (book, source_type, chapters, known) =
+                importer::parse_selected(path, &selected, options)?;
+            let options = ImportOptions {
+                keep_ruby,
+                selection: Some(ChapterSelection::Book {
+                    indices: selected,
+                    known,
+                }),
+                ..ImportOptions::default()
+            };
+            (book, source_type, options, chapters)
+        } else if paths.iter().any(is_book) {
+            return Err("一次只能导入一本电子书".to_string());
+        } else {
+            if paths.len() == 1 && !files {
+                let path = paths[0].clone();
+                let sets = db.load_heading_rule_sets().map_err(|e| e.to_string())?;
+                let sets = txt_parser::select_heading_rules(sets, headings.as_deref())?;
+                let (title, chapters, warnings) =
+                    txt_parser::parse_single_txt(&path, &sets, encoding.as_deref())?;
+                print_warnings(&warnings);
+                let options = ImportOptions {
+                    heading_rules: headings,
+                    encoding,
+                    ..ImportOptions::default()
+                };
+                (
+                    BookInfo::new(title),
+                    SourceType::SingleTxt(path),
+                    options,
+                    chapters,
+                )
+            } else {
+                let (title, chapters, warnings) =
+                    txt_parser::parse_txt_files(paths.clone(), encoding.as_deref())?;
+                print_warnings(&warnings);
+                let options = ImportOptions {
+                    encoding,
+                    ..ImportOptions::default()
+                };
+                (
+                    BookInfo::new(title),
+                    SourceType::TxtFiles(paths),
+                    options,
+                    chapters,
+                )
+            }
+        })
+    };
+
+    // Filled in by the parse, for the summary below.
+    let mut title = String::new();
+    let mut chapter_count = 0;
+    let cancel = cancel_on_ctrl_c();
+    let control = ImportControl {
+        sink: &StdoutSink,
+        cancel: &cancel,
+    };
+    let result = import::run_import(db, control, append.as_deref(), convert, || {
+        let (book, source_type, options, chapters) = parse()?;
+        if append.is_none() {
+            warn_if_in_library(db, &book, &chapters)?;
+        }
+        title = book.title.clone();
+        chapter_count = chapters.len();
+        Ok((book, source_type, options, chapters))
+    })?;
+    if append.is_some() {
+        println!(
+            "已追加 {} 章，跳过已有的 {} 章",
+            chapter_count - result.skipped.len(),
+            result.skipped.len()
+        );
+        if !result.same_title.is_empty() {
+            println!(
+                "以下章节与已有章节同名但正文不同，已一并追加：{}",
+                result.same_title.join("、")
+            );
+        }
+    } else {
+        println!("已导入《{}》，共 {} 章", title, chapter_count);
+    }
+    print_cleanup_report(&result.cleanup);
+    Ok(result.novel_id)
+}
+
+fn print_warnings(warnings: &[String]) {
+    for warning in warnings {
+        eprintln!("{}", warning);
+    }
+}
+
+/// Importing a book the library already has is allowed, but worth a warning.
+fn warn_if_in_library(
+    db: &Database,
+    book: &BookInfo,
+    chapters: &[ParsedChapter],
+) -> Result<(), String> {
+    let hashes: Vec<u64> = chapters
+        .iter()
+        .filter_map(|c| fingerprint::simhash(&c.content))
+        .collect();
+    for m in fingerprint::library_matches(db, &book.metadata.identifiers, &hashes)? {
+        let identifier = if m.same_identifier {
+            "，ISBN 等标识相同"
+        } else {
+            ""
+        };
+        eprintln!(
+            "注意: 书库中的《{}》（{}）可能是同一本书：{} 章内容相同{}",
+            m.title, m.novel_id, m.matching_chapters, identifier
+        );
+    }
+    Ok(())
+}
+
+fn print_cleanup_report(report: &CleanupReport) {
+    if report.chars_before == report.chars_after {
+        return;
+    }
+    println!(
+        "清理: 删除 {} 字（{} → {}）",
+        report.chars_before - report.chars_after,
+        report.chars_before,
+        report.chars_after
+    );
+    for hit in &report.rule_hits {
+        println!(
+            "  规则 {}: {} 处，如「{}」",
+            hit.rule,
+            hit.count,
+            hit.examples.join("」「")
+        );
+    }
+    for line in &report.repeated_lines {
+        let kept = if line.removed {
+            ""
+        } else {
+            "（像是正文，已保留）"
+        };
+        println!(
+            "  重复行「{}」: {} 章{}",
+            line.line, line.chapter_count, kept
+        );
+    }
+    if report.blank_lines_removed > 0 {
+        println!("  空行: {} 行", report.blank_lines_removed);
+    }
+}
+
+/// A cancel flag raised by Ctrl-C, letting in-flight chapters finish. A second Ctrl-C exits
+/// at once; an import cut short this way is rolled back the next time the database opens.
+fn cancel_on_ctrl_c() -> Arc<AtomicBool> {
+    let cancel = Arc::new(AtomicBool::new(false));
+    let cancel_on_signal = cancel.clone();
+    tokio::spawn(async move {
+        if tokio::signal::ctrl_c().await.is_ok() {
+            eprintln!("收到中断信号，等待进行中的章节完成后停止...（再按一次 Ctrl-C 立即退出）");
+            cancel_on_signal.store(true, Ordering::Relaxed);
+        }
+        if tokio::signal::ctrl_c().await.is_ok() {
+            eprintln!("已强制退出");
+            std::process::exit(130);
+        }
+    });
+    cancel
+}
+
+fn print_report(report: &BatchReport) {
+    println!(
+        "成功 {} 章，失败 {} 章，共 {} 章",
+        report.succeeded,
+        report.failed.len(),
+        report.total
+    );
+    for f in &report.failed {
+        println!("  {}\t{}\t{}", f.chapter_id, f.chapter_title, f.error);
+    }
+}
+
+fn lock(db: &Mutex<Database>) -> Result<MutexGuard<'_, Database>, String> {
+    db.lock().map_err(|e| e.to_string())
+}
+
+async fn run(cli: Cli) -> Result<(), String> {
+    let data_dir = cli.data_dir.unwrap_or_else(default_data_dir);
+    let db = Database::open_for_app(&data_dir).map_err(|e| format!("数据库初始化失败: {}", e))?;
+    let db = Mutex::new(db);
+
+    match cli.command {
+        Command::Import(args) => {
+            let novel_id = import(&*lock(&db)?, args)?;
+            println!("{}", novel_id);
+        }
+        Command::Resync { novel_id } => {
+            let cancel = cancel_on_ctrl_c();
+            let control = ImportControl {
+                sink: &StdoutSink,
+                cancel: &cancel,
+            };
+            let report = resync::resync_novel(&*lock(&db)?, &novel_id, control)?;
+            println!(
+                "未变 {} 章，更新 {} 章（分析已标记为过期），新增 {} 章",
+                report.unchanged,
+                report.modified.len(),
+                report.added.len()
+            );
+            for title in &report.missing {
+                println!("  源文件中已找不到: {}", title);
+            }
+            print_cleanup_report(&report.cleanup);
+        }
+        Command::Headings => {
+            for set in lock(&db)?
+                .load_heading_rule_sets()
+                .map_err(|e| e.to_string())?
+            {
+                let kind = if set.builtin { "内置" } else { "自定义" };
+                println!("{}\t{}\t{}", set.name, kind, set.patterns.join("  |  "));
+            }
+        }
+        Command::List => {
+            for n in lock(&db)?.list_novels().map_err(|e| e.to_string())? {
+                println!(
+                    "{}\t{}\t{}\t{}/{} 章已分析",
+                    n.id,
+                    n.title,
+                    n.metadata.author.as_deref().unwrap_or("-"),
+                    n.analyzed_count,
+                    n.chapter_count
+                );
+            }
+        }
+        Command::Chapters { novel_id } => {
+            let db = lock(&db)?;
+            let novel = db.load_novel(&novel_id).map_err(|e| e.to_string())?;
+            let volumes = db.list_volumes(&novel_id).map_err(|e| e.to_string())?;
+            let mut volume_id = None;
+            for c in db
+                .list_chapter_metas(&novel_id)
+                .map_err(|e| e.to_string())?
+            {
+                if c.volume_id != volume_id {
+                    volume_id = c.volume_id;
+                    if let Some(v) = volumes.iter().find(|v| Some(v.id) == volume_id) {
+                        println!("== {} ==", v.title);
+                    }
+                }
+                // "~": analyzed, but some enabled dimensions are still missing.
+                // "!": analyzed before the text changed on resync.
+                let mark = if c.is_analyzed_for(&novel.enabled_dimensions) {
+                    "✓"
+                } else if c.analysis_stale {
+                    "!"
+                } else if c.has_analysis {
+                    "~"
+                } else {
+                    " "
+                };
+                println!("{}\t{}\t{}\t{}", c.id, mark, c.index + 1, c.title);
+            }
+        }
+        Command::Duplicates { novel_id } => {
+            for d in fingerprint::find_duplicate_chapters(&*lock(&db)?, &novel_id)? {
+                let kind = if d.exact { "相同" } else { "相似" };
+                println!(
+                    "{}\t{}\t{}\t与第 {} 章「{}」{}",
+                    d.chapter_id,
+                    d.index + 1,
+                    d.title,
+                    d.duplicate_of_index + 1,
+                    d.duplicate_of_title,
+                    kind
+                );
+            }
+        }
+        Command::Rename { chapter_id, title } => {
+            let db = lock(&db)?;
+            chapter_edit::rename_chapter(&db, chapter_id, &title)?;
+        }
+        Command::Merge { chapter_ids } => {
+            let db = lock(&db)?;
+            let id = chapter_edit::merge_chapters(&db, &chapter_ids)?;
+            println!("已合并为章节 {}", id);
+        }
+        Command::Split {
+            chapter_id,
+            paragraph,
+            byte,
+            title,
+        } => {
+            let at = match (paragraph, byte) {
+                (Some(p), _) => SplitPoint::Paragraph(p.saturating_sub(1)),
+                (None, Some(b)) => SplitPoint::Byte(b),
+                (None, None) => unreachable!("clap requires --paragraph or --byte"),
+            };
+            let db = lock(&db)?;
+            let id = chapter_edit::split_chapter(&db, chapter_id, at, title.as_deref())?;
+            println!("已拆分，新章节 {}", id);
+        }
+        Command::Move {
+            chapter_id,
+            position,
+        } => {
+            let db = lock(&db)?;
+            db.move_chapter(chapter_id, position.saturating_sub(1))
+                .map_err(|e| e.to_string())?;
+        }
+        Command::Analyze {
+            novel_id,
+            chapters,
+            dimensions,
+            incremental,
+            stop_after,
+        } => {
+            let update_mode = if incremental {
+                UpdateMode::Incremental
+            } else {
+                UpdateMode::Replace
+            };
+            let (novel, metas) = {
+                let db = lock(&db)?;
+                let mut novel = db.load_novel(&novel_id).map_err(|e| e.to_string())?;
+                if !dimensions.is_empty() {
+                    novel.enabled_dimensions = dimensions;
+                }
+                let metas: Vec<ChapterMeta> = db
+                    .list_chapter_metas(&novel_id)
+                    .map_err(|e| e.to_string())?
+                    .into_iter()
+                    .filter(|m| {
+                        if !chapters.is_empty() {
+                            chapters.contains(&m.id)
+                        } else if incremental {
+                            !m.is_analyzed_for(&novel.enabled_dimensions)
+                        } else {
+                            m.needs_analysis()
+                        }
+                    })
+                    .collect();
+                (novel, metas)
+            };
+            if metas.is_empty() {
+                println!("没有需要分析的章节");
+                return Ok(());
+            }
+
+            let cancel = cancel_on_ctrl_c();
+            let policy = stop_after.map(FailurePolicy::StopAfter);
+            let report = pipeline::batch_analyze(
+                &StdoutSink,
+                &db,
+                &cancel,
+                &novel,
+                metas,
+                update_mode,
+                policy,
+            )
+            .await?;
+            print_report(&report);
+        }
+        Command::Jobs { novel_id } => {
+            for j in lock(&db)?.list_jobs(&novel_id).map_err(|e| e.to_string())? {
+                println!(
+                    "{}\t{}\t{}\t{}\t{}",
+                    j.chapter_id,
+                    j.status.as_str(),
+                    j.attempts,
+                    j.chapter_title,
+                    j.last_error.unwrap_or_default()
+                );
+            }
+        }
+        Command::Resume {
+            novel_id,
+            retry_failed,
+            stop_after,
+        } => {
+            if retry_failed {
+                lock(&db)?
+                    .retry_failed_jobs(&novel_id)
+                    .map_err(|e| e.to_string())?;
+            }
+            let cancel = cancel_on_ctrl_c();
+            let policy = stop_after.map(FailurePolicy::StopAfter);
+            let report =
+                pipeline::run_job_queue(&StdoutSink, &db, &cancel, &novel_id, policy).await?;
+            print_report(&report);
+        }
+        Command::Calls {
+            show: Some(call_id),
+            ..
+        } => {
+            let call = lock(&db)?
+                .load_llm_call(call_id)
+                .map_err(|e| e.to_string())?;
+            println!("# prompt\n{}\n", call.prompt);
+            match (call.response, call.error) {
+                (Some(response), _) => println!("# response\n{}", response),
+                (None, Some(error)) => println!("# error\n{}", error),
+                (None, None) => {}
+            }
+        }
+        Command::Calls {
+            novel_id, chapter, ..
+        } => {
+            for c in lock(&db)?
+                .list_llm_calls(&novel_id, chapter)
+                .map_err(|e| e.to_string())?
+            {
+                let tokens = match (c.prompt_tokens, c.completion_tokens) {
+                    (Some(p), Some(o)) => format!("{}+{}", p, o),
+                    _ => "-".to_string(),
+                };
+                println!(
+                    "{}\t{}\t{}\t{}\t{}ms\t{}\t{}",
+                    c.id,
+                    c.created_at,
+                    c.kind.as_str(),
+                    c.chapter_id.map(|id| id.to_string()).unwrap_or_default(),
+                    c.latency_ms,
+                    tokens,
+                    if c.success { "ok" } else { "failed" }
+                );
+            }
+        }
+        Command::Versions {
+            chapter_id,
+            activate,
+        } => {
+            let db = lock(&db)?;
+            if let Some(version_id) = activate {
+                db.set_active_analysis_version(chapter_id, version_id)
+                    .map_err(|e| e.to_string())?;
+            }
+            for v in db
+                .list_analysis_versions(chapter_id)
+                .map_err(|e| e.to_string())?
+            {
+                let mark = if v.active { "*" } else { " " };
+                let dims: Vec<String> = v
+                    .dimensions
+                    .iter()
+                    .map(|d| {
+                        serde_json::to_string(d)
+                            .unwrap_or_default()
+                            .trim_matches('"')
+                            .to_string()
+                    })
+                    .collect();
+                println!(
+                    "{}\t{}\t{}\t{}\tv{}\t{}",
+                    v.id,
+                    mark,
+                    v.created_at,
+                    v.model,
+                    v.prompt_version,
+                    dims.join(",")
+                );
+            }
+        }
+        Command::Summarize { novel_id } => {
+            let summary = pipeline::generate_full_summary(&StdoutSink, &db, &novel_id).await?;
+            println!(
+                "{}",
+                serde_json::to_string_pretty(&summary).map_err(|e| e.to_string())?
+            );
+        }
+        Command::Export {
+            novel_id,
+            dir,
+            convert,
+        } => {
+            let target = export::export_novel_report(&*lock(&db)?, &novel_id, &dir, convert)?;
+            println!("已导出到 {}", target.display());
+        }
+        Command::Config {
+            base_url,
+            api_key,
+            model,
+            max_context_tokens,
+            temperature,
+            max_concurrent_tasks,
+            tokenizer,
+        } => {
+            let db = lock(&db)?;
+            let mut config = db.load_llm_config().map_err(|e| e.to_string())?;
+            let mut changed = false;
+            if let Some(v) = base_url {
+                config.base_url = v;
+                changed = true;
+            }
+            if let Some(v) = api_key {
+                config.api_key = v;
+                changed = true;
+            }
+            if let Some(v) = model {
+                config.model = v;
+                changed = true;
+            }
+            if let Some(v) = max_context_tokens {
+                config.max_context_tokens = v;
+                changed = true;
+            }
+            if let Some(v) = temperature {
+                config.temperature = v;
+                changed = true;
+            }
+            if let Some(v) = max_concurrent_tasks {
+                config.max_concurrent_tasks = v;
+                changed = true;
+            }
+            if let Some(v) = tokenizer {
+                config.tokenizer = v;
+                changed = true;
+            }
+            if changed {
+                db.save_llm_config(&config).map_err(|e| e.to_string())?;
+            }
+            if !config.api_key.is_empty() {
+                config.api_key = "********".to_string();
+            }
+            println!(
+                "{}",
+                serde_json::to_string_pretty(&config).map_err(|e| e.to_string())?
+            );
+        }
+    }
+
+    Ok(())
+}
+
+#[tokio::main]
+async fn main() {
+    if let Err(e) = run(Cli::parse()).await {
+        eprintln!("错误: {}", e);
+        std::process::exit(1);
+    }
+}
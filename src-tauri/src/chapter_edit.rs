@@ -0,0 +1,311 @@
+use crate::models::{Chapter, ChapterMeta, SplitPoint};
+use crate::storage::Database;
+
+pub fn rename_chapter(db: &Database, chapter_id: i64, title: &str) -> Result<(), String> {
+    let title = title.trim();
+    if title.is_empty() {
+        return Err("章节标题不能为空".to_string());
+    }
+    db.rename_chapter(chapter_id, title)
+        .map_err(|e| e.to_string())
+}
+
+/// Merge adjacent chapters into the first of them and return its id. The merged chapter
+/// has to be analyzed again.
+pub fn merge_chapters(db: &Database, chapter_ids: &[i64]) -> Result<i64, String> {
+    let first = chapter_ids.first().ok_or("没有选择任何章节")?;
+    let novel_id = db.load_chapter(*first).map_err(|e| e.to_string())?.novel_id;
+    let metas = db
+        .list_chapter_metas(&novel_id)
+        .map_err(|e| e.to_string())?;
+    let ids = adjacent_chapters(&metas, chapter_ids)?;
+    let chapters = ids
+        .iter()
+        .map(|&id| db.load_chapter(id))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())?;
+    let notes: Vec<&str> = chapters.iter().filter_map(|c| c.notes.as_deref()).collect();
+    let notes = (!notes.is_empty()).then(|| notes.join("\n\n"));
+    db.merge_chapters(&ids, &merge_contents(&chapters), notes.as_deref())
+        .map_err(|e| e.to_string())?;
+    Ok(ids[0])
+}
+
+/// Split a chapter at `at`; the second half becomes a new chapter right after it, titled
+/// `title` or "<title>（续）". Returns the new chapter's id. Both halves have to be analyzed
+/// again.
+pub fn split_chapter(
+    db: &Database,
+    chapter_id: i64,
+    at: SplitPoint,
+    title: Option<&str>,
+) -> Result<i64, String> {
+    let chapter = db.load_chapter(chapter_id).map_err(|e| e.to_string())?;
+    let (head, tail) = split_content(&chapter.content, at)?;
+    let tail_title = match title.map(str::trim) {
+        Some(t) if !t.is_empty() => t.to_string(),
+        _ => format!("{}（续）", chapter.title),
+    };
+    db.split_chapter(chapter_id, &head, &tail, &tail_title)
+        .map_err(|e| e.to_string())
+}
+
+/// Cut a chapter's text in two at `at`. Both halves are trimmed and must keep some text.
+pub fn split_content(content: &str, at: SplitPoint) -> Result<(String, String), String> {
+    let offset = match at {
+        SplitPoint::Byte(offset) => {
+            if !content.is_char_boundary(offset) {
+                return Err(format!("位置 {} 不在字符边界上", offset));
+            }
+            offset
+        }
+        SplitPoint::Paragraph(n) => paragraph_offset(content, n).ok_or_else(|| {
+            format!(
+                "段落序号 {} 超出范围（共 {} 段）",
+                n,
+                content.lines().filter(|l| !l.trim().is_empty()).count()
+            )
+        })?,
+    };
+
+    let (head, tail) = content.split_at(offset);
+    let (head, tail) = (head.trim(), tail.trim());
+    if head.is_empty() || tail.is_empty() {
+        return Err("拆分后的两部分都需要有内容".to_string());
+    }
+    Ok((head.to_string(), tail.to_string()))
+}
+
+/// Byte offset where the `n`-th paragraph (0-based, one per non-empty line) starts.
+fn paragraph_offset(content: &str, n: usize) -> Option<usize> {
+    let mut offset = 0;
+    let mut count = 0;
+    for line in content.split_inclusive('\n') {
+        if !line.trim().is_empty() {
+            if count == n {
+                return Some(offset);
+            }
+            count += 1;
+        }
+        offset += line.len();
+    }
+    None
+}
+
+/// Order the chapters to merge by position and check they are next to each other.
+pub fn adjacent_chapters(metas: &[ChapterMeta], chapter_ids: &[i64]) -> Result<Vec<i64>, String> {
+    if chapter_ids.len() < 2 {
+        return Err("至少需要选择两个章节才能合并".to_string());
+    }
+    let mut positions = chapter_ids
+        .iter()
+        .map(|id| {
+            metas
+                .iter()
+                .position(|m| m.id == *id)
+                .ok_or_else(|| format!("章节 {} 不属于这本小说", id))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    positions.sort_unstable();
+    positions.dedup();
+    if positions.windows(2).any(|w| w[1] != w[0] + 1) {
+        return Err("只能合并相邻的章节".to_string());
+    }
+    if positions
+        .windows(2)
+        .any(|w| metas[w[0]].volume_id != metas[w[1]].volume_id)
+    {
+        return Err("只能合并同一卷中的章节".to_string());
+    }
+    Ok(positions.into_iter().map(|p| metas[p].id).collect())
+}
+
+/// Text of chapters merged into the first one. Later chapters keep their titles as
+/// heading lines.
+pub fn merge_contents(chapters: &[Chapter]) -> String {
+    let mut content = String::new();
+    for (i, ch) in chapters.iter().enumerate() {
+        if i > 0 {
+            content.push_str("\n\n");
+            content.push_str(&ch.title);
+            content.push('\n');
+        }
+        content.push_str(ch.content.trim());
+    }
+    content
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_content() {
+        let content = "第一段。\n\n　　第二段。\n第三段。";
+        let (head, tail) = split_content(content, SplitPoint::Paragraph(1)).unwrap();
+        assert_eq!(head, "第一段。");
+        assert_eq!(tail, "第二段。\n第三段。");
+
+        let (head, tail) = split_content(content, SplitPoint::Byte(content.len() - 12)).unwrap();
+        assert_eq!(head, "第一段。\n\n　　第二段。");
+        assert_eq!(tail, "第三段。");
+
+        assert!(split_content(content, SplitPoint::Byte(1)).is_err());
+        assert!(split_content(content, SplitPoint::Paragraph(0)).is_err());
+        assert!(split_content(content, SplitPoint::Paragraph(3)).is_err());
+    }
+
+    #[test]
+    fn test_adjacent_chapters() {
+        let metas: Vec<ChapterMeta> = (0..4)
+            .map(|i| ChapterMeta {
+                id: 10 + i,
+                index: i as usize,
+                title: String::new(),
+                has_analysis: false,
+                analyzed_dimensions: Vec::new(),
+                has_raw_response: false,
+                token_estimate: 0,
+                volume_id: Some(if i < 3 { 1 } else { 2 }),
+                analysis_stale: false,
+            })
+            .collect();
+        assert_eq!(adjacent_chapters(&metas, &[12, 11]).unwrap(), vec![11, 12]);
+        assert!(adjacent_chapters(&metas, &[12, 13]).is_err());
+        assert!(adjacent_chapters(&metas, &[10, 12]).is_err());
+        assert!(adjacent_chapters(&metas, &[10]).is_err());
+        assert!(adjacent_chapters(&metas, &[10, 99]).is_err());
+    }
+
+    #[test]
+    fn test_edit_keeps_indices_consecutive() {
+        use crate::models::{BookInfo, ImportOptions, ParsedChapter, SourceType};
+
+        let db = Database::open_in_memory().unwrap();
+        let novel_id = db
+            .create_novel(
+                BookInfo::new("测试"),
+                SourceType::SingleTxt(String::new()),
+                ImportOptions::default(),
+                None,
+                vec![
+                    ParsedChapter::new("一", "甲。\n乙。"),
+                    ParsedChapter::new("二", "丙。"),
+                    ParsedChapter::new("三", "丁。"),
+                ],
+            )
+            .unwrap();
+        let ids: Vec<i64> = db
+            .list_chapter_metas(&novel_id)
+            .unwrap()
+            .iter()
+            .map(|m| m.id)
+            .collect();
+
+        let new_id = split_chapter(&db, ids[0], SplitPoint::Paragraph(1), None).unwrap();
+        merge_chapters(&db, &[ids[2], ids[1]]).unwrap();
+        db.move_chapter(ids[1], 0).unwrap();
+
+        let metas = db.list_chapter_metas(&novel_id).unwrap();
+        let got: Vec<(usize, &str)> = metas.iter().map(|m| (m.index, m.title.as_str())).collect();
+        assert_eq!(got, vec![(0, "二"), (1, "一"), (2, "一（续）")]);
+        assert_eq!(metas[2].id, new_id);
+        assert_eq!(db.load_chapter(ids[1]).unwrap().content, "丙。\n\n三\n丁。");
+    }
+
+    #[test]
+    fn test_move_flags_reordered_analyses() {
+        use crate::models::{
+            AnalysisOrigin, BookInfo, ChapterAnalysis, ImportOptions, ParsedChapter, SourceType,
+        };
+
+        let db = Database::open_in_memory().unwrap();
+        let novel_id = db
+            .create_novel(
+                BookInfo::new("测试"),
+                SourceType::SingleTxt(String::new()),
+                ImportOptions::default(),
+                None,
+                ["一", "二", "三", "四"]
+                    .iter()
+                    .map(|t| ParsedChapter::new(*t, "正文。"))
+                    .collect(),
+            )
+            .unwrap();
+        let origin = AnalysisOrigin {
+            model: "manual".to_string(),
+            dimensions: Vec::new(),
+            prompt_version: String::new(),
+        };
+        let ids: Vec<i64> = db
+            .list_chapter_metas(&novel_id)
+            .unwrap()
+            .iter()
+            .map(|m| m.id)
+            .collect();
+        for &id in &ids {
+            db.save_chapter_analysis(id, &ChapterAnalysis::default(), &origin)
+                .unwrap();
+        }
+
+        db.move_chapter(ids[0], 2).unwrap();
+
+        let metas = db.list_chapter_metas(&novel_id).unwrap();
+        let stale: Vec<(&str, bool)> = metas
+            .iter()
+            .map(|m| (m.title.as_str(), m.analysis_stale))
+            .collect();
+        assert_eq!(
+            stale,
+            vec![("二", true), ("三", false), ("一", true), ("四", true)]
+        );
+    }
+
+    #[test]
+    fn test_merge_keeps_call_log() {
+        use crate::models::{
+            BookInfo, ImportOptions, LlmCall, LlmCallKind, ParsedChapter, SourceType,
+        };
+
+        let db = Database::open_in_memory().unwrap();
+        let novel_id = db
+            .create_novel(
+                BookInfo::new("测试"),
+                SourceType::SingleTxt(String::new()),
+                ImportOptions::default(),
+                None,
+                vec![
+                    ParsedChapter::new("一", "甲。"),
+                    ParsedChapter::new("二", "乙。"),
+                ],
+            )
+            .unwrap();
+        let ids: Vec<i64> = db
+            .list_chapter_metas(&novel_id)
+            .unwrap()
+            .iter()
+            .map(|m| m.id)
+            .collect();
+        db.record_llm_call(&LlmCall {
+            id: 0,
+            novel_id: novel_id.clone(),
+            chapter_id: Some(ids[1]),
+            kind: LlmCallKind::Chapter,
+            model: "m".to_string(),
+            prompt: "p".to_string(),
+            response: Some("r".to_string()),
+            error: None,
+            attempts: 1,
+            latency_ms: 0,
+            prompt_tokens: None,
+            completion_tokens: None,
+            created_at: String::new(),
+        })
+        .unwrap();
+
+        merge_chapters(&db, &ids).unwrap();
+        let calls = db.list_llm_calls(&novel_id, None).unwrap();
+        assert_eq!(calls.len(), 1);
+        assert_eq!(calls[0].chapter_id, None);
+    }
+}
@@ -0,0 +1,314 @@
+use crate::models::{
+    BookInfo, ChineseConversion, CleanupConfig, CleanupReport, ParsedChapter, RepeatedLine, RuleHit,
+};
+use crate::storage::Database;
+use crate::zh_convert;
+use regex::Regex;
+use std::collections::{HashMap, HashSet};
+use std::sync::LazyLock;
+
+const MAX_EXAMPLES: usize = 3;
+/// Longer lines are prose, not headers or footers.
+const MAX_REPEATED_LINE_CHARS: usize = 60;
+/// Shorter lines ("……", "“嗯。”") repeat naturally.
+const MIN_REPEATED_LINE_CHARS: usize = 5;
+/// With fewer chapters, a line in all of them is not yet a pattern.
+const MIN_REPEATED_CHAPTERS: usize = 3;
+/// What a header or footer looks like: it names the book, its author or a site, or talks
+/// about updates and pages rather than the story.
+static BOILERPLATE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(
+        r"(?i)《.+》|作者[:：]|https?://|www\.|\.(com|net|org|cc)\b|(小说|书)(网|站|屋|吧|阁)|首发|手打|更新|本章字数|第\s*\d+\s*页|求(月票|推荐|收藏)",
+    )
+    .unwrap()
+});
+
+/// Clean parsed chapters with the saved cleanup settings and apply `conversion`, the way
+/// every import does before storing. Conversion into Simplified runs before cleanup and
+/// conversion into Traditional after it, so the cleanup rules (written in Simplified) see
+/// Simplified text.
+pub fn prepare_import(
+    db: &Database,
+    book: &mut BookInfo,
+    conversion: Option<ChineseConversion>,
+    chapters: &mut [ParsedChapter],
+) -> Result<CleanupReport, String> {
+    let config = db.load_cleanup_config().map_err(|e| e.to_string())?;
+    if let Some(conv) = conversion {
+        zh_convert::convert_book(book, conv);
+    }
+    let simplify = conversion.filter(|c| c.to_simplified());
+    let traditionalize = conversion.filter(|c| !c.to_simplified());
+
+    if let Some(conv) = simplify {
+        zh_convert::convert_chapters(chapters, conv);
+    }
+    let cleanup = clean_chapters(chapters, &config)?;
+    if let Some(conv) = traditionalize {
+        zh_convert::convert_chapters(chapters, conv);
+    }
+    Ok(cleanup)
+}
+
+/// Check that every blacklist rule compiles, so broken rules are rejected when saved.
+pub fn validate_config(config: &CleanupConfig) -> Result<(), String> {
+    compile_blacklist(config).map(|_| ())
+}
+
+fn compile_blacklist(config: &CleanupConfig) -> Result<Vec<Regex>, String> {
+    config
+        .blacklist
+        .iter()
+        .filter(|r| !r.trim().is_empty())
+        .map(|r| Regex::new(r).map_err(|e| format!("清理规则「{}」的正则无效: {}", r, e)))
+        .collect()
+}
+
+/// Clean parsed chapters in place, before they are stored: blacklist rules first, then
+/// repeated headers and footers, then whitespace.
+pub fn clean_chapters(
+    chapters: &mut [ParsedChapter],
+    config: &CleanupConfig,
+) -> Result<CleanupReport, String> {
+    let total_chars = |chapters: &[ParsedChapter]| -> usize {
+        chapters.iter().map(|c| c.content.chars().count()).sum()
+    };
+    let mut report = CleanupReport {
+        chars_before: total_chars(chapters),
+        ..CleanupReport::default()
+    };
+    if !config.enabled {
+        report.chars_after = report.chars_before;
+        return Ok(report);
+    }
+
+    let rules = compile_blacklist(config)?;
+    let mut hits: Vec<RuleHit> = rules
+        .iter()
+        .map(|re| RuleHit {
+            rule: re.as_str().to_string(),
+            count: 0,
+            examples: Vec::new(),
+        })
+        .collect();
+    if !rules.is_empty() {
+        for ch in chapters.iter_mut() {
+            ch.content = apply_blacklist(&ch.content, &rules, &mut hits);
+        }
+    }
+    report.rule_hits = hits.into_iter().filter(|h| h.count > 0).collect();
+
+    if config.remove_repeated_lines {
+        report.repeated_lines = remove_repeated_lines(chapters, config.repeated_line_share);
+    }
+
+    if config.normalize_whitespace {
+        for ch in chapters.iter_mut() {
+            let (content, removed) = normalize_whitespace(&ch.content);
+            ch.content = content;
+            report.blank_lines_removed += removed;
+        }
+    }
+
+    report.chars_after = total_chars(chapters);
+    Ok(report)
+}
+
+/// Remove every match of the rules; lines holding nothing else are dropped.
+fn apply_blacklist(content: &str, rules: &[Regex], hits: &mut [RuleHit]) -> String {
+    let mut lines: Vec<String> = Vec::new();
+    for line in content.lines() {
+        let mut cleaned = line.to_string();
+        for (re, hit) in rules.iter().zip(hits.iter_mut()) {
+            let mut matched = false;
+            for m in re.find_iter(&cleaned) {
+                matched = true;
+                hit.count += 1;
+                if hit.examples.len() < MAX_EXAMPLES {
+                    hit.examples.push(m.as_str().to_string());
+                }
+            }
+            if matched {
+                cleaned = re.replace_all(&cleaned, "").into_owned();
+            }
+        }
+        if cleaned.trim().is_empty() && !line.trim().is_empty() {
+            continue;
+        }
+        lines.push(cleaned);
+    }
+    lines.join("\n")
+}
+
+/// Find short lines that appear in at least `share` of the chapters (and at least
+/// `MIN_REPEATED_CHAPTERS`), and drop those shaped like a header or footer, such as a site
+/// name or book title printed on every page. The others are reported but kept.
+fn remove_repeated_lines(chapters: &mut [ParsedChapter], share: f32) -> Vec<RepeatedLine> {
+    if chapters.len() < MIN_REPEATED_CHAPTERS {
+        return Vec::new();
+    }
+    let threshold = ((chapters.len() as f32 * share).ceil() as usize).max(MIN_REPEATED_CHAPTERS);
+
+    let mut counts: HashMap<&str, usize> = HashMap::new();
+    for ch in chapters.iter() {
+        let lines: HashSet<&str> = ch
+            .content
+            .lines()
+            .map(str::trim)
+            .filter(|l| {
+                (MIN_REPEATED_LINE_CHARS..=MAX_REPEATED_LINE_CHARS).contains(&l.chars().count())
+            })
+            .collect();
+        for line in lines {
+            *counts.entry(line).or_default() += 1;
+        }
+    }
+    let mut repeated: Vec<RepeatedLine> = counts
+        .into_iter()
+        .filter(|&(_, n)| n >= threshold)
+        .map(|(line, n)| RepeatedLine {
+            line: line.to_string(),
+            chapter_count: n,
+            removed: BOILERPLATE.is_match(line),
+        })
+        .collect();
+    if repeated.is_empty() {
+        return repeated;
+    }
+    repeated.sort_by(|a, b| {
+        b.chapter_count
+            .cmp(&a.chapter_count)
+            .then(a.line.cmp(&b.line))
+    });
+
+    let lines: HashSet<&str> = repeated
+        .iter()
+        .filter(|r| r.removed)
+        .map(|r| r.line.as_str())
+        .collect();
+    if lines.is_empty() {
+        return repeated;
+    }
+    for ch in chapters.iter_mut() {
+        ch.content = ch
+            .content
+            .lines()
+            .filter(|l| !lines.contains(l.trim()))
+            .collect::<Vec<_>>()
+            .join("\n");
+    }
+    repeated
+}
+
+/// Trim every line, drop zero-width characters and keep at most one blank line between
+/// paragraphs. Returns the text and the number of blank lines dropped.
+fn normalize_whitespace(content: &str) -> (String, usize) {
+    let mut out = String::with_capacity(content.len());
+    let mut blank_run = 0;
+    let mut removed = 0;
+    for line in content.lines() {
+        let line: String = line
+            .chars()
+            .filter(|c| !matches!(c, '\u{200B}'..='\u{200D}' | '\u{FEFF}'))
+            .collect();
+        let line = line.trim();
+        if line.is_empty() {
+            blank_run += 1;
+            continue;
+        }
+        if !out.is_empty() {
+            out.push('\n');
+            if blank_run > 0 {
+                out.push('\n');
+                removed += blank_run - 1;
+            }
+        } else {
+            removed += blank_run;
+        }
+        blank_run = 0;
+        out.push_str(line);
+    }
+    (out, removed + blank_run)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_blacklist_and_whitespace() {
+        let mut chapters = vec![ParsedChapter::new(
+            "第一章",
+            "\n　　他推开门。（本章未完，请翻页）\n\n\n\n本书由某某书屋整理\n　　灰尘在阳光里飘动。请访问 www.example.com \n\u{200B}",
+        )];
+        let report = clean_chapters(&mut chapters, &CleanupConfig::default()).unwrap();
+
+        assert_eq!(chapters[0].content, "他推开门。\n\n灰尘在阳光里飘动。");
+
+        assert_eq!(report.rule_hits.len(), 3);
+        assert_eq!(report.rule_hits[0].examples, vec!["本书由某某书屋整理"]);
+        assert_eq!(report.rule_hits[2].examples, vec!["请访问 www.example.com"]);
+        assert_eq!(report.blank_lines_removed, 4);
+        assert!(report.chars_after < report.chars_before);
+    }
+
+    #[test]
+    fn test_repeated_lines() {
+        let mut chapters: Vec<ParsedChapter> = (1..=4)
+            .map(|i| {
+                let header = if i < 4 {
+                    "《某书》 作者：某人"
+                } else {
+                    ""
+                };
+                ParsedChapter::new(
+                    format!("第{i}章"),
+                    format!("{header}\n“嗯。”\n“师父，我错了。”\n第{i}章的正文内容。"),
+                )
+            })
+            .collect();
+        let config = CleanupConfig {
+            blacklist: Vec::new(),
+            ..CleanupConfig::default()
+        };
+        let report = clean_chapters(&mut chapters, &config).unwrap();
+
+        assert_eq!(
+            report.repeated_lines,
+            vec![
+                RepeatedLine {
+                    line: "“师父，我错了。”".to_string(),
+                    chapter_count: 4,
+                    removed: false,
+                },
+                RepeatedLine {
+                    line: "《某书》 作者：某人".to_string(),
+                    chapter_count: 3,
+                    removed: true,
+                }
+            ]
+        );
+        assert_eq!(
+            chapters[0].content,
+            "“嗯。”\n“师父，我错了。”\n第1章的正文内容。"
+        );
+    }
+
+    #[test]
+    fn test_disabled_and_invalid() {
+        let config = CleanupConfig {
+            enabled: false,
+            ..CleanupConfig::default()
+        };
+        let mut chapters = vec![ParsedChapter::new("一", "本书由某某整理\n\n\n正文")];
+        let report = clean_chapters(&mut chapters, &config).unwrap();
+        assert_eq!(chapters[0].content, "本书由某某整理\n\n\n正文");
+        assert_eq!(report.chars_before, report.chars_after);
+
+        let broken = CleanupConfig {
+            blacklist: vec!["(".to_string()],
+            ..CleanupConfig::default()
+        };
+        assert!(validate_config(&broken).is_err());
+    }
+}
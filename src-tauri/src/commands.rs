@@ -0,0 +1,1024 @@
+use crate::html_text::ExtractOptions;
+use crate::import::{self, ImportControl};
+use crate::models::*;
+use crate::pipeline::{self, build_context_string};
+use crate::progress::ProgressSink;
+use crate::storage::Database;
+use crate::{
+    analysis, chapter_edit, cleanup, export, fingerprint, importer, llm, prompt, resync,
+    token_utils, txt_parser,
+};
+use base64::prelude::*;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use tauri::{Emitter, Manager, State};
+
+struct AppState {
+    db: Mutex<Database>,
+    data_dir: PathBuf,
+    batch_cancel: AtomicBool,
+    import_cancel: AtomicBool,
+}
+
+// ---- Novel Management Commands ----
+
+#[tauri::command]
+fn list_novels(state: State<AppState>) -> Result<Vec<NovelMeta>, String> {
+    let db = state.db.lock().map_err(|e| e.to_string())?;
+    db.list_novels().map_err(|e| e.to_string())
+}
+
+/// Preview an EPUB, MOBI/AZW3, FB2, DOCX, HTML or Markdown file, or a folder of HTML or
+/// Markdown chapters. `keep_ruby` keeps ruby readings in parentheses instead of dropping them.
+#[tauri::command]
+fn preview_book(
+    state: State<AppState>,
+    path: String,
+    keep_ruby: Option<bool>,
+) -> Result<BookPreview, String> {
+    let options = ExtractOptions {
+        keep_ruby: keep_ruby.unwrap_or(false),
+    };
+    let (book, chapters) = importer::preview(&path, options)?;
+    let hashes: Vec<u64> = chapters
+        .iter()
+        .filter(|c| c.suggested)
+        .filter_map(|c| c.simhash)
+        .collect();
+    let db = state.db.lock().map_err(|e| e.to_string())?;
+    let library_matches = fingerprint::library_matches(&db, &book.metadata.identifiers, &hashes)?;
+    Ok(BookPreview {
+        title: book.title,
+        path,
+        chapters,
+        library_matches,
+    })
+}
+
+/// Run `work` on a blocking task with its own database connection, so the shared one isn't
+/// held while thousands of chapters are written. Progress is emitted as `import_progress`
+/// events and `cancel_import` stops it.
+async fn with_import_connection<T, F>(app: tauri::AppHandle, work: F) -> Result<T, String>
+where
+    T: Send + 'static,
+    F: FnOnce(&Database, ImportControl) -> Result<T, String> + Send + 'static,
+{
+    tokio::task::spawn_blocking(move || {
+        let state = app.state::<AppState>();
+        let db = Database::open(&state.data_dir).map_err(|e| e.to_string())?;
+        let control = ImportControl {
+            sink: &app,
+            cancel: &state.import_cancel,
+        };
+        work(&db, control)
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+/// Parse, clean and store an import through `with_import_connection`; either way it ends,
+/// it is all or nothing.
+async fn run_import<F>(
+    app: tauri::AppHandle,
+    append_to: Option<String>,
+    conversion: Option<ChineseConversion>,
+    parse: F,
+) -> Result<ImportResult, String>
+where
+    F: FnOnce(
+            &Database,
+        ) -> Result<(BookInfo, SourceType, ImportOptions, Vec<ParsedChapter>), String>
+        + Send
+        + 'static,
+{
+    with_import_connection(app, move |db, control| {
+        import::run_import(db, control, append_to.as_deref(), conversion, || parse(db))
+    })
+    .await
+}
+
+/// With `append_to` the chapters are added to the end of that novel instead of a new one,
+/// skipping those it already has. The other import commands take it too.
+#[tauri::command]
+async fn import_book_selected(
+    app: tauri::AppHandle,
+    path: String,
+    selected_indices: Vec<usize>,
+    conversion: Option<ChineseConversion>,
+    keep_ruby: Option<bool>,
+    append_to: Option<String>,
+) -> Result<ImportResult, String> {
+    let keep_ruby = keep_ruby.unwrap_or(false);
+    run_import(app, append_to, conversion, move |_| {
+        let (book, source_type, chapters, known) =
+            importer::parse_selected(&path, &selected_indices, ExtractOptions { keep_ruby })?;
+        let options = ImportOptions {
+            keep_ruby,
+            selection: Some(ChapterSelection::Book {
+                indices: selected_indices,
+                known,
+            }),
+            ..ImportOptions::default()
+        };
+        Ok((book, source_type, options, chapters))
+    })
+    .await
+}
+
+/// `encoding` (a label such as "big5" or "utf-16le") overrides encoding detection.
+/// `conversion` converts the text between Simplified and Traditional Chinese.
+#[tauri::command]
+async fn import_txt_files(
+    app: tauri::AppHandle,
+    paths: Vec<String>,
+    encoding: Option<String>,
+    conversion: Option<ChineseConversion>,
+    append_to: Option<String>,
+) -> Result<ImportResult, String> {
+    run_import(app, append_to, conversion, move |_| {
+        let (title, chapters, _) = txt_parser::parse_txt_files(paths.clone(), encoding.as_deref())?;
+        let options = ImportOptions {
+            encoding,
+            ..ImportOptions::default()
+        };
+        Ok((
+            BookInfo::new(title),
+            SourceType::TxtFiles(paths),
+            options,
+            chapters,
+        ))
+    })
+    .await
+}
+
+/// `heading_rules` names the rule set to split by; without it the best-fitting set is used.
+#[tauri::command]
+async fn import_single_txt(
+    app: tauri::AppHandle,
+    path: String,
+    heading_rules: Option<String>,
+    encoding: Option<String>,
+    conversion: Option<ChineseConversion>,
+    append_to: Option<String>,
+) -> Result<ImportResult, String> {
+    run_import(app, append_to, conversion, move |db| {
+        let sets = db.load_heading_rule_sets().map_err(|e| e.to_string())?;
+        let sets = txt_parser::select_heading_rules(sets, heading_rules.as_deref())?;
+        let (title, chapters, _) = txt_parser::parse_single_txt(&path, &sets, encoding.as_deref())?;
+        let options = ImportOptions {
+            heading_rules,
+            encoding,
+            ..ImportOptions::default()
+        };
+        Ok((
+            BookInfo::new(title),
+            SourceType::SingleTxt(path),
+            options,
+            chapters,
+        ))
+    })
+    .await
+}
+
+#[tauri::command]
+fn preview_single_txt(
+    state: State<AppState>,
+    path: String,
+    heading_rules: Option<String>,
+    encoding: Option<String>,
+) -> Result<TxtPreview, String> {
+    let sets = {
+        let db = state.db.lock().map_err(|e| e.to_string())?;
+        db.load_heading_rule_sets().map_err(|e| e.to_string())?
+    };
+    let sets = txt_parser::select_heading_rules(sets, heading_rules.as_deref())?;
+    let (title, rule_set, encoding, chapters) =
+        txt_parser::preview_single_txt(&path, &sets, encoding.as_deref())?;
+    let hashes: Vec<u64> = chapters
+        .iter()
+        .filter(|c| c.suggested)
+        .filter_map(|c| c.simhash)
+        .collect();
+    let db = state.db.lock().map_err(|e| e.to_string())?;
+    let library_matches = fingerprint::library_matches(&db, &[], &hashes)?;
+    Ok(TxtPreview {
+        title,
+        path,
+        rule_set,
+        encoding_warning: encoding.warning(),
+        encoding,
+        chapters,
+        library_matches,
+    })
+}
+
+/// Import the chapters chosen in a TXT preview. `heading_rules` and `encoding` should be the
+/// preview's `rule_set` and encoding name so the file splits the same way again.
+#[tauri::command]
+async fn import_txt_selected(
+    app: tauri::AppHandle,
+    path: String,
+    heading_rules: Option<String>,
+    chapters: Vec<txt_parser::TxtChapterSelection>,
+    encoding: Option<String>,
+    conversion: Option<ChineseConversion>,
+    append_to: Option<String>,
+) -> Result<ImportResult, String> {
+    run_import(app, append_to, conversion, move |db| {
+        let sets = db.load_heading_rule_sets().map_err(|e| e.to_string())?;
+        let sets = txt_parser::select_heading_rules(sets, heading_rules.as_deref())?;
+        let (title, parsed, known) =
+            txt_parser::parse_txt_selected(&path, &sets, &chapters, encoding.as_deref())?;
+        let options = ImportOptions {
+            heading_rules,
+            encoding,
+            selection: Some(ChapterSelection::Txt { chapters, known }),
+            ..ImportOptions::default()
+        };
+        Ok((
+            BookInfo::new(title),
+            SourceType::SingleTxt(path),
+            options,
+            parsed,
+        ))
+    })
+    .await
+}
+
+#[tauri::command]
+fn cancel_import(state: State<AppState>) {
+    state.import_cancel.store(true, Ordering::Relaxed);
+}
+
+/// Chapters repeating an earlier chapter of the novel, exactly or nearly.
+#[tauri::command]
+fn find_duplicate_chapters(
+    state: State<AppState>,
+    novel_id: String,
+) -> Result<Vec<fingerprint::DuplicateChapter>, String> {
+    let db = state.db.lock().map_err(|e| e.to_string())?;
+    fingerprint::find_duplicate_chapters(&db, &novel_id)
+}
+
+/// Re-read the novel's source file. Unchanged chapters keep their analyses, changed ones keep
+/// them flagged as stale, and new chapters are appended. Runs like an import, on its own
+/// connection, and `cancel_import` stops it.
+#[tauri::command]
+async fn resync_novel(
+    app: tauri::AppHandle,
+    novel_id: String,
+) -> Result<resync::ResyncReport, String> {
+    with_import_connection(app, move |db, control| {
+        resync::resync_novel(db, &novel_id, control)
+    })
+    .await
+}
+
+#[tauri::command]
+fn list_heading_rule_sets(state: State<AppState>) -> Result<Vec<HeadingRuleSet>, String> {
+    let db = state.db.lock().map_err(|e| e.to_string())?;
+    db.load_heading_rule_sets().map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn save_heading_rule_sets(state: State<AppState>, sets: Vec<HeadingRuleSet>) -> Result<(), String> {
+    txt_parser::validate_heading_rules(&sets)?;
+    let db = state.db.lock().map_err(|e| e.to_string())?;
+    db.save_heading_rule_sets(&sets).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn delete_novel(state: State<AppState>, novel_id: String) -> Result<(), String> {
+    let db = state.db.lock().map_err(|e| e.to_string())?;
+    db.delete_novel(&novel_id).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn delete_chapter(state: State<AppState>, chapter_id: i64) -> Result<(), String> {
+    let db = state.db.lock().map_err(|e| e.to_string())?;
+    db.delete_chapter(chapter_id).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn delete_chapters(state: State<AppState>, chapter_ids: Vec<i64>) -> Result<(), String> {
+    let db = state.db.lock().map_err(|e| e.to_string())?;
+    db.delete_chapters(&chapter_ids).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn rename_chapter(state: State<AppState>, chapter_id: i64, title: String) -> Result<(), String> {
+    let db = state.db.lock().map_err(|e| e.to_string())?;
+    chapter_edit::rename_chapter(&db, chapter_id, &title)
+}
+
+#[tauri::command]
+fn merge_chapters(state: State<AppState>, chapter_ids: Vec<i64>) -> Result<i64, String> {
+    let db = state.db.lock().map_err(|e| e.to_string())?;
+    chapter_edit::merge_chapters(&db, &chapter_ids)
+}
+
+#[tauri::command]
+fn split_chapter(
+    state: State<AppState>,
+    chapter_id: i64,
+    at: SplitPoint,
+    title: Option<String>,
+) -> Result<i64, String> {
+    let db = state.db.lock().map_err(|e| e.to_string())?;
+    chapter_edit::split_chapter(&db, chapter_id, at, title.as_deref())
+}
+
+#[tauri::command]
+fn move_chapter(state: State<AppState>, chapter_id: i64, to_index: usize) -> Result<(), String> {
+    let db = state.db.lock().map_err(|e| e.to_string())?;
+    db.move_chapter(chapter_id, to_index)
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn clear_chapter_analysis(state: State<AppState>, chapter_id: i64) -> Result<(), String> {
+    let db = state.db.lock().map_err(|e| e.to_string())?;
+    db.clear_chapter_analysis(chapter_id)
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn get_novel(state: State<AppState>, novel_id: String) -> Result<Novel, String> {
+    let db = state.db.lock().map_err(|e| e.to_string())?;
+    db.load_novel(&novel_id).map_err(|e| e.to_string())
+}
+
+/// The novel's cover as a `data:` URL, or `None` when it has no cover.
+#[tauri::command]
+fn get_novel_cover(state: State<AppState>, novel_id: String) -> Result<Option<String>, String> {
+    let db = state.db.lock().map_err(|e| e.to_string())?;
+    let cover = db.load_novel_cover(&novel_id).map_err(|e| e.to_string())?;
+    Ok(cover.map(|c| format!("data:{};base64,{}", c.mime, BASE64_STANDARD.encode(&c.data))))
+}
+
+// ---- Chapter Commands ----
+
+#[tauri::command]
+fn list_chapters(state: State<AppState>, novel_id: String) -> Result<Vec<ChapterMeta>, String> {
+    let db = state.db.lock().map_err(|e| e.to_string())?;
+    db.list_chapter_metas(&novel_id).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn list_volumes(state: State<AppState>, novel_id: String) -> Result<Vec<Volume>, String> {
+    let db = state.db.lock().map_err(|e| e.to_string())?;
+    db.list_volumes(&novel_id).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn get_chapter(state: State<AppState>, chapter_id: i64) -> Result<Chapter, String> {
+    let db = state.db.lock().map_err(|e| e.to_string())?;
+    db.load_chapter(chapter_id).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn get_chapter_content(state: State<AppState>, chapter_id: i64) -> Result<String, String> {
+    let db = state.db.lock().map_err(|e| e.to_string())?;
+    db.load_chapter_content(chapter_id)
+        .map_err(|e| e.to_string())
+}
+
+// ---- Analysis Commands ----
+
+#[tauri::command]
+fn generate_prompt(
+    state: State<AppState>,
+    chapter_id: i64,
+    dimensions: Vec<AnalysisDimension>,
+) -> Result<String, String> {
+    let db = state.db.lock().map_err(|e| e.to_string())?;
+    let chapter = db.load_chapter(chapter_id).map_err(|e| e.to_string())?;
+    let novel = db
+        .load_novel(&chapter.novel_id)
+        .map_err(|e| e.to_string())?;
+    let config = db.load_llm_config().unwrap_or_default();
+
+    let context_str = build_context_string(
+        &db,
+        &chapter.novel_id,
+        chapter.index,
+        &config.context_injection_mode,
+    )?;
+
+    let chapter_context = prompt::ChapterContext {
+        title: &chapter.title,
+        book: &novel.metadata,
+        previous_context: context_str.as_deref(),
+        forbid_callbacks: false, // Manual mode, assume user has memory in chat session
+    };
+    let prompt_text =
+        prompt::generate_chapter_prompt(chapter_context, &chapter.content, &dimensions);
+    Ok(prompt_text)
+}
+
+#[tauri::command]
+fn estimate_prompt_tokens(
+    state: State<AppState>,
+    chapter_id: i64,
+    dimensions: Vec<AnalysisDimension>,
+) -> Result<usize, String> {
+    let db = state.db.lock().map_err(|e| e.to_string())?;
+    let chapter = db.load_chapter(chapter_id).map_err(|e| e.to_string())?;
+    let novel = db
+        .load_novel(&chapter.novel_id)
+        .map_err(|e| e.to_string())?;
+    let config = db.load_llm_config().unwrap_or_default();
+
+    let context_str = build_context_string(
+        &db,
+        &chapter.novel_id,
+        chapter.index,
+        &config.context_injection_mode,
+    )?;
+
+    let chapter_context = prompt::ChapterContext {
+        title: &chapter.title,
+        book: &novel.metadata,
+        previous_context: context_str.as_deref(),
+        forbid_callbacks: false, // Manual mode token estimate
+    };
+    let prompt_text =
+        prompt::generate_chapter_prompt(chapter_context, &chapter.content, &dimensions);
+    Ok(token_utils::tokenizer_for(&config)?.count(&prompt_text))
+}
+
+#[tauri::command]
+fn parse_manual_result(json_str: String) -> Result<ChapterAnalysis, String> {
+    analysis::parse_analysis_json(&json_str)
+}
+
+#[tauri::command]
+fn save_analysis(
+    state: State<AppState>,
+    chapter_id: i64,
+    analysis_data: ChapterAnalysis,
+) -> Result<(), String> {
+    let db = state.db.lock().map_err(|e| e.to_string())?;
+    let origin = AnalysisOrigin {
+        model: "manual".to_string(),
+        dimensions: analysis_data.dimensions(),
+        prompt_version: prompt::PROMPT_VERSION.to_string(),
+    };
+    db.save_chapter_analysis(chapter_id, &analysis_data, &origin)
+        .map(|_| ())
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn analyze_chapter_api(
+    app: tauri::AppHandle,
+    state: State<'_, AppState>,
+    chapter_id: i64,
+    dimensions: Vec<AnalysisDimension>,
+    update_mode: Option<UpdateMode>,
+) -> Result<ChapterAnalysis, String> {
+    pipeline::do_analyze_chapter(
+        &app,
+        &state.db,
+        chapter_id,
+        &dimensions,
+        update_mode.unwrap_or_default(),
+    )
+    .await
+}
+
+#[tauri::command]
+fn get_chapter_raw_response(
+    state: State<AppState>,
+    chapter_id: i64,
+) -> Result<Option<String>, String> {
+    let db = state.db.lock().map_err(|e| e.to_string())?;
+    db.load_raw_response(chapter_id).map_err(|e| e.to_string())
+}
+
+/// Run the local JSON repair on a saved raw response and store the result as the analysis.
+#[tauri::command]
+fn reparse_raw_response(
+    state: State<AppState>,
+    chapter_id: i64,
+) -> Result<ChapterAnalysis, String> {
+    let db = state.db.lock().map_err(|e| e.to_string())?;
+    let raw = db
+        .load_raw_response(chapter_id)
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| "该章节没有保存的原始返回".to_string())?;
+    let analysis = analysis::parse_analysis_json(&raw)?;
+    let origin = AnalysisOrigin {
+        model: db.load_llm_config().map_err(|e| e.to_string())?.model,
+        dimensions: analysis.dimensions(),
+        prompt_version: prompt::PROMPT_VERSION.to_string(),
+    };
+    db.save_chapter_analysis(chapter_id, &analysis, &origin)
+        .map_err(|e| e.to_string())?;
+    Ok(analysis)
+}
+
+// ---- Analysis Version Commands ----
+
+#[tauri::command]
+fn list_analysis_versions(
+    state: State<AppState>,
+    chapter_id: i64,
+) -> Result<Vec<AnalysisVersion>, String> {
+    let db = state.db.lock().map_err(|e| e.to_string())?;
+    db.list_analysis_versions(chapter_id)
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn set_active_analysis_version(
+    state: State<AppState>,
+    chapter_id: i64,
+    version_id: i64,
+) -> Result<(), String> {
+    let db = state.db.lock().map_err(|e| e.to_string())?;
+    db.set_active_analysis_version(chapter_id, version_id)
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn diff_analysis_versions(
+    state: State<AppState>,
+    from_version_id: i64,
+    to_version_id: i64,
+) -> Result<Vec<FieldDiff>, String> {
+    let db = state.db.lock().map_err(|e| e.to_string())?;
+    let from = db
+        .load_analysis_version(from_version_id)
+        .map_err(|e| e.to_string())?;
+    let to = db
+        .load_analysis_version(to_version_id)
+        .map_err(|e| e.to_string())?;
+    Ok(analysis::diff_analyses(&from.analysis, &to.analysis))
+}
+
+// ---- LLM Call Log Commands ----
+
+#[tauri::command]
+fn list_llm_calls(
+    state: State<AppState>,
+    novel_id: String,
+    chapter_id: Option<i64>,
+) -> Result<Vec<LlmCallMeta>, String> {
+    let db = state.db.lock().map_err(|e| e.to_string())?;
+    db.list_llm_calls(&novel_id, chapter_id)
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn get_llm_call(state: State<AppState>, call_id: i64) -> Result<LlmCall, String> {
+    let db = state.db.lock().map_err(|e| e.to_string())?;
+    db.load_llm_call(call_id).map_err(|e| e.to_string())
+}
+
+/// Parse the stored response of a chapter call again; with `save`, it becomes the
+/// chapter's analysis.
+#[tauri::command]
+fn reparse_llm_call(
+    state: State<AppState>,
+    call_id: i64,
+    save: Option<bool>,
+) -> Result<ChapterAnalysis, String> {
+    let db = state.db.lock().map_err(|e| e.to_string())?;
+    let call = db.load_llm_call(call_id).map_err(|e| e.to_string())?;
+    let response = call
+        .response
+        .ok_or_else(|| "该次调用没有返回内容".to_string())?;
+    let analysis = match call.kind {
+        LlmCallKind::Chapter | LlmCallKind::Segment | LlmCallKind::Repair => {
+            analysis::parse_analysis_json(&response)?
+        }
+        _ => return Err("汇总调用请在全书汇总中查看".to_string()),
+    };
+    if save.unwrap_or(false) {
+        match (call.kind, call.chapter_id) {
+            (LlmCallKind::Segment, _) => {
+                return Err("分段调用只包含部分内容，不能单独保存为章节分析".to_string())
+            }
+            (_, Some(chapter_id)) => {
+                let origin = AnalysisOrigin {
+                    model: call.model.clone(),
+                    dimensions: analysis.dimensions(),
+                    prompt_version: prompt::PROMPT_VERSION.to_string(),
+                };
+                db.save_chapter_analysis(chapter_id, &analysis, &origin)
+                    .map_err(|e| e.to_string())?;
+            }
+            (_, None) => return Err("该次调用未关联章节".to_string()),
+        }
+    }
+    Ok(analysis)
+}
+
+#[tauri::command]
+async fn batch_analyze_novel(
+    app: tauri::AppHandle,
+    state: State<'_, AppState>,
+    novel_id: String,
+    update_mode: Option<UpdateMode>,
+    failure_policy: Option<FailurePolicy>,
+) -> Result<BatchReport, String> {
+    let update_mode = update_mode.unwrap_or_default();
+    let (novel, unanalyzed) = {
+        let db = state.db.lock().map_err(|e| e.to_string())?;
+        let novel = db.load_novel(&novel_id).map_err(|e| e.to_string())?;
+        let metas = db
+            .list_chapter_metas(&novel_id)
+            .map_err(|e| e.to_string())?;
+        let unanalyzed: Vec<_> = metas
+            .into_iter()
+            .filter(|m| match update_mode {
+                UpdateMode::Replace => m.needs_analysis(),
+                UpdateMode::Incremental => !m.is_analyzed_for(&novel.enabled_dimensions),
+            })
+            .collect();
+        (novel, unanalyzed)
+    };
+
+    pipeline::batch_analyze(
+        &app,
+        &state.db,
+        &state.batch_cancel,
+        &novel,
+        unanalyzed,
+        update_mode,
+        failure_policy,
+    )
+    .await
+}
+
+#[tauri::command]
+async fn batch_analyze_chapters(
+    app: tauri::AppHandle,
+    state: State<'_, AppState>,
+    novel_id: String,
+    chapter_ids: Vec<i64>,
+    update_mode: Option<UpdateMode>,
+    failure_policy: Option<FailurePolicy>,
+) -> Result<BatchReport, String> {
+    let (novel, metas) = {
+        let db = state.db.lock().map_err(|e| e.to_string())?;
+        let novel = db.load_novel(&novel_id).map_err(|e| e.to_string())?;
+        let all_metas = db
+            .list_chapter_metas(&novel_id)
+            .map_err(|e| e.to_string())?;
+        let selected: Vec<_> = all_metas
+            .into_iter()
+            .filter(|m| chapter_ids.contains(&m.id))
+            .collect();
+        (novel, selected)
+    };
+
+    if metas.is_empty() {
+        return Ok(BatchReport::default());
+    }
+
+    state.batch_cancel.store(false, Ordering::Relaxed);
+
+    pipeline::batch_analyze(
+        &app,
+        &state.db,
+        &state.batch_cancel,
+        &novel,
+        metas,
+        update_mode.unwrap_or_default(),
+        failure_policy,
+    )
+    .await
+}
+
+// ---- Settings Commands ----
+
+#[tauri::command]
+fn get_llm_config(state: State<AppState>) -> Result<LlmConfig, String> {
+    let db = state.db.lock().map_err(|e| e.to_string())?;
+    db.load_llm_config().map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn save_llm_config(
+    app: tauri::AppHandle,
+    state: State<AppState>,
+    config: LlmConfig,
+) -> Result<(), String> {
+    let db = state.db.lock().map_err(|e| e.to_string())?;
+    db.save_llm_config(&config).map_err(|e| e.to_string())?;
+    recount_tokens(app, state.data_dir.clone());
+    Ok(())
+}
+
+/// Count chapters under the configured tokenizer on a connection of its own, so the shared
+/// one isn't held while a library is tokenized. Until then chapter lists use the heuristic.
+/// A failure is reported as an "error" `analysis_progress` event.
+fn recount_tokens(app: tauri::AppHandle, data_dir: PathBuf) {
+    std::thread::spawn(move || {
+        if let Err(e) = Database::open(&data_dir).and_then(|db| db.recount_tokens()) {
+            app.analysis_progress(ProgressEvent {
+                novel_id: String::new(),
+                chapter_id: None,
+                status: "error".to_string(),
+                current: 0,
+                total: 0,
+                message: format!("Token 重新计数失败: {}", e),
+                report: None,
+            });
+        }
+    });
+}
+
+#[tauri::command]
+fn get_cleanup_config(state: State<AppState>) -> Result<CleanupConfig, String> {
+    let db = state.db.lock().map_err(|e| e.to_string())?;
+    db.load_cleanup_config().map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn save_cleanup_config(state: State<AppState>, config: CleanupConfig) -> Result<(), String> {
+    cleanup::validate_config(&config)?;
+    let db = state.db.lock().map_err(|e| e.to_string())?;
+    db.save_cleanup_config(&config).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn list_models(state: State<'_, AppState>) -> Result<Vec<String>, String> {
+    let config = {
+        let db = state.db.lock().map_err(|e| e.to_string())?;
+        db.load_llm_config().map_err(|e| e.to_string())?
+    };
+    llm::list_models(&config).await
+}
+
+#[tauri::command]
+fn update_novel_dimensions(
+    state: State<AppState>,
+    novel_id: String,
+    dimensions: Vec<AnalysisDimension>,
+) -> Result<(), String> {
+    let db = state.db.lock().map_err(|e| e.to_string())?;
+    let mut novel = db.load_novel(&novel_id).map_err(|e| e.to_string())?;
+    novel.enabled_dimensions = dimensions;
+    db.save_novel(&novel).map_err(|e| e.to_string())
+}
+
+// ---- Summary Commands ----
+
+#[tauri::command]
+fn get_full_summary_manual_prompt(
+    state: State<'_, AppState>,
+    novel_id: String,
+) -> Result<String, String> {
+    let (novel, chapters) = {
+        let db = state.db.lock().map_err(|e| e.to_string())?;
+        let novel = db.load_novel(&novel_id).map_err(|e| e.to_string())?;
+        let chapters: Vec<Chapter> = db
+            .list_chapter_metas(&novel_id)
+            .map_err(|e| e.to_string())?
+            .into_iter()
+            .filter(|m| m.has_analysis)
+            .filter_map(|m| db.load_chapter(m.id).ok())
+            .collect();
+        (novel, chapters)
+    };
+
+    if chapters.is_empty() {
+        return Err("当前没有已分析的章节可以用来生成汇总".to_string());
+    }
+
+    let dims = &novel.enabled_dimensions;
+    let chapter_summaries: Vec<(usize, String)> = chapters
+        .into_iter()
+        .filter_map(|ch| {
+            ch.analysis
+                .map(|a| (ch.index, serde_json::to_string(&a).unwrap_or_default()))
+        })
+        .collect();
+
+    if chapter_summaries.is_empty() {
+        return Err("章节分析数据为空".to_string());
+    }
+
+    Ok(prompt::generate_manual_full_summary_prompt(
+        &chapter_summaries,
+        dims,
+    ))
+}
+
+#[tauri::command]
+fn get_novel_summary(
+    state: State<AppState>,
+    novel_id: String,
+) -> Result<Option<NovelSummary>, String> {
+    let db = state.db.lock().map_err(|e| e.to_string())?;
+    db.load_novel_summary(&novel_id).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn save_novel_summary(
+    state: State<AppState>,
+    novel_id: String,
+    summary: NovelSummary,
+) -> Result<(), String> {
+    let db = state.db.lock().map_err(|e| e.to_string())?;
+    db.save_novel_summary(&novel_id, &summary)
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn clear_novel_summary(state: State<AppState>, novel_id: String) -> Result<(), String> {
+    let db = state.db.lock().map_err(|e| e.to_string())?;
+    db.clear_novel_summary(&novel_id).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn generate_full_summary(
+    app: tauri::AppHandle,
+    state: State<'_, AppState>,
+    novel_id: String,
+) -> Result<NovelSummary, String> {
+    pipeline::generate_full_summary(&app, &state.db, &novel_id).await
+}
+
+#[tauri::command]
+async fn export_novel_report(
+    state: State<'_, AppState>,
+    novel_id: String,
+    dir_path: String,
+    conversion: Option<ChineseConversion>,
+) -> Result<(), String> {
+    let db = state.db.lock().map_err(|e| e.to_string())?;
+    export::export_novel_report(&db, &novel_id, &dir_path, conversion)?;
+    Ok(())
+}
+
+// ---- Dimension Info ----
+
+#[tauri::command]
+fn get_all_dimensions() -> Vec<serde_json::Value> {
+    AnalysisDimension::all()
+        .into_iter()
+        .map(|d| {
+            serde_json::json!({
+                "id": d,
+                "name": d.display_name(),
+                "icon": d.icon(),
+                "description": d.description(),
+                "default": AnalysisDimension::default_set().contains(&d),
+            })
+        })
+        .collect()
+}
+
+#[tauri::command]
+fn cancel_batch(state: State<AppState>) {
+    state.batch_cancel.store(true, Ordering::Relaxed);
+}
+
+// ---- Job Queue Commands ----
+
+#[tauri::command]
+fn list_jobs(state: State<AppState>, novel_id: String) -> Result<Vec<AnalysisJob>, String> {
+    let db = state.db.lock().map_err(|e| e.to_string())?;
+    db.list_jobs(&novel_id).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn resume_batch(
+    app: tauri::AppHandle,
+    state: State<'_, AppState>,
+    novel_id: String,
+    failure_policy: Option<FailurePolicy>,
+) -> Result<BatchReport, String> {
+    state.batch_cancel.store(false, Ordering::Relaxed);
+    pipeline::run_job_queue(
+        &app,
+        &state.db,
+        &state.batch_cancel,
+        &novel_id,
+        failure_policy,
+    )
+    .await
+}
+
+#[tauri::command]
+fn retry_failed_jobs(state: State<AppState>, novel_id: String) -> Result<usize, String> {
+    let db = state.db.lock().map_err(|e| e.to_string())?;
+    db.retry_failed_jobs(&novel_id).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn clear_jobs(state: State<AppState>, novel_id: String) -> Result<(), String> {
+    let db = state.db.lock().map_err(|e| e.to_string())?;
+    db.clear_jobs(&novel_id).map_err(|e| e.to_string())
+}
+
+/// Forward pipeline progress to the webview as Tauri events.
+impl ProgressSink for tauri::AppHandle {
+    fn analysis_progress(&self, event: ProgressEvent) {
+        let _ = self.emit("analysis_progress", event);
+    }
+
+    fn batch_progress(&self, event: ProgressEvent) {
+        let _ = self.emit("batch_progress", event);
+    }
+
+    fn streaming(&self, chunk: StreamingChunk) {
+        let _ = self.emit("analysis_streaming", chunk);
+    }
+
+    fn import_progress(&self, event: ProgressEvent) {
+        let _ = self.emit("import_progress", event);
+    }
+}
+
+#[cfg_attr(mobile, tauri::mobile_entry_point)]
+pub fn run() {
+    tauri::Builder::default()
+        .plugin(tauri_plugin_opener::init())
+        .plugin(tauri_plugin_dialog::init())
+        .setup(|app| {
+            let app_data_dir = app
+                .path()
+                .app_data_dir()
+                .unwrap_or_else(|_| PathBuf::from("."));
+            let db = Database::open_for_app(&app_data_dir)
+                .map_err(|e| format!("数据库初始化失败: {}", e))
+                .expect("Failed to initialize database");
+            recount_tokens(app.handle().clone(), app_data_dir.clone());
+            app.manage(AppState {
+                db: Mutex::new(db),
+                data_dir: app_data_dir,
+                batch_cancel: AtomicBool::new(false),
+                import_cancel: AtomicBool::new(false),
+            });
+            Ok(())
+        })
+        .invoke_handler(tauri::generate_handler![
+            list_novels,
+            preview_book,
+            import_book_selected,
+            import_txt_files,
+            import_single_txt,
+            preview_single_txt,
+            import_txt_selected,
+            cancel_import,
+            resync_novel,
+            find_duplicate_chapters,
+            list_heading_rule_sets,
+            save_heading_rule_sets,
+            delete_novel,
+            delete_chapter,
+            delete_chapters,
+            rename_chapter,
+            merge_chapters,
+            split_chapter,
+            move_chapter,
+            clear_chapter_analysis,
+            get_novel,
+            get_novel_cover,
+            list_chapters,
+            list_volumes,
+            get_chapter,
+            get_chapter_content,
+            generate_prompt,
+            estimate_prompt_tokens,
+            parse_manual_result,
+            save_analysis,
+            analyze_chapter_api,
+            get_chapter_raw_response,
+            reparse_raw_response,
+            list_analysis_versions,
+            set_active_analysis_version,
+            diff_analysis_versions,
+            list_llm_calls,
+            get_llm_call,
+            reparse_llm_call,
+            batch_analyze_novel,
+            cancel_batch,
+            batch_analyze_chapters,
+            list_jobs,
+            resume_batch,
+            retry_failed_jobs,
+            clear_jobs,
+            get_llm_config,
+            save_llm_config,
+            get_cleanup_config,
+            save_cleanup_config,
+            update_novel_dimensions,
+            get_novel_summary,
+            save_novel_summary,
+            clear_novel_summary,
+            get_full_summary_manual_prompt,
+            generate_full_summary,
+            export_novel_report,
+            get_all_dimensions,
+            list_models,
+        ])
+        .run(tauri::generate_context!())
+        .expect("error while running tauri application");
+}
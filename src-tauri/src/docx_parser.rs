@@ -0,0 +1,300 @@
+use crate::html_text::{ExtractOptions, ExtractedText, Writer};
+use crate::importer::{self, HeadedSection, Importer};
+use crate::models::{BookInfo, BookMetadata, ParsedChapter, SourceType};
+use roxmltree::{Document, Node};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::Read;
+
+/// Word documents. Chapters start at paragraphs with a heading style or outline level;
+/// manuscripts without any are split by their heading lines like a TXT file.
+pub struct DocxImporter;
+
+impl Importer for DocxImporter {
+    fn parse(
+        &self,
+        path: &str,
+        _options: ExtractOptions,
+    ) -> Result<(BookInfo, Vec<ParsedChapter>), String> {
+        let file = File::open(path).map_err(|e| format!("无法读取文件 {}: {}", path, e))?;
+        let mut archive =
+            zip::ZipArchive::new(file).map_err(|e| format!("无法打开 DOCX 文件: {}", e))?;
+        let mut part = |name: &str| -> Option<String> {
+            let mut file = archive.by_name(name).ok()?;
+            let mut xml = String::new();
+            file.read_to_string(&mut xml).ok()?;
+            Some(xml)
+        };
+        let document = part("word/document.xml").ok_or("DOCX 文件缺少 word/document.xml")?;
+        let styles = part("word/styles.xml");
+        let footnotes = part("word/footnotes.xml");
+        let endnotes = part("word/endnotes.xml");
+        let core = part("docProps/core.xml");
+
+        let fallback_title = importer::title_from_path(path);
+        let (title, chapters) = parse_document(
+            &document,
+            styles.as_deref(),
+            &[footnotes.as_deref(), endnotes.as_deref()],
+            &fallback_title,
+        )?;
+        let (core_title, metadata) = core
+            .as_deref()
+            .and_then(|xml| Document::parse(xml).ok())
+            .map(|core| read_core_properties(&core))
+            .unwrap_or_default();
+        let book = BookInfo {
+            title: core_title.or(title).unwrap_or(fallback_title),
+            metadata,
+            cover: None,
+        };
+        Ok((book, chapters))
+    }
+
+    fn source_type(&self, path: &str) -> SourceType {
+        SourceType::Docx(path.to_string())
+    }
+}
+
+/// The paragraphs of `word/document.xml` split at headings, with the title the document opens
+/// with, if any.
+fn parse_document(
+    document: &str,
+    styles: Option<&str>,
+    note_parts: &[Option<&str>],
+    fallback_title: &str,
+) -> Result<(Option<String>, Vec<ParsedChapter>), String> {
+    let doc = Document::parse(document).map_err(|e| format!("无法解析 DOCX 文件: {}", e))?;
+    let levels = styles
+        .and_then(|xml| Document::parse(xml).ok())
+        .map(|styles| style_levels(&styles))
+        .unwrap_or_default();
+    let note_docs: Vec<Document> = note_parts
+        .iter()
+        .flatten()
+        .filter_map(|xml| Document::parse(xml).ok())
+        .collect();
+    let notes: HashMap<(&str, &str), Node> = note_docs
+        .iter()
+        .flat_map(|d| d.root_element().children().filter(Node::is_element))
+        .filter_map(|n| Some(((n.tag_name().name(), attr(n, "id")?), n)))
+        .collect();
+
+    let body = doc
+        .root_element()
+        .children()
+        .find(|n| is(*n, "body"))
+        .ok_or("DOCX 文件没有正文")?;
+
+    let mut sections: Vec<HeadedSection> = Vec::new();
+    let mut level = None;
+    let mut writer = Writer::default();
+    let mut section_notes = Vec::new();
+    let mut note_count = 0;
+    // Paragraphs in text boxes are read as part of the paragraph anchoring them.
+    let paragraphs = body
+        .descendants()
+        .filter(|n| is(*n, "p") && !n.ancestors().skip(1).any(|a| is(a, "p")));
+    for p in paragraphs {
+        let heading = paragraph_level(p, &levels);
+        if heading.is_some() {
+            let body = std::mem::take(&mut writer).finish();
+            if level.is_some() || !body.is_empty() {
+                sections.push(HeadedSection {
+                    level,
+                    text: ExtractedText {
+                        body,
+                        notes: std::mem::take(&mut section_notes),
+                    },
+                });
+            }
+            level = heading;
+        }
+        for node in p.descendants() {
+            match node.tag_name().name() {
+                "t" if node.is_element() => writer.text(node.text().unwrap_or_default()),
+                "tab" => writer.text(" "),
+                "br" if attr(node, "type").map_or(true, |t| t == "textWrapping") => {
+                    writer.line_break()
+                }
+                "cr" => writer.line_break(),
+                name @ ("footnoteReference" | "endnoteReference") => {
+                    let kind = name.trim_end_matches("Reference");
+                    let Some(note) = attr(node, "id").and_then(|id| notes.get(&(kind, id))) else {
+                        continue;
+                    };
+                    note_count += 1;
+                    writer.text(&format!("[{}]", note_count));
+                    let mut note_writer = Writer::default();
+                    note_writer.text(&format!("[{}] ", note_count));
+                    for t in note.descendants().filter(|n| is(*n, "t")) {
+                        note_writer.text(t.text().unwrap_or_default());
+                    }
+                    section_notes.push(note_writer.finish());
+                }
+                _ => {}
+            }
+        }
+        writer.end_paragraph();
+    }
+    sections.push(HeadedSection {
+        level,
+        text: ExtractedText {
+            body: writer.finish(),
+            notes: section_notes,
+        },
+    });
+
+    let (title, chapters) = importer::split_at_headings(sections, fallback_title);
+    if chapters.iter().all(|c| c.content.trim().is_empty()) {
+        return Err("未能从 DOCX 中提取到任何内容".to_string());
+    }
+    Ok((title, chapters))
+}
+
+/// Heading level of each paragraph style: `Title` is 1 and `heading N` is N + 1, or one more
+/// than the style's outline level. Styles inherit the level of the style they're based on.
+fn style_levels(styles: &Document) -> HashMap<String, usize> {
+    let mut own: HashMap<&str, (Option<usize>, Option<&str>)> = HashMap::new();
+    for style in styles.root_element().children().filter(|n| is(*n, "style")) {
+        let Some(id) = attr(style, "styleId") else {
+            continue;
+        };
+        let name = style
+            .children()
+            .find(|n| is(*n, "name"))
+            .and_then(|n| attr(n, "val"))
+            .unwrap_or(id)
+            .to_lowercase();
+        let outline = style
+            .descendants()
+            .find(|n| is(*n, "outlineLvl"))
+            .and_then(outline_level);
+        let level = outline.or_else(|| match name.as_str() {
+            "title" => Some(1),
+            _ => name
+                .strip_prefix("heading ")
+                .and_then(|n| n.parse::<usize>().ok())
+                .map(|n| n + 1),
+        });
+        let based_on = style
+            .children()
+            .find(|n| is(*n, "basedOn"))
+            .and_then(|n| attr(n, "val"));
+        own.insert(id, (level, based_on));
+    }
+
+    let mut levels = HashMap::new();
+    for &id in own.keys() {
+        let mut current = Some(id);
+        // Guard against cycles in broken documents.
+        for _ in 0..10 {
+            let Some(&(level, based_on)) = current.and_then(|c| own.get(c)) else {
+                break;
+            };
+            if let Some(level) = level {
+                levels.insert(id.to_string(), level);
+                break;
+            }
+            current = based_on;
+        }
+    }
+    levels
+}
+
+/// Level of a heading paragraph, from its own outline level or its style's.
+fn paragraph_level(p: Node, levels: &HashMap<String, usize>) -> Option<usize> {
+    let properties = p.children().find(|n| is(*n, "pPr"))?;
+    if let Some(outline) = properties.children().find(|n| is(*n, "outlineLvl")) {
+        return outline_level(outline);
+    }
+    let style = properties
+        .children()
+        .find(|n| is(*n, "pStyle"))
+        .and_then(|n| attr(n, "val"))?;
+    levels.get(style).copied()
+}
+
+/// `w:outlineLvl` 0–8 as levels 2–10, Title being 1; 9 is body text.
+fn outline_level(node: Node) -> Option<usize> {
+    attr(node, "val")
+        .and_then(|v| v.parse::<usize>().ok())
+        .filter(|&v| v < 9)
+        .map(|v| v + 2)
+}
+
+/// Title, author and language from `docProps/core.xml`.
+fn read_core_properties(core: &Document) -> (Option<String>, BookMetadata) {
+    let field = |name: &str| {
+        core.root_element()
+            .children()
+            .find(|n| is(*n, name))
+            .and_then(|n| n.text())
+            .map(|t| t.trim().to_string())
+            .filter(|t| !t.is_empty())
+    };
+    let metadata = BookMetadata {
+        author: field("creator"),
+        language: field("language"),
+        ..BookMetadata::default()
+    };
+    (field("title"), metadata)
+}
+
+fn is(node: Node, name: &str) -> bool {
+    node.is_element() && node.tag_name().name() == name
+}
+
+/// An attribute by local name, whatever its namespace prefix (`w:val`, `w:id`...).
+fn attr<'a>(node: Node<'a, '_>, name: &str) -> Option<&'a str> {
+    node.attributes()
+        .find(|a| a.name() == name)
+        .map(|a| a.value())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const W: &str = r#"xmlns:w="http://schemas.openxmlformats.org/wordprocessingml/2006/main""#;
+
+    fn paragraph(style: Option<&str>, text: &str) -> String {
+        let properties = style
+            .map(|s| format!(r#"<w:pPr><w:pStyle w:val="{}"/></w:pPr>"#, s))
+            .unwrap_or_default();
+        format!("<w:p>{}<w:r><w:t>{}</w:t></w:r></w:p>", properties, text)
+    }
+
+    #[test]
+    fn test_parse_document() {
+        let styles = format!(
+            r#"<w:styles {W}>
+                <w:style w:styleId="Title"><w:name w:val="Title"/></w:style>
+                <w:style w:styleId="1"><w:name w:val="heading 1"/></w:style>
+                <w:style w:styleId="Chapter"><w:name w:val="Chapter"/><w:basedOn w:val="1"/></w:style>
+            </w:styles>"#
+        );
+        let body = [
+            paragraph(Some("Title"), "长夜"),
+            paragraph(Some("Chapter"), "第一章"),
+            r#"<w:p><w:r><w:t xml:space="preserve">甲。</w:t></w:r><w:r><w:footnoteReference w:id="2"/></w:r></w:p>"#.to_string(),
+            paragraph(Some("1"), "第二章"),
+            paragraph(None, "乙。"),
+        ]
+        .concat();
+        let document = format!("<w:document {W}><w:body>{body}</w:body></w:document>");
+        let footnotes = format!(
+            r#"<w:footnotes {W}><w:footnote w:id="2"><w:p><w:r><w:t>注释。</w:t></w:r></w:p></w:footnote></w:footnotes>"#
+        );
+
+        let (title, chapters) =
+            parse_document(&document, Some(&styles), &[Some(&footnotes)], "稿").unwrap();
+        assert_eq!(title.as_deref(), Some("长夜"));
+        let got: Vec<(&str, &str)> = chapters
+            .iter()
+            .map(|c| (c.title.as_str(), c.content.as_str()))
+            .collect();
+        assert_eq!(got, vec![("第一章", "甲。[1]"), ("第二章", "乙。")]);
+        assert_eq!(chapters[0].notes, vec!["[1] 注释。"]);
+    }
+}
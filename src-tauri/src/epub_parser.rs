@@ -1,91 +1,32 @@
-use epub::doc::{EpubDoc, NavPoint};
-use html2text::from_read;
-use serde::{Deserialize, Serialize};
-use std::io::Cursor;
+use crate::html_text::{self, ExtractOptions, ExtractedText};
+use crate::importer::Importer;
+use crate::models::{BookInfo, BookMetadata, CoverImage, ParsedChapter, SourceType};
+use epub::doc::{EpubDoc, MetadataItem, NavPoint};
+use std::io::{Read, Seek};
 use std::path::PathBuf;
 
-/// Keywords that indicate a page is metadata/boilerplate, not actual chapter content.
-const SKIP_KEYWORDS: &[&str] = &[
-    "版权",
-    "copyright",
-    "ISBN",
-    "出版社",
-    "制作信息",
-    "作者简介",
-    "内容简介",
-    "编辑推荐",
-    "封面",
-    "Cover",
-    "扉页",
-    "前折页",
-    "后折页",
-    "All Rights Reserved",
-    "all rights reserved",
-    "本书由",
-    "授权",
-    "版次",
-    "印次",
-    "译序",
-    "译者",
-    "校对",
-];
-
-/// A preview chapter returned to the frontend for user selection.
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct EpubPreviewChapter {
-    pub index: usize,
-    pub title: String,
-    pub char_count: usize,
-    /// Whether this chapter is suggested for import (true = real content, false = metadata/boilerplate)
-    pub suggested: bool,
-}
-
-/// Parse an EPUB file and return a preview of all chapters for user selection.
-/// Returns (book_title, preview_chapters).
-pub fn preview_epub(path: &str) -> Result<(String, Vec<EpubPreviewChapter>), String> {
-    let (title, raw_chapters) = extract_all_spine_items(path)?;
-
-    let previews: Vec<EpubPreviewChapter> = raw_chapters
-        .iter()
-        .enumerate()
-        .map(|(i, (ch_title, content))| {
-            let char_count = content.chars().count();
-            let suggested = char_count >= 100 && !is_metadata_page(content);
-            EpubPreviewChapter {
-                index: i,
-                title: ch_title.clone(),
-                char_count,
-                suggested,
-            }
-        })
-        .collect();
-
-    Ok((title, previews))
-}
-
-/// Parse an EPUB and return only the selected chapters (by index).
-pub fn parse_epub_selected(
-    path: &str,
-    selected_indices: &[usize],
-) -> Result<(String, Vec<(String, String)>), String> {
-    let (title, raw_chapters) = extract_all_spine_items(path)?;
-
-    let chapters: Vec<(String, String)> = raw_chapters
-        .into_iter()
-        .enumerate()
-        .filter(|(i, _)| selected_indices.contains(i))
-        .map(|(_, ch)| ch)
-        .collect();
+/// EPUB 2 and 3 books, one chapter per TOC entry.
+pub struct EpubImporter;
 
-    if chapters.is_empty() {
-        return Err("没有选择任何章节".to_string());
+impl Importer for EpubImporter {
+    fn parse(
+        &self,
+        path: &str,
+        options: ExtractOptions,
+    ) -> Result<(BookInfo, Vec<ParsedChapter>), String> {
+        extract_all_spine_items(path, options)
     }
 
-    Ok((title, chapters))
+    fn source_type(&self, path: &str) -> SourceType {
+        SourceType::Epub(path.to_string())
+    }
 }
 
-/// Internal: extract all spine items from an EPUB as (title, content) pairs.
-fn extract_all_spine_items(path: &str) -> Result<(String, Vec<(String, String)>), String> {
+/// Internal: extract all spine items from an EPUB as chapters.
+fn extract_all_spine_items(
+    path: &str,
+    options: ExtractOptions,
+) -> Result<(BookInfo, Vec<ParsedChapter>), String> {
     let mut doc = EpubDoc::new(path).map_err(|e| format!("无法打开 EPUB 文件: {}", e))?;
 
     let title = doc
@@ -98,155 +39,234 @@ fn extract_all_spine_items(path: &str) -> Result<(String, Vec<(String, String)>)
                 .unwrap_or("未知书名")
                 .to_string()
         });
-
-    // Flatten TOC into a list of (play_order, label, resource_path)
-    // We only care about top-level NavPoints to merge their sub-chapters,
-    // or recursively flatten them? The user said "chapters might be volumes with sub-chapters".
-    // If we only take top level, a "Volume" might contain 50 chapters, which would merge into 1 giant string.
-    // That's bad. We should keep the leaves (deepest children) or just flatten the TOC with combined labels.
-    let mut flat_toc: Vec<(String, PathBuf)> = Vec::new();
-    fn flatten_nav(navs: &[NavPoint], prefix: &str, out: &mut Vec<(String, PathBuf)>) {
+    let book = BookInfo {
+        title,
+        metadata: read_metadata(&doc.metadata),
+        cover: read_cover(&mut doc),
+    };
+
+    // Flatten the TOC. We keep every entry as a chapter, and entries with children also name
+    // the volume their children (and their own page) belong to.
+    let mut flat_toc: Vec<TocEntry> = Vec::new();
+    fn flatten_nav(navs: &[NavPoint], volume: Option<&str>, out: &mut Vec<TocEntry>) {
         for nav in navs {
-            let label = if prefix.is_empty() {
-                nav.label.clone()
-            } else {
-                format!("{} - {}", prefix, nav.label)
-            };
+            // Nested volumes (部 > 卷) are named by their whole path.
+            let own_volume = (!nav.children.is_empty()).then(|| match volume {
+                Some(parent) => format!("{} - {}", parent, nav.label),
+                None => nav.label.clone(),
+            });
 
-            // Extract just the file path, ignoring #anchors
+            // Split "chapter.xhtml#anchor" into the file and the element the entry starts at
             let path_str = nav.content.to_string_lossy().to_string();
-            let base_path = path_str.split('#').next().unwrap_or(&path_str);
-            out.push((label.clone(), PathBuf::from(base_path)));
-
-            flatten_nav(&nav.children, &label, out);
+            let (base_path, fragment) = match path_str.split_once('#') {
+                Some((base, fragment)) => (base, Some(fragment).filter(|f| !f.is_empty())),
+                None => (path_str.as_str(), None),
+            };
+            out.push(TocEntry {
+                label: nav.label.clone(),
+                path: PathBuf::from(base_path),
+                fragment: fragment.map(str::to_string),
+                volume: own_volume.clone().or_else(|| volume.map(str::to_string)),
+            });
+
+            flatten_nav(&nav.children, own_volume.as_deref().or(volume), out);
         }
     }
     let toc_clone = doc.toc.clone();
-    flatten_nav(&toc_clone, "", &mut flat_toc);
+    flatten_nav(&toc_clone, None, &mut flat_toc);
 
     let spine_ids: Vec<String> = doc.spine.iter().map(|s| s.idref.clone()).collect();
 
-    // Create a mapping from resource path to its TOC label
-    let mut path_to_label = std::collections::HashMap::new();
-    // Keep order of first appearance to roughly know the TOC sequence
-    let mut toc_sequence = Vec::new();
-    for (label, p) in &flat_toc {
-        if !path_to_label.contains_key(p) {
-            path_to_label.insert(p.clone(), label.clone());
-            toc_sequence.push(p.clone());
+    // TOC entries of each file, in TOC order. Several entries may point into one file when a
+    // publisher puts several chapters in it; they are told apart by their anchors.
+    let mut file_entries: std::collections::HashMap<PathBuf, Vec<TocEntry>> =
+        std::collections::HashMap::new();
+    for entry in &flat_toc {
+        let entries = file_entries.entry(entry.path.clone()).or_default();
+        if !entries.iter().any(|e| e.fragment == entry.fragment) {
+            entries.push(entry.clone());
         }
     }
 
-    let mut chapters: Vec<(String, String)> = Vec::new();
-    let mut current_merged_content = String::new();
-    let mut current_merged_title = String::new();
+    let mut chapters: Vec<ParsedChapter> = Vec::new();
+    let mut pending = PendingChapter::default();
 
     for spine_id in &spine_ids {
-        if let Some(resource) = doc.resources.get(spine_id) {
-            let resource_path = resource.path.clone();
-
-            // Read content
-            let mut text = String::new();
-            if let Some(content_bytes) = doc.get_resource_by_path(&resource_path) {
-                let html = String::from_utf8_lossy(&content_bytes).to_string();
-                text = from_read(Cursor::new(html.as_bytes()), 80).unwrap_or_else(|_| html.clone());
-            }
-            let trimmed = text.trim();
+        let Some(resource) = doc.resources.get(spine_id) else {
+            continue;
+        };
+        let resource_path = resource.path.clone();
+        let Some(content_bytes) = doc.get_resource_by_path(&resource_path) else {
+            continue;
+        };
+        let html = String::from_utf8_lossy(&content_bytes);
+
+        // If TOC was completely empty, each spine item is a chapter titled by its first line
+        if flat_toc.is_empty() {
+            let text = html_text::extract(&html, options);
+            let trimmed = text.body.trim();
             if trimmed.is_empty() {
                 continue;
             }
+            let title = extract_chapter_title(trimmed)
+                .unwrap_or_else(|| format!("第 {} 节", chapters.len() + 1));
+            chapters.push(ParsedChapter {
+                notes: text.notes,
+                ..ParsedChapter::new(title, trimmed)
+            });
+            continue;
+        }
 
-            // Check if this path corresponds to a TOC entry
-            if let Some(toc_label) = path_to_label.get(&resource_path) {
-                // If we already have accumulated content, push it as a chapter
-                if !current_merged_content.is_empty() {
-                    let final_title = if current_merged_title.is_empty() {
-                        format!("第 {} 节", chapters.len() + 1)
-                    } else {
-                        current_merged_title.clone()
-                    };
-                    chapters.push((final_title, current_merged_content.clone()));
-                    current_merged_content.clear();
-                }
-                current_merged_title = toc_label.clone();
-            }
-
-            // Append content
-            if !current_merged_content.is_empty() {
-                current_merged_content.push_str("\n\n");
-            }
-            current_merged_content.push_str(trimmed);
-
-            // If TOC was completely empty, fallback to naive title extraction per spine item
-            if flat_toc.is_empty() && current_merged_title.is_empty() {
-                current_merged_title = extract_chapter_title(trimmed)
-                    .unwrap_or_else(|| format!("第 {} 节", chapters.len() + 1));
+        let entries = file_entries
+            .get(&resource_path)
+            .map(Vec::as_slice)
+            .unwrap_or_default();
+        let anchors: Vec<&str> = entries
+            .iter()
+            .filter_map(|e| e.fragment.as_deref())
+            .collect();
+
+        // Read content: one paragraph per block, notes kept apart, split at the TOC anchors
+        let sections = html_text::extract_sections(&html, &anchors, options);
+        let found: Vec<String> = sections.iter().filter_map(|s| s.anchor.clone()).collect();
+        for section in sections {
+            if section.text.body.trim().is_empty() {
+                continue;
             }
-
-            // If flat_toc is empty, push immediately (no merging)
-            if flat_toc.is_empty() {
-                chapters.push((current_merged_title.clone(), current_merged_content.clone()));
-                current_merged_content.clear();
-                current_merged_title.clear();
+            // The text before the first anchor belongs to the entry pointing at the file
+            // itself (or at an anchor that isn't there); without one it continues the
+            // previous chapter.
+            let entry = match &section.anchor {
+                Some(anchor) => entries
+                    .iter()
+                    .find(|e| e.fragment.as_deref() == Some(anchor.as_str())),
+                None => entries.iter().find(|e| match &e.fragment {
+                    None => true,
+                    Some(fragment) => !found.contains(fragment),
+                }),
+            };
+            if let Some(entry) = entry {
+                pending.finish(&mut chapters);
+                pending.title = entry.label.clone();
+                pending.volume = entry.volume.clone();
             }
+            pending.append(section.text);
         }
     }
 
     // Push the last merged chapter
-    if !current_merged_content.is_empty() {
-        let final_title = if current_merged_title.is_empty() {
-            format!("第 {} 节", chapters.len() + 1)
-        } else {
-            current_merged_title
-        };
-        chapters.push((final_title, current_merged_content));
-    }
+    pending.finish(&mut chapters);
 
     if chapters.is_empty() {
         return Err("未能从 EPUB 中提取到任何内容".to_string());
     }
 
-    Ok((title, chapters))
+    Ok((book, chapters))
 }
 
-/// Check if a page is metadata/boilerplate rather than actual story content.
-/// Uses keyword density: if multiple skip keywords appear in a short page, it's likely metadata.
-fn is_metadata_page(text: &str) -> bool {
-    let lower = text.to_lowercase();
-    let text_len = text.chars().count();
-
-    // Count how many skip keywords appear
-    let keyword_hits: usize = SKIP_KEYWORDS
+/// Read the OPF metadata: Dublin Core elements, plus the series from calibre's `<meta>`
+/// tags (EPUB 2) or a `belongs-to-collection` property (EPUB 3).
+fn read_metadata(items: &[MetadataItem]) -> BookMetadata {
+    let values = |property: &str| -> Vec<&MetadataItem> {
+        items
+            .iter()
+            .filter(|m| m.property == property && !m.value.trim().is_empty())
+            .collect()
+    };
+    let first = |property: &str| values(property).first().map(|m| m.value.trim().to_string());
+
+    // Translators and illustrators are creators too; prefer those marked as authors.
+    let creators = values("creator");
+    let is_author = |m: &MetadataItem| m.refinement("role").map_or(true, |r| r.value == "aut");
+    let mut authors: Vec<&str> = creators
         .iter()
-        .filter(|kw| lower.contains(&kw.to_lowercase()))
-        .count();
+        .filter(|m| is_author(m))
+        .map(|m| m.value.trim())
+        .collect();
+    if authors.is_empty() {
+        authors = creators.iter().map(|m| m.value.trim()).collect();
+    }
 
-    // For short pages (< 500 chars), even 2 keyword hits is suspicious
-    if text_len < 500 && keyword_hits >= 2 {
-        return true;
+    let collection = values("belongs-to-collection").into_iter().next();
+    let (series, series_index) = match first("calibre:series") {
+        Some(series) => (Some(series), first("calibre:series_index")),
+        None => (
+            collection.map(|m| m.value.trim().to_string()),
+            collection
+                .and_then(|m| m.refinement("group-position"))
+                .map(|r| r.value.trim().to_string()),
+        ),
+    };
+
+    let mut identifiers: Vec<String> = Vec::new();
+    for m in values("identifier") {
+        let id = m.value.trim().to_string();
+        if !identifiers.contains(&id) {
+            identifiers.push(id);
+        }
     }
 
-    // For medium pages (< 1500 chars), need 3+ hits
-    if text_len < 1500 && keyword_hits >= 3 {
-        return true;
+    BookMetadata {
+        author: (!authors.is_empty()).then(|| authors.join("、")),
+        language: first("language"),
+        publisher: first("publisher"),
+        series,
+        series_index,
+        identifiers,
     }
+}
 
-    // Check if the page is structured like a metadata block:
-    // many short lines (key-value pairs) rather than prose paragraphs
-    let lines: Vec<&str> = text.lines().filter(|l| !l.trim().is_empty()).collect();
-    if lines.len() >= 3 {
-        let short_lines = lines
-            .iter()
-            .filter(|l| l.trim().chars().count() < 30)
-            .count();
-        let short_ratio = short_lines as f64 / lines.len() as f64;
-        // If >70% of lines are very short AND we have at least 1 keyword hit, it's metadata
-        if short_ratio > 0.7 && keyword_hits >= 1 && text_len < 2000 {
-            return true;
+fn read_cover<R: Read + Seek>(doc: &mut EpubDoc<R>) -> Option<CoverImage> {
+    let (data, mime) = doc.get_cover()?;
+    mime.starts_with("image/")
+        .then_some(CoverImage { data, mime })
+}
+
+#[derive(Debug, Clone)]
+struct TocEntry {
+    label: String,
+    path: PathBuf,
+    /// Id of the element the entry starts at
+    fragment: Option<String>,
+    volume: Option<String>,
+}
+
+/// The chapter being assembled from consecutive spine items.
+#[derive(Default)]
+struct PendingChapter {
+    title: String,
+    content: String,
+    volume: Option<String>,
+    notes: Vec<String>,
+}
+
+impl PendingChapter {
+    fn append(&mut self, text: ExtractedText) {
+        if !self.content.is_empty() {
+            self.content.push_str("\n\n");
         }
+        self.content.push_str(text.body.trim());
+        self.notes.extend(text.notes);
     }
 
-    false
+    /// Push the chapter, if it has any content, and start an empty one.
+    fn finish(&mut self, chapters: &mut Vec<ParsedChapter>) {
+        let pending = std::mem::take(self);
+        if pending.content.is_empty() {
+            return;
+        }
+        let title = if pending.title.is_empty() {
+            format!("第 {} 节", chapters.len() + 1)
+        } else {
+            pending.title
+        };
+        chapters.push(ParsedChapter {
+            title,
+            content: pending.content,
+            volume: pending.volume,
+            notes: pending.notes,
+        });
+    }
 }
 
 /// Try to extract a chapter title from the beginning of text content.
@@ -1,8 +1,104 @@
 use crate::models::*;
+use crate::storage::Database;
+use crate::zh_convert;
+use std::path::{Path, PathBuf};
+
+/// Write the Markdown report of a novel into `dir_path/《书名》分析报告`, with file names and
+/// text converted by `conversion` if given. Returns the folder that was written.
+pub fn export_novel_report(
+    db: &Database,
+    novel_id: &str,
+    dir_path: &str,
+    conversion: Option<ChineseConversion>,
+) -> Result<PathBuf, String> {
+    let novel = db.load_novel(novel_id).map_err(|e| e.to_string())?;
+    let summary = db.load_novel_summary(novel_id).map_err(|e| e.to_string())?;
+    let convert = |text: String| match conversion {
+        Some(conv) => zh_convert::convert(&text, conv),
+        None => text,
+    };
+
+    // Create the target folder "dir_path/《小说名字》分析报告"
+    let folder_name = convert(format!("《{}》分析报告", novel.title));
+    let target_dir = Path::new(dir_path).join(folder_name);
+
+    if !target_dir.exists() {
+        std::fs::create_dir_all(&target_dir).map_err(|e| e.to_string())?;
+    }
+
+    if let Some(s) = &summary {
+        let global_md = convert(generate_global_summary_md(&novel, Some(s)));
+        let sum_path = target_dir.join(convert("全书分析.md".to_string()));
+        std::fs::write(&sum_path, global_md).map_err(|e| e.to_string())?;
+    }
+
+    let metas = db.list_chapter_metas(novel_id).map_err(|e| e.to_string())?;
+    let volumes = db.list_volumes(novel_id).map_err(|e| e.to_string())?;
+
+    for meta in metas {
+        if let Ok(ch) = db.load_chapter(meta.id) {
+            // Only export chapters that have an analysis
+            if ch.analysis.is_some() {
+                let md = convert(generate_chapter_md(&ch));
+                // Chapters of a volume go into the volume's own folder
+                let ch_dir = match volumes.iter().find(|v| Some(v.id) == ch.volume_id) {
+                    Some(v) => {
+                        let dir = target_dir.join(convert(format!(
+                            "{:02}_{}",
+                            v.index + 1,
+                            safe_file_name(&v.title)
+                        )));
+                        std::fs::create_dir_all(&dir).map_err(|e| e.to_string())?;
+                        dir
+                    }
+                    None => target_dir.clone(),
+                };
+                let file_name = convert(format!(
+                    "第{:03}章_{}.md",
+                    ch.index + 1,
+                    safe_file_name(&ch.title)
+                ));
+                let ch_path = ch_dir.join(file_name);
+                std::fs::write(&ch_path, md).map_err(|e| e.to_string())?;
+            }
+        }
+    }
+
+    Ok(target_dir)
+}
+
+/// Windows-safe characters sanitization
+fn safe_file_name(name: &str) -> String {
+    name.replace(&['/', '\\', ':', '*', '?', '"', '<', '>', '|'][..], "_")
+}
 
 pub fn generate_global_summary_md(novel: &Novel, summary: Option<&NovelSummary>) -> String {
     let mut md = String::new();
     md.push_str(&format!("# 《{}》分析报告\n\n", novel.title));
+    let book = &novel.metadata;
+    let mut info: Vec<String> = Vec::new();
+    if let Some(author) = &book.author {
+        info.push(format!("- 作者：{}", author));
+    }
+    if let Some(series) = &book.series {
+        match &book.series_index {
+            Some(index) => info.push(format!("- 系列：{}（第 {} 部）", series, index)),
+            None => info.push(format!("- 系列：{}", series)),
+        }
+    }
+    if let Some(publisher) = &book.publisher {
+        info.push(format!("- 出版社：{}", publisher));
+    }
+    if let Some(language) = &book.language {
+        info.push(format!("- 语言：{}", language));
+    }
+    if !book.identifiers.is_empty() {
+        info.push(format!("- 标识符：{}", book.identifiers.join("，")));
+    }
+    if !info.is_empty() {
+        md.push_str(&info.join("\n"));
+        md.push_str("\n\n");
+    }
 
     if let Some(s) = summary {
         md.push_str("## 全书汇总\n\n");
@@ -0,0 +1,403 @@
+use crate::html_text::{ExtractOptions, Writer};
+use crate::importer::{self, Importer};
+use crate::models::{BookInfo, BookMetadata, CoverImage, ParsedChapter, SourceType};
+use base64::prelude::*;
+use encoding_rs::{Encoding, UTF_8};
+use roxmltree::{Document, Node, ParsingOptions};
+use std::collections::HashMap;
+use std::io::Read;
+
+/// FictionBook 2 files, plain or zipped (.fbz, .fb2.zip). Each innermost `<section>` is a
+/// chapter and the sections around it name its volume.
+pub struct Fb2Importer;
+
+impl Importer for Fb2Importer {
+    fn parse(
+        &self,
+        path: &str,
+        _options: ExtractOptions,
+    ) -> Result<(BookInfo, Vec<ParsedChapter>), String> {
+        let bytes = read_fb2(path)?;
+        parse_fb2(&decode(&bytes), &importer::title_from_path(path))
+    }
+
+    fn source_type(&self, path: &str) -> SourceType {
+        SourceType::Fb2(path.to_string())
+    }
+}
+
+fn read_fb2(path: &str) -> Result<Vec<u8>, String> {
+    let bytes = std::fs::read(path).map_err(|e| format!("无法读取文件 {}: {}", path, e))?;
+    if !bytes.starts_with(b"PK") {
+        return Ok(bytes);
+    }
+    let mut archive = zip::ZipArchive::new(std::io::Cursor::new(bytes))
+        .map_err(|e| format!("无法打开压缩的 FB2 文件: {}", e))?;
+    let name = archive
+        .file_names()
+        .find(|n| n.to_lowercase().ends_with(".fb2"))
+        .map(str::to_string)
+        .ok_or("压缩包里没有 FB2 文件")?;
+    let mut file = archive.by_name(&name).map_err(|e| e.to_string())?;
+    let mut bytes = Vec::new();
+    file.read_to_end(&mut bytes).map_err(|e| e.to_string())?;
+    Ok(bytes)
+}
+
+/// Decode by the encoding in the XML declaration; Russian books are often windows-1251.
+fn decode(bytes: &[u8]) -> String {
+    let head = String::from_utf8_lossy(&bytes[..bytes.len().min(200)]);
+    let encoding = head
+        .split_once("encoding=")
+        .and_then(|(_, rest)| {
+            let quote = rest.chars().next()?;
+            rest[1..].split(quote).next()
+        })
+        .and_then(|label| Encoding::for_label(label.as_bytes()))
+        .unwrap_or(UTF_8);
+    encoding.decode_with_bom_removal(bytes).0.into_owned()
+}
+
+fn parse_fb2(xml: &str, fallback_title: &str) -> Result<(BookInfo, Vec<ParsedChapter>), String> {
+    let options = ParsingOptions {
+        allow_dtd: true,
+        ..ParsingOptions::default()
+    };
+    let doc = Document::parse_with_options(xml, options)
+        .map_err(|e| format!("无法解析 FB2 文件: {}", e))?;
+    let root = doc.root_element();
+
+    let description = child(root, "description");
+    let title_info = description.and_then(|d| child(d, "title-info"));
+    let title = title_info
+        .and_then(|t| child(t, "book-title"))
+        .map(text_of)
+        .filter(|t| !t.is_empty())
+        .unwrap_or_else(|| fallback_title.to_string());
+    let book = BookInfo {
+        title,
+        metadata: description.map(read_metadata).unwrap_or_default(),
+        cover: title_info.and_then(|t| read_cover(root, t)),
+    };
+
+    let bodies: Vec<Node> = children(root, "body").collect();
+    let notes: HashMap<&str, String> = bodies
+        .iter()
+        .filter(|b| b.attribute("name").is_some())
+        .flat_map(|b| b.descendants().filter(|n| is(*n, "section")))
+        .filter_map(|s| Some((s.attribute("id")?, note_text(s))))
+        .collect();
+    let main = bodies
+        .iter()
+        .find(|b| b.attribute("name").is_none())
+        .or(bodies.first())
+        .ok_or("FB2 文件没有正文")?;
+
+    let mut chapters = Vec::new();
+    read_sections(*main, None, &notes, &mut chapters);
+    if chapters.is_empty() {
+        return Err("未能从 FB2 中提取到任何内容".to_string());
+    }
+    Ok((book, chapters))
+}
+
+/// Read the chapters of a `<body>` or `<section>`. A section with sections inside names their
+/// volume, and its own text before them is a chapter of its own.
+fn read_sections(
+    node: Node,
+    volume: Option<&str>,
+    notes: &HashMap<&str, String>,
+    chapters: &mut Vec<ParsedChapter>,
+) {
+    let title = child(node, "title")
+        .map(|t| {
+            children(t, "p")
+                .map(text_of)
+                .filter(|p| !p.is_empty())
+                .collect::<Vec<_>>()
+                .join(" ")
+        })
+        .filter(|t| !t.is_empty());
+    let nested = children(node, "section").next().is_some();
+    let own_volume = match (nested && !is(node, "body"), &title) {
+        (true, Some(title)) => Some(match volume {
+            Some(parent) => format!("{} - {}", parent, title),
+            None => title.clone(),
+        }),
+        _ => None,
+    };
+    let volume = own_volume.as_deref().or(volume);
+
+    // The body's title is the book's, not a chapter's.
+    let title = title.filter(|_| !is(node, "body"));
+    let mut writer = Writer::default();
+    let mut chapter_notes = Vec::new();
+    for child in node.children().filter(Node::is_element) {
+        match child.tag_name().name() {
+            "title" | "image" => {}
+            "section" => {
+                push_chapter(chapters, &title, volume, &mut writer, &mut chapter_notes);
+                read_sections(child, volume, notes, chapters);
+            }
+            _ => write_block(child, &mut writer, notes, &mut chapter_notes),
+        }
+    }
+    push_chapter(chapters, &title, volume, &mut writer, &mut chapter_notes);
+}
+
+/// Push the text written so far, if any, as a chapter.
+fn push_chapter(
+    chapters: &mut Vec<ParsedChapter>,
+    title: &Option<String>,
+    volume: Option<&str>,
+    writer: &mut Writer,
+    notes: &mut Vec<String>,
+) {
+    let content = std::mem::take(writer).finish();
+    if content.is_empty() {
+        return;
+    }
+    let title = title
+        .clone()
+        .unwrap_or_else(|| format!("第 {} 节", chapters.len() + 1));
+    chapters.push(ParsedChapter {
+        volume: volume.map(str::to_string),
+        notes: std::mem::take(notes),
+        ..ParsedChapter::new(title, content)
+    });
+}
+
+/// Write a block element: one paragraph per `<p>`, `<subtitle>` or poem line.
+fn write_block(
+    node: Node,
+    writer: &mut Writer,
+    notes: &HashMap<&str, String>,
+    chapter_notes: &mut Vec<String>,
+) {
+    match node.tag_name().name() {
+        "p" | "subtitle" | "v" | "text-author" | "td" | "th" => {
+            write_inline(node, writer, notes, chapter_notes);
+            writer.end_paragraph();
+        }
+        "image" | "empty-line" | "binary" => {}
+        _ => {
+            for child in node.children().filter(Node::is_element) {
+                write_block(child, writer, notes, chapter_notes);
+            }
+        }
+    }
+}
+
+fn write_inline(
+    node: Node,
+    writer: &mut Writer,
+    notes: &HashMap<&str, String>,
+    chapter_notes: &mut Vec<String>,
+) {
+    for child in node.children() {
+        if let Some(text) = child.text().filter(|_| child.is_text()) {
+            writer.text(text);
+            continue;
+        }
+        if is(child, "a") && child.attribute("type") == Some("note") {
+            let note = href(child)
+                .and_then(|h| h.strip_prefix('#'))
+                .and_then(|id| notes.get(id));
+            if let Some(note) = note.filter(|n| !chapter_notes.contains(n)) {
+                chapter_notes.push(note.clone());
+            }
+        }
+        write_inline(child, writer, notes, chapter_notes);
+    }
+}
+
+/// A note as "1 text", its title being the number the text links to.
+fn note_text(section: Node) -> String {
+    let mut writer = Writer::default();
+    for child in section.children().filter(Node::is_element) {
+        if is(child, "title") {
+            writer.text(&text_of(child));
+            writer.text(" ");
+        } else {
+            write_inline(child, &mut writer, &HashMap::new(), &mut Vec::new());
+            writer.line_break();
+        }
+    }
+    writer.finish().replace('\n', " ").trim().to_string()
+}
+
+fn read_metadata(description: Node) -> BookMetadata {
+    let title_info = child(description, "title-info");
+    let publish_info = child(description, "publish-info");
+    let field = |parent: Option<Node>, name: &str| {
+        parent
+            .and_then(|p| child(p, name))
+            .map(text_of)
+            .filter(|t| !t.is_empty())
+    };
+
+    let authors: Vec<String> = title_info
+        .into_iter()
+        .flat_map(|t| children(t, "author"))
+        .filter_map(|a| {
+            let names: Vec<String> = ["first-name", "middle-name", "last-name"]
+                .iter()
+                .filter_map(|n| field(Some(a), n))
+                .collect();
+            if names.is_empty() {
+                field(Some(a), "nickname")
+            } else {
+                Some(names.join(" "))
+            }
+        })
+        .collect();
+
+    let sequence = title_info
+        .and_then(|t| child(t, "sequence"))
+        .or_else(|| publish_info.and_then(|p| child(p, "sequence")));
+    let mut identifiers: Vec<String> = Vec::new();
+    let document_id = field(child(description, "document-info"), "id");
+    for id in [field(publish_info, "isbn"), document_id]
+        .into_iter()
+        .flatten()
+    {
+        if !identifiers.contains(&id) {
+            identifiers.push(id);
+        }
+    }
+
+    BookMetadata {
+        author: (!authors.is_empty()).then(|| authors.join("、")),
+        language: field(title_info, "lang"),
+        publisher: field(publish_info, "publisher"),
+        series: sequence
+            .and_then(|s| s.attribute("name"))
+            .map(|n| n.trim().to_string())
+            .filter(|n| !n.is_empty()),
+        series_index: sequence
+            .and_then(|s| s.attribute("number"))
+            .map(|n| n.trim().to_string()),
+        identifiers,
+    }
+}
+
+/// The image the title info's `<coverpage>` links to, stored base64-encoded in a `<binary>`.
+fn read_cover(root: Node, title_info: Node) -> Option<CoverImage> {
+    let image = child(title_info, "coverpage")?
+        .descendants()
+        .find(|n| is(*n, "image"))?;
+    let id = href(image)?.strip_prefix('#')?;
+    let binary = children(root, "binary").find(|b| b.attribute("id") == Some(id))?;
+    let mime = binary.attribute("content-type")?.to_string();
+    let encoded: String = binary
+        .text()?
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .collect();
+    let data = BASE64_STANDARD.decode(encoded).ok()?;
+    mime.starts_with("image/")
+        .then_some(CoverImage { data, mime })
+}
+
+fn is(node: Node, name: &str) -> bool {
+    node.is_element() && node.tag_name().name() == name
+}
+
+fn child<'a, 'i>(node: Node<'a, 'i>, name: &str) -> Option<Node<'a, 'i>> {
+    node.children().find(|n| is(*n, name))
+}
+
+fn children<'a, 'i: 'a>(
+    node: Node<'a, 'i>,
+    name: &'a str,
+) -> impl Iterator<Item = Node<'a, 'i>> + 'a {
+    node.children().filter(move |n| is(*n, name))
+}
+
+/// `l:href` or `xlink:href`, whatever the prefix of the XLink namespace.
+fn href<'a>(node: Node<'a, '_>) -> Option<&'a str> {
+    node.attributes()
+        .find(|a| a.name() == "href")
+        .map(|a| a.value())
+}
+
+fn text_of(node: Node) -> String {
+    let mut writer = Writer::default();
+    for text in node
+        .descendants()
+        .filter_map(|n| n.text().filter(|_| n.is_text()))
+    {
+        writer.text(text);
+    }
+    writer.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BOOK: &str = r##"<?xml version="1.0" encoding="windows-1251"?>
+<FictionBook xmlns="http://www.gribuser.ru/xml/fictionbook/2.0" xmlns:l="http://www.w3.org/1999/xlink">
+<description>
+  <title-info>
+    <author><first-name>Лев</first-name><last-name>Толстой</last-name></author>
+    <book-title>Война и мир</book-title>
+    <coverpage><image l:href="#cover.png"/></coverpage>
+    <lang>ru</lang>
+    <sequence name="Собрание" number="4"/>
+  </title-info>
+  <publish-info><publisher>Наука</publisher><isbn>978-5-00-000000-0</isbn></publish-info>
+</description>
+<body>
+  <title><p>Война и мир</p></title>
+  <section>
+    <title><p>Том первый</p></title>
+    <section>
+      <title><p>Глава I</p></title>
+      <p>— Eh bien, mon prince.<a l:href="#n1" type="note">[1]</a></p>
+      <poem><stanza><v>Строка один</v><v>Строка два</v></stanza></poem>
+    </section>
+    <section>
+      <title><p>Глава II</p></title>
+      <p>Анна Павловна кашляла.</p>
+    </section>
+  </section>
+</body>
+<body name="notes">
+  <section id="n1"><title><p>1</p></title><p>Ну, князь.</p></section>
+</body>
+<binary id="cover.png" content-type="image/png">iVBORw0K
+GgoAAAA=</binary>
+</FictionBook>"##;
+
+    #[test]
+    fn test_parse_fb2() {
+        let (bytes, _, _) = encoding_rs::WINDOWS_1251.encode(BOOK);
+        let (book, chapters) = parse_fb2(&decode(&bytes), "war").unwrap();
+
+        assert_eq!(book.title, "Война и мир");
+        assert_eq!(book.metadata.author.as_deref(), Some("Лев Толстой"));
+        assert_eq!(book.metadata.language.as_deref(), Some("ru"));
+        assert_eq!(book.metadata.series.as_deref(), Some("Собрание"));
+        assert_eq!(book.metadata.series_index.as_deref(), Some("4"));
+        assert_eq!(book.metadata.identifiers, vec!["978-5-00-000000-0"]);
+        assert_eq!(book.cover.unwrap().mime, "image/png");
+
+        let got: Vec<(&str, &str, Option<&str>)> = chapters
+            .iter()
+            .map(|c| (c.title.as_str(), c.content.as_str(), c.volume.as_deref()))
+            .collect();
+        assert_eq!(
+            got,
+            vec![
+                (
+                    "Глава I",
+                    "— Eh bien, mon prince.[1]\n\nСтрока один\n\nСтрока два",
+                    Some("Том первый")
+                ),
+                ("Глава II", "Анна Павловна кашляла.", Some("Том первый")),
+            ]
+        );
+        assert_eq!(chapters[0].notes, vec!["1 Ну, князь."]);
+    }
+}
@@ -0,0 +1,316 @@
+use crate::models::{ChineseConversion, LibraryMatch};
+use crate::storage::{self, Database};
+use crate::zh_convert;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+
+/// Characters per shingle. Four CJK characters are about two words.
+const SHINGLE_CHARS: usize = 4;
+/// Texts shorter than this, not counting whitespace and punctuation, get no SimHash: a few
+/// sentences are too little to tell a repost from a chapter that merely starts the same way.
+const MIN_SIMHASH_CHARS: usize = 200;
+/// SimHashes differing in at most this many bits are near-duplicates. It must stay below
+/// `BANDS`, so that two near-duplicates always agree on at least one band.
+pub const NEAR_DUPLICATE_BITS: u32 = 3;
+/// The 64 bits are indexed as this many 16-bit bands.
+const BANDS: u32 = 4;
+/// A novel in the library is reported when at least this many previewed chapters match it,
+/// or all of them do in a shorter book.
+const MIN_MATCHING_CHAPTERS: usize = 3;
+
+/// SimHash of a chapter's text: similar texts get hashes differing in few bits. Whitespace,
+/// punctuation and letter case are ignored, and Traditional Chinese is read as Simplified, so
+/// a book imported with either conversion still matches its source. None for texts too short
+/// to compare this way.
+pub fn simhash(text: &str) -> Option<u64> {
+    let chars: Vec<char> = zh_convert::convert(text, ChineseConversion::T2s)
+        .chars()
+        .filter(|c| c.is_alphanumeric())
+        .flat_map(char::to_lowercase)
+        .collect();
+    if chars.len() < MIN_SIMHASH_CHARS {
+        return None;
+    }
+
+    let mut weights = [0i32; 64];
+    let mut shingle = String::new();
+    for window in chars.windows(SHINGLE_CHARS) {
+        shingle.clear();
+        shingle.extend(window);
+        let hash = shingle_hash(shingle.as_bytes());
+        for (bit, weight) in weights.iter_mut().enumerate() {
+            *weight += if hash >> bit & 1 == 1 { 1 } else { -1 };
+        }
+    }
+    Some(
+        weights
+            .iter()
+            .enumerate()
+            .filter(|(_, &w)| w > 0)
+            .fold(0, |hash, (bit, _)| hash | 1 << bit),
+    )
+}
+
+/// FNV-1a with a SplitMix64 finalizer, so the bits of short inputs are evenly spread. Hashes
+/// are stored, so this must not change between builds the way std's hasher may.
+fn shingle_hash(bytes: &[u8]) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for &b in bytes {
+        hash ^= b as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash ^= hash >> 30;
+    hash = hash.wrapping_mul(0xbf58476d1ce4e5b9);
+    hash ^= hash >> 27;
+    hash = hash.wrapping_mul(0x94d049bb133111eb);
+    hash ^ hash >> 31
+}
+
+pub fn is_near_duplicate(a: u64, b: u64) -> bool {
+    (a ^ b).count_ones() <= NEAR_DUPLICATE_BITS
+}
+
+/// Finds stored SimHashes within `NEAR_DUPLICATE_BITS` of a given one without comparing
+/// against all of them: near-duplicates share at least one 16-bit band exactly.
+struct SimhashIndex<T> {
+    entries: Vec<(u64, T)>,
+    bands: HashMap<(u32, u16), Vec<usize>>,
+}
+
+impl<T> SimhashIndex<T> {
+    fn new() -> Self {
+        Self {
+            entries: Vec::new(),
+            bands: HashMap::new(),
+        }
+    }
+
+    fn insert(&mut self, hash: u64, value: T) {
+        let i = self.entries.len();
+        self.entries.push((hash, value));
+        for band in 0..BANDS {
+            self.bands.entry(band_key(hash, band)).or_default().push(i);
+        }
+    }
+
+    /// Entries near `hash`, each once, in insertion order.
+    fn near(&self, hash: u64) -> Vec<&(u64, T)> {
+        let mut found: Vec<usize> = (0..BANDS)
+            .filter_map(|band| self.bands.get(&band_key(hash, band)))
+            .flatten()
+            .copied()
+            .filter(|&i| is_near_duplicate(self.entries[i].0, hash))
+            .collect();
+        found.sort_unstable();
+        found.dedup();
+        found.into_iter().map(|i| &self.entries[i]).collect()
+    }
+}
+
+fn band_key(hash: u64, band: u32) -> (u32, u16) {
+    (band, (hash >> (band * 16)) as u16)
+}
+
+/// A chapter's stored fingerprints.
+pub struct ChapterFingerprint {
+    pub id: i64,
+    pub index: usize,
+    pub title: String,
+    pub content_hash: String,
+    pub simhash: Option<u64>,
+}
+
+/// A chapter repeating an earlier chapter of the same novel.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DuplicateChapter {
+    pub chapter_id: i64,
+    pub index: usize,
+    pub title: String,
+    pub duplicate_of_id: i64,
+    pub duplicate_of_index: usize,
+    pub duplicate_of_title: String,
+    /// The texts are identical, not merely similar.
+    pub exact: bool,
+}
+
+/// Chapters of a novel that repeat an earlier chapter, exactly or nearly, each paired with
+/// the first chapter it repeats. Analyzing these again tells nothing new.
+pub fn find_duplicate_chapters(
+    db: &Database,
+    novel_id: &str,
+) -> Result<Vec<DuplicateChapter>, String> {
+    let chapters = db
+        .list_chapter_fingerprints(novel_id)
+        .map_err(|e| e.to_string())?;
+    let empty = storage::content_hash("");
+
+    let mut first_with_hash: HashMap<&str, &ChapterFingerprint> = HashMap::new();
+    let mut index = SimhashIndex::new();
+    let mut duplicates = Vec::new();
+    for chapter in &chapters {
+        let exact = (chapter.content_hash != empty)
+            .then(|| first_with_hash.get(chapter.content_hash.as_str()))
+            .flatten();
+        let original = match (exact, chapter.simhash) {
+            (Some(&original), _) => Some((original, true)),
+            (None, Some(hash)) => index
+                .near(hash)
+                .first()
+                .map(|&&(_, original)| (original, false)),
+            (None, None) => None,
+        };
+        if let Some((original, exact)) = original {
+            duplicates.push(DuplicateChapter {
+                chapter_id: chapter.id,
+                index: chapter.index,
+                title: chapter.title.clone(),
+                duplicate_of_id: original.id,
+                duplicate_of_index: original.index,
+                duplicate_of_title: original.title.clone(),
+                exact,
+            });
+            continue;
+        }
+        first_with_hash.insert(&chapter.content_hash, chapter);
+        if let Some(hash) = chapter.simhash {
+            index.insert(hash, chapter);
+        }
+    }
+    Ok(duplicates)
+}
+
+/// Novels in the library that a book about to be imported duplicates, judged by its
+/// identifiers and the SimHashes of its chapters, most matching chapters first. SimHashes are
+/// used rather than exact hashes since stored texts have been cleaned up on import.
+pub fn library_matches(
+    db: &Database,
+    identifiers: &[String],
+    hashes: &[u64],
+) -> Result<Vec<LibraryMatch>, String> {
+    let novels = db.list_novels().map_err(|e| e.to_string())?;
+
+    let mut index = SimhashIndex::new();
+    for (novel_id, hash) in db.list_library_simhashes().map_err(|e| e.to_string())? {
+        index.insert(hash, novel_id);
+    }
+    let mut matching: HashMap<&str, usize> = HashMap::new();
+    for &hash in hashes {
+        let novels: HashSet<&str> = index.near(hash).iter().map(|(_, id)| id.as_str()).collect();
+        for novel_id in novels {
+            *matching.entry(novel_id).or_default() += 1;
+        }
+    }
+
+    let needed = MIN_MATCHING_CHAPTERS.min(hashes.len()).max(1);
+    let mut matches: Vec<LibraryMatch> = novels
+        .into_iter()
+        .filter_map(|novel| {
+            let matching_chapters = matching.get(novel.id.as_str()).copied().unwrap_or(0);
+            let same_identifier = novel
+                .metadata
+                .identifiers
+                .iter()
+                .any(|id| identifiers.iter().any(|other| same_identifier(id, other)));
+            (same_identifier || matching_chapters >= needed).then_some(LibraryMatch {
+                novel_id: novel.id,
+                title: novel.title,
+                matching_chapters,
+                same_identifier,
+            })
+        })
+        .collect();
+    matches.sort_by(|a, b| {
+        (b.same_identifier, b.matching_chapters).cmp(&(a.same_identifier, a.matching_chapters))
+    });
+    Ok(matches)
+}
+
+/// Identifiers compared without their scheme prefix ("urn:isbn:", "isbn:"), hyphens or case.
+fn same_identifier(a: &str, b: &str) -> bool {
+    let normalize = |id: &str| {
+        let lower = id.trim().to_lowercase();
+        let bare = lower.rsplit(':').next().unwrap_or_default().to_string();
+        bare.replace(['-', ' '], "")
+    };
+    let (a, b) = (normalize(a), normalize(b));
+    !a.is_empty() && a == b
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{BookInfo, ImportOptions, ParsedChapter, SourceType};
+
+    fn prose(seed: usize, sentences: usize) -> String {
+        let words = [
+            "风", "雨", "山", "河", "夜", "灯", "剑", "门", "城", "舟", "雪", "云", "马", "酒",
+            "花", "月",
+        ];
+        (0..sentences)
+            .map(|s| {
+                let sentence: String = (0..12)
+                    .map(|w| words[(seed * 7 + s * 13 + w * w * 5 + w) % words.len()])
+                    .collect();
+                format!("{}{}。", sentence, s)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_simhash() {
+        let text = prose(1, 40);
+        let hash = simhash(&text).unwrap();
+        // A repost with a changed sentence and different line breaks.
+        let repost = text
+            .replacen("。", "，\n\n", 3)
+            .replacen(&prose(1, 1), &prose(9, 1), 1);
+        assert!(is_near_duplicate(hash, simhash(&repost).unwrap()));
+        assert!(!is_near_duplicate(hash, simhash(&prose(2, 40)).unwrap()));
+        assert_eq!(simhash("第一章 太短了。"), None);
+        let traditional = zh_convert::convert(&text, ChineseConversion::S2t);
+        assert_eq!(simhash(&traditional), Some(hash));
+    }
+
+    #[test]
+    fn test_find_duplicates() {
+        let db = Database::open_in_memory().unwrap();
+        let (first, second) = (prose(1, 40), prose(2, 40));
+        let novel_id = db
+            .create_novel(
+                BookInfo::new("测试"),
+                SourceType::SingleTxt("a.txt".to_string()),
+                ImportOptions::default(),
+                None,
+                vec![
+                    ParsedChapter::new("第一章", &first),
+                    ParsedChapter::new("第二章", &second),
+                    ParsedChapter::new("第二章（重发）", &second),
+                    ParsedChapter::new("第三章", first.replacen("。", "！", 2)),
+                ],
+            )
+            .unwrap();
+
+        let found: Vec<(usize, usize, bool)> = find_duplicate_chapters(&db, &novel_id)
+            .unwrap()
+            .into_iter()
+            .map(|d| (d.index, d.duplicate_of_index, d.exact))
+            .collect();
+        assert_eq!(found, vec![(2, 1, true), (3, 0, false)]);
+
+        let hashes = [simhash(&first).unwrap(), simhash(&second).unwrap()];
+        let matches = library_matches(&db, &[], &hashes).unwrap();
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].matching_chapters, 2);
+        let other = [simhash(&prose(3, 40)).unwrap()];
+        assert!(library_matches(&db, &[], &other).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_same_identifier() {
+        assert!(same_identifier(
+            "urn:isbn:978-7-02-000220-7",
+            "9787020002207"
+        ));
+        assert!(!same_identifier("", ""));
+    }
+}
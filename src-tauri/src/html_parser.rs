@@ -0,0 +1,220 @@
+use crate::html_text::{self, ExtractOptions};
+use crate::importer::{self, HeadedSection, Importer};
+use crate::models::{BookInfo, ParsedChapter, SourceType};
+use crate::text_encoding;
+use pulldown_cmark::{html, Options, Parser};
+use scraper::{Html, Selector};
+use std::path::Path;
+
+/// Saved web pages and Markdown files. A single file is split at its headings; a folder of
+/// them is imported one file per chapter, in natural file name order.
+pub struct HtmlImporter {
+    pub markdown: bool,
+}
+
+impl Importer for HtmlImporter {
+    fn parse(
+        &self,
+        path: &str,
+        options: ExtractOptions,
+    ) -> Result<(BookInfo, Vec<ParsedChapter>), String> {
+        let fallback_title = importer::title_from_path(path);
+        if Path::new(path).is_dir() {
+            let chapters = self.parse_folder(path, options)?;
+            return Ok((BookInfo::new(fallback_title), chapters));
+        }
+
+        let html = self.read_html(path)?;
+        let sections = html_text::extract_at_headings(&html, options)
+            .into_iter()
+            .map(|section| HeadedSection {
+                level: section.anchor.as_deref().and_then(html_text::heading_level),
+                text: section.text,
+            })
+            .collect();
+        let (heading_title, chapters) = importer::split_at_headings(sections, &fallback_title);
+        if chapters.is_empty() {
+            return Err("未能从文件中提取到任何内容".to_string());
+        }
+        let title = page_title(&html)
+            .or(heading_title)
+            .unwrap_or(fallback_title);
+        Ok((BookInfo::new(title), chapters))
+    }
+
+    fn source_type(&self, path: &str) -> SourceType {
+        if self.markdown {
+            SourceType::Markdown(path.to_string())
+        } else {
+            SourceType::Html(path.to_string())
+        }
+    }
+}
+
+impl HtmlImporter {
+    fn extensions(&self) -> &'static [&'static str] {
+        if self.markdown {
+            &["md", "markdown"]
+        } else {
+            &["html", "htm", "xhtml"]
+        }
+    }
+
+    /// Read a file as HTML, rendering Markdown first.
+    fn read_html(&self, path: &str) -> Result<String, String> {
+        let (text, _) = text_encoding::read_text(path, None)?;
+        if !self.markdown {
+            return Ok(text);
+        }
+        let options = Options::ENABLE_TABLES
+            | Options::ENABLE_FOOTNOTES
+            | Options::ENABLE_STRIKETHROUGH
+            | Options::ENABLE_YAML_STYLE_METADATA_BLOCKS;
+        let mut html = String::new();
+        html::push_html(&mut html, Parser::new_ext(&text, options));
+        Ok(html)
+    }
+
+    /// One chapter per file, titled by its first heading, or else its file name.
+    fn parse_folder(
+        &self,
+        dir: &str,
+        options: ExtractOptions,
+    ) -> Result<Vec<ParsedChapter>, String> {
+        let mut files = files_with(Path::new(dir), self.extensions())?;
+        files.sort_by(|a, b| natural_cmp(a, b));
+
+        let mut chapters = Vec::new();
+        for file in &files {
+            let path = file.to_string_lossy();
+            let html = self.read_html(&path)?;
+            let sections = html_text::extract_at_headings(&html, options);
+            // Text before the first heading is usually the site's navigation.
+            let start = sections
+                .iter()
+                .position(|s| s.anchor.is_some())
+                .unwrap_or(0);
+            let mut sections = sections.into_iter().skip(start);
+            let Some(first) = sections.next() else {
+                continue;
+            };
+            let (heading, mut content) = match first.anchor {
+                Some(_) => importer::split_heading(&first.text.body),
+                None => (None, first.text.body.trim().to_string()),
+            };
+            let mut notes = first.text.notes;
+            for section in sections {
+                content.push_str("\n\n");
+                content.push_str(section.text.body.trim());
+                notes.extend(section.text.notes);
+            }
+            if content.trim().is_empty() {
+                continue;
+            }
+            let title = heading.unwrap_or_else(|| importer::title_from_path(&path));
+            chapters.push(ParsedChapter {
+                notes,
+                ..ParsedChapter::new(title, content.trim())
+            });
+        }
+        if chapters.is_empty() {
+            return Err(format!("文件夹 {} 里没有可导入的内容", dir));
+        }
+        Ok(chapters)
+    }
+}
+
+/// The importer for a folder: Markdown if it has any Markdown files, HTML otherwise.
+pub(crate) fn folder_importer(dir: &Path) -> Result<Box<dyn Importer>, String> {
+    for markdown in [true, false] {
+        let importer = HtmlImporter { markdown };
+        if !files_with(dir, importer.extensions())?.is_empty() {
+            return Ok(Box::new(importer));
+        }
+    }
+    Err(format!(
+        "文件夹 {} 里没有 Markdown 或 HTML 文件",
+        dir.display()
+    ))
+}
+
+fn files_with(dir: &Path, extensions: &[&str]) -> Result<Vec<std::path::PathBuf>, String> {
+    let entries =
+        std::fs::read_dir(dir).map_err(|e| format!("无法读取文件夹 {}: {}", dir.display(), e))?;
+    Ok(entries
+        .filter_map(|e| e.ok().map(|e| e.path()))
+        .filter(|p| {
+            p.is_file()
+                && p.extension()
+                    .and_then(|e| e.to_str())
+                    .is_some_and(|e| extensions.contains(&e.to_lowercase().as_str()))
+        })
+        .collect())
+}
+
+/// Compare paths with runs of digits compared by value, so "第2章" sorts before "第10章".
+fn natural_cmp(a: &Path, b: &Path) -> std::cmp::Ordering {
+    fn key(path: &Path) -> Vec<(u8, String)> {
+        let name = path.to_string_lossy();
+        let mut key: Vec<(u8, String)> = Vec::new();
+        for c in name.chars() {
+            let digit = c.is_ascii_digit();
+            match key.last_mut() {
+                Some((1, run)) if digit => run.push(c),
+                Some((0, run)) if !digit => run.push(c),
+                _ => key.push((digit as u8, c.to_string())),
+            }
+        }
+        // Zero-pad digit runs so they compare by value as strings.
+        for (kind, run) in key.iter_mut() {
+            if *kind == 1 {
+                *run = format!("{:0>20}", run.trim_start_matches('0'));
+            }
+        }
+        key
+    }
+    key(a).cmp(&key(b))
+}
+
+fn page_title(html: &str) -> Option<String> {
+    let selector = Selector::parse("title").ok()?;
+    let document = Html::parse_document(html);
+    let text: String = document.select(&selector).next()?.text().collect();
+    let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    (!text.is_empty()).then_some(text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_markdown_split_at_headings() {
+        let path = std::env::temp_dir().join(format!("novelparser-md-{}.md", std::process::id()));
+        std::fs::write(
+            &path,
+            "# 书名\n\n## 第一章\n\n甲。[^1]\n\n[^1]: 注释。\n\n## 第二章\n\n乙。\n",
+        )
+        .unwrap();
+        let path_str = path.to_string_lossy().to_string();
+        let (book, chapters) = HtmlImporter { markdown: true }
+            .parse(&path_str, ExtractOptions::default())
+            .unwrap();
+        std::fs::remove_file(&path).ok();
+
+        assert_eq!(book.title, "书名");
+        let got: Vec<(&str, &str)> = chapters
+            .iter()
+            .map(|c| (c.title.as_str(), c.content.as_str()))
+            .collect();
+        assert_eq!(got, vec![("第一章", "甲。1"), ("第二章", "乙。")]);
+        assert_eq!(chapters[0].notes, vec!["1\n\n注释。"]);
+    }
+
+    #[test]
+    fn test_natural_cmp() {
+        let mut names = vec!["第10章.html", "第2章.html", "第1章.html"];
+        names.sort_by(|a, b| natural_cmp(Path::new(a), Path::new(b)));
+        assert_eq!(names, vec!["第1章.html", "第2章.html", "第10章.html"]);
+    }
+}
@@ -0,0 +1,360 @@
+use scraper::node::Element;
+use scraper::{ElementRef, Html, Node};
+
+/// How EPUB XHTML and other HTML is turned into text.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ExtractOptions {
+    /// Keep ruby readings in parentheses after their base text (漢字（かんじ）) instead of
+    /// dropping them.
+    pub keep_ruby: bool,
+}
+
+/// Text of one XHTML document.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ExtractedText {
+    /// One paragraph per block element, separated by blank lines. `<br>` becomes a line
+    /// break inside the paragraph.
+    pub body: String,
+    /// Footnotes, endnotes and asides, one per note element
+    pub notes: Vec<String>,
+}
+
+/// Elements whose content is never text.
+const SKIPPED: &[&str] = &[
+    "head", "script", "style", "title", "svg", "math", "template", "noscript",
+];
+
+/// Elements that start and end a paragraph.
+const BLOCKS: &[&str] = &[
+    "p",
+    "div",
+    "section",
+    "article",
+    "header",
+    "footer",
+    "main",
+    "nav",
+    "blockquote",
+    "pre",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "ul",
+    "ol",
+    "li",
+    "dl",
+    "dt",
+    "dd",
+    "table",
+    "tr",
+    "figure",
+    "figcaption",
+    "hr",
+    "body",
+];
+
+/// `epub:type` and `role` values of notes.
+const NOTE_TYPES: &[&str] = &[
+    "footnote",
+    "footnotes",
+    "endnote",
+    "endnotes",
+    "rearnote",
+    "rearnotes",
+    "note",
+    "annotation",
+    "doc-footnote",
+    "doc-endnote",
+    "doc-endnotes",
+];
+
+/// Part of a document starting at an anchor.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Section {
+    /// Id of the element the section starts at, or the tag name of its heading; `None` for
+    /// the text before the first anchor
+    pub anchor: Option<String>,
+    pub text: ExtractedText,
+}
+
+pub fn extract(html: &str, options: ExtractOptions) -> ExtractedText {
+    extract_sections(html, &[], options)
+        .pop()
+        .map(|section| section.text)
+        .unwrap_or_default()
+}
+
+/// Extract a document, starting a new section at each element whose id is in `anchors`.
+/// Anchors that don't occur in the document are ignored.
+pub fn extract_sections(html: &str, anchors: &[&str], options: ExtractOptions) -> Vec<Section> {
+    run(html, anchors, false, options)
+}
+
+/// Extract a document, starting a new section at each `<h1>`–`<h6>`. The `anchor` of such a
+/// section is the heading's tag name, and the heading is the first paragraph of its body.
+pub fn extract_at_headings(html: &str, options: ExtractOptions) -> Vec<Section> {
+    run(html, &[], true, options)
+}
+
+fn run(html: &str, anchors: &[&str], at_headings: bool, options: ExtractOptions) -> Vec<Section> {
+    let document = Html::parse_document(html);
+    let mut extractor = Extractor {
+        options,
+        anchors,
+        at_headings,
+        sections: Vec::new(),
+        anchor: None,
+        writers: vec![Writer::default()],
+        notes: Vec::new(),
+    };
+    extractor.walk(document.root_element());
+    extractor.end_section(None);
+    extractor.sections
+}
+
+struct Extractor<'a> {
+    options: ExtractOptions,
+    anchors: &'a [&'a str],
+    at_headings: bool,
+    sections: Vec<Section>,
+    /// Anchor of the section being written
+    anchor: Option<String>,
+    /// The body, with a writer per note being read on top of it
+    writers: Vec<Writer>,
+    notes: Vec<String>,
+}
+
+impl Extractor<'_> {
+    fn out(&mut self) -> &mut Writer {
+        self.writers.last_mut().expect("body writer")
+    }
+
+    /// Close the section being written and start one at `anchor`.
+    fn end_section(&mut self, anchor: Option<String>) {
+        let body = std::mem::take(&mut self.writers[0]).finish();
+        let notes = std::mem::take(&mut self.notes);
+        let previous = std::mem::replace(&mut self.anchor, anchor);
+        // The text before the first anchor is usually empty.
+        if previous.is_some() || !body.is_empty() || !notes.is_empty() {
+            self.sections.push(Section {
+                anchor: previous,
+                text: ExtractedText { body, notes },
+            });
+        }
+    }
+
+    fn walk(&mut self, el: ElementRef) {
+        for child in el.children() {
+            let element = match child.value() {
+                Node::Text(text) => {
+                    self.out().text(text);
+                    continue;
+                }
+                Node::Element(element) => element,
+                _ => continue,
+            };
+            let Some(child_ref) = ElementRef::wrap(child) else {
+                continue;
+            };
+            let name = element.name();
+            // Anchors inside notes don't split the body.
+            if self.writers.len() == 1 {
+                let id = element.id().or_else(|| element.attr("name"));
+                if let Some(id) = id.filter(|id| self.anchors.contains(id)) {
+                    self.end_section(Some(id.to_string()));
+                } else if self.at_headings && heading_level(name).is_some() {
+                    self.end_section(Some(name.to_string()));
+                }
+            }
+            match name {
+                _ if SKIPPED.contains(&name) => {}
+                "br" => self.out().line_break(),
+                "rp" => {}
+                "rt" => {
+                    if self.options.keep_ruby {
+                        self.out().text("（");
+                        self.walk(child_ref);
+                        self.out().text("）");
+                    }
+                }
+                _ if is_note(element) => {
+                    self.writers.push(Writer::default());
+                    self.walk(child_ref);
+                    let note = self.writers.pop().expect("note writer").finish();
+                    if !note.is_empty() {
+                        self.notes.push(note);
+                    }
+                }
+                _ if BLOCKS.contains(&name) => {
+                    self.out().end_paragraph();
+                    self.walk(child_ref);
+                    self.out().end_paragraph();
+                }
+                _ => self.walk(child_ref),
+            }
+        }
+    }
+}
+
+/// Level of an `<h1>`–`<h6>` tag name.
+pub fn heading_level(name: &str) -> Option<usize> {
+    match name.as_bytes() {
+        [b'h', n @ b'1'..=b'6'] => Some((n - b'0') as usize),
+        _ => None,
+    }
+}
+
+fn is_note(element: &Element) -> bool {
+    let typed = ["epub:type", "role"]
+        .iter()
+        .filter_map(|attr| element.attr(attr))
+        .flat_map(str::split_whitespace)
+        .any(|t| NOTE_TYPES.contains(&t));
+    // Readers such as Duokan mark notes by class only.
+    let classed =
+        BLOCKS.contains(&element.name()) && element.classes().any(|c| c.contains("footnote"));
+    element.name() == "aside" || typed || classed
+}
+
+/// Collects paragraphs, collapsing whitespace the way a browser would. The other markup
+/// importers write their text through it too.
+#[derive(Default)]
+pub(crate) struct Writer {
+    paragraphs: Vec<String>,
+    current: String,
+}
+
+impl Writer {
+    pub(crate) fn text(&mut self, text: &str) {
+        for c in text.chars() {
+            if c.is_ascii_whitespace() {
+                if !self.current.is_empty() && !self.current.ends_with([' ', '\n']) {
+                    self.current.push(' ');
+                }
+            } else {
+                self.current.push(c);
+            }
+        }
+    }
+
+    pub(crate) fn line_break(&mut self) {
+        let trimmed = self.current.trim_end_matches(' ').len();
+        self.current.truncate(trimmed);
+        self.current.push('\n');
+    }
+
+    pub(crate) fn end_paragraph(&mut self) {
+        let paragraph: Vec<String> = self
+            .current
+            .lines()
+            .map(|line| join_cjk_lines(line.trim_matches(|c: char| c.is_ascii_whitespace())))
+            .filter(|line| !line.is_empty())
+            .collect();
+        if !paragraph.is_empty() {
+            self.paragraphs.push(paragraph.join("\n"));
+        }
+        self.current.clear();
+    }
+
+    pub(crate) fn finish(mut self) -> String {
+        self.end_paragraph();
+        self.paragraphs.join("\n\n")
+    }
+}
+
+/// Drop the spaces left where the source wrapped a line between two CJK characters.
+fn join_cjk_lines(line: &str) -> String {
+    let is_wide = |c: Option<&char>| c.is_some_and(|&c| c >= '\u{2E80}');
+    let chars: Vec<char> = line.chars().collect();
+    chars
+        .iter()
+        .enumerate()
+        .filter(|&(i, &c)| {
+            !(c == ' ' && i > 0 && is_wide(chars.get(i - 1)) && is_wide(chars.get(i + 1)))
+        })
+        .map(|(_, &c)| c)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PAGE: &str = r##"<?xml version="1.0" encoding="utf-8"?>
+<html xmlns="http://www.w3.org/1999/xhtml" xmlns:epub="http://www.idpf.org/2007/ops">
+<head><title>第一章</title><style>p { text-indent: 2em; }</style></head>
+<body>
+  <h2>第一章　<ruby>雨<rp>(</rp><rt>あめ</rt><rp>)</rp></ruby>の夜</h2>
+  <p>　　他推开门，走进了那间许久没有人住过的屋子，灰尘在阳光里缓缓飘动，
+     窗外传来几声鸟叫。<a epub:type="noteref" href="#n1">[1]</a></p>
+  <p>He said, "It has been
+     a long time."<br/>Nobody answered.</p>
+  <aside epub:type="footnote" id="n1"><p>[1] 这间屋子在第三卷里还会出现。</p></aside>
+</body>
+</html>"##;
+
+    #[test]
+    fn test_paragraphs_without_wrapping() {
+        let text = extract(PAGE, ExtractOptions::default());
+        assert_eq!(
+            text.body,
+            concat!(
+                "第一章　雨の夜\n\n",
+                "　　他推开门，走进了那间许久没有人住过的屋子，灰尘在阳光里缓缓飘动，窗外传来几声鸟叫。[1]\n\n",
+                "He said, \"It has been a long time.\"\nNobody answered."
+            )
+        );
+        assert_eq!(text.notes, vec!["[1] 这间屋子在第三卷里还会出现。"]);
+    }
+
+    #[test]
+    fn test_sections_at_anchors() {
+        let html = r#"<html><body>
+            <h2 id="c1">第一章</h2><p>甲。</p>
+            <div><a name="c2"></a><h2>第二章</h2><p>乙。</p></div>
+            <h2 id="c3">第三章</h2><p>丙。</p>
+        </body></html>"#;
+        let sections = extract_sections(html, &["c2", "c3", "missing"], ExtractOptions::default());
+        let got: Vec<(Option<&str>, &str)> = sections
+            .iter()
+            .map(|s| (s.anchor.as_deref(), s.text.body.as_str()))
+            .collect();
+        assert_eq!(
+            got,
+            vec![
+                (None, "第一章\n\n甲。"),
+                (Some("c2"), "第二章\n\n乙。"),
+                (Some("c3"), "第三章\n\n丙。"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_sections_at_headings() {
+        let html = r#"<html><body>
+            <h1>书名</h1><h2>第一章</h2><p>甲。</p><h3>一</h3><p>乙。</p>
+        </body></html>"#;
+        let sections = extract_at_headings(html, ExtractOptions::default());
+        let got: Vec<(Option<&str>, &str)> = sections
+            .iter()
+            .map(|s| (s.anchor.as_deref(), s.text.body.as_str()))
+            .collect();
+        assert_eq!(
+            got,
+            vec![
+                (Some("h1"), "书名"),
+                (Some("h2"), "第一章\n\n甲。"),
+                (Some("h3"), "一\n\n乙。"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_keep_ruby() {
+        let text = extract(PAGE, ExtractOptions { keep_ruby: true });
+        assert!(text.body.starts_with("第一章　雨（あめ）の夜\n\n"));
+    }
+}
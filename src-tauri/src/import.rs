@@ -0,0 +1,461 @@
+use crate::cleanup::prepare_import;
+use crate::fingerprint;
+use crate::models::{
+    BookInfo, ChineseConversion, ImportOptions, ImportResult, ParsedChapter, ProgressEvent,
+    SourceType,
+};
+use crate::progress::{NullSink, ProgressSink};
+use crate::storage::{self, Database};
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Chapters stored between two `import_progress` events.
+const PROGRESS_EVERY: usize = 20;
+
+/// Chapters committed together. Between commits other connections can write, so a long
+/// import doesn't keep the app waiting on the database.
+const CHAPTERS_PER_COMMIT: usize = 100;
+
+/// Where an import reports its progress, and the flag that cancels it.
+#[derive(Clone, Copy)]
+pub struct ImportControl<'a> {
+    pub sink: &'a dyn ProgressSink,
+    pub cancel: &'a AtomicBool,
+}
+
+impl ImportControl<'static> {
+    /// No progress reports and no way to cancel.
+    pub fn silent() -> Self {
+        static NEVER: AtomicBool = AtomicBool::new(false);
+        Self {
+            sink: &NullSink,
+            cancel: &NEVER,
+        }
+    }
+}
+
+impl ImportControl<'_> {
+    pub(crate) fn report(
+        &self,
+        novel_id: &str,
+        status: &str,
+        current: usize,
+        total: usize,
+        message: String,
+    ) {
+        self.sink.import_progress(ProgressEvent {
+            novel_id: novel_id.to_string(),
+            chapter_id: None,
+            status: status.to_string(),
+            current,
+            total,
+            message,
+            report: None,
+        });
+    }
+
+    pub(crate) fn check_cancelled(&self) -> Result<(), String> {
+        if self.cancel.load(Ordering::Relaxed) {
+            return Err("导入已取消".to_string());
+        }
+        Ok(())
+    }
+
+    /// Send the last event of a run that ended with `result`: "done" with `done_message`, or
+    /// "cancelled" or "error" with the error. Clears the cancel flag.
+    pub(crate) fn report_end<T>(
+        &self,
+        novel_id: &str,
+        result: &Result<T, String>,
+        done_message: &str,
+    ) {
+        let cancelled = self.cancel.swap(false, Ordering::Relaxed);
+        match result {
+            Ok(_) => self.report(novel_id, "done", 0, 0, done_message.to_string()),
+            Err(e) if cancelled => self.report(novel_id, "cancelled", 0, 0, e.clone()),
+            Err(e) => self.report(novel_id, "error", 0, 0, e.clone()),
+        }
+    }
+}
+
+/// Run a whole import: parse the source with `parse`, then clean and store it as a new novel,
+/// or append it to `append_to`. `parse` also returns the options the
+/// source was read with, for resyncing it later. Progress goes to the sink as
+/// `import_progress` events ending in "done", "cancelled" or "error"; whatever a failed or
+/// cancelled import stored is rolled back.
+pub fn run_import(
+    db: &Database,
+    control: ImportControl,
+    append_to: Option<&str>,
+    conversion: Option<ChineseConversion>,
+    parse: impl FnOnce() -> Result<(BookInfo, SourceType, ImportOptions, Vec<ParsedChapter>), String>,
+) -> Result<ImportResult, String> {
+    control.cancel.store(false, Ordering::Relaxed);
+    control.report("", "parsing", 0, 0, "正在解析文件".to_string());
+    let result = parse().and_then(|(book, source_type, options, chapters)| {
+        control.check_cancelled()?;
+        match append_to {
+            Some(novel_id) => {
+                append_novel(db, novel_id, book, source_type, options, chapters, control)
+            }
+            None => import_novel(
+                db,
+                book,
+                source_type,
+                options,
+                conversion,
+                chapters,
+                control,
+            ),
+        }
+    });
+    let novel_id = result.as_ref().map(|r| r.novel_id.as_str()).unwrap_or("");
+    control.report_end(novel_id, &result, "导入完成");
+    result
+}
+
+/// Clean parsed chapters with the saved cleanup settings, apply `conversion` and store them
+/// with `book` as a new novel. The novel is listed once all its chapters are stored.
+pub fn import_novel(
+    db: &Database,
+    mut book: BookInfo,
+    source_type: SourceType,
+    options: ImportOptions,
+    conversion: Option<ChineseConversion>,
+    mut chapters: Vec<ParsedChapter>,
+    control: ImportControl,
+) -> Result<ImportResult, String> {
+    control.report(
+        "",
+        "cleaning",
+        0,
+        chapters.len(),
+        "正在清理文本".to_string(),
+    );
+    let cleanup = prepare_import(db, &mut book, conversion, &mut chapters)?;
+    control.check_cancelled()?;
+
+    let tx = db.begin_transaction().map_err(|e| e.to_string())?;
+    let novel_id = db
+        .create_novel(book, source_type, options, conversion, Vec::new())
+        .map_err(|e| e.to_string())?;
+    db.begin_import(&novel_id, true)
+        .map_err(|e| e.to_string())?;
+    tx.commit().map_err(|e| e.to_string())?;
+    store_chapters(db, &novel_id, chapters, control, || Ok(()))?;
+    Ok(ImportResult {
+        novel_id,
+        cleanup,
+        skipped: Vec::new(),
+        same_title: Vec::new(),
+    })
+}
+
+/// Store chapters after the last chapter of a novel `begin_import` was called for,
+/// committing every `CHAPTERS_PER_COMMIT` chapters and reporting progress every few. `finish`
+/// runs in the last commit, which also ends the pending import. If anything fails or the
+/// import is cancelled, everything the import stored is rolled back.
+fn store_chapters(
+    db: &Database,
+    novel_id: &str,
+    chapters: Vec<ParsedChapter>,
+    control: ImportControl,
+    finish: impl FnOnce() -> Result<(), String>,
+) -> Result<Vec<i64>, String> {
+    let result = store_in_batches(db, novel_id, chapters, control, finish);
+    if result.is_err() {
+        // If this fails too, the app rolls the import back the next time it starts.
+        let _ = db.rollback_import(novel_id);
+    }
+    result
+}
+
+fn store_in_batches(
+    db: &Database,
+    novel_id: &str,
+    chapters: Vec<ParsedChapter>,
+    control: ImportControl,
+    finish: impl FnOnce() -> Result<(), String>,
+) -> Result<Vec<i64>, String> {
+    let total = chapters.len();
+    let mut appender = db.chapter_appender(novel_id).map_err(|e| e.to_string())?;
+    let mut ids = Vec::with_capacity(total);
+    let mut tx = db.begin_transaction().map_err(|e| e.to_string())?;
+    for (i, chapter) in chapters.into_iter().enumerate() {
+        control.check_cancelled()?;
+        if i > 0 && i % CHAPTERS_PER_COMMIT == 0 {
+            tx.commit().map_err(|e| e.to_string())?;
+            tx = db.begin_transaction().map_err(|e| e.to_string())?;
+        }
+        let done = i + 1;
+        let title = (done % PROGRESS_EVERY == 0 || done == total).then(|| chapter.title.clone());
+        ids.push(appender.push(chapter).map_err(|e| e.to_string())?);
+        if let Some(title) = title {
+            control.report(novel_id, "saving", done, total, title);
+        }
+    }
+    finish()?;
+    db.finish_import(novel_id).map_err(|e| e.to_string())?;
+    tx.commit().map_err(|e| e.to_string())?;
+    Ok(ids)
+}
+
+/// Clean parsed chapters like `import_novel` and add them after the last chapter of an
+/// existing novel, converted the way the novel was. Chapters the novel already has (same
+/// text, or same title and nearly the same text) and repeats within `chapters` are skipped.
+/// Chapters sharing only a title with a stored one are added and listed in `same_title`.
+/// When the new source covers the old one (more TXT files, or a newer dump of the same
+/// single TXT), resyncing reads it from then on, with `options`; the new source is saved
+/// with the last of the chapters.
+pub fn append_novel(
+    db: &Database,
+    novel_id: &str,
+    mut book: BookInfo,
+    source_type: SourceType,
+    options: ImportOptions,
+    mut chapters: Vec<ParsedChapter>,
+    control: ImportControl,
+) -> Result<ImportResult, String> {
+    let mut novel = db.load_novel(novel_id).map_err(|e| e.to_string())?;
+    control.report(
+        novel_id,
+        "cleaning",
+        0,
+        chapters.len(),
+        "正在清理文本".to_string(),
+    );
+    let cleanup = prepare_import(db, &mut book, novel.conversion, &mut chapters)?;
+    control.check_cancelled()?;
+
+    let stored = db
+        .list_chapter_fingerprints(novel_id)
+        .map_err(|e| e.to_string())?;
+    let mut hashes: HashSet<String> = stored.iter().map(|c| c.content_hash.clone()).collect();
+    let mut simhashes_by_title: HashMap<&str, Vec<Option<u64>>> = HashMap::new();
+    for chapter in &stored {
+        simhashes_by_title
+            .entry(chapter.title.trim())
+            .or_default()
+            .push(chapter.simhash);
+    }
+    let mut skipped = Vec::new();
+    let mut same_title = Vec::new();
+    let mut added = Vec::new();
+    for ch in chapters {
+        let hash = storage::content_hash(&ch.content);
+        let title_match = simhashes_by_title.get(ch.title.trim());
+        // A repost with the same title, differing only in cleanup or a few characters.
+        let near = title_match.is_some_and(|stored| {
+            fingerprint::simhash(&ch.content).is_some_and(|new| {
+                stored
+                    .iter()
+                    .flatten()
+                    .any(|&old| fingerprint::is_near_duplicate(old, new))
+            })
+        });
+        if near || !hashes.insert(hash) {
+            skipped.push(ch.title);
+        } else {
+            if title_match.is_some() {
+                same_title.push(ch.title.clone());
+            }
+            added.push(ch);
+        }
+    }
+    if added.is_empty() {
+        return Err("没有新章节可以追加，所有章节都已存在".to_string());
+    }
+
+    novel.source_type = match (novel.source_type, source_type) {
+        (SourceType::TxtFiles(mut old), SourceType::TxtFiles(new)) => {
+            for path in new {
+                if !old.contains(&path) {
+                    old.push(path);
+                }
+            }
+            if options.encoding.is_some() {
+                novel.import_options.encoding = options.encoding;
+            }
+            SourceType::TxtFiles(old)
+        }
+        (SourceType::SingleTxt(_), SourceType::SingleTxt(new)) => {
+            novel.import_options = options;
+            SourceType::SingleTxt(new)
+        }
+        (old, _) => old,
+    };
+    db.begin_import(novel_id, false)
+        .map_err(|e| e.to_string())?;
+    store_chapters(db, novel_id, added, control, || {
+        db.save_novel(&novel).map_err(|e| e.to_string())
+    })?;
+    Ok(ImportResult {
+        novel_id: novel.id,
+        cleanup,
+        skipped,
+        same_title,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_append_skips_existing_chapters() {
+        let db = Database::open_in_memory().unwrap();
+        let long = "他推开门，走进了那间许久没有人住过的屋子，灰尘在阳光里缓缓飘动。".repeat(10);
+        let novel_id = import_novel(
+            &db,
+            BookInfo::new("测试"),
+            SourceType::TxtFiles(vec!["1.txt".to_string()]),
+            ImportOptions::default(),
+            None,
+            vec![
+                ParsedChapter::new("第一章", "甲。"),
+                ParsedChapter::new("第二章", &long),
+            ],
+            ImportControl::silent(),
+        )
+        .unwrap()
+        .novel_id;
+
+        let result = append_novel(
+            &db,
+            &novel_id,
+            BookInfo::new("测试"),
+            SourceType::TxtFiles(vec!["2.txt".to_string()]),
+            ImportOptions::default(),
+            vec![
+                ParsedChapter::new("第二章", long.replacen("灰尘", "尘埃", 1)),
+                // The first chapter of a second volume.
+                ParsedChapter::new("第一章", "丁。"),
+                ParsedChapter::new("第三章", "丙。"),
+                ParsedChapter::new("第三章（重发）", "丙。"),
+            ],
+            ImportControl::silent(),
+        )
+        .unwrap();
+        assert_eq!(result.skipped, vec!["第二章", "第三章（重发）"]);
+        assert_eq!(result.same_title, vec!["第一章"]);
+
+        let titles: Vec<(usize, String)> = db
+            .list_chapter_metas(&novel_id)
+            .unwrap()
+            .into_iter()
+            .map(|m| (m.index, m.title))
+            .collect();
+        assert_eq!(
+            titles,
+            vec![
+                (0, "第一章".to_string()),
+                (1, "第二章".to_string()),
+                (2, "第一章".to_string()),
+                (3, "第三章".to_string())
+            ]
+        );
+        let novel = db.load_novel(&novel_id).unwrap();
+        assert!(matches!(novel.source_type, SourceType::TxtFiles(paths) if paths.len() == 2));
+    }
+
+    /// Cancels the import once the first batch of chapters is committed, and remembers the
+    /// novel it went to.
+    struct CancelAfterCommit<'a> {
+        cancel: &'a AtomicBool,
+        novel_id: std::sync::Mutex<String>,
+    }
+
+    impl ProgressSink for CancelAfterCommit<'_> {
+        fn analysis_progress(&self, _event: ProgressEvent) {}
+        fn batch_progress(&self, _event: ProgressEvent) {}
+        fn streaming(&self, _chunk: crate::models::StreamingChunk) {}
+        fn import_progress(&self, event: ProgressEvent) {
+            if event.status == "saving" && event.current > CHAPTERS_PER_COMMIT {
+                *self.novel_id.lock().unwrap() = event.novel_id;
+                self.cancel.store(true, Ordering::Relaxed);
+            }
+        }
+    }
+
+    fn numbered_chapters(count: usize) -> Vec<ParsedChapter> {
+        (1..=count)
+            .map(|i| ParsedChapter::new(format!("第{}章", i), format!("正文{}。", i)))
+            .collect()
+    }
+
+    #[test]
+    fn test_cancelled_import_stores_nothing() {
+        let db = Database::open_in_memory().unwrap();
+        let cancel = AtomicBool::new(false);
+        let sink = CancelAfterCommit {
+            cancel: &cancel,
+            novel_id: Default::default(),
+        };
+        let control = ImportControl {
+            sink: &sink,
+            cancel: &cancel,
+        };
+        let chapters = numbered_chapters(CHAPTERS_PER_COMMIT * 2);
+
+        let result = run_import(&db, control, None, None, || {
+            Ok((
+                BookInfo::new("测试"),
+                SourceType::SingleTxt("a.txt".to_string()),
+                ImportOptions::default(),
+                chapters,
+            ))
+        });
+        assert!(result.is_err());
+        assert!(db.list_novels().unwrap().is_empty());
+        let novel_id = sink.novel_id.lock().unwrap().clone();
+        assert!(!novel_id.is_empty());
+        assert!(db.load_novel(&novel_id).is_err());
+        assert!(!cancel.load(Ordering::Relaxed));
+    }
+
+    #[test]
+    fn test_cancelled_append_keeps_existing_chapters() {
+        let db = Database::open_in_memory().unwrap();
+        let novel_id = import_novel(
+            &db,
+            BookInfo::new("测试"),
+            SourceType::TxtFiles(vec!["1.txt".to_string()]),
+            ImportOptions::default(),
+            None,
+            vec![ParsedChapter::new("序章", "开头。")],
+            ImportControl::silent(),
+        )
+        .unwrap()
+        .novel_id;
+
+        let cancel = AtomicBool::new(false);
+        let sink = CancelAfterCommit {
+            cancel: &cancel,
+            novel_id: Default::default(),
+        };
+        let control = ImportControl {
+            sink: &sink,
+            cancel: &cancel,
+        };
+        let result = append_novel(
+            &db,
+            &novel_id,
+            BookInfo::new("测试"),
+            SourceType::TxtFiles(vec!["2.txt".to_string()]),
+            ImportOptions::default(),
+            numbered_chapters(CHAPTERS_PER_COMMIT * 2),
+            control,
+        );
+        assert!(result.is_err());
+        let titles: Vec<String> = db
+            .list_chapter_metas(&novel_id)
+            .unwrap()
+            .into_iter()
+            .map(|m| m.title)
+            .collect();
+        assert_eq!(titles, vec!["序章"]);
+        let novel = db.load_novel(&novel_id).unwrap();
+        assert!(matches!(novel.source_type, SourceType::TxtFiles(paths) if paths.len() == 1));
+        assert_eq!(db.list_novels().unwrap().len(), 1);
+    }
+}
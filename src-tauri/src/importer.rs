@@ -0,0 +1,399 @@
+use crate::html_text::{ExtractOptions, ExtractedText};
+use crate::models::{BookInfo, HeadingRuleSet, ParsedChapter, PreviewChapter, SourceType};
+use crate::{
+    docx_parser, epub_parser, fb2_parser, fingerprint, html_parser, mobi_parser, txt_parser,
+};
+use std::path::Path;
+
+/// A book format imported through the preview-and-select flow. Importers return every
+/// chapter they find; which ones are suggested for import is decided here for all formats.
+pub trait Importer {
+    /// Parse the file (or folder) into the book's metadata and all of its chapters.
+    fn parse(
+        &self,
+        path: &str,
+        options: ExtractOptions,
+    ) -> Result<(BookInfo, Vec<ParsedChapter>), String>;
+
+    /// The source recorded on a novel imported from `path`, used to resync it later.
+    fn source_type(&self, path: &str) -> SourceType;
+}
+
+/// Keywords that indicate a page is metadata/boilerplate, not actual chapter content.
+const SKIP_KEYWORDS: &[&str] = &[
+    "版权",
+    "copyright",
+    "ISBN",
+    "出版社",
+    "制作信息",
+    "作者简介",
+    "内容简介",
+    "编辑推荐",
+    "封面",
+    "Cover",
+    "扉页",
+    "前折页",
+    "后折页",
+    "All Rights Reserved",
+    "all rights reserved",
+    "本书由",
+    "授权",
+    "版次",
+    "印次",
+    "译序",
+    "译者",
+    "校对",
+];
+
+/// Longest first paragraph still taken for a heading's title.
+const MAX_TITLE_CHARS: usize = 60;
+
+/// Pick the importer for a file by its extension: EPUB, MOBI/AZW/AZW3, FB2 (also zipped as
+/// .fbz or .fb2.zip), DOCX, HTML or Markdown. A folder is imported as one chapter per
+/// Markdown or HTML file in it.
+pub fn importer_for(path: &str) -> Result<Box<dyn Importer>, String> {
+    let p = Path::new(path);
+    if p.is_dir() {
+        return html_parser::folder_importer(p);
+    }
+    let name = p
+        .file_name()
+        .and_then(|n| n.to_str())
+        .unwrap_or_default()
+        .to_lowercase();
+    let extension = name.rsplit('.').next().unwrap_or_default();
+    let importer: Box<dyn Importer> = match extension {
+        "epub" => Box::new(epub_parser::EpubImporter),
+        "mobi" | "azw" | "azw3" | "prc" => Box::new(mobi_parser::MobiImporter),
+        "fb2" | "fbz" => Box::new(fb2_parser::Fb2Importer),
+        "zip" if name.ends_with(".fb2.zip") => Box::new(fb2_parser::Fb2Importer),
+        "docx" => Box::new(docx_parser::DocxImporter),
+        "html" | "htm" | "xhtml" => Box::new(html_parser::HtmlImporter { markdown: false }),
+        "md" | "markdown" => Box::new(html_parser::HtmlImporter { markdown: true }),
+        _ => return Err(format!("不支持的文件格式: {}", path)),
+    };
+    Ok(importer)
+}
+
+/// Whether `path` can be imported through [`importer_for`] (TXT files have their own flow).
+pub fn is_supported(path: &str) -> bool {
+    importer_for(path).is_ok()
+}
+
+/// Parse a book and return a preview of all chapters for user selection.
+/// Returns (book, preview_chapters).
+pub fn preview(
+    path: &str,
+    options: ExtractOptions,
+) -> Result<(BookInfo, Vec<PreviewChapter>), String> {
+    let (book, chapters) = importer_for(path)?.parse(path, options)?;
+
+    let previews = chapters
+        .iter()
+        .enumerate()
+        .map(|(i, ch)| {
+            let char_count = ch.content.chars().count();
+            PreviewChapter {
+                index: i,
+                title: ch.title.clone(),
+                char_count,
+                suggested: is_suggested(ch),
+                volume: ch.volume.clone(),
+                simhash: fingerprint::simhash(&ch.content),
+            }
+        })
+        .collect();
+
+    Ok((book, previews))
+}
+
+/// Parse a book and return its metadata and cover with only the selected chapters (by index),
+/// along with the source to record on the novel and how many chapters the book has.
+pub fn parse_selected(
+    path: &str,
+    selected_indices: &[usize],
+    options: ExtractOptions,
+) -> Result<(BookInfo, SourceType, Vec<ParsedChapter>, usize), String> {
+    let importer = importer_for(path)?;
+    let (book, chapters) = importer.parse(path, options)?;
+    let known = chapters.len();
+
+    let chapters: Vec<ParsedChapter> = chapters
+        .into_iter()
+        .enumerate()
+        .filter(|(i, _)| selected_indices.contains(i))
+        .map(|(_, ch)| ch)
+        .collect();
+
+    if chapters.is_empty() {
+        return Err("没有选择任何章节".to_string());
+    }
+
+    Ok((book, importer.source_type(path), chapters, known))
+}
+
+/// Parse a book again keeping the chapters selected when it had `known` chapters. Chapters
+/// past those, in a newer edition, are kept when a preview would suggest them.
+pub fn parse_reselected(
+    path: &str,
+    selected_indices: &[usize],
+    known: usize,
+    options: ExtractOptions,
+) -> Result<(BookInfo, Vec<ParsedChapter>), String> {
+    let (book, chapters) = importer_for(path)?.parse(path, options)?;
+    let chapters: Vec<ParsedChapter> = chapters
+        .into_iter()
+        .enumerate()
+        .filter(|(i, ch)| selected_indices.contains(i) || (*i >= known && is_suggested(ch)))
+        .map(|(_, ch)| ch)
+        .collect();
+    if chapters.is_empty() {
+        return Err("没有可导入的章节".to_string());
+    }
+    Ok((book, chapters))
+}
+
+/// Parse a book keeping only the chapters a preview suggests by default.
+pub fn parse_suggested(
+    path: &str,
+    options: ExtractOptions,
+) -> Result<(BookInfo, SourceType, Vec<ParsedChapter>), String> {
+    let importer = importer_for(path)?;
+    let (book, mut chapters) = importer.parse(path, options)?;
+    chapters.retain(is_suggested);
+    if chapters.is_empty() {
+        return Err("没有可导入的章节".to_string());
+    }
+    Ok((book, importer.source_type(path), chapters))
+}
+
+fn is_suggested(chapter: &ParsedChapter) -> bool {
+    chapter.content.chars().count() >= 100 && !is_metadata_page(&chapter.content)
+}
+
+/// Title of a book without one in its metadata: the file or folder name.
+pub(crate) fn title_from_path(path: &str) -> String {
+    Path::new(path)
+        .file_stem()
+        .and_then(|s| s.to_str())
+        .map(|s| s.trim_end_matches(".fb2"))
+        .unwrap_or("未知书名")
+        .to_string()
+}
+
+/// Text under one heading of a document, the heading being the first paragraph of its body.
+pub(crate) struct HeadedSection {
+    /// Heading level, 1 being the outermost; `None` for the text before the first heading
+    pub level: Option<usize>,
+    pub text: ExtractedText,
+}
+
+/// Turn a document split at its headings into chapters. The chapter level is the one with
+/// the most headings (the outer one on a tie): outer headings name volumes, and deeper ones
+/// stay inside their chapter. A single outer heading opening the document is the book's
+/// title and is returned apart.
+///
+/// When no heading level repeats, the text is split with [`split_plain`] instead.
+pub(crate) fn split_at_headings(
+    sections: Vec<HeadedSection>,
+    fallback_title: &str,
+) -> (Option<String>, Vec<ParsedChapter>) {
+    let mut counts = [0usize; 10];
+    for level in sections.iter().filter_map(|s| s.level) {
+        counts[level.min(9)] += 1;
+    }
+    let chapter_level = (1..=9)
+        .filter(|&level| counts[level] >= 2)
+        .min_by_key(|&level| (std::cmp::Reverse(counts[level]), level));
+    let Some(chapter_level) = chapter_level else {
+        let mut text = ExtractedText::default();
+        for section in sections {
+            if !text.body.is_empty() {
+                text.body.push_str("\n\n");
+            }
+            text.body.push_str(section.text.body.trim());
+            text.notes.extend(section.text.notes);
+        }
+        return (None, split_plain(text, fallback_title));
+    };
+    let count = |level: usize| counts[level.min(9)];
+
+    let mut book_title = None;
+    let mut volumes: Vec<(usize, String)> = Vec::new();
+    let mut chapters: Vec<ParsedChapter> = Vec::new();
+    for section in sections {
+        let level = section.level;
+        let (heading, body) = split_heading(&section.text.body);
+        let volume = |volumes: &[(usize, String)]| {
+            let names: Vec<&str> = volumes.iter().map(|(_, name)| name.as_str()).collect();
+            (!names.is_empty()).then(|| names.join(" - "))
+        };
+        let push = |chapters: &mut Vec<ParsedChapter>, title: Option<String>, volume| {
+            if body.is_empty() {
+                return;
+            }
+            let (title, content) = match title {
+                Some(title) => (title, body.clone()),
+                // Too long for a heading: keep the paragraph as text.
+                None => (
+                    format!("第 {} 节", chapters.len() + 1),
+                    section.text.body.trim().to_string(),
+                ),
+            };
+            chapters.push(ParsedChapter {
+                volume,
+                notes: section.text.notes.clone(),
+                ..ParsedChapter::new(title, content)
+            });
+        };
+
+        match level {
+            Some(l) if l > chapter_level && !chapters.is_empty() => {
+                let last = chapters.last_mut().expect("a chapter");
+                last.content.push_str("\n\n");
+                last.content.push_str(section.text.body.trim());
+                last.notes.extend(section.text.notes);
+            }
+            Some(l) if l < chapter_level => {
+                let name = heading.unwrap_or_else(|| format!("第 {} 部分", volumes.len() + 1));
+                if chapters.is_empty() && volumes.is_empty() && count(l) == 1 {
+                    book_title = Some(name.clone());
+                    push(&mut chapters, Some(name), None);
+                    continue;
+                }
+                volumes.retain(|(level, _)| *level < l);
+                volumes.push((l, name.clone()));
+                push(&mut chapters, Some(name), volume(&volumes));
+            }
+            Some(_) => push(&mut chapters, heading, volume(&volumes)),
+            None => {
+                let title = format!("第 {} 节", chapters.len() + 1);
+                let text = section.text.body.trim();
+                if !text.is_empty() {
+                    chapters.push(ParsedChapter {
+                        notes: section.text.notes,
+                        ..ParsedChapter::new(title, text)
+                    });
+                }
+            }
+        }
+    }
+    (book_title, chapters)
+}
+
+/// Split off the first paragraph of a section as its heading, unless it's too long to be one.
+pub(crate) fn split_heading(body: &str) -> (Option<String>, String) {
+    let body = body.trim();
+    let (first, rest) = body.split_once("\n\n").unwrap_or((body, ""));
+    let title = first.replace('\n', " ");
+    if title.is_empty() || title.chars().count() > MAX_TITLE_CHARS {
+        return (None, body.to_string());
+    }
+    (Some(title), rest.trim().to_string())
+}
+
+/// Split text without heading markup at lines that look like chapter headings, the way a
+/// TXT import does with the preset rules. Text no rule set fits stays one chapter titled
+/// `title`. Notes can't be placed in a chapter any more and go with the last one, where
+/// endnotes would be.
+pub(crate) fn split_plain(text: ExtractedText, title: &str) -> Vec<ParsedChapter> {
+    let sets = HeadingRuleSet::presets();
+    let mut chapters = txt_parser::detect_heading_rules(&text.body, &sets)
+        .and_then(|rules| txt_parser::split_by_chapters(&text.body, rules).ok())
+        .unwrap_or_default();
+    if chapters.is_empty() {
+        chapters.push(ParsedChapter::new(title, text.body.trim()));
+    }
+    if let Some(last) = chapters.last_mut() {
+        last.notes = text.notes;
+    }
+    chapters
+}
+
+/// Check if a page is metadata/boilerplate rather than actual story content.
+/// Uses keyword density: if multiple skip keywords appear in a short page, it's likely metadata.
+fn is_metadata_page(text: &str) -> bool {
+    let lower = text.to_lowercase();
+    let text_len = text.chars().count();
+
+    // Count how many skip keywords appear
+    let keyword_hits: usize = SKIP_KEYWORDS
+        .iter()
+        .filter(|kw| lower.contains(&kw.to_lowercase()))
+        .count();
+
+    // For short pages (< 500 chars), even 2 keyword hits is suspicious
+    if text_len < 500 && keyword_hits >= 2 {
+        return true;
+    }
+
+    // For medium pages (< 1500 chars), need 3+ hits
+    if text_len < 1500 && keyword_hits >= 3 {
+        return true;
+    }
+
+    // Check if the page is structured like a metadata block:
+    // many short lines (key-value pairs) rather than prose paragraphs
+    let lines: Vec<&str> = text.lines().filter(|l| !l.trim().is_empty()).collect();
+    if lines.len() >= 3 {
+        let short_lines = lines
+            .iter()
+            .filter(|l| l.trim().chars().count() < 30)
+            .count();
+        let short_ratio = short_lines as f64 / lines.len() as f64;
+        // If >70% of lines are very short AND we have at least 1 keyword hit, it's metadata
+        if short_ratio > 0.7 && keyword_hits >= 1 && text_len < 2000 {
+            return true;
+        }
+    }
+
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn section(level: Option<usize>, body: &str) -> HeadedSection {
+        HeadedSection {
+            level,
+            text: ExtractedText {
+                body: body.to_string(),
+                notes: Vec::new(),
+            },
+        }
+    }
+
+    #[test]
+    fn test_split_at_headings() {
+        let sections = vec![
+            section(Some(1), "书名"),
+            section(Some(2), "第一部"),
+            section(Some(3), "第一章\n\n甲。"),
+            section(Some(4), "一\n\n乙。"),
+            section(Some(3), "第二章\n\n丙。"),
+            section(Some(2), "第二部\n\n引言。"),
+            section(Some(3), "第三章\n\n丁。"),
+        ];
+        let (title, chapters) = split_at_headings(sections, "书");
+        assert_eq!(title.as_deref(), Some("书名"));
+        let got: Vec<(&str, &str, Option<&str>)> = chapters
+            .iter()
+            .map(|c| (c.title.as_str(), c.content.as_str(), c.volume.as_deref()))
+            .collect();
+        assert_eq!(
+            got,
+            vec![
+                ("第一章", "甲。\n\n一\n\n乙。", Some("第一部")),
+                ("第二章", "丙。", Some("第一部")),
+                ("第二部", "引言。", Some("第二部")),
+                ("第三章", "丁。", Some("第二部")),
+            ]
+        );
+
+        let (title, chapters) = split_at_headings(vec![section(Some(1), "书名\n\n正文。")], "书");
+        assert_eq!(title, None);
+        assert_eq!(chapters[0].title, "书");
+        assert_eq!(chapters[0].content, "书名\n\n正文。");
+    }
+}
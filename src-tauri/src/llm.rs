@@ -1,15 +1,43 @@
-use crate::models::LlmConfig;
-use crate::token_utils::estimate_tokens;
+use crate::models::{LlmConfig, ProgressEvent, RetryClass, RetryConfig, StreamingChunk};
+use crate::progress::ProgressSink;
+use crate::token_utils;
 use async_openai::{
     config::OpenAIConfig,
+    error::OpenAIError,
     types::{
         ChatCompletionRequestMessage, ChatCompletionRequestSystemMessage,
-        ChatCompletionRequestUserMessage, CreateChatCompletionRequestArgs,
+        ChatCompletionRequestUserMessage, CompletionUsage, CreateChatCompletionRequestArgs,
     },
     Client,
 };
+use backoff::ExponentialBackoffBuilder;
 use futures::StreamExt;
-use tauri::Emitter;
+use regex::Regex;
+use std::future::Future;
+use std::sync::LazyLock;
+use std::time::Duration;
+
+/// The HTTP status in async-openai's stream errors, e.g. "Invalid status code: 502".
+static STREAM_STATUS: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?i)invalid status code:?\s*(\d{3})").unwrap());
+static RETRY_AFTER: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(
+        r"(?i)(?:retry[- ]after|try again in)[:\s]*([\d.]+)\s*(ms|milliseconds?|s|sec|seconds?)?",
+    )
+    .unwrap()
+});
+/// Error types and codes of requests that would fail the same way when sent again.
+const CLIENT_ERRORS: &[&str] = &[
+    "insufficient_quota",
+    "billing",
+    "invalid_request",
+    "invalid_api_key",
+    "authentication",
+    "permission",
+    "not_found",
+    "context_length",
+    "unsupported",
+];
 
 /// List available models from an OpenAI-compatible API.
 pub async fn list_models(config: &LlmConfig) -> Result<Vec<String>, String> {
@@ -52,28 +80,265 @@ pub async fn list_models(config: &LlmConfig) -> Result<Vec<String>, String> {
     Ok(model_ids)
 }
 
+/// Text of a successful API call plus what is known about how it went.
+pub struct LlmReply {
+    pub content: String,
+    /// Attempts used, including the successful one.
+    pub attempts: u32,
+    /// Token usage as reported by the API, when it reports it.
+    pub prompt_tokens: Option<u32>,
+    pub completion_tokens: Option<u32>,
+}
+
+/// An API call that failed for good, after `attempts` tries.
+#[derive(Debug)]
+pub struct LlmFailure {
+    pub message: String,
+    pub attempts: u32,
+}
+
+impl LlmFailure {
+    /// A failure before anything was sent.
+    fn before_request(message: String) -> Self {
+        Self {
+            message,
+            attempts: 0,
+        }
+    }
+}
+
+impl std::fmt::Display for LlmFailure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl From<LlmFailure> for String {
+    fn from(e: LlmFailure) -> Self {
+        e.message
+    }
+}
+
+/// A failed API call: the message shown to the user and, when worth retrying, why.
+struct CallError {
+    message: String,
+    retry: Option<RetryHint>,
+}
+
+struct RetryHint {
+    class: RetryClass,
+    /// Delay asked for by the server, if the error carries one.
+    after: Option<Duration>,
+}
+
+impl CallError {
+    fn fatal(message: String) -> Self {
+        Self {
+            message,
+            retry: None,
+        }
+    }
+
+    /// `stream` tells whether the error came from a streamed call.
+    fn from_openai(prefix: &str, e: OpenAIError, stream: bool) -> Self {
+        Self {
+            retry: classify_error(&e, stream),
+            message: format!("{}: {}", prefix, e),
+        }
+    }
+}
+
+/// Decide whether an async-openai error is transient. async-openai does not hand out
+/// response headers, so a `Retry-After` value is only seen when it reaches the error text.
+/// Nor does it keep the HTTP status of a failed plain call: a gateway's HTML error page comes
+/// back as a JSON deserialization error and a JSON one as an `ApiError` of any type. Those
+/// are taken as server errors unless the error names a problem with the request itself.
+fn classify_error(e: &OpenAIError, stream: bool) -> Option<RetryHint> {
+    let class = match e {
+        OpenAIError::Reqwest(re) => match re.status() {
+            Some(status) => status_class(status.as_u16())?,
+            None if re.is_timeout() || re.is_connect() || re.is_request() || re.is_body() => {
+                RetryClass::Network
+            }
+            None => return None,
+        },
+        OpenAIError::ApiError(api) => {
+            let kind = format!(
+                "{} {} {}",
+                api.r#type.as_deref().unwrap_or(""),
+                api.code.as_deref().unwrap_or(""),
+                api.message
+            )
+            .to_lowercase();
+            if kind.contains("insufficient_quota") {
+                return None;
+            } else if kind.contains("rate limit") || kind.contains("rate_limit") {
+                RetryClass::RateLimit
+            } else if CLIENT_ERRORS.iter().any(|k| kind.contains(k)) {
+                return None;
+            } else if kind.contains("server_error")
+                || kind.contains("overloaded")
+                || kind.contains("server is busy")
+                || !stream
+            {
+                RetryClass::ServerError
+            } else {
+                return None;
+            }
+        }
+        OpenAIError::JSONDeserialize(_) if !stream => RetryClass::ServerError,
+        OpenAIError::StreamError(msg) => {
+            let status = STREAM_STATUS
+                .captures(msg)
+                .and_then(|c| c[1].parse::<u16>().ok());
+            match status {
+                Some(code) => status_class(code)?,
+                None => RetryClass::Network,
+            }
+        }
+        _ => return None,
+    };
+
+    Some(RetryHint {
+        class,
+        after: parse_retry_after(&e.to_string()),
+    })
+}
+
+fn status_class(status: u16) -> Option<RetryClass> {
+    match status {
+        429 => Some(RetryClass::RateLimit),
+        500..=599 => Some(RetryClass::ServerError),
+        408 => Some(RetryClass::Network),
+        _ => None,
+    }
+}
+
+/// Pull a server-requested delay out of an error message, e.g. "Retry-After: 20"
+/// or "Please try again in 1.5s".
+fn parse_retry_after(msg: &str) -> Option<Duration> {
+    let caps = RETRY_AFTER.captures(msg)?;
+    let value: f64 = caps[1].parse().ok()?;
+    let millis = match caps.get(2).map(|m| m.as_str().to_lowercase()) {
+        Some(unit) if unit.starts_with("ms") || unit.starts_with("milli") => value,
+        _ => value * 1000.0,
+    };
+    Some(Duration::from_millis(millis as u64).min(Duration::from_secs(600)))
+}
+
+/// Exponential backoff for the given retry (1-based), capped at `max_delay_ms`. Up to 25%
+/// is taken off at random so concurrent chapters do not retry in lockstep, even once they
+/// all reach the cap.
+fn backoff_delay(retry: &RetryConfig, n: u32) -> Duration {
+    let delay = retry
+        .base_delay_ms
+        .saturating_mul(1u64 << (n - 1).min(20))
+        .min(retry.max_delay_ms);
+    let jitter = fastrand::u64(0..=delay / 4);
+    Duration::from_millis(delay - jitter)
+}
+
+/// Run an API call, retrying the error classes enabled in the config and reporting
+/// every retry as a "retrying" progress event.
+async fn with_retry<F, Fut>(
+    config: &LlmConfig,
+    sink: &dyn ProgressSink,
+    novel_id: &str,
+    chapter_id: Option<i64>,
+    mut call: F,
+) -> Result<LlmReply, LlmFailure>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<(String, Option<CompletionUsage>), CallError>>,
+{
+    let max_attempts = config.retry.max_attempts.max(1);
+    let mut attempt = 1;
+    loop {
+        let err = match call().await {
+            Ok((content, usage)) => {
+                return Ok(LlmReply {
+                    content,
+                    attempts: attempt,
+                    prompt_tokens: usage.as_ref().map(|u| u.prompt_tokens),
+                    completion_tokens: usage.as_ref().map(|u| u.completion_tokens),
+                })
+            }
+            Err(e) => e,
+        };
+        let hint = match err.retry {
+            Some(hint) if config.retry.retry_on.contains(&hint.class) => hint,
+            _ => {
+                return Err(LlmFailure {
+                    message: err.message,
+                    attempts: attempt,
+                })
+            }
+        };
+        if attempt >= max_attempts {
+            return Err(LlmFailure {
+                message: format!("{} (已重试 {} 次)", err.message, attempt - 1),
+                attempts: attempt,
+            });
+        }
+
+        let delay = hint
+            .after
+            .unwrap_or_else(|| backoff_delay(&config.retry, attempt));
+        sink.analysis_progress(ProgressEvent {
+            novel_id: novel_id.to_string(),
+            chapter_id,
+            status: "retrying".to_string(),
+            current: attempt as usize,
+            total: (max_attempts - 1) as usize,
+            message: format!(
+                "{}，{:.1} 秒后重试 ({}/{})",
+                err.message,
+                delay.as_secs_f32(),
+                attempt,
+                max_attempts - 1
+            ),
+            report: None,
+        });
+        tokio::time::sleep(delay).await;
+        attempt += 1;
+    }
+}
+
+fn build_client(config: &LlmConfig) -> Client<OpenAIConfig> {
+    let openai_config = OpenAIConfig::new()
+        .with_api_base(&config.base_url)
+        .with_api_key(&config.api_key);
+
+    // Retries are handled by `with_retry`; turn off async-openai's own rate-limit loop.
+    Client::with_config(openai_config).with_backoff(
+        ExponentialBackoffBuilder::new()
+            .with_max_elapsed_time(Some(Duration::ZERO))
+            .build(),
+    )
+}
+
 /// Call an OpenAI-compatible API with the given prompt.
 pub async fn call_api(
     config: &LlmConfig,
     prompt: &str,
+    sink: &dyn ProgressSink,
+    novel_id: &str,
     max_output: Option<u32>,
-) -> Result<String, String> {
+) -> Result<LlmReply, LlmFailure> {
     // Token check
-    let prompt_tokens = estimate_tokens(prompt);
+    let prompt_tokens = token_utils::tokenizer_for(config)
+        .map_err(LlmFailure::before_request)?
+        .count(prompt);
     let max_tokens = config.max_context_tokens as usize;
     if prompt_tokens > max_tokens {
-        return Err(format!(
+        return Err(LlmFailure::before_request(format!(
             "Prompt 预估 {} tokens，超过模型上限 {} tokens。请减少分析维度或使用更大上下文的模型。",
             prompt_tokens, max_tokens
-        ));
+        )));
     }
 
     // Configure client
-    let openai_config = OpenAIConfig::new()
-        .with_api_base(&config.base_url)
-        .with_api_key(&config.api_key);
-
-    let client = Client::with_config(openai_config);
+    let client = build_client(config);
 
     // Build request
     let mut builder = CreateChatCompletionRequestArgs::default();
@@ -100,47 +365,50 @@ pub async fn call_api(
 
     let request = builder
         .build()
-        .map_err(|e| format!("构建请求失败: {}", e))?;
+        .map_err(|e| LlmFailure::before_request(format!("构建请求失败: {}", e)))?;
 
     // Call API
-    let response = client
-        .chat()
-        .create(request)
-        .await
-        .map_err(|e| format!("API 调用失败: {}", e))?;
+    with_retry(config, sink, novel_id, None, || async {
+        let response = client
+            .chat()
+            .create(request.clone())
+            .await
+            .map_err(|e| CallError::from_openai("API 调用失败", e, false))?;
 
-    // Extract content
-    let content = response
-        .choices
-        .first()
-        .and_then(|c| c.message.content.as_ref())
-        .ok_or_else(|| "API 返回为空".to_string())?
-        .clone();
+        // Extract content
+        let content = response
+            .choices
+            .first()
+            .and_then(|c| c.message.content.as_ref())
+            .ok_or_else(|| CallError::fatal("API 返回为空".to_string()))?
+            .clone();
 
-    Ok(content)
+        Ok((content, response.usage))
+    })
+    .await
 }
 
-/// Call API with streaming, emitting partial content via Tauri events.
+/// Call API with streaming, reporting partial content to the progress sink.
 pub async fn call_api_stream(
     config: &LlmConfig,
     prompt: &str,
-    app: &tauri::AppHandle,
+    sink: &dyn ProgressSink,
+    novel_id: &str,
     chapter_id: i64,
     max_output: Option<u32>,
-) -> Result<String, String> {
-    let prompt_tokens = estimate_tokens(prompt);
+) -> Result<LlmReply, LlmFailure> {
+    let prompt_tokens = token_utils::tokenizer_for(config)
+        .map_err(LlmFailure::before_request)?
+        .count(prompt);
     let max_tokens = config.max_context_tokens as usize;
     if prompt_tokens > max_tokens {
-        return Err(format!(
+        return Err(LlmFailure::before_request(format!(
             "Prompt 预估 {} tokens，超过模型上限 {} tokens。",
             prompt_tokens, max_tokens
-        ));
+        )));
     }
 
-    let openai_config = OpenAIConfig::new()
-        .with_api_base(&config.base_url)
-        .with_api_key(&config.api_key);
-    let client = Client::with_config(openai_config);
+    let client = build_client(config);
 
     let mut builder = CreateChatCompletionRequestArgs::default();
     builder
@@ -165,42 +433,137 @@ pub async fn call_api_stream(
 
     let request = builder
         .build()
-        .map_err(|e| format!("构建请求失败: {}", e))?;
+        .map_err(|e| LlmFailure::before_request(format!("构建请求失败: {}", e)))?;
 
-    let mut stream = client
-        .chat()
-        .create_stream(request)
-        .await
-        .map_err(|e| format!("API 流式调用失败: {}", e))?;
-
-    let mut full_content = String::new();
-
-    while let Some(result) = stream.next().await {
-        match result {
-            Ok(response) => {
-                for choice in &response.choices {
-                    if let Some(ref content) = choice.delta.content {
-                        full_content.push_str(content);
-                        let _ = app.emit(
-                            "analysis_streaming",
-                            serde_json::json!({
-                                "chapter_id": chapter_id,
-                                "chunk": content,
-                                "full_content": full_content,
-                            }),
-                        );
+    with_retry(config, sink, novel_id, Some(chapter_id), || async {
+        let mut stream = client
+            .chat()
+            .create_stream(request.clone())
+            .await
+            .map_err(|e| CallError::from_openai("API 流式调用失败", e, true))?;
+
+        // A retry starts the response over, so the streamed text is rebuilt from scratch.
+        let mut full_content = String::new();
+        // Only sent by providers that report usage on streams (usually in the last chunk).
+        let mut usage = None;
+
+        while let Some(result) = stream.next().await {
+            match result {
+                Ok(response) => {
+                    if response.usage.is_some() {
+                        usage = response.usage;
+                    }
+                    for choice in &response.choices {
+                        if let Some(ref content) = choice.delta.content {
+                            full_content.push_str(content);
+                            sink.streaming(StreamingChunk {
+                                chapter_id,
+                                chunk: content.clone(),
+                                full_content: full_content.clone(),
+                            });
+                        }
                     }
                 }
+                Err(e) => {
+                    return Err(CallError::from_openai("流式响应出错", e, true));
+                }
             }
-            Err(e) => {
-                return Err(format!("流式响应出错: {}", e));
-            }
         }
+
+        if full_content.is_empty() {
+            return Err(CallError::fatal("API 返回为空".to_string()));
+        }
+
+        Ok((full_content, usage))
+    })
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_openai::error::ApiError;
+
+    #[test]
+    fn test_parse_retry_after() {
+        assert_eq!(
+            parse_retry_after("Rate limit reached. Please try again in 1.5s."),
+            Some(Duration::from_millis(1500))
+        );
+        assert_eq!(
+            parse_retry_after("try again in 20ms"),
+            Some(Duration::from_millis(20))
+        );
+        assert_eq!(
+            parse_retry_after("Retry-After: 30"),
+            Some(Duration::from_secs(30))
+        );
+        assert_eq!(parse_retry_after("bad request"), None);
+    }
+
+    #[test]
+    fn test_classify_stream_status() {
+        let e = OpenAIError::StreamError("Invalid status code: 429 Too Many Requests".to_string());
+        assert_eq!(
+            classify_error(&e, true).unwrap().class,
+            RetryClass::RateLimit
+        );
+
+        let e = OpenAIError::StreamError("Invalid status code: 502 Bad Gateway".to_string());
+        assert_eq!(
+            classify_error(&e, true).unwrap().class,
+            RetryClass::ServerError
+        );
+
+        let e = OpenAIError::StreamError("Invalid status code: 401 Unauthorized".to_string());
+        assert!(classify_error(&e, true).is_none());
+
+        let e = OpenAIError::StreamError("Transport error: connection reset".to_string());
+        assert_eq!(classify_error(&e, true).unwrap().class, RetryClass::Network);
     }
 
-    if full_content.is_empty() {
-        return Err("API 返回为空".to_string());
+    #[test]
+    fn test_classify_plain_call_errors() {
+        // A gateway's HTML error page fails to deserialize.
+        let html = serde_json::from_str::<serde_json::Value>("<html>502 Bad Gateway</html>");
+        let e = OpenAIError::JSONDeserialize(html.unwrap_err());
+        assert_eq!(
+            classify_error(&e, false).unwrap().class,
+            RetryClass::ServerError
+        );
+
+        let api_error = |kind: &str| {
+            OpenAIError::ApiError(ApiError {
+                message: "error".to_string(),
+                r#type: Some(kind.to_string()),
+                param: None,
+                code: None,
+            })
+        };
+        let e = api_error("service_unavailable_error");
+        assert_eq!(
+            classify_error(&e, false).unwrap().class,
+            RetryClass::ServerError
+        );
+        assert!(classify_error(&e, true).is_none());
+        assert!(classify_error(&api_error("invalid_request_error"), false).is_none());
+        assert!(classify_error(&api_error("insufficient_quota"), false).is_none());
     }
 
-    Ok(full_content)
+    #[test]
+    fn test_backoff_delay_grows_and_caps() {
+        let retry = RetryConfig {
+            base_delay_ms: 1000,
+            max_delay_ms: 5000,
+            ..RetryConfig::default()
+        };
+        for _ in 0..100 {
+            let first = backoff_delay(&retry, 1).as_millis();
+            let third = backoff_delay(&retry, 3).as_millis();
+            let tenth = backoff_delay(&retry, 10).as_millis();
+            assert!((750..=1000).contains(&first));
+            assert!((3000..=4000).contains(&third));
+            assert!((3750..=5000).contains(&tenth));
+        }
+    }
 }
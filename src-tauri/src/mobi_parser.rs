@@ -0,0 +1,105 @@
+use crate::html_text::{self, ExtractOptions};
+use crate::importer::{self, HeadedSection, Importer};
+use crate::models::{BookInfo, BookMetadata, CoverImage, ParsedChapter, SourceType};
+use mobi::headers::{Encryption, ExthRecord};
+use mobi::Mobi;
+
+/// EXTH record holding the book's language, e.g. "zh" or "en-us".
+const EXTH_LANGUAGE: u32 = 524;
+
+/// Kindle MOBI, AZW and AZW3 books without DRM. The text is split at its HTML headings, or,
+/// as most Kindle books have none, at lines that look like chapter headings.
+pub struct MobiImporter;
+
+impl Importer for MobiImporter {
+    fn parse(
+        &self,
+        path: &str,
+        options: ExtractOptions,
+    ) -> Result<(BookInfo, Vec<ParsedChapter>), String> {
+        let book = Mobi::from_path(path).map_err(|e| format!("无法打开 MOBI 文件: {}", e))?;
+        if !matches!(book.encryption(), Encryption::No) {
+            return Err("该 MOBI/AZW 文件有 DRM 加密，无法导入".to_string());
+        }
+        let html = book.content_as_string_lossy();
+
+        let fallback_title = importer::title_from_path(path);
+        let sections = html_text::extract_at_headings(&html, options)
+            .into_iter()
+            .map(|section| HeadedSection {
+                level: section.anchor.as_deref().and_then(html_text::heading_level),
+                text: section.text,
+            })
+            .collect();
+        let (_, chapters) = importer::split_at_headings(sections, &fallback_title);
+        if chapters.iter().all(|c| c.content.trim().is_empty()) {
+            return Err("未能从 MOBI 中提取到任何内容".to_string());
+        }
+
+        let title = Some(book.title())
+            .map(|t| t.trim().to_string())
+            .filter(|t| !t.is_empty())
+            .unwrap_or(fallback_title);
+        let info = BookInfo {
+            title,
+            metadata: read_metadata(&book),
+            cover: read_cover(&book),
+        };
+        Ok((info, chapters))
+    }
+
+    fn source_type(&self, path: &str) -> SourceType {
+        SourceType::Mobi(path.to_string())
+    }
+}
+
+fn read_metadata(book: &Mobi) -> BookMetadata {
+    let clean = |value: Option<String>| {
+        value
+            .map(|v| {
+                v.trim_matches(|c: char| c.is_whitespace() || c == '\0')
+                    .to_string()
+            })
+            .filter(|v| !v.is_empty())
+    };
+    let language = book
+        .metadata
+        .exth
+        .get_record_position(EXTH_LANGUAGE)
+        .and_then(|values| values.first())
+        .map(|v| String::from_utf8_lossy(v).to_string());
+    BookMetadata {
+        author: clean(book.author()),
+        language: clean(language),
+        publisher: clean(book.publisher()),
+        identifiers: clean(book.isbn()).into_iter().collect(),
+        ..BookMetadata::default()
+    }
+}
+
+/// The image record the EXTH cover offset points at, counted from the first image record.
+fn read_cover(book: &Mobi) -> Option<CoverImage> {
+    let offset = book
+        .metadata
+        .exth
+        .get_record(ExthRecord::CoverOffset)?
+        .first()?;
+    let offset = u32::from_be_bytes(offset.get(..4)?.try_into().ok()?) as usize;
+    let first_image = book.metadata.mobi.first_image_index as usize;
+    let records = book.raw_records();
+    let data = records
+        .range(first_image + offset..)
+        .first()?
+        .content
+        .to_vec();
+    let mime = match data.get(..4)? {
+        [0xFF, 0xD8, ..] => "image/jpeg",
+        [0x89, b'P', b'N', b'G'] => "image/png",
+        [b'G', b'I', b'F', _] => "image/gif",
+        _ => return None,
+    };
+    Some(CoverImage {
+        data,
+        mime: mime.to_string(),
+    })
+}
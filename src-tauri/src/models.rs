@@ -1,12 +1,226 @@
 use serde::{Deserialize, Serialize};
 
-// ---- EPUB Preview ----
+// ---- Book Preview ----
 
+/// Preview of an EPUB, MOBI, FB2, DOCX, HTML or Markdown import.
 #[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct EpubPreview {
+pub struct BookPreview {
     pub title: String,
     pub path: String,
-    pub chapters: Vec<crate::epub_parser::EpubPreviewChapter>,
+    pub chapters: Vec<PreviewChapter>,
+    /// Novels in the library that already seem to be this book.
+    pub library_matches: Vec<LibraryMatch>,
+}
+
+/// A novel in the library that the book being previewed seems to be already.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LibraryMatch {
+    pub novel_id: String,
+    pub title: String,
+    /// Previewed chapters with the same or nearly the same text as a chapter of this novel.
+    pub matching_chapters: usize,
+    /// The book shares an ISBN or other identifier with this novel.
+    pub same_identifier: bool,
+}
+
+/// A preview chapter returned to the frontend for user selection.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PreviewChapter {
+    pub index: usize,
+    pub title: String,
+    pub char_count: usize,
+    /// Whether this chapter is suggested for import (true = real content, false = metadata/boilerplate)
+    pub suggested: bool,
+    /// The TOC entry or heading this chapter is nested under, if any
+    pub volume: Option<String>,
+    /// For checking whether the library has the book already
+    #[serde(skip)]
+    pub simhash: Option<u64>,
+}
+
+// ---- TXT Preview ----
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TxtPreview {
+    pub title: String,
+    pub path: String,
+    /// The heading rule set that produced the split; None when no headings were found.
+    pub rule_set: Option<String>,
+    pub encoding: crate::text_encoding::DetectedEncoding,
+    /// Shown when decoding was lossy or the detected encoding is doubtful.
+    pub encoding_warning: Option<String>,
+    pub chapters: Vec<crate::txt_parser::TxtPreviewChapter>,
+    /// Novels in the library that already seem to be this book.
+    pub library_matches: Vec<LibraryMatch>,
+}
+
+// ---- TXT Heading Rules ----
+
+/// A named set of chapter-heading patterns for splitting a single TXT file. A short line
+/// matching any of the patterns starts a new chapter.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct HeadingRuleSet {
+    pub name: String,
+    pub patterns: Vec<String>,
+    /// Headings that open a volume (卷/部/篇) rather than a chapter. They are checked before
+    /// `patterns` and group the chapters that follow them.
+    #[serde(default)]
+    pub volume_patterns: Vec<String>,
+    /// Presets ship with the app; only custom sets are saved in settings.
+    #[serde(default)]
+    pub builtin: bool,
+}
+
+impl HeadingRuleSet {
+    fn preset(name: &str, patterns: &[&str], volume_patterns: &[&str]) -> Self {
+        Self {
+            name: name.to_string(),
+            patterns: patterns.iter().map(|p| p.to_string()).collect(),
+            volume_patterns: volume_patterns.iter().map(|p| p.to_string()).collect(),
+            builtin: true,
+        }
+    }
+
+    pub fn presets() -> Vec<Self> {
+        const CN_CHAPTER: &str = r"^第[零〇一二三四五六七八九十百千万亿两\d０-９]+[章回节卷集篇部]";
+        const CN_VOLUME: &str = r"^第[零〇一二三四五六七八九十百千万亿两\d０-９]+[卷部篇]";
+        vec![
+            Self::preset("中文章回", &[CN_CHAPTER], &[CN_VOLUME]),
+            Self::preset(
+                "网文",
+                &[
+                    CN_CHAPTER,
+                    r"^(序章|序言|序幕|楔子|引子|前言|番外|尾声|后记|终章|完本感言)",
+                ],
+                &[
+                    CN_VOLUME,
+                    r"^卷[零〇一二三四五六七八九十百千\d０-９]+(\s|$)",
+                ],
+            ),
+            Self::preset(
+                "English",
+                &[
+                    r"(?i)^(chapter|part|book)\s+([0-9]+|[ivxlcdm]+|one|two|three|four|five|six|seven|eight|nine|ten|eleven|twelve|thirteen|fourteen|fifteen|sixteen|seventeen|eighteen|nineteen|twenty|thirty|forty|fifty|sixty|seventy|eighty|ninety|hundred)\b",
+                    r"(?i)^(prologue|epilogue|interlude)\b",
+                ],
+                &[
+                    r"(?i)^(part|book|volume)\s+([0-9]+|[ivxlcdm]+|one|two|three|four|five|six|seven|eight|nine|ten)\b",
+                ],
+            ),
+            Self::preset(
+                "日本語",
+                &[
+                    r"^第[〇一二三四五六七八九十百千\d０-９]+[話话章幕]",
+                    r"^(プロローグ|エピローグ|序章|終章|番外編|幕間)",
+                ],
+                &[r"^第[〇一二三四五六七八九十百千\d０-９]+[巻部]"],
+            ),
+            Self::preset("数字编号", &[r"^[0-9０-９]{1,4}[.．、]\s*\S"], &[]),
+        ]
+    }
+}
+
+// ---- Import Cleanup ----
+
+/// How imported text is cleaned before it is stored.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct CleanupConfig {
+    pub enabled: bool,
+    /// Regexes for ads and site notices. Matching text is removed; lines left empty go too.
+    pub blacklist: Vec<String>,
+    /// Drop headers and footers: short lines repeated across many chapters that name the
+    /// book, its author or a site. Other repeated lines are reported and kept.
+    pub remove_repeated_lines: bool,
+    /// Share of chapters (0–1) a line must appear in to count as a header or footer.
+    pub repeated_line_share: f32,
+    /// Trim lines (including full-width indentation), drop zero-width characters and
+    /// collapse runs of blank lines.
+    pub normalize_whitespace: bool,
+}
+
+impl Default for CleanupConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            blacklist: [
+                r"本书由.{0,30}(整理|提供|首发|制作)",
+                r"(最新|更多|全本|精彩)(章节|小说).{0,20}(请|敬请)?(访问|登陆|登录|关注|搜索).{0,40}",
+                r"(请记住|天才一秒记住)本书.{0,40}",
+                r"[（(]本章未完.{0,10}[)）]",
+                r"手机(用户)?(请)?(浏览|阅读|访问).{0,40}",
+                r"(?i)(请|敬请)?(访问|登陆|登录)[:：\s]*(https?://|www\.)[\w./?=&%-]+",
+                r"(?i)(https?://|www\.)[\w./?=&%-]+",
+            ]
+            .iter()
+            .map(|r| r.to_string())
+            .collect(),
+            remove_repeated_lines: true,
+            repeated_line_share: 0.5,
+            normalize_whitespace: true,
+        }
+    }
+}
+
+/// Text removed by one blacklist rule.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct RuleHit {
+    pub rule: String,
+    pub count: usize,
+    /// The first few removed snippets
+    pub examples: Vec<String>,
+}
+
+/// A short line found in many chapters. Headers and footers are removed from every chapter
+/// they appeared in; other lines are only reported, since novels repeat lines on purpose.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct RepeatedLine {
+    pub line: String,
+    pub chapter_count: usize,
+    pub removed: bool,
+}
+
+/// What cleanup removed during one import.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct CleanupReport {
+    pub rule_hits: Vec<RuleHit>,
+    pub repeated_lines: Vec<RepeatedLine>,
+    /// Blank lines dropped when collapsing runs of them
+    pub blank_lines_removed: usize,
+    pub chars_before: usize,
+    pub chars_after: usize,
+}
+
+/// A novel created or extended by an import, with what cleanup removed from its text.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ImportResult {
+    pub novel_id: String,
+    pub cleanup: CleanupReport,
+    /// Titles of chapters left out when appending because the novel already has them
+    #[serde(default)]
+    pub skipped: Vec<String>,
+    /// Titles of chapters appended although the novel has a chapter of that title with other
+    /// text: a corrected edition, or the same chapter number in another volume
+    #[serde(default)]
+    pub same_title: Vec<String>,
+}
+
+// ---- Chinese Conversion ----
+
+/// Conversion between Simplified and Traditional Chinese, named after the OpenCC
+/// configurations. Phrases are converted as a whole ("鼠标" → "滑鼠" for Taiwan), not only
+/// single characters.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ChineseConversion {
+    /// Simplified to Traditional
+    S2t,
+    /// Traditional to Simplified
+    T2s,
+    /// Simplified to Traditional with Taiwan phrases
+    S2tw,
+    /// Simplified to Traditional with Hong Kong phrases
+    S2hk,
 }
 
 // ---- Core Structures ----
@@ -18,6 +232,17 @@ pub struct Novel {
     pub source_type: SourceType,
     pub enabled_dimensions: Vec<AnalysisDimension>,
     pub created_at: String,
+    /// How the source was read on import
+    #[serde(default)]
+    pub import_options: ImportOptions,
+    /// Conversion applied to the text on import
+    #[serde(default)]
+    pub conversion: Option<ChineseConversion>,
+    #[serde(default)]
+    pub metadata: BookMetadata,
+    /// Whether a cover image is stored; the image itself is loaded separately.
+    #[serde(default)]
+    pub has_cover: bool,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -27,6 +252,53 @@ pub struct NovelMeta {
     pub chapter_count: usize,
     pub analyzed_count: usize,
     pub created_at: String,
+    #[serde(default)]
+    pub metadata: BookMetadata,
+    #[serde(default)]
+    pub has_cover: bool,
+}
+
+/// Bibliographic details read from the source file (EPUB OPF metadata).
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct BookMetadata {
+    /// All creators, joined with "、"
+    pub author: Option<String>,
+    /// BCP 47 language tag, e.g. "zh-CN" or "ja"
+    pub language: Option<String>,
+    pub publisher: Option<String>,
+    pub series: Option<String>,
+    /// Position in the series as written in the source ("3", "2.5")
+    pub series_index: Option<String>,
+    /// ISBN, UUID and other identifiers, as written in the source
+    pub identifiers: Vec<String>,
+}
+
+/// A cover image as stored with a novel.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CoverImage {
+    pub data: Vec<u8>,
+    /// MIME type, e.g. "image/jpeg"
+    pub mime: String,
+}
+
+/// What a parser knows about the book besides its chapters.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BookInfo {
+    pub title: String,
+    pub metadata: BookMetadata,
+    pub cover: Option<CoverImage>,
+}
+
+impl BookInfo {
+    /// A book known only by its title, as with TXT files.
+    pub fn new(title: impl Into<String>) -> Self {
+        Self {
+            title: title.into(),
+            metadata: BookMetadata::default(),
+            cover: None,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -34,6 +306,46 @@ pub enum SourceType {
     Epub(String),
     TxtFiles(Vec<String>),
     SingleTxt(String),
+    /// MOBI, AZW or AZW3
+    Mobi(String),
+    /// FB2, plain or zipped
+    Fb2(String),
+    Docx(String),
+    /// An HTML file, or a folder of them
+    Html(String),
+    /// A Markdown file, or a folder of them
+    Markdown(String),
+}
+
+/// How a novel's source was read when it was imported, so a resync reads it the same way.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ImportOptions {
+    /// Heading rule set a single TXT file was split by; None picks the best-fitting one
+    #[serde(default)]
+    pub heading_rules: Option<String>,
+    /// Encoding label of TXT files, overriding detection
+    #[serde(default)]
+    pub encoding: Option<String>,
+    /// Whether ruby readings were kept in the text of a book
+    #[serde(default)]
+    pub keep_ruby: bool,
+    /// Chapters chosen in a preview; None when the parser's own choice was imported
+    #[serde(default)]
+    pub selection: Option<ChapterSelection>,
+}
+
+/// Chapters chosen in an import preview, with how many entries the preview had. Entries past
+/// those, in a newer edition of the source, are taken on resync when a preview would suggest
+/// them.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum ChapterSelection {
+    /// Chapters of a book, by index
+    Book { indices: Vec<usize>, known: usize },
+    /// Chapters of a single TXT file, merged and renamed as in the preview
+    Txt {
+        chapters: Vec<crate::txt_parser::TxtChapterSelection>,
+        known: usize,
+    },
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -44,6 +356,55 @@ pub struct Chapter {
     pub title: String,
     pub content: String,
     pub analysis: Option<ChapterAnalysis>,
+    #[serde(default)]
+    pub volume_id: Option<i64>,
+    /// Footnotes and asides taken out of the text, separated by blank lines
+    #[serde(default)]
+    pub notes: Option<String>,
+    /// The text changed on resync after the active analysis was made.
+    #[serde(default)]
+    pub analysis_stale: bool,
+}
+
+/// A chapter as it comes out of a parser, before it is stored.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParsedChapter {
+    pub title: String,
+    pub content: String,
+    /// Title of the volume (卷/部/篇, or a parent TOC entry) the chapter belongs to.
+    pub volume: Option<String>,
+    /// Footnotes and asides kept apart from `content`
+    pub notes: Vec<String>,
+}
+
+impl ParsedChapter {
+    pub fn new(title: impl Into<String>, content: impl Into<String>) -> Self {
+        Self {
+            title: title.into(),
+            content: content.into(),
+            volume: None,
+            notes: Vec::new(),
+        }
+    }
+}
+
+/// Where to cut a chapter in two.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub enum SplitPoint {
+    /// Byte offset into the content; must fall on a character boundary.
+    Byte(usize),
+    /// Start of the n-th paragraph (0-based, one per non-empty line).
+    Paragraph(usize),
+}
+
+/// A volume (卷/部/篇) grouping consecutive chapters of a novel.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Volume {
+    pub id: i64,
+    pub novel_id: String,
+    pub index: usize,
+    pub title: String,
+    pub chapter_count: usize,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -52,7 +413,174 @@ pub struct ChapterMeta {
     pub index: usize,
     pub title: String,
     pub has_analysis: bool,
+    /// Dimensions present in the active analysis.
+    pub analyzed_dimensions: Vec<AnalysisDimension>,
+    /// An unparsed LLM response is saved for this chapter.
+    pub has_raw_response: bool,
     pub token_estimate: usize,
+    pub volume_id: Option<i64>,
+    /// The text changed on resync after the active analysis was made.
+    #[serde(default)]
+    pub analysis_stale: bool,
+}
+
+impl ChapterMeta {
+    /// Whether the active analysis is up to date and has a result for every one of
+    /// `dimensions`.
+    pub fn is_analyzed_for(&self, dimensions: &[AnalysisDimension]) -> bool {
+        !self.analysis_stale
+            && dimensions
+                .iter()
+                .all(|d| self.analyzed_dimensions.contains(d))
+    }
+
+    /// Whether a full (replacing) analysis run should include this chapter.
+    pub fn needs_analysis(&self) -> bool {
+        !self.has_analysis || self.analysis_stale
+    }
+}
+
+// ---- Analysis Jobs ----
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum JobStatus {
+    Queued,
+    Running,
+    Failed,
+    Done,
+}
+
+impl JobStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Queued => "queued",
+            Self::Running => "running",
+            Self::Failed => "failed",
+            Self::Done => "done",
+        }
+    }
+
+    pub fn parse(s: &str) -> Self {
+        match s {
+            "running" => Self::Running,
+            "failed" => Self::Failed,
+            "done" => Self::Done,
+            _ => Self::Queued,
+        }
+    }
+}
+
+/// How a new analysis relates to the one already stored for the chapter.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum UpdateMode {
+    /// Request every dimension and replace the stored analysis.
+    #[default]
+    Replace,
+    /// Request only the dimensions the stored analysis lacks and merge them into it.
+    Incremental,
+}
+
+impl UpdateMode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Replace => "replace",
+            Self::Incremental => "incremental",
+        }
+    }
+
+    pub fn parse(s: &str) -> Self {
+        match s {
+            "incremental" => Self::Incremental,
+            _ => Self::Replace,
+        }
+    }
+}
+
+/// One chapter waiting in (or finished by) the persistent analysis queue.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AnalysisJob {
+    pub id: i64,
+    pub novel_id: String,
+    pub chapter_id: i64,
+    pub chapter_index: usize,
+    pub chapter_title: String,
+    pub dimensions: Vec<AnalysisDimension>,
+    pub update_mode: UpdateMode,
+    pub status: JobStatus,
+    pub attempts: u32,
+    pub last_error: Option<String>,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+// ---- LLM Call Log ----
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum LlmCallKind {
+    Chapter,
+    Segment,
+    Repair,
+    SummaryGroup,
+    SummaryFinal,
+}
+
+impl LlmCallKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Chapter => "chapter",
+            Self::Segment => "segment",
+            Self::Repair => "repair",
+            Self::SummaryGroup => "summary_group",
+            Self::SummaryFinal => "summary_final",
+        }
+    }
+
+    pub fn parse(s: &str) -> Self {
+        match s {
+            "segment" => Self::Segment,
+            "repair" => Self::Repair,
+            "summary_group" => Self::SummaryGroup,
+            "summary_final" => Self::SummaryFinal,
+            _ => Self::Chapter,
+        }
+    }
+}
+
+/// One recorded request to the LLM API. `chapter_id` is empty for whole-book summary calls.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LlmCall {
+    pub id: i64,
+    pub novel_id: String,
+    pub chapter_id: Option<i64>,
+    pub kind: LlmCallKind,
+    pub model: String,
+    pub prompt: String,
+    pub response: Option<String>,
+    pub error: Option<String>,
+    pub attempts: u32,
+    pub latency_ms: u64,
+    pub prompt_tokens: Option<u32>,
+    pub completion_tokens: Option<u32>,
+    pub created_at: String,
+}
+
+/// `LlmCall` without the prompt and response bodies, for listings.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LlmCallMeta {
+    pub id: i64,
+    pub chapter_id: Option<i64>,
+    pub kind: LlmCallKind,
+    pub model: String,
+    pub success: bool,
+    pub error: Option<String>,
+    pub attempts: u32,
+    pub latency_ms: u64,
+    pub prompt_tokens: Option<u32>,
+    pub completion_tokens: Option<u32>,
+    pub created_at: String,
 }
 
 // ---- Events ----
@@ -65,6 +593,35 @@ pub struct ProgressEvent {
     pub current: usize,
     pub total: usize,
     pub message: String,
+    /// Only set on the final event of a batch.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub report: Option<BatchReport>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BatchFailure {
+    pub chapter_id: i64,
+    pub chapter_title: String,
+    pub error: String,
+}
+
+/// Outcome of a batch run: what succeeded, what failed and why.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct BatchReport {
+    pub total: usize,
+    pub succeeded: usize,
+    pub failed: Vec<BatchFailure>,
+    /// Cancelled by the user; remaining jobs stay queued.
+    pub cancelled: bool,
+    /// Stopped by the failure policy; remaining jobs stay queued.
+    pub stopped: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StreamingChunk {
+    pub chapter_id: i64,
+    pub chunk: String,
+    pub full_content: String,
 }
 
 // ---- Analysis Dimensions ----
@@ -167,6 +724,82 @@ pub struct ChapterAnalysis {
     pub worldbuilding: Option<WorldbuildingAnalysis>,
 }
 
+impl ChapterAnalysis {
+    /// Dimensions that have a result in this analysis.
+    pub fn dimensions(&self) -> Vec<AnalysisDimension> {
+        let mut dims = Vec::new();
+        if self.characters.is_some() {
+            dims.push(AnalysisDimension::Characters);
+        }
+        if self.plot.is_some() {
+            dims.push(AnalysisDimension::Plot);
+        }
+        if self.foreshadowing.is_some() {
+            dims.push(AnalysisDimension::Foreshadowing);
+        }
+        if self.writing_technique.is_some() {
+            dims.push(AnalysisDimension::WritingTechnique);
+        }
+        if self.rhetoric.is_some() {
+            dims.push(AnalysisDimension::Rhetoric);
+        }
+        if self.emotion.is_some() {
+            dims.push(AnalysisDimension::Emotion);
+        }
+        if self.themes.is_some() {
+            dims.push(AnalysisDimension::Themes);
+        }
+        if self.worldbuilding.is_some() {
+            dims.push(AnalysisDimension::Worldbuilding);
+        }
+        dims
+    }
+
+    /// Which of `wanted` have no result in this analysis yet.
+    pub fn missing_dimensions(&self, wanted: &[AnalysisDimension]) -> Vec<AnalysisDimension> {
+        let present = self.dimensions();
+        wanted
+            .iter()
+            .filter(|d| !present.contains(d))
+            .cloned()
+            .collect()
+    }
+}
+
+// ---- Analysis Versions ----
+
+/// Where a saved analysis came from.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AnalysisOrigin {
+    /// Model name, or "manual" for results pasted in by the user.
+    pub model: String,
+    /// Dimensions requested for this version; an incremental run lists only the added ones.
+    pub dimensions: Vec<AnalysisDimension>,
+    pub prompt_version: String,
+}
+
+/// One stored result of analyzing a chapter. The active version is mirrored in `Chapter::analysis`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AnalysisVersion {
+    pub id: i64,
+    pub chapter_id: i64,
+    pub analysis: ChapterAnalysis,
+    pub model: String,
+    pub dimensions: Vec<AnalysisDimension>,
+    pub prompt_version: String,
+    pub created_at: String,
+    pub active: bool,
+}
+
+/// A field that differs between two analyses; `path` is dotted, e.g. `plot.summary`
+/// or `characters.characters[林动].traits`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FieldDiff {
+    pub path: String,
+    pub before: Option<serde_json::Value>,
+    pub after: Option<serde_json::Value>,
+}
+
 impl ChapterAnalysis {
     pub fn to_context_string(&self) -> String {
         let mut context = String::new();
@@ -413,6 +1046,84 @@ pub struct LlmConfig {
     pub max_concurrent_tasks: u32,
     #[serde(default)]
     pub context_injection_mode: ContextInjectionMode,
+    #[serde(default)]
+    pub failure_policy: FailurePolicy,
+    #[serde(default)]
+    pub retry: RetryConfig,
+    #[serde(default)]
+    pub tokenizer: TokenizerChoice,
+}
+
+/// Tokenizer used for prompt budgets, segment splits and chapter token estimates.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Default)]
+pub enum TokenizerChoice {
+    /// Chosen from the model name; models without a known BPE use the heuristic.
+    #[default]
+    Auto,
+    /// Character-based estimate, no vocabulary needed.
+    Heuristic,
+    Cl100k,
+    O200k,
+    /// Path to a HuggingFace `tokenizer.json`.
+    HuggingFace(String),
+}
+
+/// Kinds of API errors that are worth another attempt.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub enum RetryClass {
+    /// HTTP 429 / rate limit responses (not exhausted quota).
+    RateLimit,
+    /// HTTP 5xx and overloaded responses.
+    ServerError,
+    /// Timeouts, dropped connections and broken streams.
+    Network,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RetryConfig {
+    /// Total attempts per call, including the first one. 1 disables retries.
+    pub max_attempts: u32,
+    /// Delay before the first retry; doubled for every further one.
+    pub base_delay_ms: u64,
+    pub max_delay_ms: u64,
+    pub retry_on: Vec<RetryClass>,
+}
+
+impl Default for RetryConfig {
+    fn default() -> Self {
+        Self {
+            max_attempts: 5,
+            base_delay_ms: 2000,
+            max_delay_ms: 60000,
+            retry_on: vec![
+                RetryClass::RateLimit,
+                RetryClass::ServerError,
+                RetryClass::Network,
+            ],
+        }
+    }
+}
+
+/// What a batch does when a chapter fails.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Default)]
+pub enum FailurePolicy {
+    /// Keep going and report every failure at the end.
+    #[default]
+    Continue,
+    /// Stop dispatching new chapters once this many have failed.
+    StopAfter(u32),
+    /// Stop dispatching new chapters after the first failure.
+    StopOnFirst,
+}
+
+impl FailurePolicy {
+    pub fn should_stop(&self, failures: usize) -> bool {
+        match self {
+            Self::Continue => false,
+            Self::StopAfter(n) => failures >= (*n).max(1) as usize,
+            Self::StopOnFirst => failures >= 1,
+        }
+    }
 }
 
 fn default_chapter_max_tokens() -> Option<u32> {
@@ -435,6 +1146,9 @@ impl Default for LlmConfig {
             temperature: 0.3,
             max_concurrent_tasks: 3,
             context_injection_mode: ContextInjectionMode::None,
+            failure_policy: FailurePolicy::default(),
+            retry: RetryConfig::default(),
+            tokenizer: TokenizerChoice::default(),
         }
     }
 }
@@ -0,0 +1,1023 @@
+use crate::models::*;
+use crate::progress::ProgressSink;
+use crate::storage::Database;
+use crate::{analysis, llm, prompt, token_utils};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+
+pub fn build_context_string(
+    db: &Database,
+    novel_id: &str,
+    chapter_index: usize,
+    mode: &ContextInjectionMode,
+) -> Result<Option<String>, String> {
+    match mode {
+        ContextInjectionMode::None => Ok(None),
+        ContextInjectionMode::PreviousChapter => {
+            if let Some(prev) = db
+                .load_previous_chapter_analysis(novel_id, chapter_index)
+                .map_err(|e| e.to_string())?
+            {
+                Ok(Some(prev.to_context_string()))
+            } else {
+                Ok(None)
+            }
+        }
+        ContextInjectionMode::AllPrevious => {
+            let mut context = String::new();
+            let all_prev = db
+                .load_all_previous_analyses(novel_id, chapter_index)
+                .map_err(|e| e.to_string())?;
+
+            if all_prev.is_empty() {
+                return Ok(None);
+            }
+
+            for (_, title, prev) in &all_prev {
+                if let Some(plot) = &prev.plot {
+                    context.push_str(&format!("{} 摘要：{}\n", title, plot.summary));
+                }
+            }
+
+            if let Some((_, _, last)) = all_prev.last() {
+                context.push_str("\n【最近一章详细状态】\n");
+                context.push_str(&last.to_context_string());
+            }
+
+            Ok(Some(context))
+        }
+    }
+}
+
+/// Analyze one chapter through the LLM API and store the result. In incremental mode only
+/// the dimensions missing from the stored analysis are requested and merged into it.
+pub async fn do_analyze_chapter(
+    sink: &dyn ProgressSink,
+    db_mutex: &Mutex<Database>,
+    chapter_id: i64,
+    dimensions: &[AnalysisDimension],
+    update_mode: UpdateMode,
+) -> Result<ChapterAnalysis, String> {
+    let (mut chapter, book, config, context_str) = {
+        let db = db_mutex.lock().map_err(|e| e.to_string())?;
+        let chapter = db.load_chapter(chapter_id).map_err(|e| e.to_string())?;
+        let book = db
+            .load_novel(&chapter.novel_id)
+            .map_err(|e| e.to_string())?
+            .metadata;
+        let config = db.load_llm_config().map_err(|e| e.to_string())?;
+        let ctx = build_context_string(
+            &db,
+            &chapter.novel_id,
+            chapter.index,
+            &config.context_injection_mode,
+        )?;
+        (chapter, book, config, ctx)
+    };
+
+    // A stale analysis describes the old text; it is replaced, not extended.
+    let (base, dimensions) = match (update_mode, chapter.analysis.take()) {
+        (UpdateMode::Incremental, Some(existing)) if !chapter.analysis_stale => {
+            let missing = existing.missing_dimensions(dimensions);
+            if missing.is_empty() {
+                return Ok(existing);
+            }
+            (Some(existing), missing)
+        }
+        _ => (None, dimensions.to_vec()),
+    };
+    let dimensions = dimensions.as_slice();
+
+    let forbid_callbacks =
+        config.context_injection_mode == ContextInjectionMode::None || context_str.is_none();
+
+    let chapter_context = prompt::ChapterContext {
+        title: &chapter.title,
+        book: &book,
+        previous_context: context_str.as_deref(),
+        forbid_callbacks,
+    };
+    let prompt_text =
+        prompt::generate_chapter_prompt(chapter_context, &chapter.content, dimensions);
+    let counter = token_utils::tokenizer_for(&config)?;
+    let prompt_tokens = counter.count(&prompt_text);
+    let available = token_utils::calculate_available_tokens(&config, 0);
+
+    let analysis_result = if prompt_tokens > available {
+        let content_budget = token_utils::calculate_available_tokens(&config, 500);
+        let segments =
+            token_utils::split_content_by_tokens(&chapter.content, content_budget, &*counter);
+        let mut segment_analyses = Vec::new();
+
+        for (i, seg) in segments.iter().enumerate() {
+            sink.analysis_progress(ProgressEvent {
+                novel_id: chapter.novel_id.clone(),
+                chapter_id: Some(chapter_id),
+                status: "analyzing_segment".to_string(),
+                current: i + 1,
+                total: segments.len(),
+                message: format!("正在分析分段 {}/{}...", i + 1, segments.len()),
+                report: None,
+            });
+
+            let seg_prompt = prompt::generate_segment_prompt(
+                chapter_context,
+                seg,
+                i,
+                segments.len(),
+                dimensions,
+            );
+            let response = recorded_call(
+                sink,
+                db_mutex,
+                &config,
+                CallSite {
+                    novel_id: &chapter.novel_id,
+                    chapter_id: Some(chapter_id),
+                    kind: LlmCallKind::Segment,
+                },
+                &seg_prompt,
+                config.chapter_max_tokens,
+            )
+            .await?;
+            let seg_analysis = parse_or_repair(
+                sink,
+                db_mutex,
+                &config,
+                &chapter.novel_id,
+                chapter_id,
+                &response,
+            )
+            .await?;
+            segment_analyses.push(seg_analysis);
+        }
+
+        sink.analysis_progress(ProgressEvent {
+            novel_id: chapter.novel_id.clone(),
+            chapter_id: Some(chapter_id),
+            status: "merging_segments".to_string(),
+            current: segments.len(),
+            total: segments.len(),
+            message: "正在汇总分段分析...".to_string(),
+            report: None,
+        });
+
+        analysis::merge_segment_analyses(segment_analyses)
+    } else {
+        sink.analysis_progress(ProgressEvent {
+            novel_id: chapter.novel_id.clone(),
+            chapter_id: Some(chapter_id),
+            status: "analyzing".to_string(),
+            current: 0,
+            total: 1,
+            message: "正在生成分析...".to_string(),
+            report: None,
+        });
+
+        let response = recorded_call(
+            sink,
+            db_mutex,
+            &config,
+            CallSite {
+                novel_id: &chapter.novel_id,
+                chapter_id: Some(chapter_id),
+                kind: LlmCallKind::Chapter,
+            },
+            &prompt_text,
+            config.chapter_max_tokens,
+        )
+        .await?;
+        parse_or_repair(
+            sink,
+            db_mutex,
+            &config,
+            &chapter.novel_id,
+            chapter_id,
+            &response,
+        )
+        .await?
+    };
+
+    let analysis_result = match base {
+        Some(existing) => analysis::merge_new_dimensions(existing, analysis_result),
+        None => analysis_result,
+    };
+
+    let db = db_mutex.lock().map_err(|e| e.to_string())?;
+    db.save_chapter_analysis(
+        chapter_id,
+        &analysis_result,
+        &api_origin(&config, dimensions),
+    )
+    .map_err(|e| e.to_string())?;
+
+    Ok(analysis_result)
+}
+
+fn api_origin(config: &LlmConfig, dimensions: &[AnalysisDimension]) -> AnalysisOrigin {
+    AnalysisOrigin {
+        model: config.model.clone(),
+        dimensions: dimensions.to_vec(),
+        prompt_version: prompt::PROMPT_VERSION.to_string(),
+    }
+}
+
+/// Where an LLM call belongs, for the call log.
+struct CallSite<'a> {
+    novel_id: &'a str,
+    chapter_id: Option<i64>,
+    kind: LlmCallKind,
+}
+
+/// Make an LLM request and record it in the call log, whether it succeeded or not.
+/// Chapter and segment calls are streamed to the sink.
+async fn recorded_call(
+    sink: &dyn ProgressSink,
+    db_mutex: &Mutex<Database>,
+    config: &LlmConfig,
+    site: CallSite<'_>,
+    prompt_text: &str,
+    max_output: Option<u32>,
+) -> Result<String, String> {
+    let started = std::time::Instant::now();
+    let result = match (site.kind, site.chapter_id) {
+        (LlmCallKind::Chapter | LlmCallKind::Segment, Some(chapter_id)) => {
+            llm::call_api_stream(
+                config,
+                prompt_text,
+                sink,
+                site.novel_id,
+                chapter_id,
+                max_output,
+            )
+            .await
+        }
+        _ => llm::call_api(config, prompt_text, sink, site.novel_id, max_output).await,
+    };
+
+    let mut call = LlmCall {
+        id: 0,
+        novel_id: site.novel_id.to_string(),
+        chapter_id: site.chapter_id,
+        kind: site.kind,
+        model: config.model.clone(),
+        prompt: prompt_text.to_string(),
+        response: None,
+        error: None,
+        attempts: 0,
+        latency_ms: started.elapsed().as_millis() as u64,
+        prompt_tokens: None,
+        completion_tokens: None,
+        created_at: chrono::Utc::now().to_rfc3339(),
+    };
+    match &result {
+        Ok(reply) => {
+            call.response = Some(reply.content.clone());
+            call.attempts = reply.attempts;
+            call.prompt_tokens = reply.prompt_tokens;
+            call.completion_tokens = reply.completion_tokens;
+        }
+        Err(failure) => {
+            call.error = Some(failure.message.clone());
+            call.attempts = failure.attempts;
+        }
+    }
+    // Calls rejected before sending (e.g. prompt too long) cost nothing and are not logged.
+    if call.attempts > 0 {
+        let saved = db_mutex
+            .lock()
+            .map_err(|e| e.to_string())
+            .and_then(|db| db.record_llm_call(&call).map_err(|e| e.to_string()));
+        if let Err(e) = saved {
+            sink.analysis_progress(ProgressEvent {
+                novel_id: site.novel_id.to_string(),
+                chapter_id: site.chapter_id,
+                status: "log_failed".to_string(),
+                current: 0,
+                total: 0,
+                message: format!("调用记录保存失败: {}", e),
+                report: None,
+            });
+        }
+    }
+
+    result.map(|reply| reply.content).map_err(String::from)
+}
+
+/// Parse an analysis response. When even the local repair cannot make sense of it, the raw
+/// response is saved on the chapter and the model is asked once to fix its own output.
+async fn parse_or_repair(
+    sink: &dyn ProgressSink,
+    db_mutex: &Mutex<Database>,
+    config: &LlmConfig,
+    novel_id: &str,
+    chapter_id: i64,
+    response: &str,
+) -> Result<ChapterAnalysis, String> {
+    let err = match analysis::parse_analysis_json(response) {
+        Ok(a) => return Ok(a),
+        Err(e) => e,
+    };
+
+    {
+        let db = db_mutex.lock().map_err(|e| e.to_string())?;
+        db.save_raw_response(chapter_id, response)
+            .map_err(|e| e.to_string())?;
+    }
+
+    sink.analysis_progress(ProgressEvent {
+        novel_id: novel_id.to_string(),
+        chapter_id: Some(chapter_id),
+        status: "repairing".to_string(),
+        current: 0,
+        total: 1,
+        message: "返回结果不是有效 JSON，正在请求模型修复...".to_string(),
+        report: None,
+    });
+
+    let repair_prompt =
+        prompt::generate_json_repair_prompt(&analysis::clean_json_response(response), &err);
+    let repaired = recorded_call(
+        sink,
+        db_mutex,
+        config,
+        CallSite {
+            novel_id,
+            chapter_id: Some(chapter_id),
+            kind: LlmCallKind::Repair,
+        },
+        &repair_prompt,
+        config.chapter_max_tokens,
+    )
+    .await
+    .map_err(|e| format!("{}；修复请求失败: {}（原始返回已保存）", err, e))?;
+    analysis::parse_analysis_json(&repaired)
+        .map_err(|e| format!("{}；修复后仍无法解析: {}（原始返回已保存）", err, e))
+}
+
+/// Queue the given chapters for analysis of the novel's enabled dimensions and run those
+/// jobs. Other queued jobs of the novel are left for `run_job_queue`.
+pub async fn batch_analyze(
+    sink: &dyn ProgressSink,
+    db_mutex: &Mutex<Database>,
+    cancel_flag: &AtomicBool,
+    novel: &Novel,
+    metas: Vec<ChapterMeta>,
+    update_mode: UpdateMode,
+    policy: Option<FailurePolicy>,
+) -> Result<BatchReport, String> {
+    if metas.is_empty() {
+        return Ok(BatchReport::default());
+    }
+    let job_ids = {
+        let db = db_mutex.lock().map_err(|e| e.to_string())?;
+        let ids: Vec<i64> = metas.iter().map(|m| m.id).collect();
+        db.enqueue_jobs(&novel.id, &ids, &novel.enabled_dimensions, update_mode)
+            .map_err(|e| e.to_string())?
+    };
+    run_queued_jobs(
+        sink,
+        db_mutex,
+        cancel_flag,
+        &novel.id,
+        policy,
+        Some(&job_ids),
+    )
+    .await
+}
+
+/// Work through the queued jobs of a novel concurrently, reporting batch progress to the sink.
+/// Chapter failures are collected into the returned report; `policy` (or the configured
+/// failure policy) decides whether to keep dispatching after them. Stops early when
+/// `cancel_flag` is raised (the flag is reset afterwards); unfinished jobs stay queued so the
+/// batch can be resumed later, also after a restart.
+pub async fn run_job_queue(
+    sink: &dyn ProgressSink,
+    db_mutex: &Mutex<Database>,
+    cancel_flag: &AtomicBool,
+    novel_id: &str,
+    policy: Option<FailurePolicy>,
+) -> Result<BatchReport, String> {
+    run_queued_jobs(sink, db_mutex, cancel_flag, novel_id, policy, None).await
+}
+
+/// `run_job_queue`, limited to the jobs in `only` when given.
+async fn run_queued_jobs(
+    sink: &dyn ProgressSink,
+    db_mutex: &Mutex<Database>,
+    cancel_flag: &AtomicBool,
+    novel_id: &str,
+    policy: Option<FailurePolicy>,
+    only: Option<&[i64]>,
+) -> Result<BatchReport, String> {
+    let (config, mut jobs) = {
+        let db = db_mutex.lock().map_err(|e| e.to_string())?;
+        let jobs = db.list_queued_jobs(novel_id).map_err(|e| e.to_string())?;
+        (db.load_llm_config().unwrap_or_default(), jobs)
+    };
+    if let Some(ids) = only {
+        jobs.retain(|job| ids.contains(&job.id));
+    }
+    if jobs.is_empty() {
+        return Ok(BatchReport::default());
+    }
+    let policy = policy.unwrap_or(config.failure_policy);
+
+    let result = run_jobs(sink, db_mutex, cancel_flag, novel_id, &config, policy, jobs).await;
+
+    // Jobs still marked running were dropped mid-flight by an early return.
+    if let Ok(db) = db_mutex.lock() {
+        let _ = db.requeue_running_jobs(novel_id);
+    }
+    result
+}
+
+enum JobOutcome {
+    Skipped,
+    Done,
+    Failed(BatchFailure),
+}
+
+async fn run_jobs(
+    sink: &dyn ProgressSink,
+    db_mutex: &Mutex<Database>,
+    cancel_flag: &AtomicBool,
+    novel_id: &str,
+    config: &LlmConfig,
+    policy: FailurePolicy,
+    jobs: Vec<AnalysisJob>,
+) -> Result<BatchReport, String> {
+    use futures::StreamExt;
+    let total = jobs.len();
+    let completed = std::sync::Arc::new(std::sync::atomic::AtomicUsize::new(0));
+    // Raised by the failure policy; in-flight chapters still run to completion.
+    let stop = AtomicBool::new(false);
+
+    let concurrency = if config.context_injection_mode != ContextInjectionMode::None {
+        1
+    } else {
+        config.max_concurrent_tasks as usize
+    };
+
+    let mut futures = futures::stream::iter(jobs.into_iter().map(|job| {
+        let completed = completed.clone();
+        let stop = &stop;
+
+        async move {
+            if cancel_flag.load(Ordering::Relaxed) || stop.load(Ordering::Relaxed) {
+                return JobOutcome::Skipped;
+            }
+
+            // A storage error fails this job like an analysis error does; the failure policy
+            // decides whether the batch goes on.
+            let started = db_mutex
+                .lock()
+                .map_err(|e| e.to_string())
+                .and_then(|db| db.start_job(job.id).map_err(|e| e.to_string()));
+            let result = match started {
+                Ok(()) => {
+                    let completed_count = completed.load(Ordering::Relaxed);
+                    sink.batch_progress(ProgressEvent {
+                        novel_id: novel_id.to_string(),
+                        chapter_id: Some(job.chapter_id),
+                        status: "batch_analyzing".to_string(),
+                        current: completed_count,
+                        total,
+                        message: format!(
+                            "派发任务: {} (已完成 {}/{})",
+                            job.chapter_title, completed_count, total
+                        ),
+                        report: None,
+                    });
+
+                    let result = do_analyze_chapter(
+                        sink,
+                        db_mutex,
+                        job.chapter_id,
+                        &job.dimensions,
+                        job.update_mode,
+                    )
+                    .await;
+                    let finished = db_mutex.lock().map_err(|e| e.to_string()).and_then(|db| {
+                        db.finish_job(job.id, result.as_ref().err().map(|e| e.as_str()))
+                            .map_err(|e| e.to_string())
+                    });
+                    result.and_then(|analysis| finished.map(|()| analysis))
+                }
+                Err(e) => Err(e),
+            };
+
+            let completed_count = completed.fetch_add(1, Ordering::Relaxed) + 1;
+            match result {
+                Ok(_) => {
+                    sink.batch_progress(ProgressEvent {
+                        novel_id: novel_id.to_string(),
+                        chapter_id: Some(job.chapter_id),
+                        status: "chapter_done".to_string(),
+                        current: completed_count,
+                        total,
+                        message: format!(
+                            "已完成: {} (总计 {}/{})",
+                            job.chapter_title, completed_count, total
+                        ),
+                        report: None,
+                    });
+                    JobOutcome::Done
+                }
+                Err(e) => {
+                    sink.batch_progress(ProgressEvent {
+                        novel_id: novel_id.to_string(),
+                        chapter_id: Some(job.chapter_id),
+                        status: "error".to_string(),
+                        current: completed_count,
+                        total,
+                        message: format!("分析 {} 失败: {}", job.chapter_title, e),
+                        report: None,
+                    });
+                    JobOutcome::Failed(BatchFailure {
+                        chapter_id: job.chapter_id,
+                        chapter_title: job.chapter_title,
+                        error: e,
+                    })
+                }
+            }
+        }
+    }))
+    .buffer_unordered(concurrency);
+
+    let mut report = BatchReport {
+        total,
+        ..BatchReport::default()
+    };
+    while let Some(res) = futures.next().await {
+        match res {
+            JobOutcome::Skipped => {}
+            JobOutcome::Done => report.succeeded += 1,
+            JobOutcome::Failed(failure) => {
+                report.failed.push(failure);
+                if policy.should_stop(report.failed.len()) {
+                    stop.store(true, Ordering::Relaxed);
+                }
+            }
+        }
+    }
+    drop(futures);
+
+    let current = report.succeeded + report.failed.len();
+    let unfinished = current < total;
+    report.cancelled = cancel_flag.swap(false, Ordering::Relaxed) && unfinished;
+    report.stopped = !report.cancelled && unfinished && stop.load(Ordering::Relaxed);
+
+    let (status, message) = if report.cancelled {
+        (
+            "batch_cancelled",
+            format!("批量分析已暂停 ({}/{})，可稍后继续", current, total),
+        )
+    } else if report.stopped {
+        (
+            "batch_done",
+            format!(
+                "失败 {} 章，已按策略停止 ({}/{})，剩余章节可稍后继续",
+                report.failed.len(),
+                current,
+                total
+            ),
+        )
+    } else if report.failed.is_empty() {
+        ("batch_done", "批量分析完成".to_string())
+    } else {
+        (
+            "batch_done",
+            format!("批量分析完成，{} 章失败", report.failed.len()),
+        )
+    };
+    sink.batch_progress(ProgressEvent {
+        novel_id: novel_id.to_string(),
+        chapter_id: None,
+        status: status.to_string(),
+        current,
+        total,
+        message,
+        report: Some(report.clone()),
+    });
+
+    Ok(report)
+}
+
+/// Build the whole-book summary by tree-reducing chapter analyses, then store it.
+pub async fn generate_full_summary(
+    sink: &dyn ProgressSink,
+    db_mutex: &Mutex<Database>,
+    novel_id: &str,
+) -> Result<NovelSummary, String> {
+    let (novel, chapters, config) = {
+        let db = db_mutex.lock().map_err(|e| e.to_string())?;
+        let novel = db.load_novel(novel_id).map_err(|e| e.to_string())?;
+        let chapters: Vec<Chapter> = db
+            .list_chapter_metas(novel_id)
+            .map_err(|e| e.to_string())?
+            .into_iter()
+            .filter(|m| m.has_analysis)
+            .filter_map(|m| db.load_chapter(m.id).ok())
+            .collect();
+        let config = db.load_llm_config().map_err(|e| e.to_string())?;
+        (novel, chapters, config)
+    };
+
+    if chapters.is_empty() {
+        return Err("当前没有已分析的章节可以用来生成汇总".to_string());
+    }
+
+    sink.analysis_progress(ProgressEvent {
+        novel_id: novel_id.to_string(),
+        chapter_id: None,
+        status: "summarizing".to_string(),
+        current: 0,
+        total: 100,
+        message: "准备生成全书汇总...".to_string(),
+        report: None,
+    });
+
+    let dims = &novel.enabled_dimensions;
+    let max_group_size = 10;
+
+    let chapter_summaries: Vec<(usize, String)> = chapters
+        .into_iter()
+        .filter_map(|ch| {
+            ch.analysis
+                .map(|a| (ch.index, serde_json::to_string(&a).unwrap_or_default()))
+        })
+        .collect();
+
+    if chapter_summaries.is_empty() {
+        return Err("章节分析数据为空".to_string());
+    }
+
+    let mut group_summaries = Vec::new();
+    let chunks: Vec<_> = chapter_summaries.chunks(max_group_size).collect();
+    let total_chunks = chunks.len();
+
+    {
+        let db = db_mutex.lock().map_err(|e| e.to_string())?;
+        db.clear_summary_cache(novel_id)
+            .map_err(|e| e.to_string())?;
+    }
+
+    for (i, chunk) in chunks.into_iter().enumerate() {
+        sink.analysis_progress(ProgressEvent {
+            novel_id: novel_id.to_string(),
+            chapter_id: None,
+            status: "summarizing".to_string(),
+            current: i + 1,
+            total: total_chunks + 1,
+            message: format!("正在合并阶段汇总 ({}/{})", i + 1, total_chunks),
+            report: None,
+        });
+
+        let prompt_text = prompt::generate_group_summary_prompt(chunk, dims);
+        let response = recorded_call(
+            sink,
+            db_mutex,
+            &config,
+            CallSite {
+                novel_id,
+                chapter_id: None,
+                kind: LlmCallKind::SummaryGroup,
+            },
+            &prompt_text,
+            config.summary_max_tokens,
+        )
+        .await?;
+        let summary_content = analysis::clean_json_response(&response);
+        group_summaries.push(summary_content.clone());
+
+        {
+            let db = db_mutex.lock().map_err(|e| e.to_string())?;
+            db.save_summary_cache(novel_id, 1, i as i32, &summary_content)
+                .ok();
+        }
+    }
+
+    sink.analysis_progress(ProgressEvent {
+        novel_id: novel_id.to_string(),
+        chapter_id: None,
+        status: "summarizing".to_string(),
+        current: total_chunks + 1,
+        total: total_chunks + 1,
+        message: "正在生成终极全书汇总...".to_string(),
+        report: None,
+    });
+
+    let mut final_summary = if group_summaries.len() == 1 {
+        analysis::parse_summary_json(&group_summaries[0])?
+    } else {
+        let final_prompt = prompt::generate_final_summary_prompt(&group_summaries, dims);
+        let response = recorded_call(
+            sink,
+            db_mutex,
+            &config,
+            CallSite {
+                novel_id,
+                chapter_id: None,
+                kind: LlmCallKind::SummaryFinal,
+            },
+            &final_prompt,
+            config.summary_max_tokens,
+        )
+        .await?;
+        analysis::parse_summary_json(&response)?
+    };
+
+    final_summary.created_at = chrono::Utc::now().to_rfc3339();
+
+    {
+        let db = db_mutex.lock().map_err(|e| e.to_string())?;
+        db.save_novel_summary(novel_id, &final_summary)
+            .map_err(|e| e.to_string())?;
+    }
+
+    sink.analysis_progress(ProgressEvent {
+        novel_id: novel_id.to_string(),
+        chapter_id: None,
+        status: "done".to_string(),
+        current: 100,
+        total: 100,
+        message: "全书汇总完成".to_string(),
+        report: None,
+    });
+
+    Ok(final_summary)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Default)]
+    struct RecordingSink {
+        analysis: Mutex<Vec<ProgressEvent>>,
+        batch: Mutex<Vec<ProgressEvent>>,
+    }
+
+    impl ProgressSink for RecordingSink {
+        fn analysis_progress(&self, event: ProgressEvent) {
+            self.analysis.lock().unwrap().push(event);
+        }
+        fn batch_progress(&self, event: ProgressEvent) {
+            self.batch.lock().unwrap().push(event);
+        }
+        fn streaming(&self, _chunk: StreamingChunk) {}
+        fn import_progress(&self, _event: ProgressEvent) {}
+    }
+
+    /// A database holding one two-chapter novel, with a context window too small
+    /// for any prompt so the LLM call fails before touching the network.
+    fn setup() -> (Mutex<Database>, Novel) {
+        let db = Database::open_in_memory().unwrap();
+        let chapters = vec![
+            ParsedChapter::new("第一章", "第一段。\n\n第二段。"),
+            ParsedChapter::new("第二章", "第三段。"),
+        ];
+        let novel_id = db
+            .create_novel(
+                BookInfo::new("测试"),
+                SourceType::SingleTxt(String::new()),
+                ImportOptions::default(),
+                None,
+                chapters,
+            )
+            .unwrap();
+        db.save_llm_config(&LlmConfig {
+            max_context_tokens: 1,
+            chapter_max_tokens: Some(1),
+            max_concurrent_tasks: 1,
+            ..LlmConfig::default()
+        })
+        .unwrap();
+        let novel = db.load_novel(&novel_id).unwrap();
+        (Mutex::new(db), novel)
+    }
+
+    #[tokio::test]
+    async fn test_analyze_chapter_reports_segments_to_sink() {
+        let (db, novel) = setup();
+        let chapter_id = db.lock().unwrap().list_chapter_metas(&novel.id).unwrap()[0].id;
+        let sink = RecordingSink::default();
+
+        let result = do_analyze_chapter(
+            &sink,
+            &db,
+            chapter_id,
+            &novel.enabled_dimensions,
+            UpdateMode::Replace,
+        )
+        .await;
+
+        assert!(result.is_err());
+        let events = sink.analysis.lock().unwrap();
+        assert_eq!(events[0].status, "analyzing_segment");
+        assert_eq!(events[0].chapter_id, Some(chapter_id));
+    }
+
+    #[tokio::test]
+    async fn test_incremental_analysis_skips_present_dimensions() {
+        let (db, novel) = setup();
+        let chapter_id = db.lock().unwrap().list_chapter_metas(&novel.id).unwrap()[0].id;
+        let existing = analysis::parse_analysis_json(
+            r#"{"plot": {"summary": "摘要", "key_events": [], "conflicts": [], "suspense": []}}"#,
+        )
+        .unwrap();
+        db.lock()
+            .unwrap()
+            .save_chapter_analysis(
+                chapter_id,
+                &existing,
+                &api_origin(&LlmConfig::default(), &[]),
+            )
+            .unwrap();
+        let sink = RecordingSink::default();
+
+        let result = do_analyze_chapter(
+            &sink,
+            &db,
+            chapter_id,
+            &[AnalysisDimension::Plot],
+            UpdateMode::Incremental,
+        )
+        .await
+        .unwrap();
+        assert_eq!(result.plot.unwrap().summary, "摘要");
+        assert!(sink.analysis.lock().unwrap().is_empty());
+
+        let result = do_analyze_chapter(
+            &sink,
+            &db,
+            chapter_id,
+            &[AnalysisDimension::Plot, AnalysisDimension::Themes],
+            UpdateMode::Incremental,
+        )
+        .await;
+        assert!(result.is_err());
+
+        let meta = &db.lock().unwrap().list_chapter_metas(&novel.id).unwrap()[0];
+        assert_eq!(meta.analyzed_dimensions, vec![AnalysisDimension::Plot]);
+        assert!(meta.is_analyzed_for(&[AnalysisDimension::Plot]));
+        assert!(!meta.is_analyzed_for(&[AnalysisDimension::Plot, AnalysisDimension::Themes]));
+    }
+
+    #[test]
+    fn test_activate_analysis_version() {
+        let (db, novel) = setup();
+        let db = db.into_inner().unwrap();
+        let metas = db.list_chapter_metas(&novel.id).unwrap();
+        let (first, second) = (metas[0].id, metas[1].id);
+        let origin = api_origin(&LlmConfig::default(), &[]);
+        let plot = |summary: &str| {
+            analysis::parse_analysis_json(&format!(
+                r#"{{"plot": {{"summary": "{}", "key_events": [], "conflicts": [], "suspense": []}}}}"#,
+                summary
+            ))
+            .unwrap()
+        };
+        db.save_chapter_analysis(first, &plot("旧"), &origin)
+            .unwrap();
+        db.save_chapter_analysis(first, &plot("新"), &origin)
+            .unwrap();
+        db.resync_chapter_content(first, "改过的正文。", None)
+            .unwrap();
+        db.save_raw_response(first, "{").unwrap();
+        let old = db.list_analysis_versions(first).unwrap()[1].id;
+
+        assert!(db.set_active_analysis_version(second, old).is_err());
+        assert!(db.load_chapter(second).unwrap().analysis.is_none());
+
+        db.set_active_analysis_version(first, old).unwrap();
+        let chapter = db.load_chapter(first).unwrap();
+        assert_eq!(chapter.analysis.unwrap().plot.unwrap().summary, "旧");
+        assert!(!chapter.analysis_stale);
+        assert_eq!(db.load_raw_response(first).unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_batch_analyze_reports_error() {
+        let (db, novel) = setup();
+        let metas = db.lock().unwrap().list_chapter_metas(&novel.id).unwrap();
+        let sink = RecordingSink::default();
+        let cancel = AtomicBool::new(false);
+
+        let report = batch_analyze(
+            &sink,
+            &db,
+            &cancel,
+            &novel,
+            metas,
+            UpdateMode::Replace,
+            None,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(report.total, 2);
+        assert_eq!(report.succeeded, 0);
+        assert_eq!(report.failed.len(), 2);
+        assert!(!report.stopped);
+        let events = sink.batch.lock().unwrap();
+        assert_eq!(events[0].status, "batch_analyzing");
+        assert!(events.iter().any(|e| e.status == "error"));
+        let last = events.last().unwrap();
+        assert_eq!(last.status, "batch_done");
+        assert_eq!(last.report.as_ref().unwrap().failed.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_job_queue_keeps_unfinished_jobs() {
+        let (db, novel) = setup();
+        let metas = db.lock().unwrap().list_chapter_metas(&novel.id).unwrap();
+        let sink = RecordingSink::default();
+        let cancel = AtomicBool::new(false);
+
+        let report = batch_analyze(
+            &sink,
+            &db,
+            &cancel,
+            &novel,
+            metas,
+            UpdateMode::Replace,
+            Some(FailurePolicy::StopOnFirst),
+        )
+        .await
+        .unwrap();
+        assert!(report.stopped);
+        assert_eq!(report.failed.len(), 1);
+
+        let jobs = db.lock().unwrap().list_jobs(&novel.id).unwrap();
+        assert_eq!(jobs.len(), 2);
+        assert_eq!(jobs[0].status, JobStatus::Failed);
+        assert_eq!(jobs[0].attempts, 1);
+        assert!(jobs[0].last_error.is_some());
+        assert_eq!(jobs[1].status, JobStatus::Queued);
+
+        assert_eq!(db.lock().unwrap().retry_failed_jobs(&novel.id).unwrap(), 1);
+        let queued = db.lock().unwrap().list_queued_jobs(&novel.id).unwrap();
+        assert_eq!(queued.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_batch_runs_only_its_own_jobs() {
+        let (db, novel) = setup();
+        let metas = db.lock().unwrap().list_chapter_metas(&novel.id).unwrap();
+        // Left in the queue by an earlier, interrupted batch.
+        db.lock()
+            .unwrap()
+            .enqueue_jobs(&novel.id, &[metas[0].id], &[], UpdateMode::Replace)
+            .unwrap();
+        let sink = RecordingSink::default();
+        let cancel = AtomicBool::new(false);
+
+        let report = batch_analyze(
+            &sink,
+            &db,
+            &cancel,
+            &novel,
+            vec![metas[1].clone()],
+            UpdateMode::Replace,
+            None,
+        )
+        .await
+        .unwrap();
+        assert_eq!(report.total, 1);
+        let jobs = db.lock().unwrap().list_jobs(&novel.id).unwrap();
+        assert_eq!(jobs[0].status, JobStatus::Queued);
+        assert_eq!(jobs[1].status, JobStatus::Failed);
+    }
+
+    #[test]
+    fn test_only_app_startup_requeues_running_jobs() {
+        let dir = std::env::temp_dir().join(format!("novelparser-open-{}", std::process::id()));
+        let db = Database::open_for_app(&dir).unwrap();
+        let novel_id = db
+            .create_novel(
+                BookInfo::new("测试"),
+                SourceType::SingleTxt(String::new()),
+                ImportOptions::default(),
+                None,
+                vec![ParsedChapter::new("第一章", "正文。")],
+            )
+            .unwrap();
+        let chapter_id = db.list_chapter_metas(&novel_id).unwrap()[0].id;
+        db.enqueue_jobs(&novel_id, &[chapter_id], &[], UpdateMode::Replace)
+            .unwrap();
+        let job_id = db.list_jobs(&novel_id).unwrap()[0].id;
+        db.start_job(job_id).unwrap();
+
+        // A second connection, as an import opens, leaves the running job alone.
+        let status = |db: &Database| db.list_jobs(&novel_id).unwrap()[0].status;
+        assert_eq!(status(&Database::open(&dir).unwrap()), JobStatus::Running);
+        assert_eq!(
+            status(&Database::open_for_app(&dir).unwrap()),
+            JobStatus::Queued
+        );
+        drop(db);
+        std::fs::remove_dir_all(&dir).ok();
+    }
+}
@@ -0,0 +1,30 @@
+use crate::models::{ProgressEvent, StreamingChunk};
+
+/// Receives progress and streaming output from the analysis pipeline and from imports.
+///
+/// The Tauri layer forwards each call as the matching webview event
+/// (`analysis_progress`, `batch_progress`, `analysis_streaming`, `import_progress`); other
+/// front ends can print, log or record them instead.
+pub trait ProgressSink: Send + Sync {
+    /// Per-chapter and summary progress (`analysis_progress`).
+    fn analysis_progress(&self, event: ProgressEvent);
+
+    /// Batch dispatch and completion (`batch_progress`).
+    fn batch_progress(&self, event: ProgressEvent);
+
+    /// Partial LLM output while a chapter is streaming (`analysis_streaming`).
+    fn streaming(&self, chunk: StreamingChunk);
+
+    /// Parsing, cleanup and storing of an import (`import_progress`).
+    fn import_progress(&self, event: ProgressEvent);
+}
+
+/// A sink that discards everything.
+pub struct NullSink;
+
+impl ProgressSink for NullSink {
+    fn analysis_progress(&self, _event: ProgressEvent) {}
+    fn batch_progress(&self, _event: ProgressEvent) {}
+    fn streaming(&self, _chunk: StreamingChunk) {}
+    fn import_progress(&self, _event: ProgressEvent) {}
+}
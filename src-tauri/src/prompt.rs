@@ -1,13 +1,32 @@
 use crate::models::*;
 
+/// Bump when the analysis prompts change in a way that affects results; stored with
+/// every analysis version.
+pub const PROMPT_VERSION: &str = "1";
+
+/// What a chapter prompt says around the text itself.
+#[derive(Clone, Copy)]
+pub struct ChapterContext<'a> {
+    pub title: &'a str,
+    pub book: &'a BookMetadata,
+    /// Summary of earlier chapters, per the context injection mode.
+    pub previous_context: Option<&'a str>,
+    /// Ask the model not to refer back to earlier chapters it hasn't been shown.
+    pub forbid_callbacks: bool,
+}
+
 /// Generate a chapter analysis prompt based on selected dimensions.
 pub fn generate_chapter_prompt(
-    title: &str,
+    chapter: ChapterContext,
     content: &str,
     dimensions: &[AnalysisDimension],
-    previous_context: Option<&str>,
-    forbid_callbacks: bool,
 ) -> String {
+    let ChapterContext {
+        title,
+        book,
+        previous_context,
+        forbid_callbacks,
+    } = chapter;
     let mut prompt = String::new();
 
     prompt.push_str("你是一位资深的文学评论家和小说研究者，拥有敏锐的文本洞察力。\n");
@@ -15,6 +34,8 @@ pub fn generate_chapter_prompt(
     prompt.push_str("分析应当基于文本证据，避免泛泛而谈。每个维度都有一个 insights 字段，请在其中写出你最深刻的洞察。\n");
     prompt.push_str("请返回 JSON 格式。\n\n");
 
+    push_book_info(&mut prompt, book);
+
     if let Some(ctx) = previous_context {
         prompt.push_str("## 前情提要 (Context)\n\n");
         prompt.push_str(
@@ -43,14 +64,18 @@ pub fn generate_chapter_prompt(
 
 /// Generate a prompt for a chapter segment (when chapter is split due to length).
 pub fn generate_segment_prompt(
-    title: &str,
+    chapter: ChapterContext,
     segment_content: &str,
     segment_index: usize,
     total_segments: usize,
     dimensions: &[AnalysisDimension],
-    previous_context: Option<&str>,
-    forbid_callbacks: bool,
 ) -> String {
+    let ChapterContext {
+        title,
+        book,
+        previous_context,
+        forbid_callbacks,
+    } = chapter;
     let mut prompt = String::new();
 
     prompt.push_str(
@@ -58,6 +83,8 @@ pub fn generate_segment_prompt(
     );
     prompt.push_str("分析应基于文本证据。请返回 JSON 格式。\n\n");
 
+    push_book_info(&mut prompt, book);
+
     if let Some(ctx) = previous_context {
         prompt.push_str("## 前情提要 (Context)\n\n");
         prompt.push_str(
@@ -89,6 +116,24 @@ pub fn generate_segment_prompt(
     prompt
 }
 
+/// Tell the model who wrote the book and what language it is in, when the source said so.
+fn push_book_info(prompt: &mut String, book: &BookMetadata) {
+    if book.author.is_none() && book.language.is_none() {
+        return;
+    }
+    prompt.push_str("## 作品信息\n\n");
+    if let Some(author) = &book.author {
+        prompt.push_str(&format!("作者：{}\n", author));
+    }
+    if let Some(language) = &book.language {
+        prompt.push_str(&format!(
+            "原文语言：{}（无论原文为何种语言，分析都请用中文撰写）\n",
+            language
+        ));
+    }
+    prompt.push('\n');
+}
+
 /// Generate a group summary prompt for tree-reduction.
 pub fn generate_group_summary_prompt(
     chapter_summaries: &[(usize, String)],
@@ -155,6 +200,19 @@ pub fn generate_manual_full_summary_prompt(
     prompt
 }
 
+/// Ask the model to turn its own malformed output back into valid JSON.
+pub fn generate_json_repair_prompt(broken: &str, error: &str) -> String {
+    let mut prompt = String::new();
+
+    prompt.push_str("下面是一段本应为 JSON 的分析结果，但无法被解析。\n");
+    prompt.push_str("请修复其中的语法问题（引号、逗号、括号、截断等），保持原有字段和内容不变，不要补充新的分析。\n");
+    prompt.push_str("只返回修复后的 JSON，不要包含其他说明文字。\n\n");
+    prompt.push_str(&format!("## 解析错误\n{}\n\n", error));
+    prompt.push_str(&format!("## 原始内容\n{}\n", broken));
+
+    prompt
+}
+
 fn dimension_instruction(dim: &AnalysisDimension, forbid_callbacks: bool) -> &'static str {
     match dim {
         AnalysisDimension::Characters => {
@@ -0,0 +1,358 @@
+use crate::cleanup;
+use crate::html_text::ExtractOptions;
+use crate::import::ImportControl;
+use crate::models::{
+    BookInfo, ChapterSelection, CleanupReport, ImportOptions, ParsedChapter, SourceType,
+};
+use crate::storage::{self, Database};
+use crate::{importer, txt_parser};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::sync::atomic::Ordering;
+
+/// What a resync changed.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct ResyncReport {
+    /// Chapters whose text is unchanged; their analyses are kept
+    pub unchanged: usize,
+    /// Chapters matched by title whose text changed; their analyses are flagged stale
+    pub modified: Vec<i64>,
+    /// Chapters new in the source, appended after the existing ones
+    pub added: Vec<i64>,
+    /// Titles of stored chapters no longer in the source. They are kept as they are.
+    pub missing: Vec<String>,
+    pub cleanup: CleanupReport,
+}
+
+/// How a chapter of the re-parsed source relates to the stored chapters.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Match {
+    /// Same text as this stored chapter
+    Unchanged(i64),
+    /// Same title as this stored chapter, different text
+    Modified(i64),
+    New,
+}
+
+/// Re-parse a novel's source file and bring its chapters up to date. The source goes through
+/// the same cleanup and conversion as an import, then each chapter is matched to a stored one
+/// by content hash, or failing that by title. New chapters are appended. Progress goes to the
+/// sink as `import_progress` events, like an import's; cancelling it before the chapters are
+/// written leaves the novel as it was.
+pub fn resync_novel(
+    db: &Database,
+    novel_id: &str,
+    control: ImportControl,
+) -> Result<ResyncReport, String> {
+    control.cancel.store(false, Ordering::Relaxed);
+    let result = resync(db, novel_id, control);
+    control.report_end(novel_id, &result, "同步完成");
+    result
+}
+
+fn resync(db: &Database, novel_id: &str, control: ImportControl) -> Result<ResyncReport, String> {
+    let novel = db.load_novel(novel_id).map_err(|e| e.to_string())?;
+    control.report(novel_id, "parsing", 0, 0, "正在读取源文件".to_string());
+    let (mut book, mut chapters) = parse_source(db, &novel.source_type, &novel.import_options)?;
+    control.check_cancelled()?;
+    control.report(
+        novel_id,
+        "cleaning",
+        0,
+        chapters.len(),
+        "正在清理文本".to_string(),
+    );
+    let cleanup = cleanup::prepare_import(db, &mut book, novel.conversion, &mut chapters)?;
+
+    let stored = db
+        .list_chapter_hashes(novel_id)
+        .map_err(|e| e.to_string())?;
+    let matches = match_chapters(&stored, &chapters);
+
+    let mut report = ResyncReport {
+        cleanup,
+        ..ResyncReport::default()
+    };
+    let mut modified = Vec::new();
+    let mut added = Vec::new();
+    for (m, chapter) in matches.iter().zip(chapters) {
+        match *m {
+            Match::Unchanged(_) => report.unchanged += 1,
+            Match::Modified(id) => modified.push((id, chapter)),
+            Match::New => added.push(chapter),
+        }
+    }
+    report.missing = stored
+        .iter()
+        .filter(|(id, _, _)| {
+            !matches
+                .iter()
+                .any(|m| matches!(m, Match::Unchanged(i) | Match::Modified(i) if i == id))
+        })
+        .map(|(_, title, _)| title.clone())
+        .collect();
+    report.modified = modified.iter().map(|(id, _)| *id).collect();
+    control.check_cancelled()?;
+    control.report(
+        novel_id,
+        "saving",
+        0,
+        modified.len() + added.len(),
+        "正在保存章节".to_string(),
+    );
+    report.added = db
+        .resync_chapters(novel_id, &modified, added)
+        .map_err(|e| e.to_string())?;
+    Ok(report)
+}
+
+/// Parse the file a novel was imported from, the way the import read it: with the same
+/// encoding, heading rules and ruby setting, keeping the chapters chosen in its preview (merged
+/// and renamed as there) plus any new ones the preview would suggest. Without a preview,
+/// books keep the chapters an import suggests by default.
+fn parse_source(
+    db: &Database,
+    source_type: &SourceType,
+    options: &ImportOptions,
+) -> Result<(BookInfo, Vec<ParsedChapter>), String> {
+    let encoding = options.encoding.as_deref();
+    match source_type {
+        SourceType::Epub(path)
+        | SourceType::Mobi(path)
+        | SourceType::Fb2(path)
+        | SourceType::Docx(path)
+        | SourceType::Html(path)
+        | SourceType::Markdown(path) => {
+            let extract = ExtractOptions {
+                keep_ruby: options.keep_ruby,
+            };
+            match &options.selection {
+                Some(ChapterSelection::Book { indices, known }) => {
+                    importer::parse_reselected(path, indices, *known, extract)
+                }
+                _ => {
+                    let (book, _, chapters) = importer::parse_suggested(path, extract)?;
+                    Ok((book, chapters))
+                }
+            }
+        }
+        SourceType::TxtFiles(paths) => {
+            let (title, chapters, _) = txt_parser::parse_txt_files(paths.clone(), encoding)?;
+            Ok((BookInfo::new(title), chapters))
+        }
+        SourceType::SingleTxt(path) => {
+            let sets = db.load_heading_rule_sets().map_err(|e| e.to_string())?;
+            let sets = txt_parser::select_heading_rules(sets, options.heading_rules.as_deref())?;
+            let (title, chapters) = match &options.selection {
+                Some(ChapterSelection::Txt { chapters, known }) => {
+                    txt_parser::parse_txt_reselected(path, &sets, chapters, *known, encoding)?
+                }
+                _ => {
+                    let (title, chapters, _) = txt_parser::parse_single_txt(path, &sets, encoding)?;
+                    (title, chapters)
+                }
+            };
+            Ok((BookInfo::new(title), chapters))
+        }
+    }
+}
+
+/// Match parsed chapters to stored `(id, title, content hash)` rows, each stored chapter at
+/// most once: first by identical text, then by title among the rest.
+fn match_chapters(stored: &[(i64, String, String)], parsed: &[ParsedChapter]) -> Vec<Match> {
+    let mut by_hash: HashMap<&str, VecDeque<i64>> = HashMap::new();
+    for (id, _, hash) in stored {
+        by_hash.entry(hash.as_str()).or_default().push_back(*id);
+    }
+    let mut used: Vec<i64> = Vec::new();
+    let mut matches: Vec<Match> = parsed
+        .iter()
+        .map(|ch| {
+            let hash = storage::content_hash(&ch.content);
+            match by_hash.get_mut(hash.as_str()).and_then(VecDeque::pop_front) {
+                Some(id) => {
+                    used.push(id);
+                    Match::Unchanged(id)
+                }
+                None => Match::New,
+            }
+        })
+        .collect();
+
+    for (m, ch) in matches.iter_mut().zip(parsed) {
+        if *m != Match::New {
+            continue;
+        }
+        let title = ch.title.trim();
+        let found = stored
+            .iter()
+            .find(|(id, t, _)| t.trim() == title && !used.contains(id));
+        if let Some((id, _, _)) = found {
+            used.push(*id);
+            *m = Match::Modified(*id);
+        }
+    }
+    matches
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_match_chapters() {
+        let stored: Vec<(i64, String, String)> = [(1, "第一章", "甲。"), (2, "第二章", "乙。")]
+            .iter()
+            .map(|&(id, title, content)| (id, title.to_string(), storage::content_hash(content)))
+            .collect();
+        let parsed = vec![
+            ParsedChapter::new("第一章 开端", "甲。"),
+            ParsedChapter::new("第二章", "乙，改过错字。"),
+            ParsedChapter::new("第三章", "丙。"),
+        ];
+        assert_eq!(
+            match_chapters(&stored, &parsed),
+            vec![Match::Unchanged(1), Match::Modified(2), Match::New]
+        );
+    }
+
+    #[test]
+    fn test_resync_keeps_unchanged_analyses() {
+        use crate::import::{import_novel, ImportControl};
+        use crate::models::{AnalysisOrigin, ChapterAnalysis};
+
+        let db = Database::open_in_memory().unwrap();
+        let path =
+            std::env::temp_dir().join(format!("novelparser-resync-{}.txt", std::process::id()));
+        let text = |chapters: &[(&str, &str)]| -> String {
+            chapters
+                .iter()
+                .map(|(title, line)| format!("{}\n{}\n", title, line.repeat(10)))
+                .collect()
+        };
+        std::fs::write(
+            &path,
+            text(&[("第一章 开端", "甲。"), ("第二章 相遇", "乙。")]),
+        )
+        .unwrap();
+        let path_str = path.to_string_lossy().to_string();
+
+        let sets = db.load_heading_rule_sets().unwrap();
+        let (title, chapters, _) = txt_parser::parse_single_txt(&path_str, &sets, None).unwrap();
+        let novel_id = import_novel(
+            &db,
+            BookInfo::new(title),
+            SourceType::SingleTxt(path_str),
+            ImportOptions::default(),
+            None,
+            chapters,
+            ImportControl::silent(),
+        )
+        .unwrap()
+        .novel_id;
+        let origin = AnalysisOrigin {
+            model: "manual".to_string(),
+            dimensions: Vec::new(),
+            prompt_version: String::new(),
+        };
+        let metas = db.list_chapter_metas(&novel_id).unwrap();
+        for meta in &metas {
+            db.save_chapter_analysis(meta.id, &ChapterAnalysis::default(), &origin)
+                .unwrap();
+        }
+
+        std::fs::write(
+            &path,
+            text(&[
+                ("第一章 开端", "甲。"),
+                ("第二章 相遇", "乙，改过错字。"),
+                ("第三章 离别", "丙。"),
+            ]),
+        )
+        .unwrap();
+        let report = resync_novel(&db, &novel_id, ImportControl::silent()).unwrap();
+        std::fs::remove_file(&path).ok();
+
+        assert_eq!(report.unchanged, 1);
+        assert_eq!(report.modified, vec![metas[1].id]);
+        assert_eq!(report.added.len(), 1);
+        let metas = db.list_chapter_metas(&novel_id).unwrap();
+        let flags: Vec<(bool, bool)> = metas
+            .iter()
+            .map(|m| (m.has_analysis, m.analysis_stale))
+            .collect();
+        assert_eq!(flags, vec![(true, false), (true, true), (false, false)]);
+        assert_eq!(metas[2].title, "第三章 离别");
+    }
+
+    #[test]
+    fn test_resync_replays_preview_edits() {
+        use crate::import::{import_novel, ImportControl};
+        use crate::txt_parser::TxtChapterSelection;
+
+        let db = Database::open_in_memory().unwrap();
+        let path = std::env::temp_dir().join(format!(
+            "novelparser-resync-preview-{}.txt",
+            std::process::id()
+        ));
+        let content =
+            "第一章 开端\n他推开门，走进了那间许久没有人住过的屋子。\n第二章 误识别\n短\n\
+                       第三章 相遇\n灰尘在阳光里缓缓飘动，她站在窗前。\n\
+                       第四章 广告\n本站新开业，欢迎收藏，更新最快最全。\n";
+        std::fs::write(&path, content).unwrap();
+        let path_str = path.to_string_lossy().to_string();
+
+        // Merge the second entry into the first, rename the third and leave out the fourth.
+        let sets = txt_parser::select_heading_rules(
+            db.load_heading_rule_sets().unwrap(),
+            Some("中文章回"),
+        )
+        .unwrap();
+        let selections = vec![
+            TxtChapterSelection {
+                indices: vec![0, 1],
+                title: String::new(),
+            },
+            TxtChapterSelection {
+                indices: vec![2],
+                title: "第三章 改名".to_string(),
+            },
+        ];
+        let (title, chapters, known) =
+            txt_parser::parse_txt_selected(&path_str, &sets, &selections, None).unwrap();
+        let options = ImportOptions {
+            heading_rules: Some("中文章回".to_string()),
+            selection: Some(ChapterSelection::Txt {
+                chapters: selections,
+                known,
+            }),
+            ..ImportOptions::default()
+        };
+        let novel_id = import_novel(
+            &db,
+            BookInfo::new(title),
+            SourceType::SingleTxt(path_str),
+            options,
+            None,
+            chapters,
+            ImportControl::silent(),
+        )
+        .unwrap()
+        .novel_id;
+
+        let grown = format!("{content}第五章 离别\n雨停了，他们在渡口道别，各自背起行囊上路。\n");
+        std::fs::write(&path, grown).unwrap();
+        let report = resync_novel(&db, &novel_id, ImportControl::silent()).unwrap();
+        std::fs::remove_file(&path).ok();
+
+        assert_eq!(report.unchanged, 2);
+        assert!(report.modified.is_empty() && report.missing.is_empty());
+        let titles: Vec<String> = db
+            .list_chapter_metas(&novel_id)
+            .unwrap()
+            .into_iter()
+            .map(|m| m.title)
+            .collect();
+        assert_eq!(titles, vec!["第一章 开端", "第三章 改名", "第五章 离别"]);
+    }
+}
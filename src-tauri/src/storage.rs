@@ -1,17 +1,47 @@
+use crate::fingerprint::{self, ChapterFingerprint};
 use crate::models::*;
+use crate::token_utils::{self, TokenCounter};
 use rusqlite::{params, Connection, Result};
-use std::path::PathBuf;
+use sha2::{Digest, Sha256};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 
 pub struct Database {
     conn: Connection,
 }
 
 impl Database {
-    pub fn new(app_data_dir: &PathBuf) -> Result<Self> {
+    /// Open the database when the app starts: create and migrate the tables, requeue jobs
+    /// and roll back imports a shutdown interrupted. Only the app's main connection should open this way.
+    pub fn open_for_app(app_data_dir: &PathBuf) -> Result<Self> {
         std::fs::create_dir_all(app_data_dir).ok();
-        let db_path = app_data_dir.join("novelparser.db");
-        let conn = Connection::open(db_path)?;
-        let db = Self { conn };
+        let db = Self::open(app_data_dir)?;
+        db.init_tables()?;
+        // Jobs still marked running were interrupted by a shutdown; put them back in the queue.
+        db.conn.execute(
+            "UPDATE jobs SET status = 'queued' WHERE status = 'running'",
+            [],
+        )?;
+        // So were imports that hadn't finished.
+        db.rollback_pending_imports()?;
+        Ok(db)
+    }
+
+    /// Open another connection to a database `open_for_app` has set up, for work that
+    /// shouldn't hold the main one, such as an import.
+    pub fn open(app_data_dir: &Path) -> Result<Self> {
+        let conn = Connection::open(app_data_dir.join("novelparser.db"))?;
+        // Imports write through their own connection; wait for them instead of failing.
+        conn.busy_timeout(std::time::Duration::from_secs(10))?;
+        conn.execute_batch("PRAGMA foreign_keys=ON;")?;
+        Ok(Self { conn })
+    }
+
+    #[cfg(test)]
+    pub fn open_in_memory() -> Result<Self> {
+        let db = Self {
+            conn: Connection::open_in_memory()?,
+        };
         db.init_tables()?;
         Ok(db)
     }
@@ -39,6 +69,13 @@ impl Database {
                 analysis TEXT
             );
 
+            CREATE TABLE IF NOT EXISTS volumes (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                novel_id TEXT NOT NULL REFERENCES novels(id) ON DELETE CASCADE,
+                volume_index INTEGER NOT NULL,
+                title TEXT NOT NULL DEFAULT ''
+            );
+
             CREATE TABLE IF NOT EXISTS novel_summaries (
                 novel_id TEXT PRIMARY KEY REFERENCES novels(id) ON DELETE CASCADE,
                 summary TEXT NOT NULL
@@ -57,26 +94,173 @@ impl Database {
                 value TEXT NOT NULL
             );
 
+            CREATE TABLE IF NOT EXISTS jobs (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                novel_id TEXT NOT NULL REFERENCES novels(id) ON DELETE CASCADE,
+                chapter_id INTEGER NOT NULL REFERENCES chapters(id) ON DELETE CASCADE,
+                dimensions TEXT NOT NULL DEFAULT '[]',
+                status TEXT NOT NULL DEFAULT 'queued',
+                attempts INTEGER NOT NULL DEFAULT 0,
+                last_error TEXT,
+                created_at TEXT NOT NULL,
+                updated_at TEXT NOT NULL
+            );
+
+            CREATE TABLE IF NOT EXISTS llm_calls (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                novel_id TEXT NOT NULL REFERENCES novels(id) ON DELETE CASCADE,
+                chapter_id INTEGER REFERENCES chapters(id) ON DELETE SET NULL,
+                kind TEXT NOT NULL,
+                model TEXT NOT NULL,
+                prompt TEXT NOT NULL,
+                response TEXT,
+                error TEXT,
+                attempts INTEGER NOT NULL DEFAULT 1,
+                latency_ms INTEGER NOT NULL DEFAULT 0,
+                prompt_tokens INTEGER,
+                completion_tokens INTEGER,
+                created_at TEXT NOT NULL
+            );
+
+            CREATE TABLE IF NOT EXISTS chapter_analysis_versions (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                chapter_id INTEGER NOT NULL REFERENCES chapters(id) ON DELETE CASCADE,
+                analysis TEXT NOT NULL,
+                model TEXT NOT NULL DEFAULT '',
+                dimensions TEXT NOT NULL DEFAULT '[]',
+                prompt_version TEXT NOT NULL DEFAULT '',
+                created_at TEXT NOT NULL
+            );
+
+            -- Imports whose chapters are being committed in batches. Until the row is gone the
+            -- chapters after last_chapter_id (or the whole novel, for a new one) can be rolled back.
+            CREATE TABLE IF NOT EXISTS pending_imports (
+                novel_id TEXT PRIMARY KEY REFERENCES novels(id) ON DELETE CASCADE,
+                new_novel INTEGER NOT NULL,
+                last_chapter_id INTEGER NOT NULL,
+                last_volume_id INTEGER NOT NULL
+            );
+
             CREATE INDEX IF NOT EXISTS idx_chapters_novel ON chapters(novel_id, chapter_index);
+            CREATE INDEX IF NOT EXISTS idx_volumes_novel ON volumes(novel_id, volume_index);
+            CREATE INDEX IF NOT EXISTS idx_jobs_novel ON jobs(novel_id, status);
+            CREATE INDEX IF NOT EXISTS idx_llm_calls_novel ON llm_calls(novel_id, chapter_id);
+            CREATE INDEX IF NOT EXISTS idx_versions_chapter ON chapter_analysis_versions(chapter_id);
             ",
         )?;
+
+        // Columns added after the first release; older databases get them here.
+        self.add_column_if_missing("chapters", "raw_response", "TEXT")?;
+        // Token count of the content under the tokenizer named in token_counter.
+        let counted = self.add_column_if_missing("chapters", "token_count", "INTEGER")?;
+        self.add_column_if_missing("chapters", "token_counter", "TEXT")?;
+        if counted {
+            self.fill_token_estimates()?;
+        }
+        // JSON array of the dimensions in the active analysis, kept for chapter lists.
+        if self.add_column_if_missing("chapters", "analyzed_dimensions", "TEXT")? {
+            self.fill_analyzed_dimensions()?;
+        }
+        self.add_column_if_missing(
+            "chapters",
+            "volume_id",
+            "INTEGER REFERENCES volumes(id) ON DELETE SET NULL",
+        )?;
+        self.add_column_if_missing("jobs", "update_mode", "TEXT NOT NULL DEFAULT 'replace'")?;
+        self.add_column_if_missing("novels", "conversion", "TEXT")?;
+        self.add_column_if_missing("chapters", "notes", "TEXT")?;
+        for column in ["author", "language", "publisher", "series", "series_index"] {
+            self.add_column_if_missing("novels", column, "TEXT")?;
+        }
+        // JSON array of strings
+        self.add_column_if_missing("novels", "identifiers", "TEXT")?;
+        self.add_column_if_missing("novels", "cover", "BLOB")?;
+        self.add_column_if_missing("novels", "cover_mime", "TEXT")?;
+        self.add_column_if_missing("novels", "import_options", "TEXT")?;
+        self.add_column_if_missing("chapters", "analysis_stale", "INTEGER NOT NULL DEFAULT 0")?;
+        if self.add_column_if_missing("chapters", "content_hash", "TEXT")? {
+            self.fill_content_hashes()?;
+        }
+        // SimHash of the content, for finding near-duplicate chapters; NULL for short texts.
+        if self.add_column_if_missing("chapters", "simhash", "INTEGER")? {
+            self.fill_simhashes()?;
+        }
+        if self.add_column_if_missing("chapters", "active_version_id", "INTEGER")? {
+            // Keep analyses saved before version history existed as their first version.
+            self.conn.execute_batch(
+                "INSERT INTO chapter_analysis_versions (chapter_id, analysis, created_at)
+                 SELECT id, analysis, datetime('now') FROM chapters WHERE analysis IS NOT NULL;
+                 UPDATE chapters SET active_version_id = (
+                     SELECT MAX(v.id) FROM chapter_analysis_versions v WHERE v.chapter_id = chapters.id
+                 ) WHERE analysis IS NOT NULL;",
+            )?;
+        }
         Ok(())
     }
 
+    /// Returns whether the column had to be added.
+    fn add_column_if_missing(&self, table: &str, column: &str, decl: &str) -> Result<bool> {
+        let exists: bool = self.conn.query_row(
+            &format!(
+                "SELECT COUNT(*) > 0 FROM pragma_table_info('{}') WHERE name = ?1",
+                table
+            ),
+            params![column],
+            |row| row.get(0),
+        )?;
+        if !exists {
+            self.conn.execute_batch(&format!(
+                "ALTER TABLE {} ADD COLUMN {} {}",
+                table, column, decl
+            ))?;
+        }
+        Ok(!exists)
+    }
+
     // ---- Novel CRUD ----
 
+    /// Insert or update a novel. The cover is kept; it is written by `save_novel_cover`.
     pub fn save_novel(&self, novel: &Novel) -> Result<()> {
         let source_type_json = serde_json::to_string(&novel.source_type).unwrap_or_default();
         let dims_json = serde_json::to_string(&novel.enabled_dimensions).unwrap_or_default();
+        let conversion_json = novel
+            .conversion
+            .map(|c| serde_json::to_string(&c).unwrap_or_default());
+        let options_json = serde_json::to_string(&novel.import_options).unwrap_or_default();
+        let meta = &novel.metadata;
+        let identifiers_json = serde_json::to_string(&meta.identifiers).unwrap_or_default();
         self.conn.execute(
-            "INSERT OR REPLACE INTO novels (id, title, source_type, enabled_dimensions, created_at)
-             VALUES (?1, ?2, ?3, ?4, ?5)",
+            "INSERT INTO novels (id, title, source_type, enabled_dimensions, created_at, conversion,
+                                 author, language, publisher, series, series_index, identifiers,
+                                 import_options)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13)
+             ON CONFLICT(id) DO UPDATE SET
+                title = excluded.title,
+                source_type = excluded.source_type,
+                import_options = excluded.import_options,
+                enabled_dimensions = excluded.enabled_dimensions,
+                created_at = excluded.created_at,
+                conversion = excluded.conversion,
+                author = excluded.author,
+                language = excluded.language,
+                publisher = excluded.publisher,
+                series = excluded.series,
+                series_index = excluded.series_index,
+                identifiers = excluded.identifiers",
             params![
                 novel.id,
                 novel.title,
                 source_type_json,
                 dims_json,
-                novel.created_at
+                novel.created_at,
+                conversion_json,
+                meta.author,
+                meta.language,
+                meta.publisher,
+                meta.series,
+                meta.series_index,
+                identifiers_json,
+                options_json
             ],
         )?;
         Ok(())
@@ -84,17 +268,29 @@ impl Database {
 
     pub fn load_novel(&self, id: &str) -> Result<Novel> {
         self.conn.query_row(
-            "SELECT id, title, source_type, enabled_dimensions, created_at FROM novels WHERE id = ?1",
+            "SELECT id, title, source_type, enabled_dimensions, created_at, conversion,
+                    author, language, publisher, series, series_index, identifiers,
+                    cover IS NOT NULL, import_options
+             FROM novels WHERE id = ?1",
             params![id],
             |row| {
                 let source_type_str: String = row.get(2)?;
                 let dims_str: String = row.get(3)?;
+                let conversion_str: Option<String> = row.get(5)?;
+                let options_str: Option<String> = row.get(13)?;
                 Ok(Novel {
                     id: row.get(0)?,
                     title: row.get(1)?,
-                    source_type: serde_json::from_str(&source_type_str).unwrap_or(SourceType::Epub(String::new())),
+                    source_type: serde_json::from_str(&source_type_str)
+                        .unwrap_or(SourceType::Epub(String::new())),
+                    import_options: options_str
+                        .and_then(|s| serde_json::from_str(&s).ok())
+                        .unwrap_or_default(),
                     enabled_dimensions: serde_json::from_str(&dims_str).unwrap_or_default(),
                     created_at: row.get(4)?,
+                    conversion: conversion_str.and_then(|s| serde_json::from_str(&s).ok()),
+                    metadata: book_metadata_from_row(row, 6)?,
+                    has_cover: row.get(12)?,
                 })
             },
         )
@@ -104,9 +300,12 @@ impl Database {
         let mut stmt = self.conn.prepare(
             "SELECT n.id, n.title, n.created_at,
                     COUNT(c.id) as chapter_count,
-                    COUNT(c.analysis) as analyzed_count
+                    COUNT(c.analysis) as analyzed_count,
+                    n.author, n.language, n.publisher, n.series, n.series_index, n.identifiers,
+                    n.cover IS NOT NULL
              FROM novels n
              LEFT JOIN chapters c ON c.novel_id = n.id
+             WHERE n.id NOT IN (SELECT novel_id FROM pending_imports WHERE new_novel = 1)
              GROUP BY n.id
              ORDER BY n.created_at DESC",
         )?;
@@ -118,6 +317,229 @@ impl Database {
                     created_at: row.get(2)?,
                     chapter_count: row.get::<_, i64>(3)? as usize,
                     analyzed_count: row.get::<_, i64>(4)? as usize,
+                    metadata: book_metadata_from_row(row, 5)?,
+                    has_cover: row.get(11)?,
+                })
+            })?
+            .collect::<Result<Vec<_>>>()?;
+        Ok(results)
+    }
+
+    /// Store or clear a novel's cover image.
+    pub fn save_novel_cover(&self, novel_id: &str, cover: Option<&CoverImage>) -> Result<()> {
+        self.conn.execute(
+            "UPDATE novels SET cover = ?2, cover_mime = ?3 WHERE id = ?1",
+            params![
+                novel_id,
+                cover.map(|c| c.data.as_slice()),
+                cover.map(|c| c.mime.as_str())
+            ],
+        )?;
+        Ok(())
+    }
+
+    pub fn load_novel_cover(&self, novel_id: &str) -> Result<Option<CoverImage>> {
+        let cover = self.conn.query_row(
+            "SELECT cover, cover_mime FROM novels WHERE id = ?1",
+            params![novel_id],
+            |row| {
+                let data: Option<Vec<u8>> = row.get(0)?;
+                let mime: Option<String> = row.get(1)?;
+                Ok(data.map(|data| CoverImage {
+                    data,
+                    mime: mime.unwrap_or_else(|| "image/jpeg".to_string()),
+                }))
+            },
+        );
+        match cover {
+            Ok(cover) => Ok(cover),
+            Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Create a new novel from chapters parsed with `import_options` and return its id.
+    pub fn create_novel(
+        &self,
+        book: BookInfo,
+        source_type: SourceType,
+        import_options: ImportOptions,
+        conversion: Option<ChineseConversion>,
+        chapters: Vec<ParsedChapter>,
+    ) -> Result<String> {
+        let novel_id = uuid::Uuid::new_v4().to_string();
+        let novel = Novel {
+            id: novel_id.clone(),
+            title: book.title,
+            source_type,
+            import_options,
+            enabled_dimensions: AnalysisDimension::default_set(),
+            created_at: chrono::Utc::now().to_rfc3339(),
+            conversion,
+            metadata: book.metadata,
+            has_cover: book.cover.is_some(),
+        };
+        self.save_novel(&novel)?;
+        if let Some(cover) = &book.cover {
+            self.save_novel_cover(&novel_id, Some(cover))?;
+        }
+
+        self.append_chapters(&novel_id, chapters)?;
+        Ok(novel_id)
+    }
+
+    /// Add parsed chapters after the last chapter of a novel and return their ids. Each run
+    /// of consecutive chapters naming the same volume becomes one volume; a run naming the
+    /// novel's last volume continues it.
+    pub fn append_chapters(
+        &self,
+        novel_id: &str,
+        chapters: Vec<ParsedChapter>,
+    ) -> Result<Vec<i64>> {
+        let mut appender = self.chapter_appender(novel_id)?;
+        chapters
+            .into_iter()
+            .map(|parsed| appender.push(parsed))
+            .collect()
+    }
+
+    /// Append chapters one at a time, the way [`Database::append_chapters`] does, so a long
+    /// import can report progress and stop between chapters.
+    pub fn chapter_appender(&self, novel_id: &str) -> Result<ChapterAppender<'_>> {
+        let next_index = self.chapter_order(novel_id)?.len();
+        let volumes = self.list_volumes(novel_id)?;
+        let volume_count = volumes.len();
+        let last_volume = self.conn.query_row(
+            "SELECT volume_id FROM chapters WHERE novel_id = ?1
+             ORDER BY chapter_index DESC LIMIT 1",
+            params![novel_id],
+            |row| row.get::<_, Option<i64>>(0),
+        );
+        let last_volume = match last_volume {
+            Ok(id) => id,
+            Err(rusqlite::Error::QueryReturnedNoRows) => None,
+            Err(e) => return Err(e),
+        };
+        let current_volume = volumes
+            .into_iter()
+            .find(|v| Some(v.id) == last_volume)
+            .map(|v| (v.title, v.id));
+        Ok(ChapterAppender {
+            db: self,
+            novel_id: novel_id.to_string(),
+            next_index,
+            volume_count,
+            current_volume,
+            counter: self.token_counter(),
+        })
+    }
+
+    /// Start a transaction covering everything done through this database until it is
+    /// committed. Dropping it uncommitted rolls all of it back.
+    pub fn begin_transaction(&self) -> Result<rusqlite::Transaction<'_>> {
+        self.conn.unchecked_transaction()
+    }
+
+    // ---- Pending Imports ----
+
+    /// Record that an import into a novel has started, so the chapters it commits from now
+    /// on can be rolled back. A novel created for the import is left out of `list_novels`
+    /// until `finish_import`. Fails while another import into the novel is pending.
+    pub fn begin_import(&self, novel_id: &str, new_novel: bool) -> Result<()> {
+        self.conn.execute(
+            "INSERT INTO pending_imports (novel_id, new_novel, last_chapter_id, last_volume_id)
+             VALUES (?1, ?2,
+                     (SELECT COALESCE(MAX(id), 0) FROM chapters WHERE novel_id = ?1),
+                     (SELECT COALESCE(MAX(id), 0) FROM volumes WHERE novel_id = ?1))",
+            params![novel_id, new_novel],
+        )?;
+        Ok(())
+    }
+
+    /// Keep everything the pending import into a novel has stored.
+    pub fn finish_import(&self, novel_id: &str) -> Result<()> {
+        self.conn.execute(
+            "DELETE FROM pending_imports WHERE novel_id = ?1",
+            params![novel_id],
+        )?;
+        Ok(())
+    }
+
+    /// Remove everything the pending import into a novel has stored: the novel itself if the
+    /// import created it, otherwise the chapters and volumes it added.
+    pub fn rollback_import(&self, novel_id: &str) -> Result<()> {
+        let pending = self.conn.query_row(
+            "SELECT new_novel, last_chapter_id, last_volume_id FROM pending_imports
+             WHERE novel_id = ?1",
+            params![novel_id],
+            |row| {
+                Ok((
+                    row.get::<_, bool>(0)?,
+                    row.get::<_, i64>(1)?,
+                    row.get::<_, i64>(2)?,
+                ))
+            },
+        );
+        let (new_novel, last_chapter_id, last_volume_id) = match pending {
+            Ok(pending) => pending,
+            Err(rusqlite::Error::QueryReturnedNoRows) => return Ok(()),
+            Err(e) => return Err(e),
+        };
+        let tx = self.conn.unchecked_transaction()?;
+        if new_novel {
+            tx.execute("DELETE FROM novels WHERE id = ?1", params![novel_id])?;
+        } else {
+            tx.execute(
+                "DELETE FROM chapters WHERE novel_id = ?1 AND id > ?2",
+                params![novel_id, last_chapter_id],
+            )?;
+            tx.execute(
+                "DELETE FROM volumes WHERE novel_id = ?1 AND id > ?2",
+                params![novel_id, last_volume_id],
+            )?;
+            self.renumber_chapters(novel_id)?;
+            self.finish_import(novel_id)?;
+        }
+        tx.commit()
+    }
+
+    /// Roll back every pending import; see [`Database::rollback_import`].
+    fn rollback_pending_imports(&self) -> Result<()> {
+        let mut stmt = self.conn.prepare("SELECT novel_id FROM pending_imports")?;
+        let novel_ids = stmt
+            .query_map([], |row| row.get::<_, String>(0))?
+            .collect::<Result<Vec<_>>>()?;
+        for novel_id in novel_ids {
+            self.rollback_import(&novel_id)?;
+        }
+        Ok(())
+    }
+
+    // ---- Volumes ----
+
+    fn create_volume(&self, novel_id: &str, index: usize, title: &str) -> Result<i64> {
+        self.conn.execute(
+            "INSERT INTO volumes (novel_id, volume_index, title) VALUES (?1, ?2, ?3)",
+            params![novel_id, index as i64, title],
+        )?;
+        Ok(self.conn.last_insert_rowid())
+    }
+
+    /// Volumes of a novel in reading order, with how many chapters each holds.
+    pub fn list_volumes(&self, novel_id: &str) -> Result<Vec<Volume>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT v.id, v.novel_id, v.volume_index, v.title,
+                    (SELECT COUNT(*) FROM chapters c WHERE c.volume_id = v.id)
+             FROM volumes v WHERE v.novel_id = ?1 ORDER BY v.volume_index",
+        )?;
+        let results = stmt
+            .query_map(params![novel_id], |row| {
+                Ok(Volume {
+                    id: row.get(0)?,
+                    novel_id: row.get(1)?,
+                    index: row.get::<_, i64>(2)? as usize,
+                    title: row.get(3)?,
+                    chapter_count: row.get::<_, i64>(4)? as usize,
                 })
             })?
             .collect::<Result<Vec<_>>>()?;
@@ -133,9 +555,15 @@ impl Database {
     // ---- Chapter CRUD ----
 
     pub fn save_chapter(&self, chapter: &Chapter) -> Result<i64> {
+        self.insert_chapter(chapter, &*self.token_counter())
+    }
+
+    fn insert_chapter(&self, chapter: &Chapter, counter: &dyn TokenCounter) -> Result<i64> {
         self.conn.execute(
-            "INSERT INTO chapters (novel_id, chapter_index, title, content, analysis)
-             VALUES (?1, ?2, ?3, ?4, ?5)",
+            "INSERT INTO chapters (novel_id, chapter_index, title, content, analysis, volume_id, notes,
+                                   content_hash, simhash, analysis_stale, token_count, token_counter,
+                                   analyzed_dimensions)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13)",
             params![
                 chapter.novel_id,
                 chapter.index as i64,
@@ -145,35 +573,102 @@ impl Database {
                     .analysis
                     .as_ref()
                     .map(|a| serde_json::to_string(a).unwrap_or_default()),
+                chapter.volume_id,
+                chapter.notes,
+                content_hash(&chapter.content),
+                simhash(&chapter.content),
+                chapter.analysis_stale,
+                counter.count(&chapter.content) as i64,
+                counter.name(),
+                chapter.analysis.as_ref().map(dimensions_json),
             ],
         )?;
         Ok(self.conn.last_insert_rowid())
     }
 
+    /// The token counter configured for the current model; the heuristic when its tokenizer
+    /// can't be loaded.
+    pub fn token_counter(&self) -> Arc<dyn TokenCounter> {
+        self.load_llm_config()
+            .ok()
+            .and_then(|config| token_utils::tokenizer_for(&config).ok())
+            .unwrap_or_else(|| Arc::new(token_utils::Heuristic))
+    }
+
+    /// Chapter list of a novel, read from columns kept up to date on write. Token estimates
+    /// are the counts stored with each chapter; after the tokenizer changes they are the old
+    /// tokenizer's until `recount_tokens` has run.
     pub fn list_chapter_metas(&self, novel_id: &str) -> Result<Vec<ChapterMeta>> {
         let mut stmt = self.conn.prepare(
-            "SELECT id, chapter_index, title, analysis, LENGTH(content) as content_len
+            "SELECT id, chapter_index, title, analysis IS NOT NULL, raw_response IS NOT NULL,
+                    COALESCE(token_count, 0), volume_id, analysis_stale, analyzed_dimensions
              FROM chapters WHERE novel_id = ?1 ORDER BY chapter_index",
         )?;
         let results = stmt
             .query_map(params![novel_id], |row| {
-                let analysis_str: Option<String> = row.get(3)?;
-                let content_len: i64 = row.get(4)?;
+                let dims_str: Option<String> = row.get(8)?;
                 Ok(ChapterMeta {
                     id: row.get(0)?,
                     index: row.get::<_, i64>(1)? as usize,
                     title: row.get(2)?,
-                    has_analysis: analysis_str.is_some(),
-                    token_estimate: (content_len as f64 * 1.5) as usize,
+                    has_analysis: row.get(3)?,
+                    analyzed_dimensions: dims_str
+                        .and_then(|s| serde_json::from_str(&s).ok())
+                        .unwrap_or_default(),
+                    has_raw_response: row.get(4)?,
+                    token_estimate: row.get::<_, i64>(5)? as usize,
+                    volume_id: row.get(6)?,
+                    analysis_stale: row.get(7)?,
                 })
             })?
             .collect::<Result<Vec<_>>>()?;
         Ok(results)
     }
 
+    /// Count the tokens of chapters not yet counted by the configured tokenizer, one novel
+    /// per transaction. A whole library takes a while, so call this on a connection of its
+    /// own rather than the shared one. Returns how many chapters were counted.
+    pub fn recount_tokens(&self) -> Result<usize> {
+        let counter = self.token_counter();
+        let counter_name = counter.name();
+        let mut recounted = 0;
+        for novel in self.list_novels()? {
+            let uncounted: Vec<(i64, String)> = self
+                .conn
+                .prepare(
+                    "SELECT id, content FROM chapters
+                     WHERE novel_id = ?1 AND token_counter IS NOT ?2",
+                )?
+                .query_map(params![novel.id, counter_name], |row| {
+                    Ok((row.get(0)?, row.get(1)?))
+                })?
+                .collect::<Result<_>>()?;
+            if uncounted.is_empty() {
+                continue;
+            }
+            let counts: Vec<(i64, usize)> = uncounted
+                .iter()
+                .map(|(id, content)| (*id, counter.count(content)))
+                .collect();
+            let tx = self.conn.unchecked_transaction()?;
+            for (id, count) in &counts {
+                // Skip chapters whose text changed meanwhile; they were counted on write.
+                tx.execute(
+                    "UPDATE chapters SET token_count = ?1, token_counter = ?2
+                     WHERE id = ?3 AND token_counter IS NOT ?2",
+                    params![*count as i64, counter_name, id],
+                )?;
+            }
+            tx.commit()?;
+            recounted += counts.len();
+        }
+        Ok(recounted)
+    }
+
     pub fn load_chapter(&self, chapter_id: i64) -> Result<Chapter> {
         self.conn.query_row(
-            "SELECT id, novel_id, chapter_index, title, content, analysis
+            "SELECT id, novel_id, chapter_index, title, content, analysis, volume_id, notes,
+                    analysis_stale
              FROM chapters WHERE id = ?1",
             params![chapter_id],
             |row| {
@@ -185,6 +680,9 @@ impl Database {
                     title: row.get(3)?,
                     content: row.get(4)?,
                     analysis: analysis_str.and_then(|s| serde_json::from_str(&s).ok()),
+                    volume_id: row.get(6)?,
+                    notes: row.get(7)?,
+                    analysis_stale: row.get(8)?,
                 })
             },
         )
@@ -198,33 +696,487 @@ impl Database {
         )
     }
 
-    pub fn save_chapter_analysis(&self, chapter_id: i64, analysis: &ChapterAnalysis) -> Result<()> {
+    /// Store a new analysis version and make it the chapter's active analysis.
+    pub fn save_chapter_analysis(
+        &self,
+        chapter_id: i64,
+        analysis: &ChapterAnalysis,
+        origin: &AnalysisOrigin,
+    ) -> Result<i64> {
         let json = serde_json::to_string(analysis).unwrap_or_default();
+        let dims_json = serde_json::to_string(&origin.dimensions).unwrap_or_default();
+        let tx = self.conn.unchecked_transaction()?;
+        tx.execute(
+            "INSERT INTO chapter_analysis_versions (chapter_id, analysis, model, dimensions, prompt_version, created_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            params![
+                chapter_id,
+                json,
+                origin.model,
+                dims_json,
+                origin.prompt_version,
+                chrono::Utc::now().to_rfc3339()
+            ],
+        )?;
+        let version_id = tx.last_insert_rowid();
+        tx.execute(
+            "UPDATE chapters SET analysis = ?1, active_version_id = ?2, raw_response = NULL,
+                 analysis_stale = 0, analyzed_dimensions = ?3
+             WHERE id = ?4",
+            params![json, version_id, dimensions_json(analysis), chapter_id],
+        )?;
+        tx.commit()?;
+        Ok(version_id)
+    }
+
+    /// All analysis versions of a chapter, newest first.
+    pub fn list_analysis_versions(&self, chapter_id: i64) -> Result<Vec<AnalysisVersion>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT v.id, v.chapter_id, v.analysis, v.model, v.dimensions, v.prompt_version,
+                    v.created_at, c.active_version_id IS v.id
+             FROM chapter_analysis_versions v
+             JOIN chapters c ON c.id = v.chapter_id
+             WHERE v.chapter_id = ?1
+             ORDER BY v.id DESC",
+        )?;
+        let results = stmt
+            .query_map(params![chapter_id], Self::row_to_version)?
+            .collect::<Result<Vec<_>>>()?;
+        Ok(results)
+    }
+
+    pub fn load_analysis_version(&self, version_id: i64) -> Result<AnalysisVersion> {
+        self.conn.query_row(
+            "SELECT v.id, v.chapter_id, v.analysis, v.model, v.dimensions, v.prompt_version,
+                    v.created_at, c.active_version_id IS v.id
+             FROM chapter_analysis_versions v
+             JOIN chapters c ON c.id = v.chapter_id
+             WHERE v.id = ?1",
+            params![version_id],
+            Self::row_to_version,
+        )
+    }
+
+    fn row_to_version(row: &rusqlite::Row) -> Result<AnalysisVersion> {
+        let analysis_str: String = row.get(2)?;
+        let dims_str: String = row.get(4)?;
+        Ok(AnalysisVersion {
+            id: row.get(0)?,
+            chapter_id: row.get(1)?,
+            analysis: serde_json::from_str(&analysis_str).unwrap_or_default(),
+            model: row.get(3)?,
+            dimensions: serde_json::from_str(&dims_str).unwrap_or_default(),
+            prompt_version: row.get(5)?,
+            created_at: row.get(6)?,
+            active: row.get(7)?,
+        })
+    }
+
+    /// Make an earlier version the chapter's active analysis again. Fails with
+    /// `QueryReturnedNoRows` unless the version belongs to the chapter.
+    pub fn set_active_analysis_version(&self, chapter_id: i64, version_id: i64) -> Result<()> {
+        let version = self.load_analysis_version(version_id)?;
+        let updated = self.conn.execute(
+            "UPDATE chapters SET
+                 analysis = (SELECT analysis FROM chapter_analysis_versions WHERE id = ?1),
+                 active_version_id = ?1, analyzed_dimensions = ?2, analysis_stale = 0,
+                 raw_response = NULL
+             WHERE id = ?3 AND id = ?4",
+            params![
+                version_id,
+                dimensions_json(&version.analysis),
+                chapter_id,
+                version.chapter_id
+            ],
+        )?;
+        if updated != 1 {
+            return Err(rusqlite::Error::QueryReturnedNoRows);
+        }
+        Ok(())
+    }
+
+    /// Keep an LLM response that could not be parsed, so it can be repaired later.
+    pub fn save_raw_response(&self, chapter_id: i64, raw: &str) -> Result<()> {
         self.conn.execute(
-            "UPDATE chapters SET analysis = ?1 WHERE id = ?2",
-            params![json, chapter_id],
+            "UPDATE chapters SET raw_response = ?1 WHERE id = ?2",
+            params![raw, chapter_id],
         )?;
         Ok(())
     }
 
+    pub fn load_raw_response(&self, chapter_id: i64) -> Result<Option<String>> {
+        self.conn.query_row(
+            "SELECT raw_response FROM chapters WHERE id = ?1",
+            params![chapter_id],
+            |row| row.get(0),
+        )
+    }
+
     pub fn delete_chapter(&self, chapter_id: i64) -> Result<()> {
-        self.conn
-            .execute("DELETE FROM chapters WHERE id = ?1", params![chapter_id])?;
-        Ok(())
+        self.delete_chapters(&[chapter_id])
     }
 
     pub fn delete_chapters(&self, chapter_ids: &[i64]) -> Result<()> {
         let tx = self.conn.unchecked_transaction()?;
+        let mut novel_ids = Vec::new();
         for &id in chapter_ids {
+            if let Ok(novel_id) = self.chapter_novel_id(id) {
+                if !novel_ids.contains(&novel_id) {
+                    novel_ids.push(novel_id);
+                }
+            }
             tx.execute("DELETE FROM chapters WHERE id = ?1", params![id])?;
         }
+        for novel_id in &novel_ids {
+            self.renumber_chapters(novel_id)?;
+        }
         tx.commit()?;
         Ok(())
     }
 
+    // ---- Chapter Editing ----
+
+    fn chapter_novel_id(&self, chapter_id: i64) -> Result<String> {
+        self.conn.query_row(
+            "SELECT novel_id FROM chapters WHERE id = ?1",
+            params![chapter_id],
+            |row| row.get(0),
+        )
+    }
+
+    /// Chapter ids of a novel in reading order.
+    fn chapter_order(&self, novel_id: &str) -> Result<Vec<i64>> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT id FROM chapters WHERE novel_id = ?1 ORDER BY chapter_index, id")?;
+        let ids = stmt
+            .query_map(params![novel_id], |row| row.get(0))?
+            .collect::<Result<Vec<i64>>>()?;
+        Ok(ids)
+    }
+
+    fn write_chapter_order(&self, chapter_ids: &[i64]) -> Result<()> {
+        for (i, id) in chapter_ids.iter().enumerate() {
+            self.conn.execute(
+                "UPDATE chapters SET chapter_index = ?1 WHERE id = ?2",
+                params![i as i64, id],
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Close the gaps left by removed chapters so indices run 0..n again.
+    fn renumber_chapters(&self, novel_id: &str) -> Result<()> {
+        self.write_chapter_order(&self.chapter_order(novel_id)?)
+    }
+
+    /// Replace a chapter's text. Its analysis, raw response and token count belonged to the
+    /// old text and are dropped; stored analysis versions are kept.
+    pub fn update_chapter_content(&self, chapter_id: i64, content: &str) -> Result<()> {
+        let counter = self.token_counter();
+        self.conn.execute(
+            "UPDATE chapters SET content = ?1, content_hash = ?2, simhash = ?3, analysis = NULL,
+                 analyzed_dimensions = NULL, active_version_id = NULL, analysis_stale = 0, raw_response = NULL,
+                 token_count = ?4, token_counter = ?5
+             WHERE id = ?6",
+            params![
+                content,
+                content_hash(content),
+                simhash(content),
+                counter.count(content) as i64,
+                counter.name(),
+                chapter_id
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// Replace a chapter's text with a newer edition from its source. Unlike
+    /// `update_chapter_content` the analysis is kept, flagged as stale.
+    pub fn resync_chapter_content(
+        &self,
+        chapter_id: i64,
+        content: &str,
+        notes: Option<&str>,
+    ) -> Result<()> {
+        let counter = self.token_counter();
+        self.conn.execute(
+            "UPDATE chapters SET content = ?1, content_hash = ?2, simhash = ?3, notes = ?4,
+                 analysis_stale = analysis IS NOT NULL, raw_response = NULL,
+                 token_count = ?5, token_counter = ?6
+             WHERE id = ?7",
+            params![
+                content,
+                content_hash(content),
+                simhash(content),
+                notes,
+                counter.count(content) as i64,
+                counter.name(),
+                chapter_id
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// Apply a resync in one transaction: give `modified` chapters their new text (keeping
+    /// their analyses, flagged stale) and append `added` ones. Returns the new chapters' ids.
+    pub fn resync_chapters(
+        &self,
+        novel_id: &str,
+        modified: &[(i64, ParsedChapter)],
+        added: Vec<ParsedChapter>,
+    ) -> Result<Vec<i64>> {
+        let tx = self.conn.unchecked_transaction()?;
+        for (id, chapter) in modified {
+            let notes = (!chapter.notes.is_empty()).then(|| chapter.notes.join("\n\n"));
+            self.resync_chapter_content(*id, &chapter.content, notes.as_deref())?;
+        }
+        let ids = self.append_chapters(novel_id, added)?;
+        tx.commit()?;
+        Ok(ids)
+    }
+
+    /// Id, title and content hash of each chapter of a novel, in reading order.
+    pub fn list_chapter_hashes(&self, novel_id: &str) -> Result<Vec<(i64, String, String)>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT id, title, content_hash FROM chapters
+             WHERE novel_id = ?1 ORDER BY chapter_index",
+        )?;
+        let results = stmt
+            .query_map(params![novel_id], |row| {
+                Ok((row.get(0)?, row.get(1)?, row.get(2)?))
+            })?
+            .collect::<Result<Vec<_>>>()?;
+        Ok(results)
+    }
+
+    /// Fingerprints of each chapter of a novel, in reading order.
+    pub fn list_chapter_fingerprints(&self, novel_id: &str) -> Result<Vec<ChapterFingerprint>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT id, chapter_index, title, content_hash, simhash FROM chapters
+             WHERE novel_id = ?1 ORDER BY chapter_index",
+        )?;
+        let results = stmt
+            .query_map(params![novel_id], |row| {
+                Ok(ChapterFingerprint {
+                    id: row.get(0)?,
+                    index: row.get::<_, i64>(1)? as usize,
+                    title: row.get(2)?,
+                    content_hash: row.get(3)?,
+                    simhash: row.get::<_, Option<i64>>(4)?.map(|h| h as u64),
+                })
+            })?
+            .collect::<Result<Vec<_>>>()?;
+        Ok(results)
+    }
+
+    /// Novel id and SimHash of every chapter in the library that has one.
+    pub fn list_library_simhashes(&self) -> Result<Vec<(String, u64)>> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT novel_id, simhash FROM chapters WHERE simhash IS NOT NULL")?;
+        let results = stmt
+            .query_map([], |row| Ok((row.get(0)?, row.get::<_, i64>(1)? as u64)))?
+            .collect::<Result<Vec<_>>>()?;
+        Ok(results)
+    }
+
+    /// Hash the content of chapters stored before hashes were kept.
+    fn fill_content_hashes(&self) -> Result<()> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT id, content FROM chapters WHERE content_hash IS NULL")?;
+        let rows = stmt
+            .query_map([], |row| {
+                Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?))
+            })?
+            .collect::<Result<Vec<_>>>()?;
+        let tx = self.conn.unchecked_transaction()?;
+        for (id, content) in rows {
+            tx.execute(
+                "UPDATE chapters SET content_hash = ?1 WHERE id = ?2",
+                params![content_hash(&content), id],
+            )?;
+        }
+        tx.commit()
+    }
+
+    /// Estimate the tokens of chapters stored before token counts were kept, so chapter lists
+    /// have a number until `recount_tokens` counts them with the configured tokenizer.
+    fn fill_token_estimates(&self) -> Result<()> {
+        let mut stmt = self.conn.prepare("SELECT id, content FROM chapters")?;
+        let rows = stmt
+            .query_map([], |row| {
+                Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?))
+            })?
+            .collect::<Result<Vec<_>>>()?;
+        let tx = self.conn.unchecked_transaction()?;
+        for (id, content) in rows {
+            tx.execute(
+                "UPDATE chapters SET token_count = ?1, token_counter = ?2 WHERE id = ?3",
+                params![
+                    token_utils::Heuristic.count(&content) as i64,
+                    token_utils::Heuristic.name(),
+                    id
+                ],
+            )?;
+        }
+        tx.commit()
+    }
+
+    /// Record the analyzed dimensions of chapters analyzed before they were kept.
+    fn fill_analyzed_dimensions(&self) -> Result<()> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT id, analysis FROM chapters WHERE analysis IS NOT NULL")?;
+        let rows = stmt
+            .query_map([], |row| {
+                Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?))
+            })?
+            .collect::<Result<Vec<_>>>()?;
+        let tx = self.conn.unchecked_transaction()?;
+        for (id, analysis) in rows {
+            let analysis: ChapterAnalysis = serde_json::from_str(&analysis).unwrap_or_default();
+            tx.execute(
+                "UPDATE chapters SET analyzed_dimensions = ?1 WHERE id = ?2",
+                params![dimensions_json(&analysis), id],
+            )?;
+        }
+        tx.commit()
+    }
+
+    /// SimHash the content of chapters stored before SimHashes were kept.
+    fn fill_simhashes(&self) -> Result<()> {
+        let mut stmt = self.conn.prepare("SELECT id, content FROM chapters")?;
+        let rows = stmt
+            .query_map([], |row| {
+                Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?))
+            })?
+            .collect::<Result<Vec<_>>>()?;
+        let tx = self.conn.unchecked_transaction()?;
+        for (id, content) in rows {
+            tx.execute(
+                "UPDATE chapters SET simhash = ?1 WHERE id = ?2",
+                params![simhash(&content), id],
+            )?;
+        }
+        tx.commit()
+    }
+
+    /// Renaming keeps the analysis; the text it was made from is unchanged.
+    pub fn rename_chapter(&self, chapter_id: i64, title: &str) -> Result<()> {
+        self.conn.execute(
+            "UPDATE chapters SET title = ?1 WHERE id = ?2",
+            params![title, chapter_id],
+        )?;
+        Ok(())
+    }
+
+    /// Merge chapters (adjacent, in reading order) into the first one, which gets `content`.
+    pub fn merge_chapters(
+        &self,
+        chapter_ids: &[i64],
+        content: &str,
+        notes: Option<&str>,
+    ) -> Result<()> {
+        let Some((&first, rest)) = chapter_ids.split_first() else {
+            return Ok(());
+        };
+        let tx = self.conn.unchecked_transaction()?;
+        let novel_id = self.chapter_novel_id(first)?;
+        self.update_chapter_content(first, content)?;
+        tx.execute(
+            "UPDATE chapters SET notes = ?1 WHERE id = ?2",
+            params![notes, first],
+        )?;
+        for &id in rest {
+            tx.execute("DELETE FROM chapters WHERE id = ?1", params![id])?;
+        }
+        self.renumber_chapters(&novel_id)?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Keep `head` in the chapter and insert `tail` right after it as a new chapter of the
+    /// same volume. Returns the new chapter's id.
+    pub fn split_chapter(
+        &self,
+        chapter_id: i64,
+        head: &str,
+        tail: &str,
+        tail_title: &str,
+    ) -> Result<i64> {
+        let tx = self.conn.unchecked_transaction()?;
+        let chapter = self.load_chapter(chapter_id)?;
+        self.update_chapter_content(chapter_id, head)?;
+        // Same index as the original; the higher id sorts it right after before renumbering.
+        let new_id = self.save_chapter(&Chapter {
+            id: None,
+            novel_id: chapter.novel_id.clone(),
+            index: chapter.index,
+            title: tail_title.to_string(),
+            content: tail.to_string(),
+            analysis: None,
+            volume_id: chapter.volume_id,
+            notes: None,
+            analysis_stale: false,
+        })?;
+        self.renumber_chapters(&chapter.novel_id)?;
+        tx.commit()?;
+        Ok(new_id)
+    }
+
+    /// Move a chapter to position `to_index` (clamped to the end). It joins the volume of
+    /// the chapter it now follows, or precedes when moved to the front. The chapter and
+    /// those that now follow something else were analyzed with other context, so their
+    /// analyses are flagged as stale.
+    pub fn move_chapter(&self, chapter_id: i64, to_index: usize) -> Result<()> {
+        let tx = self.conn.unchecked_transaction()?;
+        let novel_id = self.chapter_novel_id(chapter_id)?;
+        let old_order = self.chapter_order(&novel_id)?;
+        let mut order = old_order.clone();
+        order.retain(|&id| id != chapter_id);
+        let to_index = to_index.min(order.len());
+        order.insert(to_index, chapter_id);
+        self.write_chapter_order(&order)?;
+
+        if order != old_order {
+            let old_index = old_order.iter().position(|&id| id == chapter_id);
+            let reordered = [
+                Some(chapter_id),
+                old_index.and_then(|i| old_order.get(i + 1).copied()),
+                order.get(to_index + 1).copied(),
+            ];
+            for id in reordered.into_iter().flatten() {
+                tx.execute(
+                    "UPDATE chapters SET analysis_stale = analysis IS NOT NULL WHERE id = ?1",
+                    params![id],
+                )?;
+            }
+        }
+
+        let neighbour = if to_index > 0 {
+            order.get(to_index - 1)
+        } else {
+            order.get(1)
+        };
+        if let Some(&neighbour) = neighbour {
+            tx.execute(
+                "UPDATE chapters SET volume_id = (SELECT volume_id FROM chapters WHERE id = ?1)
+                 WHERE id = ?2",
+                params![neighbour, chapter_id],
+            )?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Unset the active analysis; stored versions are kept.
     pub fn clear_chapter_analysis(&self, chapter_id: i64) -> Result<()> {
         self.conn.execute(
-            "UPDATE chapters SET analysis = NULL WHERE id = ?1",
+            "UPDATE chapters SET analysis = NULL, analyzed_dimensions = NULL, active_version_id = NULL,
+                 analysis_stale = 0
+             WHERE id = ?1",
             params![chapter_id],
         )?;
         Ok(())
@@ -282,6 +1234,218 @@ impl Database {
     
             Ok(analyses)
         }
+    // ---- Analysis Jobs ----
+
+    /// Queue chapters for analysis, replacing any earlier job for the same chapter. Returns
+    /// the ids of the new jobs.
+    pub fn enqueue_jobs(
+        &self,
+        novel_id: &str,
+        chapter_ids: &[i64],
+        dimensions: &[AnalysisDimension],
+        update_mode: UpdateMode,
+    ) -> Result<Vec<i64>> {
+        let dims_json = serde_json::to_string(dimensions).unwrap_or_default();
+        let now = chrono::Utc::now().to_rfc3339();
+        let tx = self.conn.unchecked_transaction()?;
+        let mut job_ids = Vec::with_capacity(chapter_ids.len());
+        for &chapter_id in chapter_ids {
+            tx.execute(
+                "DELETE FROM jobs WHERE chapter_id = ?1",
+                params![chapter_id],
+            )?;
+            tx.execute(
+                "INSERT INTO jobs (novel_id, chapter_id, dimensions, update_mode, status, attempts, created_at, updated_at)
+                 VALUES (?1, ?2, ?3, ?4, 'queued', 0, ?5, ?5)",
+                params![novel_id, chapter_id, dims_json, update_mode.as_str(), now],
+            )?;
+            job_ids.push(tx.last_insert_rowid());
+        }
+        tx.commit()?;
+        Ok(job_ids)
+    }
+
+    pub fn list_jobs(&self, novel_id: &str) -> Result<Vec<AnalysisJob>> {
+        self.query_jobs(novel_id, None)
+    }
+
+    /// Queued jobs of a novel in chapter order.
+    pub fn list_queued_jobs(&self, novel_id: &str) -> Result<Vec<AnalysisJob>> {
+        self.query_jobs(novel_id, Some(JobStatus::Queued))
+    }
+
+    fn query_jobs(&self, novel_id: &str, status: Option<JobStatus>) -> Result<Vec<AnalysisJob>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT j.id, j.novel_id, j.chapter_id, c.chapter_index, c.title, j.dimensions,
+                    j.status, j.attempts, j.last_error, j.created_at, j.updated_at, j.update_mode
+             FROM jobs j
+             JOIN chapters c ON c.id = j.chapter_id
+             WHERE j.novel_id = ?1 AND (?2 IS NULL OR j.status = ?2)
+             ORDER BY c.chapter_index",
+        )?;
+        let results = stmt
+            .query_map(params![novel_id, status.map(|s| s.as_str())], |row| {
+                let dims_str: String = row.get(5)?;
+                let status_str: String = row.get(6)?;
+                let update_mode_str: String = row.get(11)?;
+                Ok(AnalysisJob {
+                    id: row.get(0)?,
+                    novel_id: row.get(1)?,
+                    chapter_id: row.get(2)?,
+                    chapter_index: row.get::<_, i64>(3)? as usize,
+                    chapter_title: row.get(4)?,
+                    dimensions: serde_json::from_str(&dims_str).unwrap_or_default(),
+                    update_mode: UpdateMode::parse(&update_mode_str),
+                    status: JobStatus::parse(&status_str),
+                    attempts: row.get::<_, i64>(7)? as u32,
+                    last_error: row.get(8)?,
+                    created_at: row.get(9)?,
+                    updated_at: row.get(10)?,
+                })
+            })?
+            .collect::<Result<Vec<_>>>()?;
+        Ok(results)
+    }
+
+    /// Mark a job as running and count the attempt.
+    pub fn start_job(&self, job_id: i64) -> Result<()> {
+        self.conn.execute(
+            "UPDATE jobs SET status = 'running', attempts = attempts + 1, updated_at = ?1
+             WHERE id = ?2",
+            params![chrono::Utc::now().to_rfc3339(), job_id],
+        )?;
+        Ok(())
+    }
+
+    pub fn finish_job(&self, job_id: i64, error: Option<&str>) -> Result<()> {
+        let status = if error.is_some() {
+            JobStatus::Failed
+        } else {
+            JobStatus::Done
+        };
+        self.conn.execute(
+            "UPDATE jobs SET status = ?1, last_error = ?2, updated_at = ?3 WHERE id = ?4",
+            params![
+                status.as_str(),
+                error,
+                chrono::Utc::now().to_rfc3339(),
+                job_id
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// Put jobs left running by an interrupted batch back in the queue.
+    pub fn requeue_running_jobs(&self, novel_id: &str) -> Result<()> {
+        self.conn.execute(
+            "UPDATE jobs SET status = 'queued' WHERE novel_id = ?1 AND status = 'running'",
+            params![novel_id],
+        )?;
+        Ok(())
+    }
+
+    pub fn retry_failed_jobs(&self, novel_id: &str) -> Result<usize> {
+        self.conn.execute(
+            "UPDATE jobs SET status = 'queued', updated_at = ?1 WHERE novel_id = ?2 AND status = 'failed'",
+            params![chrono::Utc::now().to_rfc3339(), novel_id],
+        )
+    }
+
+    /// Drop every job of a novel that is not currently running.
+    pub fn clear_jobs(&self, novel_id: &str) -> Result<()> {
+        self.conn.execute(
+            "DELETE FROM jobs WHERE novel_id = ?1 AND status != 'running'",
+            params![novel_id],
+        )?;
+        Ok(())
+    }
+
+    // ---- LLM Call Log ----
+
+    pub fn record_llm_call(&self, call: &LlmCall) -> Result<i64> {
+        self.conn.execute(
+            "INSERT INTO llm_calls (novel_id, chapter_id, kind, model, prompt, response, error,
+                                    attempts, latency_ms, prompt_tokens, completion_tokens, created_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12)",
+            params![
+                call.novel_id,
+                call.chapter_id,
+                call.kind.as_str(),
+                call.model,
+                call.prompt,
+                call.response,
+                call.error,
+                call.attempts,
+                call.latency_ms as i64,
+                call.prompt_tokens,
+                call.completion_tokens,
+                call.created_at,
+            ],
+        )?;
+        Ok(self.conn.last_insert_rowid())
+    }
+
+    /// Calls of a novel, newest first; limited to one chapter when `chapter_id` is given.
+    pub fn list_llm_calls(
+        &self,
+        novel_id: &str,
+        chapter_id: Option<i64>,
+    ) -> Result<Vec<LlmCallMeta>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT id, chapter_id, kind, model, response IS NOT NULL, error, attempts,
+                    latency_ms, prompt_tokens, completion_tokens, created_at
+             FROM llm_calls
+             WHERE novel_id = ?1 AND (?2 IS NULL OR chapter_id = ?2)
+             ORDER BY id DESC",
+        )?;
+        let results = stmt
+            .query_map(params![novel_id, chapter_id], |row| {
+                let kind_str: String = row.get(2)?;
+                Ok(LlmCallMeta {
+                    id: row.get(0)?,
+                    chapter_id: row.get(1)?,
+                    kind: LlmCallKind::parse(&kind_str),
+                    model: row.get(3)?,
+                    success: row.get(4)?,
+                    error: row.get(5)?,
+                    attempts: row.get(6)?,
+                    latency_ms: row.get::<_, i64>(7)? as u64,
+                    prompt_tokens: row.get(8)?,
+                    completion_tokens: row.get(9)?,
+                    created_at: row.get(10)?,
+                })
+            })?
+            .collect::<Result<Vec<_>>>()?;
+        Ok(results)
+    }
+
+    pub fn load_llm_call(&self, call_id: i64) -> Result<LlmCall> {
+        self.conn.query_row(
+            "SELECT id, novel_id, chapter_id, kind, model, prompt, response, error, attempts,
+                    latency_ms, prompt_tokens, completion_tokens, created_at
+             FROM llm_calls WHERE id = ?1",
+            params![call_id],
+            |row| {
+                let kind_str: String = row.get(3)?;
+                Ok(LlmCall {
+                    id: row.get(0)?,
+                    novel_id: row.get(1)?,
+                    chapter_id: row.get(2)?,
+                    kind: LlmCallKind::parse(&kind_str),
+                    model: row.get(4)?,
+                    prompt: row.get(5)?,
+                    response: row.get(6)?,
+                    error: row.get(7)?,
+                    attempts: row.get(8)?,
+                    latency_ms: row.get::<_, i64>(9)? as u64,
+                    prompt_tokens: row.get(10)?,
+                    completion_tokens: row.get(11)?,
+                    created_at: row.get(12)?,
+                })
+            },
+        )
+    }
+
     // ---- Novel Summary ----
 
     pub fn save_novel_summary(&self, novel_id: &str, summary: &NovelSummary) -> Result<()> {
@@ -356,6 +1520,35 @@ impl Database {
         }
     }
 
+    pub fn save_cleanup_config(&self, config: &CleanupConfig) -> Result<()> {
+        let json = serde_json::to_string(config).unwrap_or_default();
+        self.save_setting("cleanup_config", &json)
+    }
+
+    pub fn load_cleanup_config(&self) -> Result<CleanupConfig> {
+        match self.load_setting("cleanup_config")? {
+            Some(json) => Ok(serde_json::from_str(&json).unwrap_or_default()),
+            None => Ok(CleanupConfig::default()),
+        }
+    }
+
+    /// Built-in heading rule presets followed by the user's custom sets.
+    pub fn load_heading_rule_sets(&self) -> Result<Vec<HeadingRuleSet>> {
+        let mut sets = HeadingRuleSet::presets();
+        if let Some(json) = self.load_setting("heading_rules")? {
+            let custom: Vec<HeadingRuleSet> = serde_json::from_str(&json).unwrap_or_default();
+            sets.extend(custom.into_iter().filter(|s| !s.builtin));
+        }
+        Ok(sets)
+    }
+
+    /// Replace the custom heading rule sets; presets in `sets` are ignored.
+    pub fn save_heading_rule_sets(&self, sets: &[HeadingRuleSet]) -> Result<()> {
+        let custom: Vec<&HeadingRuleSet> = sets.iter().filter(|s| !s.builtin).collect();
+        let json = serde_json::to_string(&custom).unwrap_or_default();
+        self.save_setting("heading_rules", &json)
+    }
+
     // ---- Summary Cache ----
 
     pub fn save_summary_cache(
@@ -380,3 +1573,80 @@ impl Database {
         Ok(())
     }
 }
+
+/// Read the six metadata columns (author … identifiers) starting at `start`.
+fn book_metadata_from_row(row: &rusqlite::Row, start: usize) -> Result<BookMetadata> {
+    let identifiers: Option<String> = row.get(start + 5)?;
+    Ok(BookMetadata {
+        author: row.get(start)?,
+        language: row.get(start + 1)?,
+        publisher: row.get(start + 2)?,
+        series: row.get(start + 3)?,
+        series_index: row.get(start + 4)?,
+        identifiers: identifiers
+            .and_then(|s| serde_json::from_str(&s).ok())
+            .unwrap_or_default(),
+    })
+}
+
+/// Adds chapters after the last chapter of a novel; see [`Database::chapter_appender`].
+pub struct ChapterAppender<'a> {
+    db: &'a Database,
+    novel_id: String,
+    next_index: usize,
+    volume_count: usize,
+    /// Title and id of the volume the last chapter is in
+    current_volume: Option<(String, i64)>,
+    counter: Arc<dyn TokenCounter>,
+}
+
+impl ChapterAppender<'_> {
+    /// Store one chapter and return its id.
+    pub fn push(&mut self, parsed: ParsedChapter) -> Result<i64> {
+        let volume_id = match parsed.volume {
+            None => None,
+            Some(name) => match &self.current_volume {
+                Some((current, id)) if *current == name => Some(*id),
+                _ => {
+                    let id = self
+                        .db
+                        .create_volume(&self.novel_id, self.volume_count, &name)?;
+                    self.volume_count += 1;
+                    self.current_volume = Some((name, id));
+                    Some(id)
+                }
+            },
+        };
+        if volume_id.is_none() {
+            self.current_volume = None;
+        }
+        let chapter = Chapter {
+            id: None,
+            novel_id: self.novel_id.clone(),
+            index: self.next_index,
+            title: parsed.title,
+            content: parsed.content,
+            analysis: None,
+            volume_id,
+            notes: (!parsed.notes.is_empty()).then(|| parsed.notes.join("\n\n")),
+            analysis_stale: false,
+        };
+        self.next_index += 1;
+        self.db.insert_chapter(&chapter, &*self.counter)
+    }
+}
+
+/// SHA-256 of a chapter's text, hex-encoded. Identifies unchanged chapters on resync.
+pub fn content_hash(content: &str) -> String {
+    format!("{:x}", Sha256::digest(content.as_bytes()))
+}
+
+/// The `analyzed_dimensions` column for a chapter's active analysis.
+fn dimensions_json(analysis: &ChapterAnalysis) -> String {
+    serde_json::to_string(&analysis.dimensions()).unwrap_or_default()
+}
+
+/// The SimHash as SQLite stores it, a signed 64-bit integer.
+fn simhash(content: &str) -> Option<i64> {
+    fingerprint::simhash(content).map(|h| h as i64)
+}
@@ -0,0 +1,183 @@
+use chardetng::EncodingDetector;
+use encoding_rs::{Encoding, UTF_16BE, UTF_16LE, UTF_8};
+use serde::{Deserialize, Serialize};
+use std::fs;
+
+/// How a text file was decoded.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct DetectedEncoding {
+    /// Name of the encoding, e.g. "UTF-8", "GBK", "Big5", "Shift_JIS", "UTF-16LE"
+    pub name: String,
+    /// 0–1. 1 for a byte order mark, valid UTF-8 or an encoding chosen by the user
+    pub confidence: f32,
+    /// Some bytes were invalid in this encoding and were replaced with U+FFFD
+    pub lossy: bool,
+}
+
+impl DetectedEncoding {
+    /// Below this the guess is shown to the user as doubtful.
+    pub const LOW_CONFIDENCE: f32 = 0.6;
+
+    /// Why the decoded text may be garbled, if it may.
+    pub fn warning(&self) -> Option<String> {
+        if self.lossy {
+            Some(format!(
+                "按 {} 解码时有无法识别的字节，部分文字可能是乱码，可以手动指定编码",
+                self.name
+            ))
+        } else if self.confidence < Self::LOW_CONFIDENCE {
+            Some(format!(
+                "编码识别为 {}（置信度 {:.0}%），如果文字是乱码请手动指定编码",
+                self.name,
+                self.confidence * 100.0
+            ))
+        } else {
+            None
+        }
+    }
+}
+
+/// Read a text file, detecting its encoding unless `encoding` names one (any WHATWG label:
+/// "big5", "shift_jis", "utf-16le", "gbk"...).
+pub fn read_text(path: &str, encoding: Option<&str>) -> Result<(String, DetectedEncoding), String> {
+    let bytes = fs::read(path).map_err(|e| format!("无法读取文件 {}: {}", path, e))?;
+    decode(&bytes, encoding)
+}
+
+pub fn decode(bytes: &[u8], encoding: Option<&str>) -> Result<(String, DetectedEncoding), String> {
+    let (encoding, confidence) = match encoding.map(str::trim).filter(|l| !l.is_empty()) {
+        Some(label) => (
+            Encoding::for_label(label.as_bytes()).ok_or_else(|| format!("未知编码 {}", label))?,
+            1.0,
+        ),
+        None => detect(bytes),
+    };
+    let (text, lossy) = encoding.decode_with_bom_removal(bytes);
+    Ok((
+        text.into_owned(),
+        DetectedEncoding {
+            name: encoding.name().to_string(),
+            confidence,
+            lossy,
+        },
+    ))
+}
+
+/// Guess the encoding of `bytes` with a confidence between 0 and 1.
+fn detect(bytes: &[u8]) -> (&'static Encoding, f32) {
+    if let Some((encoding, _)) = Encoding::for_bom(bytes) {
+        return (encoding, 1.0);
+    }
+    if let Some(encoding) = detect_utf16(bytes) {
+        return (encoding, 0.9);
+    }
+    if std::str::from_utf8(bytes).is_ok() {
+        return (UTF_8, 1.0);
+    }
+
+    // Statistical detection over the legacy CJK and European encodings (GBK, Big5,
+    // Shift_JIS, EUC-JP, EUC-KR, windows-125x...).
+    let mut detector = EncodingDetector::new();
+    detector.feed(bytes, true);
+    let (encoding, clear_winner) = detector.guess_assess(None, true);
+    let confidence = if !clear_winner {
+        0.4
+    } else if encoding
+        .decode_without_bom_handling_and_without_replacement(bytes)
+        .is_none()
+    {
+        // The best candidate still has invalid bytes.
+        0.5
+    } else {
+        0.8
+    };
+    (encoding, confidence)
+}
+
+/// UTF-16 without a byte order mark. Text in any other encoding has no zero bytes; of the
+/// two byte orders, the wrong one decodes into surrogates and scattered scripts.
+fn detect_utf16(bytes: &[u8]) -> Option<&'static Encoding> {
+    let sample = &bytes[..bytes.len().min(8192) & !1];
+    if !sample.contains(&0) {
+        return None;
+    }
+    [UTF_16LE, UTF_16BE]
+        .into_iter()
+        .map(|encoding| {
+            let (text, _) = encoding.decode_without_bom_handling(sample);
+            (encoding, plausible_share(&text))
+        })
+        .filter(|&(_, share)| share > 0.8)
+        .max_by(|a, b| a.1.total_cmp(&b.1))
+        .map(|(encoding, _)| encoding)
+}
+
+/// Share of characters a novel is expected to consist of: ASCII, Latin-1, punctuation, CJK
+/// ideographs, kana, Hangul and full-width forms.
+fn plausible_share(text: &str) -> f32 {
+    let (mut plausible, mut total) = (0usize, 0usize);
+    for c in text.chars() {
+        total += 1;
+        if matches!(c,
+            '\t' | '\n' | '\r' | ' '..='~' | '\u{A0}'..='\u{FF}' | '\u{2000}'..='\u{206F}'
+            | '\u{3000}'..='\u{30FF}' | '\u{3400}'..='\u{4DBF}' | '\u{4E00}'..='\u{9FFF}'
+            | '\u{AC00}'..='\u{D7AF}' | '\u{FF00}'..='\u{FFEF}')
+        {
+            plausible += 1;
+        }
+    }
+    plausible as f32 / total.max(1) as f32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use encoding_rs::{BIG5, GBK, SHIFT_JIS};
+
+    const ZH: &str = "　　他推開門，走進了那間許久沒有人住過的屋子，灰塵在陽光裡緩緩飄動。\n　　窗外傳來幾聲鳥叫，他在桌邊坐下，翻開那本泛黃的日記，讀到天色漸暗。\n";
+    const ZH_HANS: &str = "　　他推开门，走进了那间许久没有人住过的屋子，灰尘在阳光里缓缓飘动。\n　　窗外传来几声鸟叫，他在桌边坐下，翻开那本泛黄的日记，读到天色渐暗。\n";
+    const JA: &str = "　　彼はドアを開けて、長い間誰も住んでいなかった部屋に入った。埃が光の中でゆっくりと舞っている。\n　　窓の外から鳥の声が聞こえた。\n";
+
+    fn detected(bytes: &[u8]) -> (String, DetectedEncoding) {
+        decode(bytes, None).unwrap()
+    }
+
+    #[test]
+    fn test_detect_legacy_encodings() {
+        for (encoding, text) in [(BIG5, ZH), (GBK, ZH_HANS), (SHIFT_JIS, JA)] {
+            let (bytes, _, _) = encoding.encode(text);
+            let (decoded, info) = detected(&bytes);
+            assert_eq!(info.name, encoding.name());
+            assert_eq!(decoded, text);
+            assert!(!info.lossy);
+        }
+    }
+
+    #[test]
+    fn test_detect_utf16() {
+        let le: Vec<u8> = ZH.encode_utf16().flat_map(u16::to_le_bytes).collect();
+        let (decoded, info) = detected(&le);
+        assert_eq!((decoded.as_str(), info.name.as_str()), (ZH, "UTF-16LE"));
+
+        let be: Vec<u8> = [0xFEFF]
+            .into_iter()
+            .chain(JA.encode_utf16())
+            .flat_map(u16::to_be_bytes)
+            .collect();
+        let (decoded, info) = detected(&be);
+        assert_eq!((decoded.as_str(), info.name.as_str()), (JA, "UTF-16BE"));
+        assert_eq!(info.confidence, 1.0);
+    }
+
+    #[test]
+    fn test_override_and_lossy() {
+        let (bytes, _, _) = BIG5.encode(ZH);
+        let (decoded, info) = decode(&bytes, Some("big5")).unwrap();
+        assert_eq!(decoded, ZH);
+        assert_eq!(info.confidence, 1.0);
+
+        let (_, info) = decode(&bytes, Some("utf-8")).unwrap();
+        assert!(info.lossy && info.warning().is_some());
+        assert!(decode(&bytes, Some("klingon")).is_err());
+    }
+}
@@ -1,4 +1,129 @@
 use crate::models::*;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::UNIX_EPOCH;
+use tiktoken_rs::tokenizer::Tokenizer as BpeName;
+use tiktoken_rs::CoreBPE;
+
+/// Counts tokens for budgets and estimates.
+pub trait TokenCounter: Send + Sync {
+    fn count(&self, text: &str) -> usize;
+    /// Names the tokenizer; counts cached under another name are stale.
+    fn name(&self) -> String;
+}
+
+/// The character heuristic of [`estimate_tokens`].
+pub struct Heuristic;
+
+impl TokenCounter for Heuristic {
+    fn count(&self, text: &str) -> usize {
+        estimate_tokens(text)
+    }
+
+    fn name(&self) -> String {
+        "heuristic".to_string()
+    }
+}
+
+/// An OpenAI BPE vocabulary (cl100k_base / o200k_base).
+struct Bpe {
+    name: &'static str,
+    bpe: &'static CoreBPE,
+}
+
+impl TokenCounter for Bpe {
+    fn count(&self, text: &str) -> usize {
+        self.bpe.encode_ordinary(text).len()
+    }
+
+    fn name(&self) -> String {
+        self.name.to_string()
+    }
+}
+
+/// A tokenizer loaded from a HuggingFace `tokenizer.json`.
+struct HuggingFace {
+    path: String,
+    /// Modification time of the file when it was loaded, in seconds
+    modified: u64,
+    tokenizer: Arc<tokenizers::Tokenizer>,
+}
+
+impl TokenCounter for HuggingFace {
+    fn count(&self, text: &str) -> usize {
+        match self.tokenizer.encode(text, false) {
+            Ok(encoding) => encoding.len(),
+            Err(_) => estimate_tokens(text),
+        }
+    }
+
+    fn name(&self) -> String {
+        // A replaced file may be another tokenizer; counts made with the old one are stale.
+        format!("hf:{}@{}", self.path, self.modified)
+    }
+}
+
+fn cl100k() -> &'static CoreBPE {
+    static BPE: OnceLock<CoreBPE> = OnceLock::new();
+    BPE.get_or_init(|| tiktoken_rs::cl100k_base().expect("bundled cl100k vocabulary"))
+}
+
+fn o200k() -> &'static CoreBPE {
+    static BPE: OnceLock<CoreBPE> = OnceLock::new();
+    BPE.get_or_init(|| tiktoken_rs::o200k_base().expect("bundled o200k vocabulary"))
+}
+
+/// Load a tokenizer.json, or take the one loaded last if it came from the same, unchanged
+/// file; they take a while to parse. Returns it with the file's modification time.
+fn load_hugging_face(path: &str) -> Result<(Arc<tokenizers::Tokenizer>, u64), String> {
+    static CACHE: Mutex<Option<(String, u64, Arc<tokenizers::Tokenizer>)>> = Mutex::new(None);
+    let load_error = |e: &dyn std::fmt::Display| format!("无法加载 tokenizer 文件 {}: {}", path, e);
+    let modified = std::fs::metadata(path)
+        .and_then(|m| m.modified())
+        .map_err(|e| load_error(&e))?
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs());
+    let mut cache = CACHE.lock().map_err(|e| e.to_string())?;
+    if let Some((cached_path, cached_modified, tokenizer)) = &*cache {
+        if cached_path == path && *cached_modified == modified {
+            return Ok((tokenizer.clone(), modified));
+        }
+    }
+    let tokenizer = tokenizers::Tokenizer::from_file(path).map_err(|e| load_error(&e))?;
+    let tokenizer = Arc::new(tokenizer);
+    *cache = Some((path.to_string(), modified, tokenizer.clone()));
+    Ok((tokenizer, modified))
+}
+
+/// The token counter configured for the model in `config`.
+pub fn tokenizer_for(config: &LlmConfig) -> Result<Arc<dyn TokenCounter>, String> {
+    let choice = match &config.tokenizer {
+        TokenizerChoice::Auto => match tiktoken_rs::tokenizer::get_tokenizer(&config.model) {
+            Some(BpeName::O200kBase) => TokenizerChoice::O200k,
+            Some(BpeName::Cl100kBase) => TokenizerChoice::Cl100k,
+            _ => TokenizerChoice::Heuristic,
+        },
+        other => other.clone(),
+    };
+    Ok(match choice {
+        TokenizerChoice::Cl100k => Arc::new(Bpe {
+            name: "cl100k",
+            bpe: cl100k(),
+        }),
+        TokenizerChoice::O200k => Arc::new(Bpe {
+            name: "o200k",
+            bpe: o200k(),
+        }),
+        TokenizerChoice::HuggingFace(path) => {
+            let (tokenizer, modified) = load_hugging_face(&path)?;
+            Arc::new(HuggingFace {
+                path,
+                modified,
+                tokenizer,
+            })
+        }
+        TokenizerChoice::Auto | TokenizerChoice::Heuristic => Arc::new(Heuristic),
+    })
+}
 
 /// Estimate token count for a text string.
 /// Uses a conservative heuristic: Chinese chars ≈ 1.5 tokens each, ASCII words ≈ 1 token.
@@ -30,8 +155,12 @@ pub fn calculate_available_tokens(config: &LlmConfig, prompt_template_tokens: us
 
 /// Split chapter content into segments that fit within the token budget.
 /// Splits on paragraph boundaries (\n\n) to maintain readability.
-pub fn split_content_by_tokens(content: &str, max_tokens: usize) -> Vec<String> {
-    if estimate_tokens(content) <= max_tokens {
+pub fn split_content_by_tokens(
+    content: &str,
+    max_tokens: usize,
+    counter: &dyn TokenCounter,
+) -> Vec<String> {
+    if counter.count(content) <= max_tokens {
         return vec![content.to_string()];
     }
 
@@ -41,7 +170,7 @@ pub fn split_content_by_tokens(content: &str, max_tokens: usize) -> Vec<String>
     let mut current_tokens: usize = 0;
 
     for para in paragraphs {
-        let para_tokens = estimate_tokens(para);
+        let para_tokens = counter.count(para);
 
         if current_tokens + para_tokens > max_tokens && !current_segment.is_empty() {
             segments.push(current_segment.trim().to_string());
@@ -63,7 +192,7 @@ pub fn split_content_by_tokens(content: &str, max_tokens: usize) -> Vec<String>
     // If we still have segments that are too long, do a hard split by lines
     let mut final_segments: Vec<String> = Vec::new();
     for seg in segments {
-        if estimate_tokens(&seg) <= max_tokens {
+        if counter.count(&seg) <= max_tokens {
             final_segments.push(seg);
         } else {
             // Hard split by lines
@@ -71,7 +200,7 @@ pub fn split_content_by_tokens(content: &str, max_tokens: usize) -> Vec<String>
             let mut chunk = String::new();
             let mut chunk_tokens: usize = 0;
             for line in lines {
-                let line_tokens = estimate_tokens(line);
+                let line_tokens = counter.count(line);
                 if chunk_tokens + line_tokens > max_tokens && !chunk.is_empty() {
                     final_segments.push(chunk.trim().to_string());
                     chunk = String::new();
@@ -109,10 +238,65 @@ mod tests {
         assert!(tokens > 5 && tokens < 20);
     }
 
+    #[test]
+    fn test_tokenizer_for_model() {
+        let config = LlmConfig {
+            model: "gpt-4o".to_string(),
+            ..LlmConfig::default()
+        };
+        let counter = tokenizer_for(&config).unwrap();
+        assert_eq!(counter.name(), "o200k");
+        let text = "这是一段中文测试文本";
+        assert!(counter.count(text) < estimate_tokens(text));
+
+        let config = LlmConfig {
+            model: "deepseek-chat".to_string(),
+            ..LlmConfig::default()
+        };
+        assert_eq!(tokenizer_for(&config).unwrap().name(), "heuristic");
+
+        let config = LlmConfig {
+            tokenizer: TokenizerChoice::HuggingFace("/nonexistent/tokenizer.json".to_string()),
+            ..LlmConfig::default()
+        };
+        assert!(tokenizer_for(&config).is_err());
+    }
+
+    #[test]
+    fn test_stored_token_counts() {
+        use crate::storage::Database;
+
+        let db = Database::open_in_memory().unwrap();
+        let text = "这是一段中文测试文本";
+        let novel_id = db
+            .create_novel(
+                BookInfo::new("测试"),
+                SourceType::SingleTxt(String::new()),
+                ImportOptions::default(),
+                None,
+                vec![ParsedChapter::new("一", text)],
+            )
+            .unwrap();
+        let estimate = |db: &Database| db.list_chapter_metas(&novel_id).unwrap()[0].token_estimate;
+        let o200k_count = o200k().encode_ordinary(text).len();
+        assert_eq!(estimate(&db), o200k_count);
+
+        // The old tokenizer's counts stand until the recount.
+        db.save_llm_config(&LlmConfig {
+            tokenizer: TokenizerChoice::Cl100k,
+            ..LlmConfig::default()
+        })
+        .unwrap();
+        assert_eq!(estimate(&db), o200k_count);
+        assert_eq!(db.recount_tokens().unwrap(), 1);
+        assert_eq!(estimate(&db), cl100k().encode_ordinary(text).len());
+        assert_eq!(db.recount_tokens().unwrap(), 0);
+    }
+
     #[test]
     fn test_split_short_content() {
         let content = "Short text";
-        let segments = split_content_by_tokens(content, 1000);
+        let segments = split_content_by_tokens(content, 1000, &Heuristic);
         assert_eq!(segments.len(), 1);
     }
 
@@ -122,7 +306,7 @@ mod tests {
             .map(|i| format!("这是第{}段很长的文本内容，用来测试分段功能。", i))
             .collect::<Vec<_>>()
             .join("\n\n");
-        let segments = split_content_by_tokens(&content, 100);
+        let segments = split_content_by_tokens(&content, 100, &Heuristic);
         assert!(segments.len() > 1);
     }
 }
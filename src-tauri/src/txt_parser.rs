@@ -1,20 +1,29 @@
-use encoding_rs::{GB18030, GBK};
+use crate::fingerprint;
+use crate::models::{HeadingRuleSet, ParsedChapter};
+use crate::text_encoding::{self, DetectedEncoding};
 use regex::Regex;
-use std::fs;
+use serde::{Deserialize, Serialize};
 use std::path::Path;
 
-/// Parse multiple TXT files as individual chapters.
-pub fn parse_txt_files(paths: Vec<String>) -> Result<(String, Vec<(String, String)>), String> {
-    let mut chapters: Vec<(String, String)> = Vec::new();
+/// Parse multiple TXT files as individual chapters. `encoding` overrides detection for
+/// every file. Also returns a warning, prefixed with the path, for each file whose decoding
+/// was lossy or doubtful.
+pub fn parse_txt_files(
+    paths: Vec<String>,
+    encoding: Option<&str>,
+) -> Result<(String, Vec<ParsedChapter>, Vec<String>), String> {
+    let mut chapters: Vec<ParsedChapter> = Vec::new();
+    let mut warnings = Vec::new();
 
     for path in &paths {
-        let content = read_txt_auto_encoding(path)?;
+        let (content, detected) = text_encoding::read_text(path, encoding)?;
+        warnings.extend(detected.warning().map(|w| format!("{}: {}", path, w)));
         let title = Path::new(path)
             .file_stem()
             .and_then(|s| s.to_str())
             .unwrap_or("未知章节")
             .to_string();
-        chapters.push((title, content));
+        chapters.push(ParsedChapter::new(title, content));
     }
 
     let title = if paths.len() == 1 {
@@ -37,119 +46,635 @@ pub fn parse_txt_files(paths: Vec<String>) -> Result<(String, Vec<(String, Strin
         names.first().unwrap_or(&"未知书名").to_string()
     };
 
-    Ok((title, chapters))
+    Ok((title, chapters, warnings))
 }
 
-/// Parse a single large TXT file, splitting by chapter headings.
-pub fn parse_single_txt(path: &str) -> Result<(String, Vec<(String, String)>), String> {
-    let content = read_txt_auto_encoding(path)?;
-    let title = Path::new(path)
-        .file_stem()
-        .and_then(|s| s.to_str())
-        .unwrap_or("未知书名")
-        .to_string();
+/// Parse a single large TXT file, splitting by chapter headings. With one rule set that set
+/// is used; with several, the one that splits the file most convincingly. Also returns the
+/// decoding warning, if any, as `parse_txt_files` does.
+pub fn parse_single_txt(
+    path: &str,
+    rule_sets: &[HeadingRuleSet],
+    encoding: Option<&str>,
+) -> Result<(String, Vec<ParsedChapter>, Vec<String>), String> {
+    let (content, detected) = text_encoding::read_text(path, encoding)?;
+    let warnings: Vec<String> = detected
+        .warning()
+        .map(|w| format!("{}: {}", path, w))
+        .into_iter()
+        .collect();
+    let title = book_title(path);
 
-    let chapters = split_by_chapters(&content);
+    let chapters = match rule_sets {
+        [rules] => split_by_chapters(&content, rules)?,
+        sets => match detect_heading_rules(&content, sets) {
+            Some(rules) => split_by_chapters(&content, rules)?,
+            None => Vec::new(),
+        },
+    };
 
     if chapters.is_empty() {
         // If no chapter markers found, treat the entire file as one chapter
-        return Ok((title, vec![("全文".to_string(), content)]));
+        return Ok((title, vec![ParsedChapter::new("全文", content)], warnings));
     }
 
-    Ok((title, chapters))
+    Ok((title, chapters, warnings))
 }
 
-/// Split text content by Chinese chapter heading patterns.
-fn split_by_chapters(content: &str) -> Vec<(String, String)> {
-    // Match patterns like: 第一章, 第1章, 第二十三章, 第一回, 第三节 etc.
-    let pattern = r"(?m)^[　\s]*(第[零〇一二三四五六七八九十百千万亿\d]+[章回节卷集篇部][^\n]*)";
-    let re = Regex::new(pattern).unwrap();
+fn book_title(path: &str) -> String {
+    Path::new(path)
+        .file_stem()
+        .and_then(|s| s.to_str())
+        .unwrap_or("未知书名")
+        .to_string()
+}
 
-    let matches: Vec<(usize, &str)> = re
-        .find_iter(content)
-        .map(|m| (m.start(), m.as_str().trim()))
-        .collect();
+/// A preview chapter of a single TXT file, returned to the frontend for review.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TxtPreviewChapter {
+    pub index: usize,
+    pub title: String,
+    pub char_count: usize,
+    /// Whether this chapter is suggested for import
+    pub suggested: bool,
+    /// Why this entry looks wrong (too short, duplicate title...), if it does
+    pub warning: Option<String>,
+    /// The heading pattern that started this chapter; None for text before the first heading
+    pub pattern: Option<String>,
+    /// The volume heading this chapter falls under, if any
+    pub volume: Option<String>,
+    /// For checking whether the library has the book already
+    #[serde(skip)]
+    pub simhash: Option<u64>,
+}
 
-    if matches.is_empty() {
-        return Vec::new();
+/// One chapter to import after a preview: the previewed entries it is made of, in order
+/// (several when merging), under the given title.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TxtChapterSelection {
+    pub indices: Vec<usize>,
+    pub title: String,
+}
+
+/// A chapter cut out by the heading rules, before anything is filtered.
+struct Section {
+    title: String,
+    body: String,
+    pattern: Option<String>,
+    volume: Option<String>,
+}
+
+/// The result of splitting a file for review.
+struct SplitPreview {
+    title: String,
+    rule_set: Option<String>,
+    encoding: DetectedEncoding,
+    sections: Vec<Section>,
+}
+
+/// Split the file for review: every section, including the text before the first heading and
+/// bodies too short to keep.
+fn preview_sections(
+    path: &str,
+    rule_sets: &[HeadingRuleSet],
+    encoding: Option<&str>,
+) -> Result<SplitPreview, String> {
+    let (content, encoding) = text_encoding::read_text(path, encoding)?;
+    let rules = match rule_sets {
+        [rules] => Some(rules),
+        sets => detect_heading_rules(&content, sets),
+    };
+    let sections = match rules {
+        Some(rules) => split_sections(&content, &compile_rules(rules)?),
+        None => Vec::new(),
+    };
+    if sections.is_empty() {
+        let whole = Section {
+            title: "全文".to_string(),
+            body: content,
+            pattern: None,
+            volume: None,
+        };
+        return Ok(SplitPreview {
+            title: book_title(path),
+            rule_set: None,
+            encoding,
+            sections: vec![whole],
+        });
     }
+    Ok(SplitPreview {
+        title: book_title(path),
+        rule_set: rules.map(|r| r.name.clone()),
+        encoding,
+        sections,
+    })
+}
+
+/// Preview how a single TXT file splits, flagging entries that look wrong.
+/// Returns (book_title, rule_set_name, detected_encoding, preview_chapters).
+pub fn preview_single_txt(
+    path: &str,
+    rule_sets: &[HeadingRuleSet],
+    encoding: Option<&str>,
+) -> Result<
+    (
+        String,
+        Option<String>,
+        DetectedEncoding,
+        Vec<TxtPreviewChapter>,
+    ),
+    String,
+> {
+    let SplitPreview {
+        title,
+        rule_set,
+        encoding,
+        sections,
+    } = preview_sections(path, rule_sets, encoding)?;
+
+    let counts: Vec<usize> = sections.iter().map(|s| s.body.chars().count()).collect();
+    let median = {
+        let mut sorted = counts.clone();
+        sorted.sort_unstable();
+        sorted[sorted.len() / 2]
+    };
+
+    let chapters = sections
+        .iter()
+        .enumerate()
+        .map(|(i, section)| {
+            let char_count = counts[i];
+            // The same chapter title in another volume is normal.
+            let duplicate_of = sections[..i]
+                .iter()
+                .position(|s| s.title == section.title && s.volume == section.volume);
+            let (suggested, warning) = if section.pattern.is_none() && sections.len() == 1 {
+                (true, Some("未识别到章节标题，将整体作为一章".to_string()))
+            } else if section.pattern.is_none() {
+                (
+                    char_count >= MIN_CHAPTER_CHARS,
+                    Some("第一个章节标题之前的内容".to_string()),
+                )
+            } else if char_count < MIN_CHAPTER_CHARS {
+                (false, Some(format!("正文过短（{} 字）", char_count)))
+            } else if let Some(j) = duplicate_of {
+                (true, Some(format!("标题与第 {} 条重复", j + 1)))
+            } else if sections.len() >= 3 && char_count > median * 5 {
+                (true, Some("篇幅远超其他章节，可能漏识别了标题".to_string()))
+            } else {
+                (true, None)
+            };
+            TxtPreviewChapter {
+                index: i,
+                title: section.title.clone(),
+                char_count,
+                suggested,
+                warning,
+                pattern: section.pattern.clone(),
+                volume: section.volume.clone(),
+                simhash: fingerprint::simhash(&section.body),
+            }
+        })
+        .collect();
+
+    Ok((title, rule_set, encoding, chapters))
+}
+
+/// Build the chapters chosen in a preview, and return how many entries the preview had.
+/// Merged entries keep their heading lines in the text, since a wrongly detected heading is
+/// usually part of the prose.
+pub fn parse_txt_selected(
+    path: &str,
+    rule_sets: &[HeadingRuleSet],
+    selections: &[TxtChapterSelection],
+    encoding: Option<&str>,
+) -> Result<(String, Vec<ParsedChapter>, usize), String> {
+    let SplitPreview {
+        title, sections, ..
+    } = preview_sections(path, rule_sets, encoding)?;
+    let chapters = build_selected(&sections, selections)?;
+    Ok((title, chapters, sections.len()))
+}
 
-    let mut chapters: Vec<(String, String)> = Vec::new();
+/// Split the file again and build the chapters chosen in a preview that had `known` entries.
+/// Entries past those, in a newer edition of the file, are added when the preview would
+/// suggest them.
+pub fn parse_txt_reselected(
+    path: &str,
+    rule_sets: &[HeadingRuleSet],
+    selections: &[TxtChapterSelection],
+    known: usize,
+    encoding: Option<&str>,
+) -> Result<(String, Vec<ParsedChapter>), String> {
+    let SplitPreview {
+        title, sections, ..
+    } = preview_sections(path, rule_sets, encoding)?;
+    let mut selections = selections.to_vec();
+    selections.extend(
+        sections
+            .iter()
+            .enumerate()
+            .skip(known)
+            .filter(|(_, s)| s.pattern.is_some() && s.body.chars().count() >= MIN_CHAPTER_CHARS)
+            .map(|(i, _)| TxtChapterSelection {
+                indices: vec![i],
+                title: String::new(),
+            }),
+    );
+    Ok((title, build_selected(&sections, &selections)?))
+}
 
-    for i in 0..matches.len() {
-        let (start, title) = matches[i];
-        let end = if i + 1 < matches.len() {
-            matches[i + 1].0
+fn build_selected(
+    sections: &[Section],
+    selections: &[TxtChapterSelection],
+) -> Result<Vec<ParsedChapter>, String> {
+    let mut chapters = Vec::new();
+    for selection in selections {
+        let mut body = String::new();
+        for (n, &i) in selection.indices.iter().enumerate() {
+            let section = sections
+                .get(i)
+                .ok_or_else(|| format!("章节序号 {} 超出范围（共 {} 条）", i, sections.len()))?;
+            if n > 0 {
+                body.push_str("\n\n");
+                if section.pattern.is_some() {
+                    body.push_str(&section.title);
+                    body.push('\n');
+                }
+            }
+            body.push_str(&section.body);
+        }
+        let Some(&first) = selection.indices.first() else {
+            continue;
+        };
+        let chapter_title = if selection.title.trim().is_empty() {
+            sections[first].title.clone()
         } else {
-            content.len()
+            selection.title.trim().to_string()
         };
+        chapters.push(ParsedChapter {
+            title: chapter_title,
+            content: body,
+            volume: sections[first].volume.clone(),
+            notes: Vec::new(),
+        });
+    }
 
-        let chapter_content = &content[start..end];
-        // Remove the title line from content
-        let body = chapter_content
-            .strip_prefix(title)
-            .unwrap_or(chapter_content)
-            .trim()
-            .to_string();
+    Ok(chapters)
+}
+
+/// The rule sets to try for an import: the one called `name`, or all of them when no name
+/// (or "auto") is given.
+pub fn select_heading_rules(
+    sets: Vec<HeadingRuleSet>,
+    name: Option<&str>,
+) -> Result<Vec<HeadingRuleSet>, String> {
+    match name {
+        None | Some("auto") => Ok(sets),
+        Some(name) => sets
+            .into_iter()
+            .find(|s| s.name == name)
+            .map(|s| vec![s])
+            .ok_or_else(|| format!("未找到章节标题规则「{}」", name)),
+    }
+}
+
+/// Check that every pattern compiles, so broken custom rules are rejected when saved.
+pub fn validate_heading_rules(sets: &[HeadingRuleSet]) -> Result<(), String> {
+    for rules in sets {
+        if rules.name.trim().is_empty() {
+            return Err("章节标题规则需要名称".to_string());
+        }
+        compile_rules(rules)?;
+    }
+    Ok(())
+}
 
-        if body.len() > 10 {
-            chapters.push((title.to_string(), body));
+/// Pick the rule set whose split looks most like real chapters; earlier sets win ties.
+/// Sets with invalid patterns are skipped.
+pub fn detect_heading_rules<'a>(
+    content: &str,
+    sets: &'a [HeadingRuleSet],
+) -> Option<&'a HeadingRuleSet> {
+    let mut best: Option<(&HeadingRuleSet, i64)> = None;
+    for rules in sets {
+        let Ok(compiled) = compile_rules(rules) else {
+            continue;
+        };
+        let score = split_score(&split_with(content, &compiled));
+        if score > 0 && best.map_or(true, |(_, s)| score > s) {
+            best = Some((rules, score));
         }
     }
+    best.map(|(rules, _)| rules)
+}
+
+/// Longest line still taken for a heading; longer matches are prose mentioning a chapter.
+const MAX_HEADING_CHARS: usize = 50;
+/// Chapters shorter than this are fragments, e.g. headings matched inside a list.
+const MIN_CHAPTER_CHARS: usize = 20;
 
+/// Chapters of a sensible length count for a split, fragments count against it, and a
+/// single chapter is no split at all.
+fn split_score(chapters: &[ParsedChapter]) -> i64 {
+    if chapters.len() < 2 {
+        return 0;
+    }
     chapters
+        .iter()
+        .map(|ch| {
+            if ch.content.chars().count() >= MIN_CHAPTER_CHARS {
+                1
+            } else {
+                -1
+            }
+        })
+        .sum()
+}
+
+struct CompiledRules {
+    chapter: Vec<Regex>,
+    volume: Vec<Regex>,
+}
+
+fn compile_rules(rules: &HeadingRuleSet) -> Result<CompiledRules, String> {
+    let compile = |patterns: &[String]| {
+        patterns
+            .iter()
+            .map(|p| {
+                Regex::new(p)
+                    .map_err(|e| format!("章节标题规则「{}」的正则无效: {}", rules.name, e))
+            })
+            .collect::<Result<Vec<_>, _>>()
+    };
+    Ok(CompiledRules {
+        chapter: compile(&rules.patterns)?,
+        volume: compile(&rules.volume_patterns)?,
+    })
+}
+
+/// Split text content at lines matching the heading rules. Headings with no text under
+/// them, such as a table of contents, are dropped; short chapters are kept.
+pub fn split_by_chapters(
+    content: &str,
+    rules: &HeadingRuleSet,
+) -> Result<Vec<ParsedChapter>, String> {
+    Ok(split_with(content, &compile_rules(rules)?))
+}
+
+fn split_with(content: &str, rules: &CompiledRules) -> Vec<ParsedChapter> {
+    split_sections(content, rules)
+        .into_iter()
+        .filter(|s| s.pattern.is_some() && !s.body.is_empty())
+        .map(|s| ParsedChapter {
+            title: s.title,
+            content: s.body,
+            volume: s.volume,
+            notes: Vec::new(),
+        })
+        .collect()
 }
 
-/// Read a TXT file with automatic encoding detection (UTF-8 / GBK / GB18030).
-fn read_txt_auto_encoding(path: &str) -> Result<String, String> {
-    let bytes = fs::read(path).map_err(|e| format!("无法读取文件 {}: {}", path, e))?;
+struct Heading<'a> {
+    /// Start of the heading line
+    start: usize,
+    /// Start of the text under the heading
+    body_start: usize,
+    title: &'a str,
+    pattern: &'a Regex,
+    volume: bool,
+}
 
-    // Try UTF-8 first
-    if let Ok(text) = std::str::from_utf8(&bytes) {
-        return Ok(text.to_string());
+fn split_sections(content: &str, rules: &CompiledRules) -> Vec<Section> {
+    let mut headings: Vec<Heading> = Vec::new();
+    let mut offset = 0;
+    for line in content.split_inclusive('\n') {
+        // Also trims the full-width indentation common in Chinese texts.
+        let title = line.trim();
+        if !title.is_empty() && title.chars().count() <= MAX_HEADING_CHARS {
+            let matched = match rules.volume.iter().find(|re| re.is_match(title)) {
+                Some(re) => Some((re, true)),
+                None => rules
+                    .chapter
+                    .iter()
+                    .find(|re| re.is_match(title))
+                    .map(|re| (re, false)),
+            };
+            if let Some((pattern, volume)) = matched {
+                headings.push(Heading {
+                    start: offset,
+                    body_start: offset + line.len(),
+                    title,
+                    pattern,
+                    volume,
+                });
+            }
+        }
+        offset += line.len();
     }
 
-    // Try UTF-8 with BOM
-    if bytes.starts_with(&[0xEF, 0xBB, 0xBF]) {
-        if let Ok(text) = std::str::from_utf8(&bytes[3..]) {
-            return Ok(text.to_string());
+    // A volume heading only opens a volume when chapters follow it; a book split into
+    // 第一部, 第二部... without chapters inside keeps them as its chapters.
+    for i in 0..headings.len() {
+        if headings[i].volume && headings.get(i + 1).map_or(true, |next| next.volume) {
+            headings[i].volume = false;
         }
     }
 
-    // Try GBK
-    let (cow, _, had_errors) = GBK.decode(&bytes);
-    if !had_errors {
-        return Ok(cow.into_owned());
+    let Some(first_start) = headings.first().map(|h| h.start) else {
+        return Vec::new();
+    };
+
+    let mut sections: Vec<Section> = Vec::new();
+
+    let preface = content[..first_start].trim();
+    if !preface.is_empty() {
+        sections.push(Section {
+            title: "前言".to_string(),
+            body: preface.to_string(),
+            pattern: None,
+            volume: None,
+        });
     }
 
-    // Try GB18030
-    let (cow, _, had_errors) = GB18030.decode(&bytes);
-    if !had_errors {
-        return Ok(cow.into_owned());
+    let mut volume: Option<String> = None;
+    for (i, heading) in headings.iter().enumerate() {
+        let end = headings
+            .get(i + 1)
+            .map(|next| next.start)
+            .unwrap_or(content.len());
+        let body = content[heading.body_start..end].trim();
+        if heading.volume {
+            volume = Some(heading.title.to_string());
+            // Only text between the volume heading and its first chapter (an epigraph, an
+            // introduction) becomes an entry of its own.
+            if body.is_empty() {
+                continue;
+            }
+        }
+        sections.push(Section {
+            title: heading.title.to_string(),
+            body: body.to_string(),
+            pattern: Some(heading.pattern.as_str().to_string()),
+            volume: volume.clone(),
+        });
     }
 
-    // Fallback: lossy UTF-8
-    Ok(String::from_utf8_lossy(&bytes).into_owned())
+    sections
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    fn preset(name: &str) -> HeadingRuleSet {
+        HeadingRuleSet::presets()
+            .into_iter()
+            .find(|s| s.name == name)
+            .unwrap()
+    }
+
     #[test]
     fn test_split_by_chapters() {
         let content = "前言内容\n\n第一章 开端\n这是第一章的内容。\n第二章 发展\n这是第二章的内容。\n第三章 结局\n这是第三章的内容。";
-        let chapters = split_by_chapters(content);
+        let chapters = split_by_chapters(content, &preset("中文章回")).unwrap();
         assert_eq!(chapters.len(), 3);
-        assert!(chapters[0].0.contains("第一章"));
-        assert!(chapters[1].0.contains("第二章"));
-        assert!(chapters[2].0.contains("第三章"));
+        assert!(chapters[0].title.contains("第一章"));
+        assert!(chapters[1].title.contains("第二章"));
+        assert!(chapters[2].title.contains("第三章"));
+    }
+
+    #[test]
+    fn test_split_keeps_short_chapters() {
+        let content = "目录\n第一章 开端\n第二章 请假\n\n第一章 开端\n这是第一章的内容。\n第二章 请假\n停更。";
+        let chapters = split_by_chapters(content, &preset("中文章回")).unwrap();
+        let got: Vec<(&str, &str)> = chapters
+            .iter()
+            .map(|c| (c.title.as_str(), c.content.as_str()))
+            .collect();
+        assert_eq!(
+            got,
+            vec![
+                ("第一章 开端", "这是第一章的内容。"),
+                ("第二章 请假", "停更。"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_split_volumes() {
+        let body = "他推开门，走进了那间许久没有人住过的屋子，灰尘在阳光里缓缓飘动。";
+        let content = format!(
+            "第一卷 风起\n第一章 开端\n{body}\n第二章 发展\n{body}\n第二卷 云涌\n　　卷首语：山雨欲来。\n第一章 重逢\n{body}"
+        );
+        let chapters = split_by_chapters(&content, &preset("中文章回")).unwrap();
+        let got: Vec<(&str, Option<&str>)> = chapters
+            .iter()
+            .map(|c| (c.title.as_str(), c.volume.as_deref()))
+            .collect();
+        assert_eq!(
+            got,
+            vec![
+                ("第一章 开端", Some("第一卷 风起")),
+                ("第二章 发展", Some("第一卷 风起")),
+                ("第二卷 云涌", Some("第二卷 云涌")),
+                ("第一章 重逢", Some("第二卷 云涌")),
+            ]
+        );
+
+        // Without chapters inside, 第X部 headings are the chapters themselves.
+        let parts = format!("第一部\n{body}\n第二部\n{body}");
+        let chapters = split_by_chapters(&parts, &preset("中文章回")).unwrap();
+        assert_eq!(chapters.len(), 2);
+        assert!(chapters.iter().all(|c| c.volume.is_none()));
     }
 
     #[test]
     fn test_no_chapters() {
         let content = "这是一段没有章节标记的普通文本。";
-        let chapters = split_by_chapters(content);
+        let chapters = split_by_chapters(content, &preset("中文章回")).unwrap();
         assert!(chapters.is_empty());
     }
+
+    #[test]
+    fn test_detect_heading_rules() {
+        let body = "他推开门，走进了那间许久没有人住过的屋子，灰尘在阳光里缓缓飘动。";
+        let cn = format!(
+            "　　序章 往事\n{body}\n第一章 开端\n{body}\n第二章 发展\n{body}\n番外 后日谈\n{body}"
+        );
+        let en = format!("Prologue\n{body}\nChapter 1\n{body}\nCHAPTER TWO\n{body}");
+
+        let presets = HeadingRuleSet::presets();
+        assert_eq!(detect_heading_rules(&cn, &presets).unwrap().name, "网文");
+        assert_eq!(detect_heading_rules(&en, &presets).unwrap().name, "English");
+        assert!(detect_heading_rules(body, &presets).is_none());
+    }
+
+    #[test]
+    fn test_preview_and_import_selected() {
+        let body = "他推开门，走进了那间许久没有人住过的屋子，灰尘在阳光里缓缓飘动。";
+        let content = format!(
+            "简介：一个故事\n第一章 开端\n{body}\n第二章 发展\n短\n第三章 继续\n{body}\n第一章 开端\n{body}"
+        );
+        let path =
+            std::env::temp_dir().join(format!("novelparser-preview-{}.txt", std::process::id()));
+        std::fs::write(&path, &content).unwrap();
+        let path = path.to_str().unwrap();
+        let rules = [preset("中文章回")];
+
+        let (_, rule_set, encoding, chapters) = preview_single_txt(path, &rules, None).unwrap();
+        assert_eq!(encoding.name, "UTF-8");
+        assert_eq!(rule_set.as_deref(), Some("中文章回"));
+        let titles: Vec<&str> = chapters.iter().map(|c| c.title.as_str()).collect();
+        assert_eq!(
+            titles,
+            vec![
+                "前言",
+                "第一章 开端",
+                "第二章 发展",
+                "第三章 继续",
+                "第一章 开端"
+            ]
+        );
+        assert!(chapters[0].pattern.is_none() && !chapters[0].suggested);
+        assert!(!chapters[2].suggested && chapters[2].warning.is_some());
+        assert!(chapters[4].suggested && chapters[4].warning.is_some());
+
+        let selections = vec![
+            TxtChapterSelection {
+                indices: vec![1, 2],
+                title: String::new(),
+            },
+            TxtChapterSelection {
+                indices: vec![3],
+                title: "第三章 改名".to_string(),
+            },
+        ];
+        let (_, imported, known) = parse_txt_selected(path, &rules, &selections, None).unwrap();
+        assert_eq!(known, 5);
+
+        assert_eq!(imported.len(), 2);
+        assert_eq!(imported[0].title, "第一章 开端");
+        assert!(imported[0].content.ends_with("第二章 发展\n短"));
+        assert_eq!(imported[1].title, "第三章 改名");
+
+        // A newer edition with one more chapter, and one too short to suggest.
+        let grown = format!("{}\n第四章 新篇\n{body}\n第五章 请假\n短", content);
+        std::fs::write(path, grown).unwrap();
+        let (_, resynced) = parse_txt_reselected(path, &rules, &selections, known, None).unwrap();
+        std::fs::remove_file(path).ok();
+        let titles: Vec<&str> = resynced.iter().map(|c| c.title.as_str()).collect();
+        assert_eq!(titles, vec!["第一章 开端", "第三章 改名", "第四章 新篇"]);
+    }
+
+    #[test]
+    fn test_invalid_custom_rule() {
+        let rules = HeadingRuleSet {
+            name: "坏规则".to_string(),
+            patterns: vec!["(第".to_string()],
+            volume_patterns: Vec::new(),
+            builtin: false,
+        };
+        assert!(validate_heading_rules(&[rules]).is_err());
+    }
 }
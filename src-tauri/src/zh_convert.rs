@@ -0,0 +1,73 @@
+use crate::models::{BookInfo, ChineseConversion, ParsedChapter};
+use zhconv::{zhconv, Variant};
+
+impl ChineseConversion {
+    fn target(self) -> Variant {
+        match self {
+            Self::S2t => Variant::ZhHant,
+            Self::T2s => Variant::ZhCN,
+            Self::S2tw => Variant::ZhTW,
+            Self::S2hk => Variant::ZhHK,
+        }
+    }
+
+    /// Whether the converted text is Simplified Chinese.
+    pub fn to_simplified(self) -> bool {
+        self == Self::T2s
+    }
+}
+
+pub fn convert(text: &str, conversion: ChineseConversion) -> String {
+    zhconv(text, conversion.target())
+}
+
+/// Convert the title and the names in the metadata of a book.
+pub fn convert_book(book: &mut BookInfo, conversion: ChineseConversion) {
+    book.title = convert(&book.title, conversion);
+    let meta = &mut book.metadata;
+    for value in [&mut meta.author, &mut meta.publisher, &mut meta.series]
+        .into_iter()
+        .flatten()
+    {
+        *value = convert(value, conversion);
+    }
+}
+
+/// Convert the titles, volume names, text and notes of parsed chapters in place.
+pub fn convert_chapters(chapters: &mut [ParsedChapter], conversion: ChineseConversion) {
+    for ch in chapters.iter_mut() {
+        ch.title = convert(&ch.title, conversion);
+        ch.content = convert(&ch.content, conversion);
+        if let Some(volume) = &ch.volume {
+            ch.volume = Some(convert(volume, conversion));
+        }
+        for note in ch.notes.iter_mut() {
+            *note = convert(note, conversion);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_conversions() {
+        assert_eq!(
+            convert("头发乱了，他用鼠标点开软件。", ChineseConversion::S2t),
+            "頭髮亂了，他用鼠標點開軟件。"
+        );
+        assert_eq!(
+            convert("頭髮亂了，他用滑鼠點開軟體。", ChineseConversion::T2s),
+            "头发乱了，他用鼠标点开软件。"
+        );
+        assert_eq!(
+            convert("头发乱了，他用鼠标点开软件。", ChineseConversion::S2tw),
+            "頭髮亂了，他用滑鼠點開軟體。"
+        );
+        assert_eq!(
+            convert("他的头发乱了。", ChineseConversion::S2hk),
+            "他的頭髮亂了。"
+        );
+    }
+}
@@ -1,16 +1,19 @@
 import { useState, useMemo } from 'react';
 import { createPortal } from 'react-dom';
-import type { EpubPreview } from '../types';
+import type { BookPreview, ChineseConversion } from '../types';
 import { X, CheckSquare, Square } from 'lucide-react';
 import { motion } from 'framer-motion';
+import ConversionSelect from './ConversionSelect';
+import LibraryMatchWarning from './LibraryMatchWarning';
 
 interface Props {
-    preview: EpubPreview;
-    onConfirm: (selectedIndices: number[]) => void;
+    preview: BookPreview;
+    /** `keepRuby` keeps ruby readings in parentheses instead of dropping them */
+    onConfirm: (selectedIndices: number[], conversion: ChineseConversion | null, keepRuby: boolean) => void;
     onCancel: () => void;
 }
 
-export default function EpubPreviewModal({ preview, onConfirm, onCancel }: Props) {
+export default function BookPreviewModal({ preview, onConfirm, onCancel }: Props) {
     const [selected, setSelected] = useState<Set<number>>(() => {
         const initial = new Set<number>();
         preview.chapters.forEach((ch) => {
@@ -18,6 +21,8 @@ export default function EpubPreviewModal({ preview, onConfirm, onCancel }: Props
         });
         return initial;
     });
+    const [conversion, setConversion] = useState<ChineseConversion | null>(null);
+    const [keepRuby, setKeepRuby] = useState(false);
 
     const toggle = (idx: number) => {
         setSelected((prev) => {
@@ -44,7 +49,7 @@ export default function EpubPreviewModal({ preview, onConfirm, onCancel }: Props
 
     const handleConfirm = () => {
         const indices = Array.from(selected).sort((a, b) => a - b);
-        onConfirm(indices);
+        onConfirm(indices, conversion, keepRuby);
     };
 
     return createPortal(
@@ -86,6 +91,8 @@ export default function EpubPreviewModal({ preview, onConfirm, onCancel }: Props
                     </span>
                 </div>
 
+                <LibraryMatchWarning matches={preview.library_matches} />
+
                 {/* Chapter List */}
                 <div className="overflow-y-auto flex-1 p-2">
                     {preview.chapters.map((ch) => (
@@ -105,6 +112,9 @@ export default function EpubPreviewModal({ preview, onConfirm, onCancel }: Props
                             </button>
                             <div className="flex-1 min-w-0">
                                 <span className="text-sm font-medium truncate block">{ch.title}</span>
+                                {ch.volume && (
+                                    <span className="text-xs text-base-content/40 truncate block">{ch.volume}</span>
+                                )}
                             </div>
                             <span className="text-xs text-base-content/40 shrink-0">
                                 {ch.char_count.toLocaleString()} 字
@@ -117,7 +127,18 @@ export default function EpubPreviewModal({ preview, onConfirm, onCancel }: Props
                 </div>
 
                 {/* Footer */}
-                <div className="flex justify-end gap-2 p-4 border-t border-base-300 shrink-0 bg-base-200 rounded-b-2xl">
+                <div className="flex justify-end items-center gap-2 p-4 border-t border-base-300 shrink-0 bg-base-200 rounded-b-2xl">
+                    <ConversionSelect value={conversion} onChange={setConversion} />
+                    <label className="label cursor-pointer gap-1 text-xs" title="保留注音假名（ruby），否则丢弃">
+                        <input
+                            type="checkbox"
+                            className="checkbox checkbox-xs"
+                            checked={keepRuby}
+                            onChange={(e) => setKeepRuby(e.target.checked)}
+                        />
+                        保留注音
+                    </label>
+                    <div className="flex-1" />
                     <button className="btn btn-ghost btn-sm" onClick={onCancel}>取消</button>
                     <button
                         className="btn btn-primary btn-sm"
@@ -0,0 +1,70 @@
+import { useState } from 'react';
+import type { CleanupReport } from '../types';
+import { Eraser, X, ChevronDown, ChevronRight } from 'lucide-react';
+
+interface Props {
+    report: CleanupReport;
+    /** Chapters skipped when appending because the novel already had them */
+    skipped?: string[];
+    /** Chapters appended although the novel has one of the same title with other text */
+    sameTitle?: string[];
+    onDismiss: () => void;
+}
+
+export default function CleanupReportBanner({ report, skipped = [], sameTitle = [], onDismiss }: Props) {
+    const [expanded, setExpanded] = useState(false);
+    const removed = report.chars_before - report.chars_after;
+    const keptLines = report.repeated_lines.filter((line) => !line.removed).length;
+    if (removed <= 0 && skipped.length === 0 && sameTitle.length === 0 && keptLines === 0) return null;
+
+    return (
+        <div className="mt-3 rounded-lg bg-info/10 text-xs border border-info/20">
+            <div className="flex items-center gap-2 px-2 py-1.5">
+                <Eraser size={12} className="text-info shrink-0" />
+                <button className="flex-1 flex items-center gap-1 text-left" onClick={() => setExpanded(!expanded)}>
+                    {expanded ? <ChevronDown size={12} /> : <ChevronRight size={12} />}
+                    {removed > 0
+                        ? `导入时清理了 ${removed.toLocaleString()} 字`
+                        : skipped.length > 0
+                          ? `跳过已有章节 ${skipped.length} 章`
+                          : sameTitle.length > 0
+                            ? `追加了 ${sameTitle.length} 章同名章节`
+                            : `保留了 ${keptLines} 行重复出现的文字`}
+                </button>
+                <button className="btn btn-ghost btn-xs btn-square" onClick={onDismiss} title="关闭">
+                    <X size={12} />
+                </button>
+            </div>
+            {expanded && (
+                <ul className="px-3 pb-2 space-y-1 text-base-content/70">
+                    {report.rule_hits.map((hit) => (
+                        <li key={hit.rule} title={hit.rule}>
+                            规则命中 {hit.count} 处：{hit.examples.map((ex) => `「${ex}」`).join('')}
+                        </li>
+                    ))}
+                    {report.repeated_lines.map((line) => (
+                        <li key={line.line}>
+                            重复行「{line.line}」出现在 {line.chapter_count} 章
+                            {!line.removed && '，像是正文，已保留（如是广告可加入清理规则）'}
+                        </li>
+                    ))}
+                    {report.blank_lines_removed > 0 && (
+                        <li>多余空行 {report.blank_lines_removed} 行</li>
+                    )}
+                    {skipped.length > 0 && (
+                        <li title={skipped.join('\n')}>
+                            跳过已有章节 {skipped.length} 章：{skipped.slice(0, 5).map((t) => `「${t}」`).join('')}
+                            {skipped.length > 5 && ' 等'}
+                        </li>
+                    )}
+                    {sameTitle.length > 0 && (
+                        <li title={sameTitle.join('\n')}>
+                            与已有章节同名但正文不同，已追加 {sameTitle.length} 章：{sameTitle.slice(0, 5).map((t) => `「${t}」`).join('')}
+                            {sameTitle.length > 5 && ' 等'}
+                        </li>
+                    )}
+                </ul>
+            )}
+        </div>
+    );
+}
@@ -0,0 +1,29 @@
+import type { ChineseConversion } from '../types';
+
+export const CONVERSIONS: { value: ChineseConversion; label: string }[] = [
+    { value: 't2s', label: '繁体 → 简体' },
+    { value: 's2t', label: '简体 → 繁体' },
+    { value: 's2tw', label: '简体 → 台湾正体' },
+    { value: 's2hk', label: '简体 → 香港繁体' },
+];
+
+interface Props {
+    value: ChineseConversion | null;
+    onChange: (value: ChineseConversion | null) => void;
+}
+
+export default function ConversionSelect({ value, onChange }: Props) {
+    return (
+        <select
+            className="select select-bordered select-xs"
+            value={value ?? ''}
+            onChange={(e) => onChange((e.target.value || null) as ChineseConversion | null)}
+            title="简繁转换"
+        >
+            <option value="">不转换简繁</option>
+            {CONVERSIONS.map((c) => (
+                <option key={c.value} value={c.value}>{c.label}</option>
+            ))}
+        </select>
+    );
+}
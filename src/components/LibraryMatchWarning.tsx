@@ -0,0 +1,27 @@
+import type { LibraryMatch } from '../types';
+import { AlertTriangle } from 'lucide-react';
+
+interface Props {
+    matches: LibraryMatch[];
+}
+
+/** Warns in an import preview that the library seems to have the book already */
+export default function LibraryMatchWarning({ matches }: Props) {
+    if (matches.length === 0) return null;
+
+    return (
+        <div className="flex items-start gap-2 px-4 py-2 text-xs bg-warning/10 text-warning border-b border-base-300 shrink-0">
+            <AlertTriangle size={14} className="shrink-0 mt-0.5" />
+            <ul className="space-y-0.5">
+                {matches.map((m) => (
+                    <li key={m.novel_id}>
+                        书库中的《{m.title}》可能是同一本书：
+                        {m.matching_chapters > 0 && `${m.matching_chapters} 章内容相同`}
+                        {m.matching_chapters > 0 && m.same_identifier && '，'}
+                        {m.same_identifier && 'ISBN 等标识相同'}
+                    </li>
+                ))}
+            </ul>
+        </div>
+    );
+}
@@ -2,7 +2,8 @@ import { useEffect, useState } from 'react';
 import { createPortal } from 'react-dom';
 import { useNovelStore } from '../store/novelStore';
 import type { LlmConfig } from '../types';
-import { X, Save, RefreshCw } from 'lucide-react';
+import { X, Save, RefreshCw, FolderOpen } from 'lucide-react';
+import { open } from '@tauri-apps/plugin-dialog';
 import { motion } from 'framer-motion';
 
 const MODEL_PRESETS: { name: string; tokens: number }[] = [
@@ -284,6 +285,117 @@ export default function LlmConfigModal({ onClose }: Props) {
                             <option value="AllPrevious">全部已有章节</option>
                         </select>
                     </div>
+
+                    {/* Tokenizer */}
+                    <div className="form-control mt-4">
+                        <label className="label">
+                            <span className="label-text">Token 计数方式</span>
+                            <span className="label-text-alt text-base-content/50">影响分段与章节 Token 估算</span>
+                        </label>
+                        <div className="flex gap-2">
+                            <select
+                                className="select select-bordered select-sm flex-1 focus:outline-none focus:border-primary focus:ring-1 focus:ring-primary shadow-sm transition-shadow"
+                                value={typeof config.tokenizer === 'object' ? 'HuggingFace' : (config.tokenizer || 'Auto')}
+                                onChange={(e) => {
+                                    const v = e.target.value;
+                                    setConfig({
+                                        ...config,
+                                        tokenizer: v === 'HuggingFace' ? { HuggingFace: '' } : (v as 'Auto' | 'Heuristic' | 'Cl100k' | 'O200k'),
+                                    });
+                                }}
+                            >
+                                <option value="Auto">按模型自动选择</option>
+                                <option value="Heuristic">字符估算</option>
+                                <option value="Cl100k">cl100k (GPT-4 / GPT-3.5)</option>
+                                <option value="O200k">o200k (GPT-4o 及更新)</option>
+                                <option value="HuggingFace">HuggingFace tokenizer.json</option>
+                            </select>
+                        </div>
+                        {typeof config.tokenizer === 'object' && (
+                            <div className="flex gap-2 mt-2">
+                                <input
+                                    type="text"
+                                    placeholder="tokenizer.json 路径"
+                                    className="input input-bordered input-sm flex-1 focus:outline-none focus:border-primary focus:ring-1 focus:ring-primary shadow-sm transition-shadow"
+                                    value={config.tokenizer.HuggingFace}
+                                    onChange={(e) => setConfig({ ...config, tokenizer: { HuggingFace: e.target.value } })}
+                                />
+                                <button
+                                    className="btn btn-ghost btn-sm btn-square"
+                                    title="选择文件"
+                                    onClick={async () => {
+                                        const path = await open({ multiple: false, filters: [{ name: 'tokenizer.json', extensions: ['json'] }] });
+                                        if (typeof path === 'string') {
+                                            setConfig({ ...config, tokenizer: { HuggingFace: path } });
+                                        }
+                                    }}
+                                >
+                                    <FolderOpen size={14} />
+                                </button>
+                            </div>
+                        )}
+                    </div>
+
+                    {/* Retry */}
+                    <div className="form-control mt-4">
+                        <label className="label">
+                            <span className="label-text">失败重试 (限流 / 服务端错误 / 网络错误)</span>
+                        </label>
+                        <div className="flex gap-2 items-center text-sm">
+                            <span>最多尝试</span>
+                            <input
+                                type="number"
+                                min="1"
+                                max="20"
+                                className="input input-bordered input-sm w-20 focus:outline-none focus:border-primary focus:ring-1 focus:ring-primary shadow-sm transition-shadow"
+                                value={config.retry?.max_attempts ?? 5}
+                                onChange={(e) => setConfig({ ...config, retry: { ...config.retry, max_attempts: Math.max(1, parseInt(e.target.value) || 1) } })}
+                            />
+                            <span>次，初始间隔</span>
+                            <input
+                                type="number"
+                                min="0"
+                                step="0.5"
+                                className="input input-bordered input-sm w-20 focus:outline-none focus:border-primary focus:ring-1 focus:ring-primary shadow-sm transition-shadow"
+                                value={(config.retry?.base_delay_ms ?? 2000) / 1000}
+                                onChange={(e) => setConfig({ ...config, retry: { ...config.retry, base_delay_ms: Math.max(0, Math.round((parseFloat(e.target.value) || 0) * 1000)) } })}
+                            />
+                            <span>秒</span>
+                        </div>
+                    </div>
+
+                    {/* Failure Policy */}
+                    <div className="form-control mt-4">
+                        <label className="label">
+                            <span className="label-text">批量分析失败处理</span>
+                        </label>
+                        <div className="flex gap-2">
+                            <select
+                                className="select select-bordered select-sm flex-1 focus:outline-none focus:border-primary focus:ring-1 focus:ring-primary shadow-sm transition-shadow"
+                                value={typeof config.failure_policy === 'object' ? 'StopAfter' : (config.failure_policy || 'Continue')}
+                                onChange={(e) => {
+                                    const v = e.target.value;
+                                    setConfig({
+                                        ...config,
+                                        failure_policy: v === 'StopAfter' ? { StopAfter: 3 } : (v as 'Continue' | 'StopOnFirst'),
+                                    });
+                                }}
+                            >
+                                <option value="Continue">跳过失败章节继续</option>
+                                <option value="StopAfter">失败达到 N 章后停止</option>
+                                <option value="StopOnFirst">首次失败即停止</option>
+                            </select>
+                            {typeof config.failure_policy === 'object' && (
+                                <input
+                                    type="number"
+                                    min="1"
+                                    className="input input-bordered input-sm w-20 focus:outline-none focus:border-primary focus:ring-1 focus:ring-primary shadow-sm transition-shadow"
+                                    value={config.failure_policy.StopAfter}
+                                    onChange={(e) => setConfig({ ...config, failure_policy: { StopAfter: Math.max(1, parseInt(e.target.value) || 1) } })}
+                                />
+                            )}
+                        </div>
+                    </div>
                 </div>
 
                 <div className="flex justify-end gap-2 p-4 border-t border-base-300 shrink-0 bg-base-200 rounded-b-2xl">
@@ -0,0 +1,33 @@
+import { useEffect, useState } from 'react';
+import { BookOpen } from 'lucide-react';
+import { useNovelStore } from '../store/novelStore';
+
+interface Props {
+    novelId: string;
+    hasCover: boolean;
+    className?: string;
+}
+
+/** The novel's cover image, or a book icon when it has none. */
+export default function NovelCover({ novelId, hasCover, className = '' }: Props) {
+    const { getNovelCover } = useNovelStore();
+    const [src, setSrc] = useState<string | null>(null);
+
+    useEffect(() => {
+        if (!hasCover) return;
+        let cancelled = false;
+        getNovelCover(novelId)
+            .then((url) => { if (!cancelled) setSrc(url); })
+            .catch(() => { if (!cancelled) setSrc(null); });
+        return () => { cancelled = true; };
+    }, [novelId, hasCover, getNovelCover]);
+
+    if (!src) {
+        return (
+            <div className={`flex items-center justify-center rounded bg-base-300 shrink-0 ${className}`}>
+                <BookOpen size={18} className="text-primary" />
+            </div>
+        );
+    }
+    return <img src={src} alt="封面" className={`object-cover rounded shrink-0 ${className}`} />;
+}
@@ -0,0 +1,228 @@
+import { useState } from 'react';
+import { createPortal } from 'react-dom';
+import type { TxtPreview, TxtChapterSelection, HeadingRuleSet, ChineseConversion } from '../types';
+import { X, CheckSquare, Square, ArrowUpToLine, Unlink, AlertTriangle } from 'lucide-react';
+import { motion } from 'framer-motion';
+import ConversionSelect from './ConversionSelect';
+import LibraryMatchWarning from './LibraryMatchWarning';
+
+interface Props {
+    preview: TxtPreview;
+    ruleSets: HeadingRuleSet[];
+    /** Name of the rule set chosen by the user; null = auto-detect */
+    chosenRules: string | null;
+    onChangeRules: (name: string | null) => void;
+    /** Encoding label chosen by the user; null = auto-detect */
+    chosenEncoding: string | null;
+    onChangeEncoding: (encoding: string | null) => void;
+    onConfirm: (chapters: TxtChapterSelection[], conversion: ChineseConversion | null) => void;
+    onCancel: () => void;
+}
+
+const ENCODINGS = ['UTF-8', 'GBK', 'GB18030', 'Big5', 'Shift_JIS', 'EUC-JP', 'EUC-KR', 'UTF-16LE', 'UTF-16BE'];
+
+interface Row {
+    indices: number[];
+    title: string;
+    selected: boolean;
+}
+
+const initialRows = (preview: TxtPreview): Row[] =>
+    preview.chapters.map((ch) => ({ indices: [ch.index], title: ch.title, selected: ch.suggested }));
+
+export default function TxtPreviewModal({ preview, ruleSets, chosenRules, onChangeRules, chosenEncoding, onChangeEncoding, onConfirm, onCancel }: Props) {
+    const [rows, setRows] = useState<Row[]>(() => initialRows(preview));
+    const [previewKey, setPreviewKey] = useState(preview);
+    const [conversion, setConversion] = useState<ChineseConversion | null>(null);
+
+    // A new split (other rule set) resets all edits.
+    if (previewKey !== preview) {
+        setPreviewKey(preview);
+        setRows(initialRows(preview));
+    }
+
+    const update = (i: number, patch: Partial<Row>) => {
+        setRows((prev) => prev.map((row, j) => (j === i ? { ...row, ...patch } : row)));
+    };
+
+    const mergeIntoPrevious = (i: number) => {
+        setRows((prev) => {
+            const next = [...prev];
+            next[i - 1] = { ...next[i - 1], indices: [...next[i - 1].indices, ...next[i].indices], selected: true };
+            next.splice(i, 1);
+            return next;
+        });
+    };
+
+    const unmerge = (i: number) => {
+        setRows((prev) => {
+            const parts = prev[i].indices.map((idx, n) => ({
+                indices: [idx],
+                title: n === 0 ? prev[i].title : preview.chapters[idx].title,
+                selected: true,
+            }));
+            return [...prev.slice(0, i), ...parts, ...prev.slice(i + 1)];
+        });
+    };
+
+    const setAll = (selected: boolean) => {
+        setRows((prev) => prev.map((row) => ({ ...row, selected })));
+    };
+
+    const selectedRows = rows.filter((row) => row.selected);
+    const rowChars = (row: Row) => row.indices.reduce((sum, idx) => sum + preview.chapters[idx].char_count, 0);
+    const totalChars = selectedRows.reduce((sum, row) => sum + rowChars(row), 0);
+
+    const handleConfirm = () => {
+        onConfirm(selectedRows.map((row) => ({ indices: row.indices, title: row.title })), conversion);
+    };
+
+    return createPortal(
+        <div className="fixed inset-0 z-[9999] flex items-center justify-center pointer-events-auto">
+            <motion.div
+                className="absolute inset-0 bg-base-300/40 backdrop-blur-sm"
+                onClick={onCancel}
+                initial={{ opacity: 0 }}
+                animate={{ opacity: 1 }}
+                exit={{ opacity: 0 }}
+                transition={{ duration: 0.2 }}
+            />
+
+            <motion.div
+                className="relative z-10 w-full max-w-2xl bg-base-100/95 backdrop-blur shadow-2xl overflow-hidden flex flex-col max-h-[90vh] rounded-2xl border border-base-content/10 m-4"
+                initial={{ opacity: 0, scale: 0.95, y: 20 }}
+                animate={{ opacity: 1, scale: 1, y: 0 }}
+                exit={{ opacity: 0, scale: 0.95, y: 20 }}
+                transition={{ type: "spring", bounce: 0, duration: 0.4 }}
+            >
+                {/* Header */}
+                <div className="flex items-center justify-between p-4 border-b border-base-300 shrink-0">
+                    <div>
+                        <h3 className="font-bold text-lg">检查章节切分</h3>
+                        <p className="text-sm text-base-content/50">
+                            《{preview.title}》 — 共 {preview.chapters.length} 个条目
+                            {preview.rule_set ? ` · 规则: ${preview.rule_set}` : ' · 未识别到章节标题'}
+                        </p>
+                    </div>
+                    <button className="btn btn-ghost btn-sm btn-square" onClick={onCancel}>
+                        <X size={16} />
+                    </button>
+                </div>
+
+                {/* Toolbar */}
+                <div className="flex items-center gap-2 px-4 py-2 border-b border-base-300 shrink-0 bg-base-100/50">
+                    <button className="btn btn-xs btn-outline" onClick={() => setAll(true)}>全选</button>
+                    <button className="btn btn-xs btn-outline" onClick={() => setAll(false)}>全不选</button>
+                    <select
+                        className="select select-bordered select-xs"
+                        value={chosenRules ?? ''}
+                        onChange={(e) => onChangeRules(e.target.value || null)}
+                    >
+                        <option value="">自动识别</option>
+                        {ruleSets.map((set) => (
+                            <option key={set.name} value={set.name}>{set.name}</option>
+                        ))}
+                    </select>
+                    <select
+                        className="select select-bordered select-xs"
+                        value={chosenEncoding ?? ''}
+                        onChange={(e) => onChangeEncoding(e.target.value || null)}
+                        title={`识别结果: ${preview.encoding.name}（置信度 ${Math.round(preview.encoding.confidence * 100)}%）`}
+                    >
+                        <option value="">自动识别编码 ({preview.encoding.name})</option>
+                        {ENCODINGS.map((enc) => (
+                            <option key={enc} value={enc}>{enc}</option>
+                        ))}
+                    </select>
+                    <div className="flex-1" />
+                    <span className="text-xs text-base-content/50">
+                        已选 {selectedRows.length} 章 · 约 {totalChars.toLocaleString()} 字
+                    </span>
+                </div>
+
+                {preview.encoding_warning && (
+                    <div className="flex items-center gap-2 px-4 py-2 text-xs bg-warning/10 text-warning border-b border-base-300 shrink-0">
+                        <AlertTriangle size={14} className="shrink-0" />
+                        <span>{preview.encoding_warning}</span>
+                    </div>
+                )}
+
+                <LibraryMatchWarning matches={preview.library_matches} />
+
+                {/* Chapter List */}
+                <div className="overflow-y-auto flex-1 p-2">
+                    {rows.map((row, i) => {
+                        const first = preview.chapters[row.indices[0]];
+                        const chars = rowChars(row);
+                        return (
+                            <div
+                                key={row.indices.join('-')}
+                                className={`flex items-center gap-3 px-3 py-2 rounded-lg transition-colors hover:bg-base-300/50 group ${row.selected ? '' : 'opacity-50'}`}
+                            >
+                                <button
+                                    className="btn btn-ghost btn-xs btn-square p-0"
+                                    onClick={() => update(i, { selected: !row.selected })}
+                                >
+                                    {row.selected
+                                        ? <CheckSquare size={18} className="text-primary" />
+                                        : <Square size={18} className="text-base-content/30" />
+                                    }
+                                </button>
+                                <div className="flex-1 min-w-0">
+                                    <input
+                                        className="input input-ghost input-xs w-full text-sm font-medium px-1"
+                                        value={row.title}
+                                        onChange={(e) => update(i, { title: e.target.value })}
+                                        title={first.pattern ?? undefined}
+                                    />
+                                    {first.volume && (
+                                        <span className="text-xs text-base-content/40 px-1">{first.volume}</span>
+                                    )}
+                                    {row.indices.length > 1 && (
+                                        <span className="text-xs text-base-content/40 px-1">已合并 {row.indices.length} 条</span>
+                                    )}
+                                </div>
+                                <span className="text-xs text-base-content/40 shrink-0">
+                                    {chars.toLocaleString()} 字
+                                </span>
+                                {row.indices.length === 1 && first.warning && (
+                                    <span className="badge badge-xs badge-warning shrink-0" title={first.warning}>
+                                        {first.warning.length > 10 ? `${first.warning.slice(0, 10)}…` : first.warning}
+                                    </span>
+                                )}
+                                {row.indices.length > 1 ? (
+                                    <button className="btn btn-ghost btn-xs btn-square shrink-0" title="拆开" onClick={() => unmerge(i)}>
+                                        <Unlink size={13} />
+                                    </button>
+                                ) : i > 0 && (
+                                    <button
+                                        className="btn btn-ghost btn-xs btn-square shrink-0 opacity-0 group-hover:opacity-100"
+                                        title="合并到上一章"
+                                        onClick={() => mergeIntoPrevious(i)}
+                                    >
+                                        <ArrowUpToLine size={13} />
+                                    </button>
+                                )}
+                            </div>
+                        );
+                    })}
+                </div>
+
+                {/* Footer */}
+                <div className="flex justify-end items-center gap-2 p-4 border-t border-base-300 shrink-0 bg-base-200 rounded-b-2xl">
+                    <ConversionSelect value={conversion} onChange={setConversion} />
+                    <div className="flex-1" />
+                    <button className="btn btn-ghost btn-sm" onClick={onCancel}>取消</button>
+                    <button
+                        className="btn btn-primary btn-sm"
+                        onClick={handleConfirm}
+                        disabled={selectedRows.length === 0}
+                    >
+                        导入 {selectedRows.length} 个章节
+                    </button>
+                </div>
+            </motion.div>
+        </div>,
+        document.body
+    );
+}
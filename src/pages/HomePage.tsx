@@ -2,12 +2,30 @@ import { useEffect, useState } from 'react';
 import { useNavigate } from 'react-router-dom';
 import { useNovelStore } from '../store/novelStore';
 import { open } from '@tauri-apps/plugin-dialog';
-import { Plus, BookOpen, Trash2, FileText } from 'lucide-react';
-import EpubPreviewModal from '../components/EpubPreviewModal';
+import { Plus, Trash2, FileText, FilePlus, FolderOpen } from 'lucide-react';
+import NovelCover from '../components/NovelCover';
+import BookPreviewModal from '../components/BookPreviewModal';
+import TxtPreviewModal from '../components/TxtPreviewModal';
 import ConfirmDialog from '../components/ConfirmDialog';
-import type { EpubPreview } from '../types';
+import type { BookPreview, TxtPreview, TxtChapterSelection, HeadingRuleSet, ChineseConversion } from '../types';
 import { AnimatePresence, motion, Variants } from 'framer-motion';
 
+/** Labels of the `import_progress` stages */
+const IMPORT_STAGES: Record<string, string> = {
+    parsing: '解析中',
+    cleaning: '清理中',
+    saving: '保存中',
+    done: '导入完成',
+    cancelled: '已取消',
+    error: '导入失败',
+};
+const IMPORT_ENDED = ['done', 'cancelled', 'error'];
+
+/** Formats imported through the book preview; everything else is read as TXT */
+const BOOK_EXTENSIONS = ['epub', 'mobi', 'azw', 'azw3', 'fb2', 'fbz', 'zip', 'docx', 'html', 'htm', 'xhtml', 'md', 'markdown'];
+
+const isBook = (path: string) => BOOK_EXTENSIONS.includes(path.split('.').pop()?.toLowerCase() ?? '');
+
 const containerVariants: Variants = {
     hidden: { opacity: 0 },
     show: {
@@ -22,18 +40,28 @@ const itemVariants: Variants = {
 };
 
 export default function HomePage() {
-    const { novels, fetchNovels, previewEpub, importEpubSelected, importSingleTxt, importTxtFiles, deleteNovel, selectNovel } = useNovelStore();
+    const {
+        novels, fetchNovels, previewBook, importBookSelected, previewSingleTxt, importTxtSelected,
+        listHeadingRuleSets, importTxtFiles, deleteNovel, selectNovel, importProgress, cancelImport,
+    } = useNovelStore();
     const navigate = useNavigate();
-    const [epubPreview, setEpubPreview] = useState<EpubPreview | null>(null);
+    const [bookPreview, setBookPreview] = useState<BookPreview | null>(null);
+    const [txtPreview, setTxtPreview] = useState<TxtPreview | null>(null);
+    const [txtRules, setTxtRules] = useState<string | null>(null);
+    const [txtEncoding, setTxtEncoding] = useState<string | null>(null);
+    const [ruleSets, setRuleSets] = useState<HeadingRuleSet[]>([]);
     const [novelToDelete, setNovelToDelete] = useState<{ id: string; title: string } | null>(null);
+    // Novel the chosen files are appended to, instead of importing a new one
+    const [appendTo, setAppendTo] = useState<string | null>(null);
 
     useEffect(() => { fetchNovels(); }, [fetchNovels]);
 
-    const handleImport = async () => {
+    const handleImport = async (target: string | null = null) => {
+        setAppendTo(target);
         const result = await open({
             multiple: true,
             filters: [
-                { name: '小说文件', extensions: ['epub', 'txt'] },
+                { name: '小说文件', extensions: ['txt', ...BOOK_EXTENSIONS] },
             ],
         });
 
@@ -42,24 +70,29 @@ export default function HomePage() {
         try {
             const paths = result as string[];
 
-            if (paths.length === 1 && paths[0].endsWith('.epub')) {
-                // EPUB: show preview modal for user selection
-                const preview = await previewEpub(paths[0]);
-                setEpubPreview(preview);
+            if (paths.length === 1 && isBook(paths[0])) {
+                // E-books and documents: show preview modal for user selection
+                const preview = await previewBook(paths[0]);
+                setBookPreview(preview);
                 return;
             }
 
-            let novelId: string;
             if (paths.length === 1) {
-                novelId = await importSingleTxt(paths[0]);
-            } else {
-                const txtPaths = paths.filter(p => p.endsWith('.txt'));
-                if (txtPaths.length === 0) {
-                    throw new Error('请选择 TXT 文件');
-                }
-                novelId = await importTxtFiles(txtPaths);
+                // Single TXT: review the detected chapter split first
+                const [preview, sets] = await Promise.all([previewSingleTxt(paths[0]), listHeadingRuleSets()]);
+                setRuleSets(sets);
+                setTxtRules(null);
+                setTxtEncoding(null);
+                setTxtPreview(preview);
+                return;
             }
 
+            const txtPaths = paths.filter(p => p.endsWith('.txt'));
+            if (txtPaths.length === 0) {
+                throw new Error('请选择 TXT 文件');
+            }
+            const novelId = await importTxtFiles(txtPaths, undefined, undefined, target ?? undefined);
+
             await selectNovel(novelId);
             navigate(`/novel/${novelId}`);
         } catch (e) {
@@ -67,15 +100,67 @@ export default function HomePage() {
         }
     };
 
-    const handleEpubConfirm = async (selectedIndices: number[]) => {
-        if (!epubPreview) return;
+    /** A folder of saved web chapters or Markdown files, one chapter per file */
+    const handleImportFolder = async () => {
+        setAppendTo(null);
+        const dir = await open({ directory: true });
+        if (!dir) return;
         try {
-            const novelId = await importEpubSelected(epubPreview.path, selectedIndices);
-            setEpubPreview(null);
+            setBookPreview(await previewBook(dir as string));
+        } catch (e) {
+            console.error('Import failed:', e);
+        }
+    };
+
+    const handleBookConfirm = async (selectedIndices: number[], conversion: ChineseConversion | null, keepRuby: boolean) => {
+        if (!bookPreview) return;
+        // Close the preview so the import progress shows
+        setBookPreview(null);
+        try {
+            const novelId = await importBookSelected(
+                bookPreview.path, selectedIndices, conversion ?? undefined, keepRuby, appendTo ?? undefined,
+            );
             await selectNovel(novelId);
             navigate(`/novel/${novelId}`);
         } catch (e) {
-            console.error('EPUB import failed:', e);
+            console.error('Book import failed:', e);
+        }
+    };
+
+    const handleTxtRulesChange = async (name: string | null) => {
+        if (!txtPreview) return;
+        try {
+            const preview = await previewSingleTxt(txtPreview.path, name ?? undefined, txtEncoding ?? undefined);
+            setTxtRules(name);
+            setTxtPreview(preview);
+        } catch (e) {
+            console.error('TXT preview failed:', e);
+        }
+    };
+
+    const handleTxtEncodingChange = async (encoding: string | null) => {
+        if (!txtPreview) return;
+        try {
+            const preview = await previewSingleTxt(txtPreview.path, txtRules ?? undefined, encoding ?? undefined);
+            setTxtEncoding(encoding);
+            setTxtPreview(preview);
+        } catch (e) {
+            console.error('TXT preview failed:', e);
+        }
+    };
+
+    const handleTxtConfirm = async (chapters: TxtChapterSelection[], conversion: ChineseConversion | null) => {
+        if (!txtPreview) return;
+        setTxtPreview(null);
+        try {
+            const novelId = await importTxtSelected(
+                txtPreview.path, txtPreview.rule_set, chapters, txtPreview.encoding.name, conversion ?? undefined,
+                appendTo ?? undefined,
+            );
+            await selectNovel(novelId);
+            navigate(`/novel/${novelId}`);
+        } catch (e) {
+            console.error('TXT import failed:', e);
         }
     };
 
@@ -101,20 +186,50 @@ export default function HomePage() {
                             </h1>
                             <p className="text-base-content/60 mt-1">AI 驱动的小说分析工具</p>
                         </div>
-                        <button className="btn btn-primary gap-2" onClick={handleImport}>
-                            <Plus size={18} />
-                            导入小说
-                        </button>
+                        <div className="flex gap-2">
+                            <button className="btn btn-ghost gap-2" onClick={handleImportFolder} title="每个 HTML 或 Markdown 文件一章">
+                                <FolderOpen size={18} />
+                                导入文件夹
+                            </button>
+                            <button className="btn btn-primary gap-2" onClick={() => handleImport()}>
+                                <Plus size={18} />
+                                导入小说
+                            </button>
+                        </div>
                     </div>
 
+                    {/* Import progress */}
+                    {importProgress && (
+                        <div className="card bg-base-200 border border-base-300 mb-6">
+                            <div className="card-body py-4 gap-2">
+                                <div className="flex items-center justify-between text-sm">
+                                    <span className="truncate">
+                                        {IMPORT_STAGES[importProgress.status] ?? importProgress.status}
+                                        {importProgress.message && ` · ${importProgress.message}`}
+                                    </span>
+                                    {!IMPORT_ENDED.includes(importProgress.status) && (
+                                        <button className="btn btn-ghost btn-xs" onClick={() => cancelImport()}>
+                                            取消
+                                        </button>
+                                    )}
+                                </div>
+                                <progress
+                                    className="progress progress-primary w-full"
+                                    value={importProgress.total > 0 ? importProgress.current : undefined}
+                                    max={importProgress.total > 0 ? importProgress.total : undefined}
+                                />
+                            </div>
+                        </div>
+                    )}
+
                     {/* Novel Grid */}
                     {novels.length === 0 ? (
                         <div className="card bg-base-200 border border-base-300">
                             <div className="card-body items-center text-center py-16">
                                 <FileText size={48} className="text-base-content/30 mb-4" />
                                 <h2 className="card-title text-base-content/50">还没有导入任何小说</h2>
-                                <p className="text-base-content/40">支持 EPUB 和 TXT 格式</p>
-                                <button className="btn btn-primary btn-sm mt-4 gap-2" onClick={handleImport}>
+                                <p className="text-base-content/40">支持 EPUB、MOBI/AZW3、FB2、DOCX、HTML、Markdown 和 TXT 格式</p>
+                                <button className="btn btn-primary btn-sm mt-4 gap-2" onClick={() => handleImport()}>
                                     <Plus size={16} />
                                     导入第一本
                                 </button>
@@ -136,17 +251,36 @@ export default function HomePage() {
                                 >
                                     <div className="card-body p-5">
                                         <div className="flex items-start justify-between">
-                                            <div className="flex items-center gap-2">
-                                                <BookOpen size={18} className="text-primary" />
-                                                <h3 className="card-title text-base line-clamp-1">{novel.title}</h3>
+                                            <div className="flex items-start gap-3 min-w-0">
+                                                <NovelCover novelId={novel.id} hasCover={novel.has_cover} className="w-10 h-14" />
+                                                <div className="min-w-0">
+                                                    <h3 className="card-title text-base line-clamp-1">{novel.title}</h3>
+                                                    {novel.metadata.author && (
+                                                        <p className="text-xs text-base-content/60 line-clamp-1">{novel.metadata.author}</p>
+                                                    )}
+                                                    {novel.metadata.series && (
+                                                        <p className="text-xs text-base-content/40 line-clamp-1">
+                                                            {novel.metadata.series}{novel.metadata.series_index && ` #${novel.metadata.series_index}`}
+                                                        </p>
+                                                    )}
+                                                </div>
+                                            </div>
+                                            <div className="flex">
+                                                <button
+                                                    className="btn btn-ghost btn-xs"
+                                                    onClick={(e) => { e.stopPropagation(); handleImport(novel.id); }}
+                                                    title="追加章节"
+                                                >
+                                                    <FilePlus size={14} />
+                                                </button>
+                                                <button
+                                                    className="btn btn-ghost btn-xs text-error"
+                                                    onClick={(e) => handleDelete(e, novel.id, novel.title)}
+                                                    title="删除"
+                                                >
+                                                    <Trash2 size={14} />
+                                                </button>
                                             </div>
-                                            <button
-                                                className="btn btn-ghost btn-xs text-error"
-                                                onClick={(e) => handleDelete(e, novel.id, novel.title)}
-                                                title="删除"
-                                            >
-                                                <Trash2 size={14} />
-                                            </button>
                                         </div>
 
                                         <div className="flex gap-3 mt-3">
@@ -176,11 +310,26 @@ export default function HomePage() {
             </div>
 
             <AnimatePresence>
-                {epubPreview && (
-                    <EpubPreviewModal
-                        preview={epubPreview}
-                        onConfirm={handleEpubConfirm}
-                        onCancel={() => setEpubPreview(null)}
+                {bookPreview && (
+                    <BookPreviewModal
+                        preview={bookPreview}
+                        onConfirm={handleBookConfirm}
+                        onCancel={() => setBookPreview(null)}
+                    />
+                )}
+            </AnimatePresence>
+
+            <AnimatePresence>
+                {txtPreview && (
+                    <TxtPreviewModal
+                        preview={txtPreview}
+                        ruleSets={ruleSets}
+                        chosenRules={txtRules}
+                        onChangeRules={handleTxtRulesChange}
+                        chosenEncoding={txtEncoding}
+                        onChangeEncoding={handleTxtEncodingChange}
+                        onConfirm={handleTxtConfirm}
+                        onCancel={() => setTxtPreview(null)}
                     />
                 )}
             </AnimatePresence>
@@ -1,14 +1,17 @@
-import { useEffect, useState, useRef } from 'react';
+import { Fragment, useEffect, useState, useRef } from 'react';
 import { useParams } from 'react-router-dom';
 import { useNovelStore } from '../store/novelStore';
 import ChapterAnalysisView from '../components/ChapterAnalysisView';
 import ManualPromptPanel from '../components/ManualPromptPanel';
 import FullBookManualPromptPanel from '../components/FullBookManualPromptPanel';
 import DimensionSelector from '../components/DimensionSelector';
-import { Play, CheckCircle, Circle, ChevronRight, Zap, ClipboardCopy, Settings2, Trash2, ListChecks, X, Download } from 'lucide-react';
+import { Play, CheckCircle, Circle, CircleDot, ChevronRight, Zap, ClipboardCopy, Settings2, Trash2, ListChecks, X, Download, Combine, Pencil, ArrowUp, ArrowDown, RefreshCw, AlertTriangle, Copy } from 'lucide-react';
 import ConfirmDialog from '../components/ConfirmDialog';
+import CleanupReportBanner from '../components/CleanupReportBanner';
+import { CONVERSIONS } from '../components/ConversionSelect';
 import { open } from '@tauri-apps/plugin-dialog';
 import { invoke } from '@tauri-apps/api/core';
+import type { ChapterMeta, ChineseConversion, DuplicateChapter } from '../types';
 
 const formatTime = (ms: number) => {
     const s = Math.floor(ms / 1000);
@@ -215,14 +218,19 @@ export default function NovelPage() {
     const { novelId } = useParams<{ novelId: string }>();
     const [exportAlert, setExportAlert] = useState<{ title: string, msg: string, kind: 'info' | 'error' } | null>(null);
     const {
-        currentNovel, chapters, selectedChapter,
+        currentNovel, chapters, volumes, selectedChapter,
+        renameChapter, mergeChapters, moveChapter,
         selectNovel, selectChapter, analysisMode, setAnalysisMode,
         analyzeChapterApi, batchAnalyzeNovel, batchAnalyzeChapters, cancelBatch,
         deleteChapter, clearChapterAnalysis, analyzingChapterIds, loading, fetchDimensions,
-        progress, batchProgress, streamContent, batchStartTime
+        progress, batchProgress, streamContent, batchStartTime, reparseRawResponse,
+        lastImport, dismissLastImport, resyncNovel, findDuplicateChapters, importProgress, cancelImport
     } = useNovelStore();
 
     const hasAnyAnalysis = chapters.some(c => c.has_analysis);
+    const isComplete = (c: ChapterMeta) =>
+        !c.analysis_stale && (currentNovel?.enabled_dimensions || []).every(d => c.analyzed_dimensions.includes(d));
+    const hasIncomplete = chapters.some(c => c.has_analysis && !isComplete(c));
 
     const [showDimSelector, setShowDimSelector] = useState(false);
     const [viewMode, setViewMode] = useState<'chapter' | 'full-book'>('chapter');
@@ -230,9 +238,14 @@ export default function NovelPage() {
     const [clearTarget, setClearTarget] = useState<{ id: number; title: string } | null>(null);
     const [isCancelling, setIsCancelling] = useState(false);
     const [confirmBatchDelete, setConfirmBatchDelete] = useState(false);
+    const [resyncing, setResyncing] = useState(false);
+    // Chapters repeating an earlier one, by chapter id, from the last duplicate check
+    const [duplicates, setDuplicates] = useState<{ novelId: string; byChapter: Map<number, DuplicateChapter> } | null>(null);
 
     // Multi-select state
     const [multiSelectMode, setMultiSelectMode] = useState(false);
+    const [renaming, setRenaming] = useState<{ id: number, title: string } | null>(null);
+    const [confirmMerge, setConfirmMerge] = useState(false);
     const [multiSelectIds, setMultiSelectIds] = useState<Set<number>>(new Set());
 
     useEffect(() => {
@@ -287,7 +300,8 @@ export default function NovelPage() {
         }
     };
 
-    const handleExport = async () => {
+    const handleExport = async (conversion: ChineseConversion | null) => {
+        (document.activeElement as HTMLElement | null)?.blur();
         try {
             const dirPath = await open({
                 directory: true,
@@ -295,7 +309,7 @@ export default function NovelPage() {
                 title: '选择导出目录'
             });
             if (dirPath) {
-                await invoke('export_novel_report', { novelId: currentNovel.id, dirPath: dirPath as string });
+                await invoke('export_novel_report', { novelId: currentNovel.id, dirPath: dirPath as string, conversion });
                 setExportAlert({ title: '导出成功', msg: `已成功将分析报告导出至:\n${dirPath}`, kind: 'info' });
             }
         } catch (e) {
@@ -304,6 +318,52 @@ export default function NovelPage() {
         }
     };
 
+    const handleResync = async () => {
+        setResyncing(true);
+        try {
+            const report = await resyncNovel(currentNovel.id);
+            const lines = [
+                `未变 ${report.unchanged} 章，更新 ${report.modified.length} 章，新增 ${report.added.length} 章。`,
+            ];
+            if (report.modified.length > 0) {
+                lines.push('更新章节的分析已保留，但标记为过期，可重新分析。');
+            }
+            if (report.missing.length > 0) {
+                lines.push(`源文件中已找不到 ${report.missing.length} 章（已保留）：${report.missing.join('、')}`);
+            }
+            setExportAlert({ title: '同步完成', msg: lines.join('\n'), kind: 'info' });
+        } catch (e) {
+            console.error('Resync failed:', e);
+            setExportAlert({ title: '错误', msg: `同步失败: ${e}`, kind: 'error' });
+        } finally {
+            setResyncing(false);
+        }
+    };
+
+    /** Mark repeated chapters and select them, to delete or to leave out of batch analysis */
+    const handleFindDuplicates = async () => {
+        try {
+            const found = await findDuplicateChapters(currentNovel.id);
+            setDuplicates({ novelId: currentNovel.id, byChapter: new Map(found.map(d => [d.chapter_id, d])) });
+            if (found.length === 0) {
+                setExportAlert({ title: '查找重复章节', msg: '没有发现重复的章节。', kind: 'info' });
+                return;
+            }
+            setMultiSelectMode(true);
+            setMultiSelectIds(new Set(found.map(d => d.chapter_id)));
+            const exact = found.filter(d => d.exact).length;
+            setExportAlert({
+                title: '查找重复章节',
+                msg: `发现 ${found.length} 章与前面的章节重复（完全相同 ${exact} 章，内容相似 ${found.length - exact} 章），已全部选中。\n可以删除它们，或取消选择后再批量分析，避免重复付费。`,
+                kind: 'info',
+            });
+        } catch (e) {
+            console.error('Duplicate check failed:', e);
+            setExportAlert({ title: '错误', msg: `查找重复章节失败: ${e}`, kind: 'error' });
+        }
+    };
+    const duplicateOf = duplicates?.novelId === currentNovel.id ? duplicates.byChapter : null;
+
     const isChapterBusy = (id: number) =>
         analyzingChapterIds.has(id) || (batchProgress?.status === 'batch_analyzing' && batchProgress?.chapter_id === id);
 
@@ -318,6 +378,9 @@ export default function NovelPage() {
                             {chapters.filter(c => c.has_analysis).length}/{chapters.length} 已分析
                         </div>
                     </div>
+                    {lastImport?.novel_id === currentNovel.id && (
+                        <CleanupReportBanner report={lastImport.cleanup} skipped={lastImport.skipped} sameTitle={lastImport.same_title} onDismiss={dismissLastImport} />
+                    )}
 
                     <div className="flex gap-2 mt-4 tabs tabs-boxed bg-base-300/50 p-1 rounded-full">
                         <button
@@ -359,11 +422,33 @@ export default function NovelPage() {
                         </button>
                         <button
                             className="btn btn-ghost btn-xs btn-square"
-                            onClick={handleExport}
-                            title="导出分析报告"
+                            onClick={resyncing ? () => cancelImport() : handleResync}
+                            title={resyncing
+                                ? `${importProgress?.message || '同步中'}，点击取消`
+                                : '从源文件同步：保留未变章节的分析，追加新章节'}
                         >
-                            <Download size={14} />
+                            {resyncing ? <span className="loading loading-spinner loading-xs" /> : <RefreshCw size={14} />}
                         </button>
+                        <button
+                            className="btn btn-ghost btn-xs btn-square"
+                            onClick={handleFindDuplicates}
+                            title="查找与前文重复或高度相似的章节"
+                        >
+                            <Copy size={14} />
+                        </button>
+                        <div className="dropdown dropdown-end">
+                            <button tabIndex={0} className="btn btn-ghost btn-xs btn-square" title="导出分析报告">
+                                <Download size={14} />
+                            </button>
+                            <ul tabIndex={0} className="dropdown-content menu menu-sm bg-base-100 rounded-box shadow z-20 w-44 p-1">
+                                <li><button onClick={() => handleExport(null)}>导出</button></li>
+                                {CONVERSIONS.map((c) => (
+                                    <li key={c.value}>
+                                        <button onClick={() => handleExport(c.value)}>导出（{c.label}）</button>
+                                    </li>
+                                ))}
+                            </ul>
+                        </div>
                         <button
                             className="btn btn-ghost btn-xs btn-square"
                             onClick={() => setShowDimSelector(!showDimSelector)}
@@ -420,6 +505,14 @@ export default function NovelPage() {
                                     <Play size={14} /> 分析 ({multiSelectIds.size})
                                 </button>
                             )}
+                            <button
+                                className="btn btn-outline btn-sm flex-none gap-1"
+                                onClick={() => setConfirmMerge(true)}
+                                disabled={multiSelectIds.size < 2 || loading || !!batchProgress}
+                                title="合并相邻的章节"
+                            >
+                                <Combine size={14} /> 合并
+                            </button>
                             <button
                                 className="btn btn-error btn-outline btn-sm flex-none gap-1"
                                 onClick={() => setConfirmBatchDelete(true)}
@@ -479,14 +572,29 @@ export default function NovelPage() {
                         >
                             <Play size={14} /> 批量分析未分析章节
                         </button>
+                        {hasIncomplete && (
+                            <button
+                                className={`btn btn-ghost btn-sm w-full mt-2 ${loading ? 'btn-disabled' : ''}`}
+                                onClick={() => batchAnalyzeNovel(currentNovel.id, undefined, 'incremental')}
+                                disabled={loading}
+                                title="只请求已分析章节缺少的维度，并合并到现有结果中"
+                            >
+                                <Play size={14} /> 补全缺失维度
+                            </button>
+                        )}
                     </div>
                 )}
 
                 {/* Chapter list */}
                 <div className="flex-1 overflow-y-auto">
-                    {chapters.map((ch) => (
+                    {chapters.map((ch, i) => (
+                        <Fragment key={ch.id}>
+                        {ch.volume_id !== null && ch.volume_id !== chapters[i - 1]?.volume_id && (
+                            <div className="px-4 py-1.5 text-xs font-semibold text-base-content/50 bg-base-200 border-b border-base-300/50">
+                                {volumes.find(v => v.id === ch.volume_id)?.title}
+                            </div>
+                        )}
                         <button
-                            key={ch.id}
                             className={`w-full text-left px-4 py-3 border-b border-base-300/50 flex items-center gap-3 hover:bg-base-300/50 transition-colors group ${!multiSelectMode && selectedChapter?.id === ch.id && viewMode === 'chapter' ? 'bg-base-300' : ''
                                 } ${multiSelectMode && multiSelectIds.has(ch.id) ? 'bg-accent/10' : ''}`}
                             onClick={() => {
@@ -509,18 +617,83 @@ export default function NovelPage() {
                                     />
                                 ) : isChapterBusy(ch.id) ? (
                                     <span className="loading loading-spinner loading-xs text-primary" />
-                                ) : ch.has_analysis ? (
+                                ) : ch.analysis_stale ? (
+                                    <span title="正文已更新，分析已过期"><AlertTriangle size={16} className="text-error" /></span>
+                                ) : ch.has_analysis && isComplete(ch) ? (
                                     <CheckCircle size={16} className="text-success" />
+                                ) : ch.has_analysis ? (
+                                    <CircleDot size={16} className="text-warning" />
                                 ) : (
                                     <Circle size={16} className="text-base-content/30" />
                                 )}
                             </div>
                             <div className="flex-1 min-w-0">
-                                <p className="text-sm font-medium line-clamp-1">{ch.title || `第 ${ch.index + 1} 章`}</p>
-                                <p className="text-xs text-base-content/40">~{ch.token_estimate.toLocaleString()} tokens</p>
+                                {renaming?.id === ch.id ? (
+                                    <input
+                                        className="input input-bordered input-xs w-full text-sm"
+                                        value={renaming.title}
+                                        autoFocus
+                                        onClick={(e) => e.stopPropagation()}
+                                        onChange={(e) => setRenaming({ id: ch.id, title: e.target.value })}
+                                        onKeyDown={(e) => {
+                                            e.stopPropagation();
+                                            if (e.key === 'Enter' && renaming.title.trim()) {
+                                                renameChapter(ch.id, renaming.title, currentNovel.id);
+                                                setRenaming(null);
+                                            } else if (e.key === 'Escape') {
+                                                setRenaming(null);
+                                            }
+                                        }}
+                                        onBlur={() => setRenaming(null)}
+                                    />
+                                ) : (
+                                    <p className="text-sm font-medium line-clamp-1">{ch.title || `第 ${ch.index + 1} 章`}</p>
+                                )}
+                                <p className="text-xs text-base-content/40">
+                                    ~{ch.token_estimate.toLocaleString()} tokens
+                                    {duplicateOf?.has(ch.id) && (
+                                        <span className="text-warning">
+                                            {' · '}{duplicateOf.get(ch.id)!.exact ? '重复' : '近似'}第 {duplicateOf.get(ch.id)!.duplicate_of_index + 1} 章
+                                        </span>
+                                    )}
+                                </p>
                             </div>
-                            {!multiSelectMode && (
+                            {!multiSelectMode && renaming?.id !== ch.id && (
                                 <>
+                                    <div className="flex opacity-0 group-hover:opacity-100 transition-opacity flex-shrink-0">
+                                        <button
+                                            className="btn btn-ghost btn-xs btn-square"
+                                            onClick={(e) => {
+                                                e.stopPropagation();
+                                                setRenaming({ id: ch.id, title: ch.title });
+                                            }}
+                                            title="重命名"
+                                        >
+                                            <Pencil size={12} />
+                                        </button>
+                                        <button
+                                            className="btn btn-ghost btn-xs btn-square"
+                                            onClick={(e) => {
+                                                e.stopPropagation();
+                                                moveChapter(ch.id, ch.index - 1, currentNovel.id);
+                                            }}
+                                            disabled={i === 0 || !!batchProgress}
+                                            title="上移"
+                                        >
+                                            <ArrowUp size={12} />
+                                        </button>
+                                        <button
+                                            className="btn btn-ghost btn-xs btn-square"
+                                            onClick={(e) => {
+                                                e.stopPropagation();
+                                                moveChapter(ch.id, ch.index + 1, currentNovel.id);
+                                            }}
+                                            disabled={i === chapters.length - 1 || !!batchProgress}
+                                            title="下移"
+                                        >
+                                            <ArrowDown size={12} />
+                                        </button>
+                                    </div>
                                     <button
                                         className="btn btn-ghost btn-xs text-error opacity-0 group-hover:opacity-100 transition-opacity flex-shrink-0"
                                         onClick={(e) => {
@@ -535,6 +708,7 @@ export default function NovelPage() {
                                 </>
                             )}
                         </button>
+                        </Fragment>
                     ))}
                 </div>
             </aside>
@@ -610,6 +784,15 @@ export default function NovelPage() {
                             <Play size={16} />
                             开始 API 分析
                         </button>
+                        {chapters.find(c => c.id === selectedChapter.id)?.has_raw_response && (
+                            <button
+                                className="btn btn-ghost btn-sm"
+                                onClick={() => reparseRawResponse(selectedChapter.id!).catch(e => console.error('Reparse failed:', e))}
+                                disabled={!!batchProgress}
+                            >
+                                从已保存的原始返回恢复
+                            </button>
+                        )}
                     </div>
                 ) : (
                     <div className="flex-1 overflow-y-auto p-6">
@@ -664,6 +847,21 @@ export default function NovelPage() {
                 />
             )}
 
+            {confirmMerge && (
+                <ConfirmDialog
+                    title="合并章节"
+                    message={`确定要把选定的 ${multiSelectIds.size} 个相邻章节合并为一章吗？合并后需要重新分析。`}
+                    confirmText="合并"
+                    kind="warning"
+                    onConfirm={() => {
+                        mergeChapters(Array.from(multiSelectIds), currentNovel.id).catch(e => console.error('Merge failed:', e));
+                        setConfirmMerge(false);
+                        exitMultiSelect();
+                    }}
+                    onCancel={() => setConfirmMerge(false)}
+                />
+            )}
+
             {exportAlert && (
                 <ConfirmDialog
                     title={exportAlert.title}
@@ -4,7 +4,10 @@ import { listen } from '@tauri-apps/api/event';
 import type {
     NovelMeta, Novel, ChapterMeta, Chapter, ChapterAnalysis,
     LlmConfig, AnalysisDimension, AnalysisMode, DimensionInfo, NovelSummary,
-    ProgressEvent, StreamingEvent, EpubPreview,
+    ProgressEvent, StreamingEvent, BookPreview, AnalysisJob, BatchReport, FailurePolicy,
+    LlmCall, LlmCallMeta, AnalysisVersion, FieldDiff, UpdateMode,
+    HeadingRuleSet, TxtPreview, TxtChapterSelection, Volume, SplitPoint,
+    CleanupConfig, ImportResult, ChineseConversion, ResyncReport, DuplicateChapter,
 } from '../types';
 
 interface NovelStore {
@@ -12,6 +15,7 @@ interface NovelStore {
     novels: NovelMeta[];
     currentNovel: Novel | null;
     chapters: ChapterMeta[];
+    volumes: Volume[];
     selectedChapter: Chapter | null;
     llmConfig: LlmConfig;
     analysisMode: AnalysisMode;
@@ -21,28 +25,56 @@ interface NovelStore {
     progress: ProgressEvent | null;
     batchProgress: ProgressEvent | null;
     batchStartTime: number | null;
+    /** Progress of the running import, until shortly after it ends */
+    importProgress: ProgressEvent | null;
     streamContent: Record<number, string>;
     analyzingChapterIds: Set<number>;
+    /** Result of the most recent import, with what cleanup removed */
+    lastImport: ImportResult | null;
     loading: boolean;
     error: string | null;
 
     // Actions
     fetchNovels: () => Promise<void>;
-    previewEpub: (path: string) => Promise<EpubPreview>;
-    importEpubSelected: (path: string, selectedIndices: number[]) => Promise<string>;
-    importTxtFiles: (paths: string[]) => Promise<string>;
-    importSingleTxt: (path: string) => Promise<string>;
+    /** Preview an EPUB, MOBI/AZW3, FB2, DOCX, HTML or Markdown file, or a folder of HTML or Markdown chapters */
+    previewBook: (path: string) => Promise<BookPreview>;
+    importBookSelected: (path: string, selectedIndices: number[], conversion?: ChineseConversion, keepRuby?: boolean, appendTo?: string) => Promise<string>;
+    importTxtFiles: (paths: string[], encoding?: string, conversion?: ChineseConversion, appendTo?: string) => Promise<string>;
+    importSingleTxt: (path: string, headingRules?: string, encoding?: string, conversion?: ChineseConversion, appendTo?: string) => Promise<string>;
+    previewSingleTxt: (path: string, headingRules?: string, encoding?: string) => Promise<TxtPreview>;
+    importTxtSelected: (path: string, headingRules: string | null, chapters: TxtChapterSelection[], encoding?: string, conversion?: ChineseConversion, appendTo?: string) => Promise<string>;
+    listHeadingRuleSets: () => Promise<HeadingRuleSet[]>;
+    saveHeadingRuleSets: (sets: HeadingRuleSet[]) => Promise<void>;
+    getCleanupConfig: () => Promise<CleanupConfig>;
+    saveCleanupConfig: (config: CleanupConfig) => Promise<void>;
+    dismissLastImport: () => void;
+    getNovelCover: (novelId: string) => Promise<string | null>;
+    resyncNovel: (novelId: string) => Promise<ResyncReport>;
+    /** Chapters repeating an earlier chapter of the novel, exactly or nearly */
+    findDuplicateChapters: (novelId: string) => Promise<DuplicateChapter[]>;
     deleteNovel: (id: string) => Promise<void>;
     deleteChapter: (chapterId: number, novelId: string) => Promise<void>;
     deleteChapters: (chapterIds: number[], novelId: string) => Promise<void>;
+    renameChapter: (chapterId: number, title: string, novelId: string) => Promise<void>;
+    mergeChapters: (chapterIds: number[], novelId: string) => Promise<number>;
+    splitChapter: (chapterId: number, at: SplitPoint, novelId: string, title?: string) => Promise<number>;
+    moveChapter: (chapterId: number, toIndex: number, novelId: string) => Promise<void>;
     clearChapterAnalysis: (chapterId: number, novelId: string) => Promise<void>;
     selectNovel: (id: string) => Promise<void>;
     fetchChapters: (novelId: string) => Promise<void>;
     selectChapter: (chapterId: number) => Promise<void>;
     generatePrompt: (chapterId: number) => Promise<string>;
     estimateTokens: (chapterId: number) => Promise<number>;
-    analyzeChapterApi: (chapterId: number) => Promise<ChapterAnalysis>;
+    analyzeChapterApi: (chapterId: number, updateMode?: UpdateMode) => Promise<ChapterAnalysis>;
     parseManualResult: (json: string) => Promise<ChapterAnalysis>;
+    getChapterRawResponse: (chapterId: number) => Promise<string | null>;
+    reparseRawResponse: (chapterId: number) => Promise<ChapterAnalysis>;
+    listAnalysisVersions: (chapterId: number) => Promise<AnalysisVersion[]>;
+    setActiveAnalysisVersion: (chapterId: number, versionId: number) => Promise<void>;
+    diffAnalysisVersions: (fromVersionId: number, toVersionId: number) => Promise<FieldDiff[]>;
+    listLlmCalls: (novelId: string, chapterId?: number) => Promise<LlmCallMeta[]>;
+    getLlmCall: (callId: number) => Promise<LlmCall>;
+    reparseLlmCall: (callId: number, save?: boolean) => Promise<ChapterAnalysis>;
     saveAnalysis: (chapterId: number, analysis: ChapterAnalysis) => Promise<void>;
     fetchLlmConfig: () => Promise<void>;
     saveLlmConfig: (config: LlmConfig) => Promise<void>;
@@ -57,9 +89,15 @@ interface NovelStore {
     setError: (error: string | null) => void;
     clearSelection: () => void;
     clearNovelSummary: (novelId: string) => Promise<void>;
-    batchAnalyzeNovel: (novelId: string) => Promise<void>;
-    batchAnalyzeChapters: (novelId: string, chapterIds: number[]) => Promise<void>;
+    batchAnalyzeNovel: (novelId: string, failurePolicy?: FailurePolicy, updateMode?: UpdateMode) => Promise<BatchReport>;
+    batchAnalyzeChapters: (novelId: string, chapterIds: number[], failurePolicy?: FailurePolicy, updateMode?: UpdateMode) => Promise<BatchReport>;
     cancelBatch: () => Promise<void>;
+    /** Stop the running import; nothing of it is kept */
+    cancelImport: () => Promise<void>;
+    listJobs: (novelId: string) => Promise<AnalysisJob[]>;
+    resumeBatch: (novelId: string, failurePolicy?: FailurePolicy) => Promise<BatchReport>;
+    retryFailedJobs: (novelId: string) => Promise<number>;
+    clearJobs: (novelId: string) => Promise<void>;
     initEventListeners: () => Promise<void>;
 }
 
@@ -67,6 +105,7 @@ export const useNovelStore = create<NovelStore>((set, get) => ({
     novels: [],
     currentNovel: null,
     chapters: [],
+    volumes: [],
     selectedChapter: null,
     llmConfig: {
         base_url: 'https://api.openai.com/v1',
@@ -78,6 +117,14 @@ export const useNovelStore = create<NovelStore>((set, get) => ({
         temperature: 0.3,
         max_concurrent_tasks: 3,
         context_injection_mode: 'None',
+        failure_policy: 'Continue',
+        retry: {
+            max_attempts: 5,
+            base_delay_ms: 2000,
+            max_delay_ms: 60000,
+            retry_on: ['RateLimit', 'ServerError', 'Network'],
+        },
+        tokenizer: 'Auto',
     },
     analysisMode: 'manual',
     dimensions: [],
@@ -86,8 +133,10 @@ export const useNovelStore = create<NovelStore>((set, get) => ({
     progress: null,
     batchProgress: null,
     batchStartTime: null,
+    importProgress: null,
     streamContent: {},
     analyzingChapterIds: new Set<number>(),
+    lastImport: null,
     loading: false,
     error: null,
 
@@ -100,10 +149,10 @@ export const useNovelStore = create<NovelStore>((set, get) => ({
         }
     },
 
-    previewEpub: async (path) => {
+    previewBook: async (path) => {
         set({ loading: true, error: null });
         try {
-            const preview = await invoke<EpubPreview>('preview_epub', { path });
+            const preview = await invoke<BookPreview>('preview_book', { path });
             set({ loading: false });
             return preview;
         } catch (e) {
@@ -112,51 +161,112 @@ export const useNovelStore = create<NovelStore>((set, get) => ({
         }
     },
 
-    importEpubSelected: async (path, selectedIndices) => {
+    importBookSelected: async (path, selectedIndices, conversion, keepRuby, appendTo) => {
         set({ loading: true, error: null });
         try {
-            const id = await invoke<string>('import_epub_selected', { path, selectedIndices });
+            const result = await invoke<ImportResult>('import_book_selected', { path, selectedIndices, conversion, keepRuby, appendTo });
             await get().fetchNovels();
-            set({ loading: false });
-            return id;
+            set({ loading: false, lastImport: result });
+            return result.novel_id;
         } catch (e) {
             set({ loading: false, error: String(e) });
             throw e;
         }
     },
 
-    importTxtFiles: async (paths) => {
+    importTxtFiles: async (paths, encoding, conversion, appendTo) => {
         set({ loading: true, error: null });
         try {
-            const id = await invoke<string>('import_txt_files', { paths });
+            const result = await invoke<ImportResult>('import_txt_files', { paths, encoding, conversion, appendTo });
             await get().fetchNovels();
-            set({ loading: false });
-            return id;
+            set({ loading: false, lastImport: result });
+            return result.novel_id;
         } catch (e) {
             set({ loading: false, error: String(e) });
             throw e;
         }
     },
 
-    importSingleTxt: async (path) => {
+    importSingleTxt: async (path, headingRules, encoding, conversion, appendTo) => {
         set({ loading: true, error: null });
         try {
-            const id = await invoke<string>('import_single_txt', { path });
+            const result = await invoke<ImportResult>('import_single_txt', { path, headingRules, encoding, conversion, appendTo });
             await get().fetchNovels();
+            set({ loading: false, lastImport: result });
+            return result.novel_id;
+        } catch (e) {
+            set({ loading: false, error: String(e) });
+            throw e;
+        }
+    },
+
+    previewSingleTxt: async (path, headingRules, encoding) => {
+        set({ loading: true, error: null });
+        try {
+            const preview = await invoke<TxtPreview>('preview_single_txt', { path, headingRules, encoding });
             set({ loading: false });
-            return id;
+            return preview;
+        } catch (e) {
+            set({ loading: false, error: String(e) });
+            throw e;
+        }
+    },
+
+    importTxtSelected: async (path, headingRules, chapters, encoding, conversion, appendTo) => {
+        set({ loading: true, error: null });
+        try {
+            const result = await invoke<ImportResult>('import_txt_selected', { path, headingRules, chapters, encoding, conversion, appendTo });
+            await get().fetchNovels();
+            set({ loading: false, lastImport: result });
+            return result.novel_id;
         } catch (e) {
             set({ loading: false, error: String(e) });
             throw e;
         }
     },
 
+    listHeadingRuleSets: async () => {
+        return await invoke<HeadingRuleSet[]>('list_heading_rule_sets');
+    },
+
+    saveHeadingRuleSets: async (sets) => {
+        await invoke('save_heading_rule_sets', { sets });
+    },
+
+    getCleanupConfig: async () => {
+        return await invoke<CleanupConfig>('get_cleanup_config');
+    },
+
+    saveCleanupConfig: async (config) => {
+        await invoke('save_cleanup_config', { config });
+    },
+
+    dismissLastImport: () => set({ lastImport: null }),
+
+    getNovelCover: async (novelId) => {
+        return await invoke<string | null>('get_novel_cover', { novelId });
+    },
+
+    resyncNovel: async (novelId) => {
+        const report = await invoke<ResyncReport>('resync_novel', { novelId });
+        await get().fetchChapters(novelId);
+        const selected = get().selectedChapter;
+        if (selected?.id && report.modified.includes(selected.id)) {
+            await get().selectChapter(selected.id);
+        }
+        return report;
+    },
+
+    findDuplicateChapters: async (novelId) => {
+        return await invoke<DuplicateChapter[]>('find_duplicate_chapters', { novelId });
+    },
+
     deleteNovel: async (id) => {
         try {
             await invoke('delete_novel', { novelId: id });
             await get().fetchNovels();
             if (get().currentNovel?.id === id) {
-                set({ currentNovel: null, chapters: [], selectedChapter: null });
+                set({ currentNovel: null, chapters: [], volumes: [], selectedChapter: null });
             }
         } catch (e) {
             set({ error: String(e) });
@@ -189,6 +299,57 @@ export const useNovelStore = create<NovelStore>((set, get) => ({
         }
     },
 
+    renameChapter: async (chapterId, title, novelId) => {
+        try {
+            await invoke('rename_chapter', { chapterId, title });
+            await get().fetchChapters(novelId);
+            const selected = get().selectedChapter;
+            if (selected && selected.id === chapterId) {
+                set({ selectedChapter: { ...selected, title: title.trim() } });
+            }
+        } catch (e) {
+            set({ error: String(e) });
+        }
+    },
+
+    mergeChapters: async (chapterIds, novelId) => {
+        try {
+            const mergedId = await invoke<number>('merge_chapters', { chapterIds });
+            await get().fetchChapters(novelId);
+            const selected = get().selectedChapter;
+            if (selected && selected.id && chapterIds.includes(selected.id)) {
+                await get().selectChapter(mergedId);
+            }
+            return mergedId;
+        } catch (e) {
+            set({ error: String(e) });
+            throw e;
+        }
+    },
+
+    splitChapter: async (chapterId, at, novelId, title) => {
+        try {
+            const newId = await invoke<number>('split_chapter', { chapterId, at, title: title ?? null });
+            await get().fetchChapters(novelId);
+            if (get().selectedChapter?.id === chapterId) {
+                await get().selectChapter(chapterId);
+            }
+            return newId;
+        } catch (e) {
+            set({ error: String(e) });
+            throw e;
+        }
+    },
+
+    moveChapter: async (chapterId, toIndex, novelId) => {
+        try {
+            await invoke('move_chapter', { chapterId, toIndex });
+            await get().fetchChapters(novelId);
+        } catch (e) {
+            set({ error: String(e) });
+        }
+    },
+
     clearChapterAnalysis: async (chapterId, novelId) => {
         try {
             await invoke('clear_chapter_analysis', { chapterId });
@@ -213,8 +374,11 @@ export const useNovelStore = create<NovelStore>((set, get) => ({
 
     fetchChapters: async (novelId) => {
         try {
-            const chapters = await invoke<ChapterMeta[]>('list_chapters', { novelId });
-            set({ chapters });
+            const [chapters, volumes] = await Promise.all([
+                invoke<ChapterMeta[]>('list_chapters', { novelId }),
+                invoke<Volume[]>('list_volumes', { novelId }),
+            ]);
+            set({ chapters, volumes });
         } catch (e) {
             set({ error: String(e) });
         }
@@ -240,7 +404,62 @@ export const useNovelStore = create<NovelStore>((set, get) => ({
         return invoke<number>('estimate_prompt_tokens', { chapterId, dimensions: dims });
     },
 
-    analyzeChapterApi: async (chapterId) => {
+    getChapterRawResponse: async (chapterId) => {
+        return await invoke<string | null>('get_chapter_raw_response', { chapterId });
+    },
+
+    reparseRawResponse: async (chapterId) => {
+        set({ error: null });
+        try {
+            const analysis = await invoke<ChapterAnalysis>('reparse_raw_response', { chapterId });
+            await get().selectChapter(chapterId);
+            if (get().currentNovel) {
+                await get().fetchChapters(get().currentNovel!.id);
+            }
+            return analysis;
+        } catch (e) {
+            set({ error: String(e) });
+            throw e;
+        }
+    },
+
+    listAnalysisVersions: async (chapterId) => {
+        return await invoke<AnalysisVersion[]>('list_analysis_versions', { chapterId });
+    },
+
+    setActiveAnalysisVersion: async (chapterId, versionId) => {
+        await invoke('set_active_analysis_version', { chapterId, versionId });
+        await get().selectChapter(chapterId);
+        if (get().currentNovel) {
+            await get().fetchChapters(get().currentNovel!.id);
+        }
+    },
+
+    diffAnalysisVersions: async (fromVersionId, toVersionId) => {
+        return await invoke<FieldDiff[]>('diff_analysis_versions', { fromVersionId, toVersionId });
+    },
+
+    listLlmCalls: async (novelId, chapterId) => {
+        return await invoke<LlmCallMeta[]>('list_llm_calls', { novelId, chapterId });
+    },
+
+    getLlmCall: async (callId) => {
+        return await invoke<LlmCall>('get_llm_call', { callId });
+    },
+
+    reparseLlmCall: async (callId, save) => {
+        const analysis = await invoke<ChapterAnalysis>('reparse_llm_call', { callId, save });
+        const chapter = get().selectedChapter;
+        if (save && chapter?.id != null) {
+            await get().selectChapter(chapter.id);
+            if (get().currentNovel) {
+                await get().fetchChapters(get().currentNovel!.id);
+            }
+        }
+        return analysis;
+    },
+
+    analyzeChapterApi: async (chapterId, updateMode) => {
         const ids = new Set(get().analyzingChapterIds);
         ids.add(chapterId);
         set({
@@ -251,7 +470,7 @@ export const useNovelStore = create<NovelStore>((set, get) => ({
         try {
             const dims = get().currentNovel?.enabled_dimensions || [];
             const analysis = await invoke<ChapterAnalysis>('analyze_chapter_api', {
-                chapterId, dimensions: dims,
+                chapterId, dimensions: dims, updateMode,
             });
             // Refresh chapter and list
             await get().selectChapter(chapterId);
@@ -394,12 +613,13 @@ export const useNovelStore = create<NovelStore>((set, get) => ({
     setError: (error) => set({ error }),
     clearSelection: () => set({ selectedChapter: null }),
 
-    batchAnalyzeNovel: async (novelId) => {
+    batchAnalyzeNovel: async (novelId, failurePolicy, updateMode) => {
         set({ loading: true, error: null });
         try {
-            await invoke('batch_analyze_novel', { novelId });
+            const report = await invoke<BatchReport>('batch_analyze_novel', { novelId, updateMode, failurePolicy });
             await get().fetchChapters(novelId);
             set({ loading: false });
+            return report;
         } catch (e) {
             set({ loading: false, error: String(e) });
             throw e;
@@ -410,18 +630,48 @@ export const useNovelStore = create<NovelStore>((set, get) => ({
         await invoke('cancel_batch');
     },
 
-    batchAnalyzeChapters: async (novelId, chapterIds) => {
+    cancelImport: async () => {
+        await invoke('cancel_import');
+    },
+
+    batchAnalyzeChapters: async (novelId, chapterIds, failurePolicy, updateMode) => {
+        set({ loading: true, error: null });
+        try {
+            const report = await invoke<BatchReport>('batch_analyze_chapters', { novelId, chapterIds, updateMode, failurePolicy });
+            await get().fetchChapters(novelId);
+            set({ loading: false });
+            return report;
+        } catch (e) {
+            set({ loading: false, error: String(e) });
+            throw e;
+        }
+    },
+
+    listJobs: async (novelId) => {
+        return await invoke<AnalysisJob[]>('list_jobs', { novelId });
+    },
+
+    resumeBatch: async (novelId, failurePolicy) => {
         set({ loading: true, error: null });
         try {
-            await invoke('batch_analyze_chapters', { novelId, chapterIds });
+            const report = await invoke<BatchReport>('resume_batch', { novelId, failurePolicy });
             await get().fetchChapters(novelId);
             set({ loading: false });
+            return report;
         } catch (e) {
             set({ loading: false, error: String(e) });
             throw e;
         }
     },
 
+    retryFailedJobs: async (novelId) => {
+        return await invoke<number>('retry_failed_jobs', { novelId });
+    },
+
+    clearJobs: async (novelId) => {
+        await invoke('clear_jobs', { novelId });
+    },
+
     initEventListeners: async () => {
         await listen<ProgressEvent>('analysis_progress', (event) => {
             set({ progress: event.payload });
@@ -466,7 +716,7 @@ export const useNovelStore = create<NovelStore>((set, get) => ({
                 }
             }
 
-            if (payload.status === 'batch_done' || payload.status === 'batch_cancelled' || payload.status === 'error') {
+            if (payload.status === 'batch_done' || payload.status === 'batch_cancelled') {
                 setTimeout(() => set({ batchProgress: null }), 3000);
                 if (get().currentNovel?.id === payload.novel_id) {
                     get().fetchChapters(payload.novel_id);
@@ -475,6 +725,14 @@ export const useNovelStore = create<NovelStore>((set, get) => ({
             }
         });
 
+        await listen<ProgressEvent>('import_progress', (event) => {
+            set({ importProgress: event.payload });
+            const status = event.payload.status;
+            if (status === 'done' || status === 'cancelled' || status === 'error') {
+                setTimeout(() => set({ importProgress: null }), 3000);
+            }
+        });
+
         await listen<StreamingEvent>('analysis_streaming', (event) => {
             const payload = event.payload;
             set({
@@ -26,6 +26,20 @@ export interface NovelMeta {
   chapter_count: number;
   analyzed_count: number;
   created_at: string;
+  metadata: BookMetadata;
+  has_cover: boolean;
+}
+
+/** Bibliographic details read from the source file (EPUB OPF metadata) */
+export interface BookMetadata {
+  /** All creators, joined with "、" */
+  author: string | null;
+  /** BCP 47 language tag, e.g. "zh-CN" or "ja" */
+  language: string | null;
+  publisher: string | null;
+  series: string | null;
+  series_index: string | null;
+  identifiers: string[];
 }
 
 export interface Novel {
@@ -34,19 +48,37 @@ export interface Novel {
   source_type: SourceType;
   enabled_dimensions: AnalysisDimension[];
   created_at: string;
+  /** Conversion applied to the text on import */
+  conversion: ChineseConversion | null;
+  metadata: BookMetadata;
+  /** Whether a cover image is stored; load it with getNovelCover */
+  has_cover: boolean;
 }
 
+/** Simplified/Traditional conversion, named after the OpenCC configurations */
+export type ChineseConversion = 's2t' | 't2s' | 's2tw' | 's2hk';
+
 export type SourceType =
   | { Epub: string }
   | { TxtFiles: string[] }
-  | { SingleTxt: string };
+  | { SingleTxt: string }
+  | { Mobi: string }
+  | { Fb2: string }
+  | { Docx: string }
+  | { Html: string }
+  | { Markdown: string };
 
 export interface ChapterMeta {
   id: number;
   index: number;
   title: string;
   has_analysis: boolean;
+  analyzed_dimensions: AnalysisDimension[];
+  has_raw_response: boolean;
   token_estimate: number;
+  volume_id: number | null;
+  /** The text changed on resync after the active analysis was made */
+  analysis_stale: boolean;
 }
 
 export interface Chapter {
@@ -56,6 +88,35 @@ export interface Chapter {
   title: string;
   content: string;
   analysis: ChapterAnalysis | null;
+  volume_id: number | null;
+  /** Footnotes and asides taken out of the text */
+  notes: string | null;
+  /** The text changed on resync after the active analysis was made */
+  analysis_stale: boolean;
+}
+
+/** What re-reading a novel's source file changed */
+export interface ResyncReport {
+  /** Chapters whose text is unchanged; their analyses are kept */
+  unchanged: number;
+  /** Chapters whose text changed; their analyses are flagged stale */
+  modified: number[];
+  /** Chapters new in the source, appended after the existing ones */
+  added: number[];
+  /** Titles of stored chapters no longer in the source; they are kept */
+  missing: string[];
+  cleanup: CleanupReport;
+}
+
+/** Where to cut a chapter in two: a byte offset, or the n-th (0-based) non-empty line */
+export type SplitPoint = { Byte: number } | { Paragraph: number };
+
+export interface Volume {
+  id: number;
+  novel_id: string;
+  index: number;
+  title: string;
+  chapter_count: number;
 }
 
 // ---- Analysis Types ----
@@ -203,10 +264,90 @@ export interface LlmConfig {
   temperature: number;
   max_concurrent_tasks: number;
   context_injection_mode: ContextInjectionMode;
+  failure_policy: FailurePolicy;
+  retry: RetryConfig;
+  tokenizer: TokenizerChoice;
 }
 
+export type TokenizerChoice = 'Auto' | 'Heuristic' | 'Cl100k' | 'O200k' | { HuggingFace: string };
+
+export type RetryClass = 'RateLimit' | 'ServerError' | 'Network';
+
+export interface RetryConfig {
+  max_attempts: number;
+  base_delay_ms: number;
+  max_delay_ms: number;
+  retry_on: RetryClass[];
+}
+
+export type FailurePolicy = 'Continue' | 'StopOnFirst' | { StopAfter: number };
+
 export type AnalysisMode = 'api' | 'manual';
 
+// ---- Analysis Jobs ----
+
+export type JobStatus = 'queued' | 'running' | 'failed' | 'done';
+
+export type UpdateMode = 'replace' | 'incremental';
+
+export interface AnalysisJob {
+  id: number;
+  novel_id: string;
+  chapter_id: number;
+  chapter_index: number;
+  chapter_title: string;
+  dimensions: AnalysisDimension[];
+  update_mode: UpdateMode;
+  status: JobStatus;
+  attempts: number;
+  last_error: string | null;
+  created_at: string;
+  updated_at: string;
+}
+
+// ---- Analysis Versions ----
+
+export interface AnalysisVersion {
+  id: number;
+  chapter_id: number;
+  analysis: ChapterAnalysis;
+  model: string;
+  dimensions: AnalysisDimension[];
+  prompt_version: string;
+  created_at: string;
+  active: boolean;
+}
+
+export interface FieldDiff {
+  path: string;
+  before: unknown | null;
+  after: unknown | null;
+}
+
+// ---- LLM Call Log ----
+
+export type LlmCallKind = 'chapter' | 'segment' | 'repair' | 'summary_group' | 'summary_final';
+
+export interface LlmCallMeta {
+  id: number;
+  chapter_id: number | null;
+  kind: LlmCallKind;
+  model: string;
+  success: boolean;
+  error: string | null;
+  attempts: number;
+  latency_ms: number;
+  prompt_tokens: number | null;
+  completion_tokens: number | null;
+  created_at: string;
+}
+
+export interface LlmCall extends Omit<LlmCallMeta, 'success'> {
+  novel_id: string;
+  prompt: string;
+  response: string | null;
+}
+
 // ---- Events ----
 
 export interface ProgressEvent {
@@ -216,6 +357,21 @@ export interface ProgressEvent {
   current: number;
   total: number;
   message: string;
+  report?: BatchReport;
+}
+
+export interface BatchFailure {
+  chapter_id: number;
+  chapter_title: string;
+  error: string;
+}
+
+export interface BatchReport {
+  total: number;
+  succeeded: number;
+  failed: BatchFailure[];
+  cancelled: boolean;
+  stopped: boolean;
 }
 
 export interface StreamingEvent {
@@ -224,17 +380,131 @@ export interface StreamingEvent {
   full_content: string;
 }
 
-// ---- EPUB Preview ----
+// ---- Book Preview (EPUB, MOBI, FB2, DOCX, HTML, Markdown) ----
+
+export interface PreviewChapter {
+  index: number;
+  title: string;
+  char_count: number;
+  suggested: boolean;
+  volume: string | null;
+}
+
+export interface BookPreview {
+  title: string;
+  path: string;
+  chapters: PreviewChapter[];
+  /** Novels in the library that already seem to be this book */
+  library_matches: LibraryMatch[];
+}
+
+// ---- Fingerprints ----
+
+export interface LibraryMatch {
+  novel_id: string;
+  title: string;
+  /** Previewed chapters with the same or nearly the same text as a chapter of this novel */
+  matching_chapters: number;
+  /** The book shares an ISBN or other identifier with this novel */
+  same_identifier: boolean;
+}
+
+/** A chapter repeating an earlier chapter of the same novel */
+export interface DuplicateChapter {
+  chapter_id: number;
+  index: number;
+  title: string;
+  duplicate_of_id: number;
+  duplicate_of_index: number;
+  duplicate_of_title: string;
+  /** The texts are identical, not merely similar */
+  exact: boolean;
+}
+
+// ---- TXT Preview ----
 
-export interface EpubPreviewChapter {
+export interface TxtPreviewChapter {
   index: number;
   title: string;
   char_count: number;
   suggested: boolean;
+  warning: string | null;
+  pattern: string | null;
+  volume: string | null;
+}
+
+export interface DetectedEncoding {
+  name: string;
+  /** 0–1; 1 for a BOM, valid UTF-8 or a user-chosen encoding */
+  confidence: number;
+  /** Some bytes could not be decoded and were replaced */
+  lossy: boolean;
 }
 
-export interface EpubPreview {
+export interface TxtPreview {
   title: string;
   path: string;
-  chapters: EpubPreviewChapter[];
+  rule_set: string | null;
+  encoding: DetectedEncoding;
+  encoding_warning: string | null;
+  chapters: TxtPreviewChapter[];
+  /** Novels in the library that already seem to be this book */
+  library_matches: LibraryMatch[];
+}
+
+export interface TxtChapterSelection {
+  indices: number[];
+  title: string;
+}
+
+// ---- TXT Heading Rules ----
+
+export interface HeadingRuleSet {
+  name: string;
+  patterns: string[];
+  volume_patterns: string[];
+  builtin: boolean;
+}
+
+// ---- Import Cleanup ----
+
+export interface CleanupConfig {
+  enabled: boolean;
+  /** Regexes; matches are removed, lines left empty are dropped */
+  blacklist: string[];
+  /** Drop short lines found in many chapters that look like site headers or footers */
+  remove_repeated_lines: boolean;
+  /** Share of chapters (0–1) a line must appear in to count as repeated */
+  repeated_line_share: number;
+  normalize_whitespace: boolean;
+}
+
+export interface RuleHit {
+  rule: string;
+  count: number;
+  examples: string[];
+}
+
+export interface RepeatedLine {
+  line: string;
+  chapter_count: number;
+  /** False for lines that don't look like a header or footer; those are kept */
+  removed: boolean;
+}
+
+export interface CleanupReport {
+  rule_hits: RuleHit[];
+  repeated_lines: RepeatedLine[];
+  blank_lines_removed: number;
+  chars_before: number;
+  chars_after: number;
+}
+
+export interface ImportResult {
+  novel_id: string;
+  cleanup: CleanupReport;
+  /** Titles of chapters left out when appending because the novel already has them */
+  skipped: string[];
+  /** Titles of chapters appended although the novel has a chapter of that title with other text */
+  same_title: string[];
 }